@@ -0,0 +1,576 @@
+//! The accessibility tree of an editor, so that screen readers can read and navigate its text.
+//!
+//! The editor is a [`Role::MultilineTextInput`] node with one [`Role::InlineTextBox`] child per
+//! visible line. Each line node holds the text of its line, line ending included, split into
+//! grapheme clusters (the smallest units the cursor moves by) and words, and the editor node holds
+//! the primary selection as positions in those lines.
+//!
+//! AccessKit measures characters in UTF-8, as the buffer does; the platform adapters convert them
+//! to the UTF-16 indices that the platform accessibility APIs use. [`AccessTree::utf16_offset`]
+//! gives the same index for a position, for the bridges that need it directly, such as an input
+//! method.
+
+use std::ops::Range;
+
+use accesskit::{
+    Action, ActionData, ActionRequest, Node, NodeBuilder, NodeId, Role, TextPosition,
+    TextSelection, Tree, TreeUpdate,
+};
+use lapce_xi_rope::{Rope, RopeDelta};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    cursor::{Cursor, CursorMode},
+    editor::EditType,
+    encoding::offset_utf8_to_utf16,
+    selection::{SelRegion, Selection},
+    word::{get_char_property, CharClassification},
+};
+
+/// What handling an [`ActionRequest`] did.
+#[derive(Debug)]
+pub enum AccessAction {
+    /// The selection was moved. The update holds the editor node.
+    Selection(TreeUpdate),
+    /// The selected text was replaced. The update holds the editor node and the changed lines,
+    /// and the edit is returned for everything else that follows the buffer.
+    Edit {
+        update: TreeUpdate,
+        old_text: Rope,
+        delta: RopeDelta,
+        inval_lines: InvalLines,
+    },
+}
+
+/// Builds the accessibility nodes of an editor.
+///
+/// A line node's id is the editor's id plus one plus its line number, so the editor should be
+/// given an id with enough room after it for the lines of its buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessTree {
+    root: NodeId,
+    lines: Range<usize>,
+}
+
+impl AccessTree {
+    pub fn new(root: NodeId) -> Self {
+        Self { root, lines: 0..0 }
+    }
+
+    /// The id of the editor node.
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// The lines that have a node.
+    pub fn lines(&self) -> Range<usize> {
+        self.lines.clone()
+    }
+
+    /// The id of the node of `line`.
+    pub fn line_id(&self, line: usize) -> NodeId {
+        NodeId(self.root.0 + 1 + line as u64)
+    }
+
+    /// The line of the node `id`, if it is one of the visible lines.
+    pub fn line_of_id(&self, id: NodeId) -> Option<usize> {
+        let line = id.0.checked_sub(self.root.0 + 1)?;
+        let line = usize::try_from(line).ok()?;
+        self.lines.contains(&line).then_some(line)
+    }
+
+    /// The whole tree, with nodes for the visible `lines`. The editor node is the root of the
+    /// tree and has the focus; an editor inside a larger tree takes the `nodes` of the update.
+    pub fn build(&mut self, buffer: &Buffer, cursor: &Cursor, lines: Range<usize>) -> TreeUpdate {
+        self.lines = self.clamp(buffer, lines);
+        let mut nodes = vec![(self.root, self.root_node(buffer, cursor))];
+        nodes.extend(
+            self.lines
+                .clone()
+                .map(|line| (self.line_id(line), line_node(buffer, line))),
+        );
+        TreeUpdate {
+            nodes,
+            tree: Some(Tree::new(self.root)),
+            focus: self.root,
+        }
+    }
+
+    /// Changes the visible lines, such as after scrolling. Only the lines that were not visible
+    /// before are sent, along with the editor node for its children; the nodes of the lines that
+    /// are no longer visible are removed with it.
+    pub fn set_lines(
+        &mut self,
+        buffer: &Buffer,
+        cursor: &Cursor,
+        lines: Range<usize>,
+    ) -> TreeUpdate {
+        let old = self.lines.clone();
+        self.lines = self.clamp(buffer, lines);
+        let added = self.lines.clone().filter(|line| !old.contains(line));
+        self.update(buffer, cursor, added)
+    }
+
+    /// The update after the cursor moved, which only changes the editor node.
+    pub fn update_selection(&self, buffer: &Buffer, cursor: &Cursor) -> TreeUpdate {
+        self.update(buffer, cursor, std::iter::empty())
+    }
+
+    /// The update after an edit. The lines of `inval_lines` are sent again, and every visible line
+    /// after them too when the edit added or removed lines, as those lines moved to other nodes.
+    pub fn apply_edit(
+        &mut self,
+        buffer: &Buffer,
+        cursor: &Cursor,
+        inval_lines: &InvalLines,
+    ) -> TreeUpdate {
+        self.lines = self.clamp(buffer, self.lines.clone());
+        let start = inval_lines.start_line;
+        let end = if inval_lines.inval_count == inval_lines.new_count {
+            start + inval_lines.new_count
+        } else {
+            self.lines.end
+        };
+        let changed = start.max(self.lines.start)..end.min(self.lines.end);
+        self.update(buffer, cursor, changed)
+    }
+
+    /// Handles a [`Action::SetTextSelection`] or [`Action::ReplaceSelectedText`] request for the
+    /// editor or one of its lines. Other requests are left to the caller.
+    pub fn handle_action(
+        &mut self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        request: &ActionRequest,
+    ) -> Option<AccessAction> {
+        if request.target != self.root && self.line_of_id(request.target).is_none() {
+            return None;
+        }
+        match (request.action, request.data.as_ref()) {
+            (Action::SetTextSelection, Some(ActionData::SetTextSelection(selection))) => {
+                let anchor = self.offset_of(buffer, selection.anchor)?;
+                let focus = self.offset_of(buffer, selection.focus)?;
+                let selection = Selection::sel_region(SelRegion::new(anchor, focus, None));
+                cursor.update_selection(buffer, selection);
+                Some(AccessAction::Selection(
+                    self.update_selection(buffer, cursor),
+                ))
+            }
+            (Action::ReplaceSelectedText, Some(ActionData::Value(value))) => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(offset) => Selection::caret(offset),
+                    _ => cursor.edit_selection(buffer),
+                };
+                let (old_text, delta, inval_lines) =
+                    buffer.edit(&[(selection, value.as_ref())], EditType::InsertChars);
+                cursor.apply_delta(&delta);
+                let update = self.apply_edit(buffer, cursor, &inval_lines);
+                Some(AccessAction::Edit {
+                    update,
+                    old_text,
+                    delta,
+                    inval_lines,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The position of `offset`, if its line is visible. An offset inside a grapheme cluster is
+    /// on that cluster, and an offset in a line ending is on the line break.
+    pub fn position_of(&self, text: &impl RopeText, offset: usize) -> Option<TextPosition> {
+        let offset = offset.min(text.len());
+        let line = text.line_of_offset(offset);
+        if !self.lines.contains(&line) {
+            return None;
+        }
+        let column = offset - text.offset_of_line(line);
+        let content = text.line_content(line);
+        let character_index = characters(&content)
+            .take_while(|(index, character)| index + character.len() <= column)
+            .count();
+        Some(TextPosition {
+            node: self.line_id(line),
+            character_index,
+        })
+    }
+
+    /// The offset of `position`, if it is in one of the visible lines. A position past the line
+    /// break of its line is the start of the next line.
+    pub fn offset_of(&self, text: &impl RopeText, position: TextPosition) -> Option<usize> {
+        let line = self.line_of_id(position.node)?;
+        let content = text.line_content(line);
+        let column = characters(&content)
+            .nth(position.character_index)
+            .map(|(index, _)| index)
+            .unwrap_or(content.len());
+        Some(text.offset_of_line(line) + column)
+    }
+
+    /// The offset of `position` in UTF-16 code units from the start of the text.
+    pub fn utf16_offset(&self, text: &impl RopeText, position: TextPosition) -> Option<usize> {
+        let offset = self.offset_of(text, position)?;
+        Some(offset_utf8_to_utf16(
+            text.char_indices_iter(0..offset),
+            offset,
+        ))
+    }
+
+    /// The selection of the editor node: the primary region of the cursor, anchored where it was
+    /// started. An end outside the visible lines is moved to the nearest visible line, and there
+    /// is no selection when the caret itself is not visible.
+    pub fn text_selection(&self, text: &impl RopeText, cursor: &Cursor) -> Option<TextSelection> {
+        let (anchor, focus) = match &cursor.mode {
+            CursorMode::Normal(offset) => (*offset, *offset),
+            CursorMode::Visual { start, end, .. } => (*start, *end),
+            CursorMode::Insert(selection) => {
+                let region = selection.last_inserted().or(selection.last())?;
+                (region.start, region.end)
+            }
+        };
+        let focus = self.position_of(text, focus)?;
+        let anchor = self.position_of(text, self.clamp_offset(text, anchor))?;
+        Some(TextSelection { anchor, focus })
+    }
+
+    fn update(
+        &self,
+        buffer: &Buffer,
+        cursor: &Cursor,
+        lines: impl Iterator<Item = usize>,
+    ) -> TreeUpdate {
+        let mut nodes = vec![(self.root, self.root_node(buffer, cursor))];
+        nodes.extend(lines.map(|line| (self.line_id(line), line_node(buffer, line))));
+        TreeUpdate {
+            nodes,
+            tree: None,
+            focus: self.root,
+        }
+    }
+
+    fn root_node(&self, buffer: &Buffer, cursor: &Cursor) -> Node {
+        let mut node = NodeBuilder::new(Role::MultilineTextInput);
+        node.set_children(
+            self.lines
+                .clone()
+                .map(|line| self.line_id(line))
+                .collect::<Vec<_>>(),
+        );
+        if let Some(selection) = self.text_selection(buffer, cursor) {
+            node.set_text_selection(selection);
+        }
+        node.add_action(Action::Focus);
+        node.add_action(Action::SetTextSelection);
+        node.add_action(Action::ReplaceSelectedText);
+        node.build()
+    }
+
+    fn clamp(&self, buffer: &Buffer, lines: Range<usize>) -> Range<usize> {
+        let end = lines.end.min(buffer.num_lines());
+        lines.start.min(end)..end
+    }
+
+    fn clamp_offset(&self, text: &impl RopeText, offset: usize) -> usize {
+        let line = text.line_of_offset(offset.min(text.len()));
+        if line < self.lines.start {
+            text.offset_of_line(self.lines.start)
+        } else if line >= self.lines.end {
+            text.offset_line_end(text.offset_of_line(self.lines.end - 1), true)
+        } else {
+            offset
+        }
+    }
+}
+
+/// The characters of `content` as the nodes count them, along with their byte indices: its
+/// grapheme clusters, split as in [`split_cluster`].
+fn characters(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .grapheme_indices(true)
+        .flat_map(|(mut index, grapheme)| {
+            split_cluster(grapheme).map(move |character| {
+                index += character.len();
+                (index - character.len(), character)
+            })
+        })
+}
+
+/// Split a grapheme cluster into pieces of at most 255 bytes, since AccessKit gives the length
+/// of a character in a `u8`. Nearly every cluster is a single piece.
+fn split_cluster(grapheme: &str) -> impl Iterator<Item = &str> {
+    let mut rest = grapheme;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = rest.len().min(u8::MAX as usize);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some(piece)
+    })
+}
+
+/// The node of `line`, with its text split into grapheme clusters and words.
+fn line_node(text: &impl RopeText, line: usize) -> Node {
+    let content = text.line_content(line);
+    let mut character_lengths = Vec::new();
+    let mut word_lengths = Vec::new();
+    let mut previous: Option<CharClassification> = None;
+    for grapheme in content.graphemes(true) {
+        let class = grapheme
+            .chars()
+            .next()
+            .map(get_char_property)
+            .unwrap_or(CharClassification::Space);
+        let class = match class {
+            CharClassification::Cr | CharClassification::Lf => CharClassification::Space,
+            class => class,
+        };
+        // Trailing whitespace belongs to the word before it; a word starts on any other change
+        // of class.
+        let starts_word = match previous {
+            None => true,
+            Some(previous) => class != previous && class != CharClassification::Space,
+        };
+        for (i, character) in split_cluster(grapheme).enumerate() {
+            match word_lengths.last_mut() {
+                Some(length) if (i > 0 || !starts_word) && *length < u8::MAX => *length += 1,
+                _ => word_lengths.push(1),
+            }
+            character_lengths.push(character.len() as u8);
+        }
+        previous = Some(class);
+    }
+
+    let mut node = NodeBuilder::new(Role::InlineTextBox);
+    node.set_value(content.into_owned());
+    node.set_character_lengths(character_lengths);
+    node.set_word_lengths(word_lengths);
+    node.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use accesskit::{Action, ActionData, ActionRequest, NodeId, TextPosition, TextSelection};
+
+    use super::{AccessAction, AccessTree};
+    use crate::{
+        buffer::Buffer,
+        cursor::{Cursor, CursorMode},
+        editor::EditType,
+        selection::{SelRegion, Selection},
+    };
+
+    fn insert(selection: Selection) -> Cursor {
+        Cursor::new(CursorMode::Insert(selection), None, None)
+    }
+
+    fn position(node: u64, character_index: usize) -> TextPosition {
+        TextPosition {
+            node: NodeId(node),
+            character_index,
+        }
+    }
+
+    #[test]
+    fn build_lines() {
+        let buffer = Buffer::new("fn main() {\n    a\u{301}bc;\n}");
+        let cursor = insert(Selection::region(3, 5));
+        let mut tree = AccessTree::new(NodeId(100));
+        let update = tree.build(&buffer, &cursor, 0..10);
+        assert_eq!(tree.lines(), 0..3);
+        assert_eq!(update.nodes.len(), 4);
+        assert_eq!(update.focus, NodeId(100));
+
+        let root = &update.nodes[0].1;
+        assert_eq!(root.children(), &[NodeId(101), NodeId(102), NodeId(103)]);
+        assert_eq!(
+            root.text_selection(),
+            Some(&TextSelection {
+                anchor: position(101, 3),
+                focus: position(101, 5),
+            })
+        );
+
+        let line = &update.nodes[2].1;
+        assert_eq!(line.value(), Some("    a\u{301}bc;\n"));
+        // The combining accent is in the same character as its letter.
+        assert_eq!(line.character_lengths(), &[1, 1, 1, 1, 3, 1, 1, 1, 1]);
+        assert_eq!(line.word_lengths(), &[4, 3, 2]);
+        assert_eq!(update.nodes[1].1.word_lengths(), &[3, 4, 3, 2]);
+    }
+
+    #[test]
+    fn long_clusters() {
+        // A letter with 200 combining accents is a single cluster of 401 bytes
+        let text = format!("a{}b", "\u{301}".repeat(200));
+        let buffer = Buffer::new(&text);
+        let mut tree = AccessTree::new(NodeId(0));
+        let update = tree.build(&buffer, &Cursor::origin(false), 0..1);
+
+        let line = &update.nodes[1].1;
+        assert_eq!(line.character_lengths(), &[255, 146, 1]);
+        assert_eq!(line.word_lengths(), &[3]);
+        let lengths: usize = line.character_lengths().iter().map(|&l| l as usize).sum();
+        assert_eq!(lengths, text.len());
+        assert_eq!(tree.position_of(&buffer, 401), Some(position(1, 2)));
+        assert_eq!(tree.offset_of(&buffer, position(1, 2)), Some(401));
+    }
+
+    #[test]
+    fn selection_outside_lines() {
+        let buffer = Buffer::new("a\nb\nc\nd\n");
+        let mut tree = AccessTree::new(NodeId(0));
+
+        let update = tree.build(&buffer, &insert(Selection::region(0, 5)), 1..3);
+        assert_eq!(
+            update.nodes[0].1.text_selection(),
+            Some(&TextSelection {
+                anchor: position(2, 0),
+                focus: position(3, 1),
+            })
+        );
+
+        let update = tree.update_selection(&buffer, &insert(Selection::caret(7)));
+        assert_eq!(update.nodes.len(), 1);
+        assert_eq!(update.nodes[0].1.text_selection(), None);
+    }
+
+    #[test]
+    fn positions() {
+        let buffer = Buffer::new("ab\r\ncd");
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &Cursor::origin(false), 0..2);
+
+        // The line ending is one character.
+        assert_eq!(tree.position_of(&buffer, 2), Some(position(1, 2)));
+        assert_eq!(tree.position_of(&buffer, 3), Some(position(1, 2)));
+        assert_eq!(tree.position_of(&buffer, 6), Some(position(2, 2)));
+        assert_eq!(tree.offset_of(&buffer, position(1, 2)), Some(2));
+        assert_eq!(tree.offset_of(&buffer, position(1, 3)), Some(4));
+        assert_eq!(tree.offset_of(&buffer, position(2, 5)), Some(6));
+        assert_eq!(tree.offset_of(&buffer, position(3, 0)), None);
+    }
+
+    #[test]
+    fn utf16_offset() {
+        let buffer = Buffer::new("𝔸b\nçd");
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &Cursor::origin(false), 0..2);
+        assert_eq!(tree.utf16_offset(&buffer, position(1, 1)), Some(2));
+        assert_eq!(tree.utf16_offset(&buffer, position(2, 1)), Some(5));
+    }
+
+    #[test]
+    fn apply_edit() {
+        let mut buffer = Buffer::new("a\nb\nc\nd\n");
+        let mut cursor = insert(Selection::caret(0));
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &cursor, 0..3);
+
+        let (_, delta, inval_lines) =
+            buffer.edit(&[(Selection::caret(3), "x")], EditType::InsertChars);
+        cursor.apply_delta(&delta);
+        let update = tree.apply_edit(&buffer, &cursor, &inval_lines);
+        let ids: Vec<_> = update.nodes.iter().map(|(id, _)| id.0).collect();
+        assert_eq!(ids, [0, 2]);
+        assert_eq!(update.nodes[1].1.value(), Some("bx\n"));
+        assert!(update.tree.is_none());
+
+        let (_, _, inval_lines) =
+            buffer.edit(&[(Selection::caret(0), "z\n")], EditType::InsertChars);
+        let update = tree.apply_edit(&buffer, &cursor, &inval_lines);
+        let ids: Vec<_> = update.nodes.iter().map(|(id, _)| id.0).collect();
+        assert_eq!(ids, [0, 1, 2, 3]);
+        assert_eq!(update.nodes[2].1.value(), Some("a\n"));
+    }
+
+    #[test]
+    fn set_lines() {
+        let buffer = Buffer::new("a\nb\nc\nd\n");
+        let cursor = insert(Selection::caret(0));
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &cursor, 0..2);
+        let update = tree.set_lines(&buffer, &cursor, 1..3);
+        let ids: Vec<_> = update.nodes.iter().map(|(id, _)| id.0).collect();
+        assert_eq!(ids, [0, 3]);
+        assert_eq!(update.nodes[0].1.children(), &[NodeId(2), NodeId(3)]);
+    }
+
+    #[test]
+    fn set_text_selection() {
+        let mut buffer = Buffer::new("one\ntwo\n");
+        let mut cursor = insert(Selection::caret(0));
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &cursor, 0..3);
+
+        let request = ActionRequest {
+            action: Action::SetTextSelection,
+            target: NodeId(0),
+            data: Some(ActionData::SetTextSelection(TextSelection {
+                anchor: position(2, 3),
+                focus: position(1, 1),
+            })),
+        };
+        let Some(AccessAction::Selection(update)) =
+            tree.handle_action(&mut buffer, &mut cursor, &request)
+        else {
+            panic!("expected a selection");
+        };
+        assert_eq!(
+            cursor.mode,
+            CursorMode::Insert(Selection::sel_region(SelRegion::new(7, 1, None)))
+        );
+        assert_eq!(
+            update.nodes[0].1.text_selection(),
+            Some(&TextSelection {
+                anchor: position(2, 3),
+                focus: position(1, 1),
+            })
+        );
+
+        let request = ActionRequest {
+            target: NodeId(9),
+            ..request
+        };
+        assert!(tree
+            .handle_action(&mut buffer, &mut cursor, &request)
+            .is_none());
+    }
+
+    #[test]
+    fn replace_selected_text() {
+        let mut buffer = Buffer::new("one\ntwo\n");
+        let mut cursor = insert(Selection::region(1, 6));
+        let mut tree = AccessTree::new(NodeId(0));
+        tree.build(&buffer, &cursor, 0..3);
+
+        let request = ActionRequest {
+            action: Action::ReplaceSelectedText,
+            target: NodeId(1),
+            data: Some(ActionData::Value("ld\nne".into())),
+        };
+        let Some(AccessAction::Edit {
+            update, old_text, ..
+        }) = tree.handle_action(&mut buffer, &mut cursor, &request)
+        else {
+            panic!("expected an edit");
+        };
+        assert_eq!(old_text.to_string(), "one\ntwo\n");
+        assert_eq!(buffer.to_string(), "old\nneo\n");
+        assert_eq!(cursor.offset(), 6);
+        assert_eq!(update.nodes[1].1.value(), Some("old\n"));
+        assert_eq!(update.nodes[2].1.value(), Some("neo\n"));
+        assert_eq!(
+            update.nodes[0].1.text_selection(),
+            Some(&TextSelection {
+                anchor: position(2, 2),
+                focus: position(2, 2),
+            })
+        );
+    }
+}
@@ -0,0 +1,664 @@
+//! A live diff of a buffer against a base text, such as the content of the file at `HEAD`, for
+//! the version-control gutter.
+//!
+//! The diff is a list of hunks, the line ranges of the buffer that differ from the base and the
+//! base lines they replaced. Between hunks, the lines of the two texts correspond one to one. An
+//! edit of the buffer is merged right away into the hunks around it, which keeps that
+//! correspondence but marks the merged hunk as stale. Stale hunks are diffed again once edits
+//! have paused for the debounce delay, and only the lines they cover are compared.
+
+use std::{
+    ops::Range,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use lapce_xi_rope::{Rope, RopeDelta};
+
+use crate::{
+    buffer::{
+        diff::{rope_diff, DiffLines},
+        rope_text::{RopeText, RopeTextRef},
+        Buffer, InvalLines,
+    },
+    command::FocusCommand,
+    cursor::Cursor,
+    editor::EditType,
+    selection::Selection,
+};
+
+/// How long edits pause before stale hunks are diffed again.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HunkKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+/// Lines of the buffer that differ from the base. Removed lines have an empty range of buffer
+/// lines, which starts at the line that follows them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// The lines of the base that the hunk replaces.
+    pub base: Range<usize>,
+    /// The lines of the buffer.
+    pub lines: Range<usize>,
+    /// Whether the hunk covers edits that were not diffed yet, in which case it can be larger
+    /// than the actual difference.
+    stale: bool,
+}
+
+impl Hunk {
+    pub fn kind(&self) -> HunkKind {
+        if self.base.is_empty() {
+            HunkKind::Added
+        } else if self.lines.is_empty() {
+            HunkKind::Removed
+        } else {
+            HunkKind::Modified
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+}
+
+/// The diff of a buffer against a base text.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use std::time::{Duration, Instant};
+/// # use jihaz_composer::{
+/// #     base_diff::{BaseDiff, HunkKind},
+/// #     buffer::{rope_text::RopeText, Buffer},
+/// #     editor::EditType,
+/// #     selection::Selection,
+/// # };
+/// let mut buffer = Buffer::new("one\ntwo\nthree\n");
+/// let mut diff = BaseDiff::new("one\ntwo\nthree\n".into(), buffer.text(), Duration::from_millis(300));
+/// assert!(diff.hunks().is_empty());
+///
+/// let now = Instant::now();
+/// let (_, _, inval_lines) =
+///     buffer.edit(&[(Selection::region(4, 7), "2")], EditType::InsertChars);
+/// diff.apply_edit(&inval_lines, now);
+///
+/// // The edit shows at once, and is diffed again after the delay
+/// assert_eq!(diff.hunks()[0].lines, 1..2);
+/// assert_eq!(diff.deadline(), Some(now + Duration::from_millis(300)));
+/// diff.update(buffer.text(), now + Duration::from_millis(300));
+/// assert_eq!(diff.hunks()[0].kind(), HunkKind::Modified);
+/// assert!(!diff.hunks()[0].is_stale());
+/// ```
+#[derive(Clone, Debug)]
+pub struct BaseDiff {
+    base: Rope,
+    hunks: Vec<Hunk>,
+    debounce: Duration,
+    last_edit: Option<Instant>,
+}
+
+impl BaseDiff {
+    pub fn new(base: Rope, text: &Rope, debounce: Duration) -> Self {
+        let mut diff = Self {
+            base,
+            hunks: Vec::new(),
+            debounce,
+            last_edit: None,
+        };
+        diff.set_base(diff.base.clone(), text);
+        diff
+    }
+
+    pub fn base(&self) -> &Rope {
+        &self.base
+    }
+
+    /// Replace the base, as when the file is committed or checked out, and diff the whole text.
+    pub fn set_base(&mut self, base: Rope, text: &Rope) {
+        self.base = base;
+        self.hunks = vec![Hunk {
+            base: 0..RopeTextRef::new(&self.base).num_lines(),
+            lines: 0..RopeTextRef::new(text).num_lines(),
+            stale: true,
+        }];
+        self.flush(text);
+    }
+
+    /// The hunks, in the order of their lines.
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    /// The hunk on a line of the buffer, or the hunk of the lines removed before it.
+    pub fn hunk_at(&self, line: usize) -> Option<&Hunk> {
+        let index = self.hunks.partition_point(|hunk| hunk.lines.end <= line);
+        self.hunks
+            .get(index)
+            .filter(|hunk| hunk.lines.start <= line)
+            .or_else(|| {
+                // A removed hunk at the line ends at it
+                self.hunks[..index]
+                    .last()
+                    .filter(|hunk| hunk.lines.is_empty() && hunk.lines.start == line)
+            })
+    }
+
+    /// The hunks on any of the lines of `lines`, with those removed before a line of it.
+    pub fn hunks_in_lines(&self, lines: Range<usize>) -> &[Hunk] {
+        let start = self
+            .hunks
+            .partition_point(|hunk| hunk.lines.end < lines.start);
+        let end = self
+            .hunks
+            .partition_point(|hunk| hunk.lines.start < lines.end);
+        &self.hunks[start..end.max(start)]
+    }
+
+    /// Merge an edit of the buffer into the hunks, which are diffed again once edits pause.
+    pub fn apply_edit(&mut self, inval_lines: &InvalLines, now: Instant) {
+        self.merge(inval_lines);
+        self.last_edit = Some(now);
+    }
+
+    /// When the stale hunks are due to be diffed again, at which point [`BaseDiff::update`]
+    /// should be called.
+    pub fn deadline(&self) -> Option<Instant> {
+        if !self.hunks.iter().any(|hunk| hunk.stale) {
+            return None;
+        }
+        self.last_edit.map(|last_edit| last_edit + self.debounce)
+    }
+
+    /// Diff the stale hunks again if edits paused for long enough. Returns whether they were.
+    pub fn update(&mut self, text: &Rope, now: Instant) -> bool {
+        if self.deadline().is_some_and(|deadline| now >= deadline) {
+            self.flush(text);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Diff the stale hunks again now.
+    pub fn flush(&mut self, text: &Rope) {
+        let mut hunks = Vec::with_capacity(self.hunks.len());
+        for hunk in std::mem::take(&mut self.hunks) {
+            if hunk.stale {
+                diff_lines(&self.base, text, hunk.base, hunk.lines, &mut hunks);
+            } else {
+                hunks.push(hunk);
+            }
+        }
+        self.hunks = hunks;
+        self.last_edit = None;
+    }
+
+    /// The replacement of the old lines of an edit by its new lines, as a stale hunk that takes
+    /// in the hunks it touches.
+    fn merge(&mut self, inval_lines: &InvalLines) {
+        let start = inval_lines.start_line;
+        let end = start + inval_lines.inval_count;
+        let first = self.hunks.partition_point(|hunk| hunk.lines.end < start);
+        let last = self.hunks.partition_point(|hunk| hunk.lines.start <= end);
+        // Outside of hunks, a line of the buffer is `shift` lines after the base line it was
+        // left as
+        let mut shift = 0isize;
+        for hunk in &self.hunks[..first] {
+            shift += hunk.lines.len() as isize - hunk.base.len() as isize;
+        }
+        let (lines_start, base_start) = match self.hunks[first..last].first() {
+            Some(hunk) if hunk.lines.start <= start => (hunk.lines.start, hunk.base.start),
+            _ => (start, (start as isize - shift) as usize),
+        };
+        for hunk in &self.hunks[first..last] {
+            shift += hunk.lines.len() as isize - hunk.base.len() as isize;
+        }
+        let (lines_end, base_end) = match self.hunks[first..last].last() {
+            Some(hunk) if hunk.lines.end >= end => (hunk.lines.end, hunk.base.end),
+            _ => (end, (end as isize - shift) as usize),
+        };
+
+        let line_shift = inval_lines.new_count as isize - inval_lines.inval_count as isize;
+        let shifted = |line: usize| (line as isize + line_shift) as usize;
+        let merged = Hunk {
+            base: base_start..base_end,
+            lines: lines_start..shifted(lines_end),
+            stale: true,
+        };
+        for hunk in &mut self.hunks[last..] {
+            hunk.lines = shifted(hunk.lines.start)..shifted(hunk.lines.end);
+        }
+        self.hunks.splice(first..last, [merged]);
+    }
+
+    /// The hunk after `line`, wrapping around to the first one.
+    pub fn next_hunk(&self, line: usize) -> Option<&Hunk> {
+        self.hunks
+            .iter()
+            .find(|hunk| hunk.lines.start > line)
+            .or(self.hunks.first())
+    }
+
+    /// The hunk before `line`, wrapping around to the last one.
+    pub fn previous_hunk(&self, line: usize) -> Option<&Hunk> {
+        self.hunks
+            .iter()
+            .rev()
+            .find(|hunk| hunk.lines.start < line)
+            .or(self.hunks.last())
+    }
+
+    /// Run [`FocusCommand::NextDiff`] or [`FocusCommand::PreviousDiff`], moving the cursor to
+    /// the start of the hunk it goes to.
+    pub fn do_command(&self, cmd: &FocusCommand, buffer: &Buffer, cursor: &mut Cursor) {
+        let line = buffer.line_of_offset(cursor.offset());
+        let hunk = match cmd {
+            FocusCommand::NextDiff => self.next_hunk(line),
+            FocusCommand::PreviousDiff => self.previous_hunk(line),
+            _ => None,
+        };
+        if let Some(hunk) = hunk {
+            cursor.set_offset(buffer.offset_of_line(hunk.lines.start), false, false);
+        }
+    }
+
+    /// Replace the lines of the hunk on `line` with the base lines, as an edit that can be
+    /// undone.
+    pub fn revert_hunk(
+        &mut self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        line: usize,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let hunk = self.hunk_at(line)?.clone();
+        let (base_lines, lines) = self.extent(&hunk, buffer.text());
+        let base = RopeTextRef::new(&self.base);
+        let replacement = base.slice_to_cow(
+            base.offset_of_line(base_lines.start)..base.offset_of_line(base_lines.end),
+        );
+        let region = Selection::region(
+            buffer.offset_of_line(lines.start),
+            buffer.offset_of_line(lines.end),
+        );
+        let edit = buffer.edit(&[(region, replacement.as_ref())], EditType::Other);
+        self.merge(&edit.2);
+        self.flush(buffer.text());
+        cursor.set_offset(buffer.offset_of_line(hunk.lines.start), false, false);
+        Some(edit)
+    }
+
+    /// A patch of `hunks` in the unified format, with `context` lines around them, to stage them
+    /// with `git apply --cached`, which takes `--unidiff-zero` for patches without context.
+    /// Lines are numbered as in the base with only these hunks applied, so that other changes can
+    /// stay unstaged.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use jihaz_composer::{base_diff::BaseDiff, xi_rope::Rope};
+    /// let text = Rope::from("a\nB\nc\nd\n");
+    /// let diff = BaseDiff::new("a\nb\nc\n".into(), &text, Duration::ZERO);
+    /// assert_eq!(
+    ///     diff.patch(&text, "src/lib.rs", &diff.hunks()[..1], 1),
+    ///     "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+    /// );
+    /// ```
+    pub fn patch(&self, text: &Rope, path: &str, hunks: &[Hunk], context: usize) -> String {
+        let mut patch = format!("--- a/{path}\n+++ b/{path}\n");
+        let base_len = line_count(&self.base);
+        let mut shift = 0isize;
+        let mut hunks = hunks.iter().map(|hunk| self.extent(hunk, text)).peekable();
+        while let Some((base_lines, lines)) = hunks.next() {
+            // The hunks whose context lines touch are patched in one section
+            let old_start = base_lines.start.saturating_sub(context);
+            let mut body = String::new();
+            let before = push_lines(&mut body, ' ', &self.base, old_start..base_lines.start);
+            let (mut old_count, mut new_count) = (before, before);
+            let mut section = Some((base_lines, lines));
+            let mut old_end = old_start;
+            while let Some((base_lines, lines)) = section.take() {
+                let removed = push_lines(&mut body, '-', &self.base, base_lines.clone());
+                let added = push_lines(&mut body, '+', text, lines);
+                old_count += removed;
+                new_count += added;
+                old_end = base_lines.end;
+                if let Some(next) = hunks.next_if(|(next, _)| next.start <= old_end + context * 2) {
+                    let between = push_lines(&mut body, ' ', &self.base, old_end..next.0.start);
+                    old_count += between;
+                    new_count += between;
+                    section = Some(next);
+                }
+            }
+            let after = push_lines(
+                &mut body,
+                ' ',
+                &self.base,
+                old_end..(old_end + context).min(base_len),
+            );
+            old_count += after;
+            new_count += after;
+
+            // An empty range is numbered by the line before it
+            let start = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+            let new_start = (old_start as isize + shift) as usize;
+            patch.push_str(&format!(
+                "@@ -{},{old_count} +{},{new_count} @@\n",
+                start(old_start, old_count),
+                start(new_start, new_count),
+            ));
+            patch.push_str(&body);
+            shift += new_count as isize - old_count as isize;
+        }
+        patch
+    }
+
+    /// The line ranges of a hunk, extended to the line before it when it starts past the end of
+    /// either text, so that the texts can be cut at line boundaries.
+    ///
+    /// This is the case of the lines added or removed at the end of a text without a final line
+    /// ending: removing `b` from `a\nb` changes the first line too, from `a\n` to `a`.
+    fn extent(&self, hunk: &Hunk, text: &Rope) -> (Range<usize>, Range<usize>) {
+        let past_end = hunk.lines.start == RopeTextRef::new(text).num_lines()
+            || hunk.base.start == RopeTextRef::new(&self.base).num_lines();
+        if past_end && hunk.lines.start > 0 && hunk.base.start > 0 {
+            (
+                hunk.base.start - 1..hunk.base.end,
+                hunk.lines.start - 1..hunk.lines.end,
+            )
+        } else {
+            (hunk.base.clone(), hunk.lines.clone())
+        }
+    }
+}
+
+/// The text of a range of lines, with a line ending added when the range ends the text so that
+/// [`rope_diff`], which doesn't count an empty last line, sees as many lines as the range has.
+fn lines_text(text: &Rope, lines: &Range<usize>) -> Rope {
+    let text = RopeTextRef::new(text);
+    let mut slice = text
+        .slice_to_cow(text.offset_of_line(lines.start)..text.offset_of_line(lines.end))
+        .into_owned();
+    if !lines.is_empty() && lines.end >= text.num_lines() {
+        slice.push('\n');
+    }
+    Rope::from(slice)
+}
+
+/// The number of lines of a text as patches count them, without the empty line after a final
+/// line ending.
+fn line_count(text: &Rope) -> usize {
+    let text = RopeTextRef::new(text);
+    let last_line = text.last_line();
+    if text.line_len(last_line) == 0 {
+        last_line
+    } else {
+        last_line + 1
+    }
+}
+
+/// Add the lines of `lines` to a patch, marked with `prefix`, and return how many there were.
+fn push_lines(patch: &mut String, prefix: char, text: &Rope, lines: Range<usize>) -> usize {
+    let end = lines.end.min(line_count(text));
+    let text = RopeTextRef::new(text);
+    for line in lines.start..end {
+        patch.push(prefix);
+        patch
+            .push_str(&text.slice_to_cow(text.offset_of_line(line)..text.offset_of_line(line + 1)));
+        if !patch.ends_with('\n') {
+            patch.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    end.saturating_sub(lines.start)
+}
+
+/// Diff base lines with the buffer lines that replaced them, adding the hunks found.
+fn diff_lines(
+    base: &Rope,
+    text: &Rope,
+    base_lines: Range<usize>,
+    lines: Range<usize>,
+    hunks: &mut Vec<Hunk>,
+) {
+    if base_lines.is_empty() || lines.is_empty() {
+        if base_lines.len() + lines.len() > 0 {
+            hunks.push(Hunk {
+                base: base_lines,
+                lines,
+                stale: false,
+            });
+        }
+        return;
+    }
+    let Some(diff) = rope_diff(
+        lines_text(base, &base_lines),
+        lines_text(text, &lines),
+        0,
+        Arc::new(AtomicU64::new(0)),
+        None,
+    ) else {
+        return;
+    };
+    let mut hunk: Option<Hunk> = None;
+    let (mut base_line, mut line) = (base_lines.start, lines.start);
+    for diff_lines in diff {
+        match diff_lines {
+            DiffLines::Both(info) => {
+                hunks.extend(hunk.take());
+                base_line = base_lines.start + info.left.end;
+                line = lines.start + info.right.end;
+            }
+            DiffLines::Left(range) => {
+                let hunk = hunk.get_or_insert(Hunk {
+                    base: base_line..base_line,
+                    lines: line..line,
+                    stale: false,
+                });
+                base_line = base_lines.start + range.end;
+                hunk.base.end = base_line;
+            }
+            DiffLines::Right(range) => {
+                let hunk = hunk.get_or_insert(Hunk {
+                    base: base_line..base_line,
+                    lines: line..line,
+                    stale: false,
+                });
+                line = lines.start + range.end;
+                hunk.lines.end = line;
+            }
+        }
+    }
+    hunks.extend(hunk);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use lapce_xi_rope::Rope;
+
+    use super::{BaseDiff, Hunk, HunkKind};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        command::FocusCommand,
+        cursor::{Cursor, CursorMode},
+        editor::EditType,
+        selection::Selection,
+    };
+
+    const BASE: &str = "one\ntwo\nthree\nfour\nfive\nsix\n";
+
+    fn hunks(diff: &BaseDiff) -> Vec<(HunkKind, std::ops::Range<usize>, std::ops::Range<usize>)> {
+        diff.hunks()
+            .iter()
+            .map(|hunk| (hunk.kind(), hunk.base.clone(), hunk.lines.clone()))
+            .collect()
+    }
+
+    fn edit(buffer: &mut Buffer, diff: &mut BaseDiff, start: usize, end: usize, text: &str) {
+        let (_, _, inval_lines) =
+            buffer.edit(&[(Selection::region(start, end), text)], EditType::Other);
+        diff.apply_edit(&inval_lines, Instant::now());
+    }
+
+    #[test]
+    fn kinds() {
+        let text = Rope::from("zero\none\nTWO\nthree\nfive\nsix\nseven\n");
+        let diff = BaseDiff::new(BASE.into(), &text, Duration::ZERO);
+        assert_eq!(
+            hunks(&diff),
+            [
+                (HunkKind::Added, 0..0, 0..1),
+                (HunkKind::Modified, 1..2, 2..3),
+                (HunkKind::Removed, 3..4, 4..4),
+                (HunkKind::Added, 6..6, 6..7),
+            ]
+        );
+        assert_eq!(diff.hunk_at(2).map(Hunk::kind), Some(HunkKind::Modified));
+        assert_eq!(diff.hunk_at(4).map(Hunk::kind), Some(HunkKind::Removed));
+        assert_eq!(diff.hunk_at(5), None);
+        assert_eq!(diff.hunks_in_lines(2..5).len(), 2);
+    }
+
+    #[test]
+    fn edits() {
+        let mut buffer = Buffer::new(BASE);
+        let mut diff = BaseDiff::new(BASE.into(), buffer.text(), Duration::from_millis(100));
+
+        // An edit is a stale hunk until it is diffed again
+        edit(&mut buffer, &mut diff, 8, 8, "new\n");
+        assert_eq!(hunks(&diff), [(HunkKind::Modified, 2..3, 2..4)]);
+        assert!(diff.hunks()[0].is_stale());
+        let deadline = diff.deadline().unwrap();
+        assert!(!diff.update(buffer.text(), deadline - Duration::from_millis(1)));
+        assert!(diff.update(buffer.text(), deadline));
+        assert_eq!(hunks(&diff), [(HunkKind::Added, 2..2, 2..3)]);
+        assert_eq!(diff.deadline(), None);
+
+        // Hunks after an edit shift with the lines, and hunks that it touches are merged into it
+        let offset = buffer.offset_of_line(6);
+        edit(&mut buffer, &mut diff, offset, offset + 4, "");
+        edit(&mut buffer, &mut diff, 0, 0, "zero\n");
+        diff.flush(buffer.text());
+        assert_eq!(
+            hunks(&diff),
+            [
+                (HunkKind::Added, 0..0, 0..1),
+                (HunkKind::Added, 2..2, 3..4),
+                (HunkKind::Removed, 5..6, 7..7),
+            ]
+        );
+        let end = buffer.offset_of_line(4);
+        edit(&mut buffer, &mut diff, 0, end, "one\ntwo\n");
+        assert_eq!(hunks(&diff)[0], (HunkKind::Modified, 0..3, 0..3));
+        diff.flush(buffer.text());
+        assert_eq!(hunks(&diff), [(HunkKind::Removed, 5..6, 5..5)]);
+
+        // Undoing the edits brings the text back to the base
+        while let Some((_, _, inval_lines, _)) = buffer.do_undo() {
+            diff.apply_edit(&inval_lines, Instant::now());
+        }
+        diff.flush(buffer.text());
+        assert!(diff.hunks().is_empty());
+    }
+
+    #[test]
+    fn navigation() {
+        let buffer = Buffer::new("zero\none\nTWO\nthree\nfour\nfive\nsix\n");
+        let diff = BaseDiff::new(BASE.into(), buffer.text(), Duration::ZERO);
+        let starts = |line| {
+            (
+                diff.next_hunk(line).map(|hunk| hunk.lines.start),
+                diff.previous_hunk(line).map(|hunk| hunk.lines.start),
+            )
+        };
+        assert_eq!(starts(0), (Some(2), Some(2)));
+        assert_eq!(starts(2), (Some(0), Some(0)));
+        assert_eq!(starts(5), (Some(0), Some(2)));
+
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        diff.do_command(&FocusCommand::NextDiff, &buffer, &mut cursor);
+        assert_eq!(cursor.offset(), 9);
+        diff.do_command(&FocusCommand::PreviousDiff, &buffer, &mut cursor);
+        assert_eq!(cursor.offset(), 0);
+    }
+
+    #[test]
+    fn revert() {
+        let mut buffer = Buffer::new("one\nTWO\nthree\nfive\nsix\nseven");
+        let mut diff = BaseDiff::new(BASE.into(), buffer.text(), Duration::ZERO);
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+
+        assert!(diff.revert_hunk(&mut buffer, &mut cursor, 0).is_none());
+        diff.revert_hunk(&mut buffer, &mut cursor, 1).unwrap();
+        assert_eq!(buffer.to_string(), "one\ntwo\nthree\nfive\nsix\nseven");
+        assert_eq!(cursor.offset(), 4);
+        diff.revert_hunk(&mut buffer, &mut cursor, 3).unwrap();
+        assert_eq!(
+            buffer.to_string(),
+            "one\ntwo\nthree\nfour\nfive\nsix\nseven"
+        );
+
+        // The empty line after the final line ending of the base replaces the last line
+        diff.revert_hunk(&mut buffer, &mut cursor, 6).unwrap();
+        assert_eq!(buffer.to_string(), BASE);
+        assert!(diff.hunks().is_empty());
+
+        // Reverting is an edit like any other
+        let (_, _, inval_lines, _) = buffer.do_undo().unwrap();
+        diff.apply_edit(&inval_lines, Instant::now());
+        diff.flush(buffer.text());
+        assert_eq!(
+            buffer.to_string(),
+            "one\ntwo\nthree\nfour\nfive\nsix\nseven"
+        );
+        assert_eq!(hunks(&diff), [(HunkKind::Modified, 6..7, 6..7)]);
+
+        // Lines added after a last line without a line ending change that line too
+        let mut buffer = Buffer::new("a\nb");
+        let mut diff = BaseDiff::new("a".into(), buffer.text(), Duration::ZERO);
+        assert_eq!(hunks(&diff), [(HunkKind::Added, 1..1, 1..2)]);
+        diff.revert_hunk(&mut buffer, &mut cursor, 1).unwrap();
+        assert_eq!(buffer.to_string(), "a");
+    }
+
+    #[test]
+    fn patch() {
+        let text = Rope::from("one\nTWO\nthree\nfour\nsix\nseven\neight\nnine\nten\n");
+        let base = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten";
+        let diff = BaseDiff::new(base.into(), &text, Duration::ZERO);
+        let all = diff.hunks();
+        assert_eq!(all.len(), 3);
+
+        // Hunks whose context touches are in one section
+        assert_eq!(
+            diff.patch(&text, "f", &all[..2], 1),
+            "--- a/f\n+++ b/f\n\
+             @@ -1,6 +1,5 @@\n one\n-two\n+TWO\n three\n four\n-five\n six\n"
+        );
+        // Lines are numbered as if only the hunks of the patch were applied
+        assert_eq!(
+            diff.patch(&text, "f", &all[1..], 0),
+            "--- a/f\n+++ b/f\n\
+             @@ -5,1 +4,0 @@\n-five\n\
+             @@ -10,1 +9,1 @@\n-ten\n\\ No newline at end of file\n+ten\n"
+        );
+        assert_eq!(
+            diff.patch(&text, "f", &all[..1], 0),
+            "--- a/f\n+++ b/f\n@@ -2,1 +2,1 @@\n-two\n+TWO\n"
+        );
+
+        let text = Rope::from("a\nb");
+        let diff = BaseDiff::new("a".into(), &text, Duration::ZERO);
+        assert_eq!(
+            diff.patch(&text, "f", diff.hunks(), 3),
+            "--- a/f\n+++ b/f\n\
+             @@ -1,1 +1,2 @@\n-a\n\\ No newline at end of file\n+a\n+b\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! Bidirectional text support, for lines that mix right-to-left and left-to-right text.
+//!
+//! The unicode bidirectional algorithm is run on each line on its own, which gives the visual
+//! order of the grapheme clusters of the line. A caret between two clusters is then identified
+//! by its offset along with a [`CursorAffinity`], since at a direction boundary the same offset is
+//! drawn at two different places.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use unicode_bidi::{BidiInfo, Level};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    buffer::rope_text::RopeText, cursor::CursorAffinity, mode::Mode, visual_line::TextMeasure,
+};
+
+/// How the left and right arrow keys move the cursor through bidirectional text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BidiMovement {
+    /// Left moves towards the start of the text and right towards its end, whatever the
+    /// direction of the text under the cursor.
+    #[default]
+    Logical,
+    /// Left and right move the cursor to the left and to the right on the screen.
+    Visual,
+}
+
+/// A horizontal direction on the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HorizontalDirection {
+    Left,
+    Right,
+}
+
+/// The result of the bidirectional algorithm for a single line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BidiLine {
+    /// The grapheme clusters of the line, in logical order.
+    clusters: Vec<Range<usize>>,
+    /// Whether each cluster is right-to-left.
+    rtl: Vec<bool>,
+    /// The index of the cluster at each visual position, from left to right.
+    visual: Vec<usize>,
+    /// The visual position of each cluster.
+    visual_index: Vec<usize>,
+    base_rtl: bool,
+    len: usize,
+}
+
+impl BidiLine {
+    /// Run the bidirectional algorithm on `text`, the content of a line without its line ending.
+    /// The base direction is that of the first strong character.
+    pub fn new(text: &str) -> Self {
+        Self::build(text, None)
+    }
+
+    /// Run the bidirectional algorithm on `text` with a given base direction.
+    pub fn with_base_direction(text: &str, rtl: bool) -> Self {
+        Self::build(text, Some(if rtl { Level::rtl() } else { Level::ltr() }))
+    }
+
+    fn build(text: &str, base_level: Option<Level>) -> Self {
+        let clusters: Vec<Range<usize>> = text
+            .grapheme_indices(true)
+            .map(|(start, g)| start..start + g.len())
+            .collect();
+        let info = BidiInfo::new(text, base_level);
+        let rtl: Vec<bool> = clusters
+            .iter()
+            .map(|cluster| info.levels[cluster.start].is_rtl())
+            .collect();
+        let base_rtl = match (base_level, info.paragraphs.first()) {
+            (Some(level), _) => level.is_rtl(),
+            (None, Some(paragraph)) => paragraph.level.is_rtl(),
+            (None, None) => false,
+        };
+
+        let mut visual = Vec::with_capacity(clusters.len());
+        for paragraph in &info.paragraphs {
+            let (levels, runs) = info.visual_runs(paragraph, paragraph.range.clone());
+            for run in runs {
+                let first = clusters.partition_point(|c| c.start < run.start);
+                let last = clusters.partition_point(|c| c.start < run.end);
+                if levels[run.start].is_rtl() {
+                    visual.extend((first..last).rev());
+                } else {
+                    visual.extend(first..last);
+                }
+            }
+        }
+        let mut visual_index = vec![0; clusters.len()];
+        for (position, cluster) in visual.iter().enumerate() {
+            visual_index[*cluster] = position;
+        }
+
+        Self {
+            clusters,
+            rtl,
+            visual,
+            visual_index,
+            base_rtl,
+            len: text.len(),
+        }
+    }
+
+    /// Whether the base direction of the line is right-to-left.
+    pub fn is_rtl(&self) -> bool {
+        self.base_rtl
+    }
+
+    /// Whether the line contains both right-to-left and left-to-right clusters.
+    pub fn is_mixed(&self) -> bool {
+        self.rtl.iter().any(|rtl| *rtl) && self.rtl.iter().any(|rtl| !*rtl)
+    }
+
+    /// The grapheme clusters of the line, from left to right on the screen.
+    pub fn visual_clusters(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.visual
+            .iter()
+            .map(|cluster| self.clusters[*cluster].clone())
+    }
+
+    /// The visual position of the caret at `offset`, from `0` at the left edge of the line to the
+    /// number of clusters at its right edge.
+    pub fn caret_slot(&self, offset: usize, affinity: CursorAffinity) -> usize {
+        if self.clusters.is_empty() {
+            return 0;
+        }
+        let after = self.clusters.partition_point(|c| c.start < offset);
+        let after = (after < self.clusters.len()).then_some(after);
+        let before = self
+            .clusters
+            .partition_point(|c| c.end < offset)
+            .min(self.clusters.len() - 1);
+        let before = (offset > 0).then_some(before);
+
+        let use_before = match (before, after) {
+            (Some(_), None) => true,
+            (None, _) => false,
+            (Some(_), Some(_)) => affinity == CursorAffinity::Backward,
+        };
+        if use_before {
+            let before = before.unwrap_or(0);
+            // The caret is on the trailing edge of the cluster before it
+            if self.rtl[before] {
+                self.visual_index[before]
+            } else {
+                self.visual_index[before] + 1
+            }
+        } else {
+            let after = after.unwrap_or(0);
+            // The caret is on the leading edge of the cluster after it
+            if self.rtl[after] {
+                self.visual_index[after] + 1
+            } else {
+                self.visual_index[after]
+            }
+        }
+    }
+
+    /// The offset and affinity of the caret at the visual position `slot`.
+    ///
+    /// At a direction boundary, both neighbouring clusters give a different offset for the same
+    /// position, and `prefer_left` chooses the cluster on the left of it.
+    pub fn slot_caret(&self, slot: usize, prefer_left: bool) -> (usize, CursorAffinity) {
+        let count = self.clusters.len();
+        if count == 0 {
+            return (0, CursorAffinity::Forward);
+        }
+        let slot = slot.min(count);
+        let use_left = slot == count || (slot > 0 && prefer_left);
+        if use_left {
+            let left = self.visual[slot - 1];
+            if self.rtl[left] {
+                (self.clusters[left].start, CursorAffinity::Forward)
+            } else {
+                (self.clusters[left].end, CursorAffinity::Backward)
+            }
+        } else {
+            let right = self.visual[slot];
+            if self.rtl[right] {
+                (self.clusters[right].end, CursorAffinity::Backward)
+            } else {
+                (self.clusters[right].start, CursorAffinity::Forward)
+            }
+        }
+    }
+
+    /// Move the caret at `offset` one cluster to the left or to the right on the screen.
+    /// Returns `None` when the caret is already at that edge of the line.
+    pub fn move_visual(
+        &self,
+        offset: usize,
+        affinity: CursorAffinity,
+        direction: HorizontalDirection,
+    ) -> Option<(usize, CursorAffinity)> {
+        let slot = self.caret_slot(offset, affinity);
+        let slot = match direction {
+            HorizontalDirection::Left => slot.checked_sub(1)?,
+            HorizontalDirection::Right if slot < self.clusters.len() => slot + 1,
+            HorizontalDirection::Right => return None,
+        };
+        Some(self.slot_caret(slot, direction == HorizontalDirection::Right))
+    }
+
+    /// Move the block cursor on the cluster at `offset` to the cluster on its left or right on
+    /// the screen, as in normal mode. Returns `None` at the edge of the line.
+    pub fn move_block_visual(
+        &self,
+        offset: usize,
+        direction: HorizontalDirection,
+    ) -> Option<usize> {
+        let cluster = self
+            .clusters
+            .partition_point(|c| c.end <= offset)
+            .min(self.clusters.len().checked_sub(1)?);
+        let position = self.visual_index[cluster];
+        let position = match direction {
+            HorizontalDirection::Left => position.checked_sub(1)?,
+            HorizontalDirection::Right => position + 1,
+        };
+        self.visual
+            .get(position)
+            .map(|cluster| self.clusters[*cluster].start)
+    }
+
+    /// The horizontal position of the caret at `offset`, where `text` is the content the line
+    /// was built from.
+    pub fn caret_x(
+        &self,
+        text: &str,
+        offset: usize,
+        affinity: CursorAffinity,
+        measure: &mut impl TextMeasure,
+    ) -> f64 {
+        let slot = self.caret_slot(offset.min(self.len), affinity);
+        self.visual[..slot]
+            .iter()
+            .map(|cluster| measure.measure(&text[self.clusters[*cluster].clone()]))
+            .sum()
+    }
+
+    /// The horizontal spans covered by the selection of `range` of the line, from left to right.
+    ///
+    /// A logically contiguous range can be split into several spans on the screen when it covers
+    /// text of both directions.
+    pub fn selection_rects(
+        &self,
+        text: &str,
+        range: Range<usize>,
+        measure: &mut impl TextMeasure,
+    ) -> Vec<Range<f64>> {
+        let mut rects: Vec<Range<f64>> = Vec::new();
+        let mut x = 0.0;
+        let mut previous_selected = false;
+        for cluster in &self.visual {
+            let cluster = &self.clusters[*cluster];
+            let width = measure.measure(&text[cluster.clone()]);
+            let selected = range.start <= cluster.start && cluster.end <= range.end;
+            if selected {
+                match rects.last_mut() {
+                    Some(rect) if previous_selected => rect.end = x + width,
+                    _ => rects.push(x..x + width),
+                }
+            }
+            previous_selected = selected;
+            x += width;
+        }
+        rects
+    }
+}
+
+/// Move the cursor at `offset` horizontally by `count` clusters.
+///
+/// With [`BidiMovement::Logical`] this is the same as [`RopeText::move_left`] and
+/// [`RopeText::move_right`]. With [`BidiMovement::Visual`] the cursor moves in the direction of
+/// the arrow on the screen, and in insert mode moving past the edge of a line continues on the
+/// line before or after it, depending on the direction of the line.
+pub fn move_horizontal(
+    text: &impl RopeText,
+    offset: usize,
+    affinity: CursorAffinity,
+    direction: HorizontalDirection,
+    mode: Mode,
+    count: usize,
+    movement: BidiMovement,
+) -> (usize, CursorAffinity) {
+    if movement == BidiMovement::Logical {
+        let offset = match direction {
+            HorizontalDirection::Left => text.move_left(offset, mode, count),
+            HorizontalDirection::Right => text.move_right(offset, mode, count),
+        };
+        return (offset, affinity);
+    }
+
+    let mut offset = offset;
+    let mut affinity = affinity;
+    for _ in 0..count {
+        let line = text.line_of_offset(offset);
+        let line_start = text.offset_of_line(line);
+        let line_end = text.line_end_offset(line, true);
+        let content = text.slice_to_cow(line_start..line_end);
+        let bidi = BidiLine::new(&content);
+
+        if mode != Mode::Insert {
+            match bidi.move_block_visual(offset - line_start, direction) {
+                Some(new_offset) => offset = line_start + new_offset,
+                None => break,
+            }
+            continue;
+        }
+
+        if let Some((new_offset, new_affinity)) =
+            bidi.move_visual(offset - line_start, affinity, direction)
+        {
+            offset = line_start + new_offset;
+            affinity = new_affinity;
+            continue;
+        }
+
+        let towards_end = (direction == HorizontalDirection::Right) != bidi.is_rtl();
+        if towards_end && line < text.last_line() {
+            offset = text.offset_of_line(line + 1);
+            affinity = CursorAffinity::Forward;
+        } else if !towards_end && line > 0 {
+            offset = text.line_end_offset(line - 1, true);
+            affinity = CursorAffinity::Backward;
+        } else {
+            break;
+        }
+    }
+    (offset, affinity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::Buffer, visual_line::MonospaceMeasure};
+
+    // "abc " followed by the Arabic word "سلام"
+    const MIXED: &str = "abc سلام";
+
+    fn visual_text(text: &str) -> String {
+        BidiLine::new(text)
+            .visual_clusters()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn visual_order() {
+        assert_eq!(visual_text("abc"), "abc");
+        assert_eq!(visual_text(MIXED), "abc مالس");
+        // A left-to-right number inside right-to-left text
+        assert_eq!(visual_text("عدد 123 هنا"), "انه 123 ددع");
+        assert!(BidiLine::new(MIXED).is_mixed());
+        assert!(!BidiLine::new(MIXED).is_rtl());
+        assert!(BidiLine::new("سلام abc").is_rtl());
+    }
+
+    #[test]
+    fn harakat_stay_with_their_letter() {
+        // "بَ" is a letter with a fatha, a single cluster
+        let line = BidiLine::new("بَت");
+        assert_eq!(line.visual_clusters().collect::<Vec<_>>(), [4..6, 0..4]);
+    }
+
+    #[test]
+    fn visual_movement_through_mixed_line() {
+        let line = BidiLine::new(MIXED);
+        // Walk from the left edge to the right edge of the line
+        let mut caret = (0, CursorAffinity::Forward);
+        let mut slots = vec![line.caret_slot(caret.0, caret.1)];
+        while let Some(next) = line.move_visual(caret.0, caret.1, HorizontalDirection::Right) {
+            caret = next;
+            slots.push(line.caret_slot(caret.0, caret.1));
+        }
+        assert_eq!(slots, (0..=8).collect::<Vec<_>>());
+        // The right edge of the line is the logical start of the arabic word
+        assert_eq!(caret, (4, CursorAffinity::Forward));
+
+        // The offset between the two runs is drawn at two places
+        assert_eq!(line.caret_slot(4, CursorAffinity::Backward), 4);
+        assert_eq!(line.caret_slot(4, CursorAffinity::Forward), 8);
+        // The end of the line is drawn right after the space
+        assert_eq!(line.caret_slot(MIXED.len(), CursorAffinity::Backward), 4);
+    }
+
+    #[test]
+    fn block_cursor_movement() {
+        let line = BidiLine::new(MIXED);
+        // From `c` to the space, then to the last arabic letter which is drawn next to it
+        assert_eq!(
+            line.move_block_visual(2, HorizontalDirection::Right),
+            Some(3)
+        );
+        assert_eq!(
+            line.move_block_visual(3, HorizontalDirection::Right),
+            Some(10)
+        );
+        assert_eq!(line.move_block_visual(4, HorizontalDirection::Right), None);
+        assert_eq!(line.move_block_visual(0, HorizontalDirection::Left), None);
+    }
+
+    #[test]
+    fn move_horizontal_across_lines() {
+        let buffer = Buffer::new("سلام\nabc");
+        // Moving left at the left edge of an rtl line goes to the start of the next line
+        let (offset, affinity) = move_horizontal(
+            &buffer,
+            8,
+            CursorAffinity::Backward,
+            HorizontalDirection::Left,
+            Mode::Insert,
+            1,
+            BidiMovement::Visual,
+        );
+        assert_eq!((offset, affinity), (9, CursorAffinity::Forward));
+
+        let (offset, _) = move_horizontal(
+            &buffer,
+            9,
+            CursorAffinity::Forward,
+            HorizontalDirection::Left,
+            Mode::Insert,
+            1,
+            BidiMovement::Visual,
+        );
+        assert_eq!(offset, 8);
+
+        // Logical movement is unchanged
+        let (offset, _) = move_horizontal(
+            &buffer,
+            0,
+            CursorAffinity::Forward,
+            HorizontalDirection::Right,
+            Mode::Insert,
+            1,
+            BidiMovement::Logical,
+        );
+        assert_eq!(offset, 2);
+    }
+
+    #[test]
+    fn selection_rects_of_mixed_runs() {
+        let mut measure = MonospaceMeasure::default();
+        let line = BidiLine::new(MIXED);
+        // From `b` to the first arabic letter, which is drawn at the right end of the line
+        let rects = line.selection_rects(MIXED, 1..6, &mut measure);
+        assert_eq!(rects, [1.0..4.0, 7.0..8.0]);
+
+        assert_eq!(
+            line.caret_x(MIXED, 4, CursorAffinity::Forward, &mut measure),
+            8.0
+        );
+        assert_eq!(
+            line.caret_x(MIXED, 4, CursorAffinity::Backward, &mut measure),
+            4.0
+        );
+    }
+}
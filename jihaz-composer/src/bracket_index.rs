@@ -0,0 +1,651 @@
+//! An index of the brackets of a buffer, for matching brackets, finding the pair around an offset
+//! and coloring brackets by their nesting depth without scanning the text each time.
+//!
+//! The index keeps the offsets of all the bracket characters of the text and is rebased through
+//! the deltas of the buffer, so that an edit only scans the text it inserts. Brackets in strings
+//! and comments are left out when a [`BracketSyntax`] is given, and the remaining brackets are
+//! paired. An edit only pairs again the brackets of the innermost pair around it; the other pairs
+//! are moved with the edit. Queries are binary searches over the paired brackets.
+//!
+//! Moving the pairs is not free: the brackets are kept in arrays, so an edit shifts the offsets
+//! of every bracket after it and renumbers the pairs after it, which takes time in proportion to
+//! the number of brackets though it reads no text. An index stops keeping brackets beyond
+//! [`MAX_BRACKETS`] to bound that time, and is then [overflowed](BracketIndex::is_overflowed).
+
+use std::ops::Range;
+
+use lapce_xi_rope::{DeltaElement, Rope, RopeDelta};
+use tree_sitter::Tree;
+
+use crate::{
+    large_file::BufferFeatures,
+    syntax_util::{matching_char, matching_pair_direction},
+};
+
+/// Tells the index which brackets are code, as opposed to those in strings and comments.
+pub trait BracketSyntax {
+    fn is_code(&self, offset: usize) -> bool;
+}
+
+impl<F: Fn(usize) -> bool> BracketSyntax for F {
+    fn is_code(&self, offset: usize) -> bool {
+        self(offset)
+    }
+}
+
+/// Brackets are code unless they are in a node whose kind names a string, a character or a
+/// comment, which is what most tree-sitter grammars call them. Interpolations within strings are
+/// code again.
+impl BracketSyntax for Tree {
+    fn is_code(&self, offset: usize) -> bool {
+        let mut node = self
+            .root_node()
+            .descendant_for_byte_range(offset, offset + 1);
+        while let Some(current) = node {
+            let kind = current.kind();
+            if kind.contains("interpolation") || kind.contains("substitution") {
+                return true;
+            }
+            if kind.contains("string") || kind.contains("comment") || kind == "char_literal" {
+                return false;
+            }
+            node = current.parent();
+        }
+        true
+    }
+}
+
+/// A bracket of the text that is code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bracket {
+    pub offset: usize,
+    pub c: char,
+    /// The number of pairs around the bracket, not counting its own.
+    pub depth: usize,
+    /// The offset of the bracket it pairs with, if any.
+    pub matching: Option<usize>,
+    /// The index of the opening bracket of the innermost pair around this bracket.
+    parent: Option<usize>,
+    /// The index of the bracket it pairs with.
+    partner: Option<usize>,
+}
+
+impl Bracket {
+    pub fn is_open(&self) -> bool {
+        matching_pair_direction(self.c) == Some(true)
+    }
+}
+
+/// The most bracket characters that a [`BracketIndex`] keeps, as every edit takes time in
+/// proportion to their number.
+pub const MAX_BRACKETS: usize = 200_000;
+
+#[derive(Clone, Debug, Default)]
+pub struct BracketIndex {
+    /// The offsets of all the bracket characters of the text, in strings and comments too.
+    all: Vec<(usize, char)>,
+    /// The brackets that are code, paired.
+    brackets: Vec<Bracket>,
+    /// Whether the text had more than [`MAX_BRACKETS`] brackets, so the index keeps none.
+    overflowed: bool,
+}
+
+impl BracketIndex {
+    pub fn new(text: &Rope, syntax: Option<&dyn BracketSyntax>) -> Self {
+        let mut index = Self::default();
+        scan(text, 0, &mut index.all);
+        if index.all.len() > MAX_BRACKETS {
+            index.overflow();
+        }
+        index.resolve(syntax);
+        index
+    }
+
+    /// Whether the text has more than [`MAX_BRACKETS`] brackets, in which case the index is
+    /// empty and stays so through edits; brackets should then be matched by scanning the text,
+    /// and a new index can be made once the text has fewer of them.
+    pub fn is_overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    fn overflow(&mut self) {
+        self.overflowed = true;
+        self.all = Vec::new();
+        self.brackets = Vec::new();
+    }
+
+    /// An index of `text` if `features` allow for one, which doesn't use `syntax` if they don't
+    /// allow for it. In large-file mode there is no index, and brackets are matched by scanning
+    /// the text around them instead, as [`WordCursor::match_pairs`] does.
+    ///
+    /// [`WordCursor::match_pairs`]: crate::word::WordCursor::match_pairs
+    pub fn with_features(
+        text: &Rope,
+        syntax: Option<&dyn BracketSyntax>,
+        features: BufferFeatures,
+    ) -> Option<Self> {
+        let syntax = syntax.filter(|_| features.syntax);
+        features
+            .bracket_index
+            .then(|| Self::new(text, syntax))
+            .filter(|index| !index.is_overflowed())
+    }
+
+    /// Rebase the index through an edit of the text, scanning the inserted text for brackets.
+    ///
+    /// Only the brackets inside the innermost pair around the edit are paired again, and only
+    /// they are checked against `syntax`, which should be that of the text after the edit. The
+    /// pair grows to the one around it when the edit can change how the brackets outside pair,
+    /// such as when it leaves a closing bracket without its opening one. An edit that changes
+    /// what is a string or comment further away, such as by inserting a quote, should be
+    /// followed by [`BracketIndex::update_syntax`] for the ranges whose syntax changed.
+    ///
+    /// The brackets after the edit are moved and renumbered, which takes time in proportion to
+    /// their number; an edit that leaves more than [`MAX_BRACKETS`] of them overflows the index.
+    pub fn apply_delta(&mut self, delta: &RopeDelta, syntax: Option<&dyn BracketSyntax>) {
+        if self.overflowed {
+            return;
+        }
+        let (interval, new_len) = delta.summary();
+        let start = self
+            .all
+            .partition_point(|(offset, _)| *offset < interval.start);
+        let end = self
+            .all
+            .partition_point(|(offset, _)| *offset < interval.end);
+
+        // The brackets of the changed part of the text; the ones before it stay and the ones
+        // after it move with its new length
+        let mut changed = Vec::new();
+        let mut pos = 0;
+        for el in &delta.els {
+            match el {
+                DeltaElement::Copy(copy_start, copy_end) => {
+                    let copied = self.all[start..end]
+                        .iter()
+                        .filter(|(offset, _)| (*copy_start..*copy_end).contains(offset));
+                    for (offset, c) in copied {
+                        changed.push((pos + offset - copy_start, *c));
+                    }
+                    pos += copy_end - copy_start;
+                }
+                DeltaElement::Insert(text) => {
+                    scan(text, pos, &mut changed);
+                    pos += text.len();
+                }
+            }
+        }
+        for (offset, _) in &mut self.all[end..] {
+            *offset = *offset - interval.end + interval.start + new_len;
+        }
+        self.all.splice(start..end, changed);
+        if self.all.len() > MAX_BRACKETS {
+            self.overflow();
+            return;
+        }
+
+        self.repair(
+            interval.start..interval.end,
+            interval.start..interval.start + new_len,
+            syntax,
+        );
+    }
+
+    /// Recompute which brackets are code, in case the syntax changed without an edit.
+    pub fn set_syntax(&mut self, syntax: Option<&dyn BracketSyntax>) {
+        self.resolve(syntax);
+    }
+
+    /// Recompute which brackets of `range` are code, such as for the ranges that a reparse of
+    /// the syntax tree changed.
+    pub fn update_syntax(&mut self, range: Range<usize>, syntax: Option<&dyn BracketSyntax>) {
+        self.repair(range.clone(), range, syntax);
+    }
+
+    fn resolve(&mut self, syntax: Option<&dyn BracketSyntax>) {
+        let mut brackets = code_brackets(&self.all, syntax);
+        pair(&mut brackets);
+        self.brackets = brackets;
+    }
+
+    /// Pair again the brackets around the text at `old`, which is now at `new` and whose
+    /// brackets are already in `all`.
+    fn repair(&mut self, old: Range<usize>, new: Range<usize>, syntax: Option<&dyn BracketSyntax>) {
+        let rebase = |offset: usize| {
+            if offset >= old.end {
+                offset - old.end + new.end
+            } else {
+                offset
+            }
+        };
+        let start = self.brackets.partition_point(|b| b.offset < old.start);
+        let end = self.brackets.partition_point(|b| b.offset < old.end);
+
+        // The pairs around the edit, from the innermost, are the ones of the last bracket before
+        // it
+        let mut around = start.checked_sub(1);
+        while let Some(open) = around {
+            let bracket = self.brackets[open];
+            around = bracket.parent;
+            let Some(close) = bracket
+                .partner
+                .filter(|close| *close >= end && *close > open)
+            else {
+                continue;
+            };
+            let from = self
+                .all
+                .partition_point(|(offset, _)| *offset <= bracket.offset);
+            let to = self
+                .all
+                .partition_point(|(offset, _)| *offset < rebase(self.brackets[close].offset));
+            let mut inner = code_brackets(&self.all[from..to], syntax);
+            let (closed, unpaired) = pair(&mut inner);
+            // Otherwise a closing bracket inside pairs with a bracket outside, or the closing
+            // bracket of the pair with one inside, so the pair changes too
+            if !closed || unpaired.iter().any(|i| inner[*i].c == bracket.c) {
+                continue;
+            }
+
+            let removed = close - open - 1;
+            let index = |i: usize| {
+                if i >= close {
+                    i - removed + inner.len()
+                } else {
+                    i
+                }
+            };
+            for (i, b) in self.brackets.iter_mut().enumerate() {
+                if i <= open || i >= close {
+                    b.offset = rebase(b.offset);
+                    b.matching = b.matching.map(rebase);
+                    b.partner = b.partner.map(index);
+                    b.parent = b.parent.map(index);
+                }
+            }
+            for b in &mut inner {
+                b.depth += bracket.depth + 1;
+                b.parent = b.parent.map(|p| p + open + 1).or(Some(open));
+                b.partner = b.partner.map(|p| p + open + 1);
+            }
+            self.brackets.splice(open + 1..close, inner);
+            return;
+        }
+        self.resolve(syntax);
+    }
+
+    pub fn len(&self) -> usize {
+        self.brackets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brackets.is_empty()
+    }
+
+    /// The brackets that are code and start within `range`, e.g. the visible part of the text
+    /// for rainbow brackets.
+    pub fn brackets(&self, range: Range<usize>) -> &[Bracket] {
+        let start = self.brackets.partition_point(|b| b.offset < range.start);
+        let end = self.brackets.partition_point(|b| b.offset < range.end);
+        &self.brackets[start..end.max(start)]
+    }
+
+    pub fn bracket_at(&self, offset: usize) -> Option<&Bracket> {
+        self.brackets
+            .binary_search_by_key(&offset, |b| b.offset)
+            .ok()
+            .map(|i| &self.brackets[i])
+    }
+
+    /// The offset of the bracket that pairs with the bracket at `offset`, like
+    /// [`crate::word::WordCursor::match_pairs`].
+    pub fn matching_bracket(&self, offset: usize) -> Option<usize> {
+        self.bracket_at(offset)?.matching
+    }
+
+    /// The index of the opening bracket of the innermost pair that opens before `offset` and
+    /// closes at or after it.
+    fn enclosing(&self, offset: usize) -> Option<usize> {
+        let last = self
+            .brackets
+            .partition_point(|b| b.offset < offset)
+            .checked_sub(1)?;
+        let bracket = &self.brackets[last];
+        // Nothing is between the last bracket before `offset` and `offset`, so if it is an
+        // opening bracket with a pair, the pair is around `offset`
+        if bracket.is_open() && bracket.matching.is_some() {
+            Some(last)
+        } else {
+            bracket.parent
+        }
+    }
+
+    /// The offsets of the innermost pair of brackets around `offset`, like
+    /// [`crate::word::WordCursor::find_enclosing_pair`].
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::bracket_index::BracketIndex;
+    /// # use lapce_xi_rope::Rope;
+    /// let index = BracketIndex::new(&Rope::from("outer {{inner} world"), None);
+    /// assert_eq!(index.enclosing_pair(10), Some((7, 13)));
+    /// assert_eq!(index.depth_at(10), 1);
+    /// assert_eq!(index.enclosing_pair(15), None);
+    /// ```
+    pub fn enclosing_pair(&self, offset: usize) -> Option<(usize, usize)> {
+        let open = &self.brackets[self.enclosing(offset)?];
+        Some((open.offset, open.matching?))
+    }
+
+    /// The number of pairs of brackets around `offset`.
+    pub fn depth_at(&self, offset: usize) -> usize {
+        self.enclosing(offset)
+            .map(|open| self.brackets[open].depth + 1)
+            .unwrap_or(0)
+    }
+}
+
+/// The brackets of `all` that are code, not paired yet.
+fn code_brackets(all: &[(usize, char)], syntax: Option<&dyn BracketSyntax>) -> Vec<Bracket> {
+    all.iter()
+        .filter(|(offset, _)| syntax.is_none_or(|syntax| syntax.is_code(*offset)))
+        .map(|&(offset, c)| Bracket {
+            offset,
+            c,
+            depth: 0,
+            matching: None,
+            parent: None,
+            partner: None,
+        })
+        .collect()
+}
+
+/// Pair `brackets` on their own. Returns whether every closing bracket has a pair, and the
+/// indices of the opening brackets that are still waiting for theirs at the end.
+fn pair(brackets: &mut [Bracket]) -> (bool, Vec<usize>) {
+    // A closing bracket pairs with the last unpaired opening bracket of its kind, and the
+    // opening brackets after that one are left unpaired
+    let mut closed = true;
+    let mut open: Vec<usize> = Vec::new();
+    for i in 0..brackets.len() {
+        if brackets[i].is_open() {
+            open.push(i);
+            continue;
+        }
+        let opening = matching_char(brackets[i].c);
+        if let Some(pos) = open.iter().rposition(|j| Some(brackets[*j].c) == opening) {
+            brackets[i].partner = Some(open[pos]);
+            brackets[open[pos]].partner = Some(i);
+            open.truncate(pos);
+        } else {
+            closed = false;
+        }
+    }
+
+    let mut enclosing: Vec<usize> = Vec::new();
+    for (i, bracket) in brackets.iter_mut().enumerate() {
+        let partner = bracket.partner;
+        if partner.is_some_and(|partner| partner < i) {
+            enclosing.pop();
+        }
+        bracket.depth = enclosing.len();
+        bracket.parent = enclosing.last().copied();
+        if partner.is_some_and(|partner| partner > i) {
+            enclosing.push(i);
+        }
+    }
+    for i in 0..brackets.len() {
+        brackets[i].matching = brackets[i].partner.map(|partner| brackets[partner].offset);
+    }
+    (closed, open)
+}
+
+/// Add the brackets of `text` to `brackets`, with `text` starting at `offset`.
+fn scan(text: &Rope, offset: usize, brackets: &mut Vec<(usize, char)>) {
+    let mut chunk_start = offset;
+    for chunk in text.iter_chunks(..) {
+        // Brackets are ascii, so they can't be part of another character
+        for (i, byte) in chunk.bytes().enumerate() {
+            if matches!(byte, b'(' | b')' | b'[' | b']' | b'{' | b'}') {
+                brackets.push((chunk_start + i, byte as char));
+            }
+        }
+        chunk_start += chunk.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use lapce_xi_rope::Rope;
+
+    use super::{BracketIndex, MAX_BRACKETS};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        editor::EditType,
+        large_file::BufferFeatures,
+        selection::Selection,
+        word::WordCursor,
+    };
+
+    const CODE: &str =
+        "fn main() {\n    let v = [(1, 2), (3, 4)];\n    if v.is_empty() { return; }\n}\n";
+
+    fn depths(index: &BracketIndex) -> Vec<(char, usize)> {
+        index
+            .brackets(0..usize::MAX)
+            .iter()
+            .map(|b| (b.c, b.depth))
+            .collect()
+    }
+
+    #[test]
+    fn agrees_with_word_cursor() {
+        let text = Rope::from(CODE);
+        let index = BracketIndex::new(&text, None);
+        for offset in 0..=text.len() {
+            assert_eq!(
+                index.matching_bracket(offset),
+                WordCursor::new(&text, offset).match_pairs(),
+                "matching bracket at {offset}"
+            );
+            assert_eq!(
+                index.enclosing_pair(offset),
+                WordCursor::new(&text, offset).find_enclosing_pair(),
+                "enclosing pair at {offset}"
+            );
+        }
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let index = BracketIndex::new(&Rope::from("{[(a)]} (b"), None);
+        assert_eq!(
+            depths(&index),
+            [
+                ('{', 0),
+                ('[', 1),
+                ('(', 2),
+                (')', 2),
+                (']', 1),
+                ('}', 0),
+                ('(', 0)
+            ]
+        );
+        assert_eq!(index.depth_at(0), 0);
+        assert_eq!(index.depth_at(1), 1);
+        assert_eq!(index.depth_at(4), 3);
+        assert_eq!(index.depth_at(6), 1);
+        assert_eq!(index.depth_at(7), 0);
+        // An unclosed bracket isn't a pair
+        assert_eq!(index.depth_at(10), 0);
+        assert_eq!(index.matching_bracket(8), None);
+
+        // A closing bracket of another kind doesn't close a pair
+        let index = BracketIndex::new(&Rope::from("(a]b)"), None);
+        assert_eq!(index.matching_bracket(0), Some(4));
+        assert_eq!(index.matching_bracket(2), None);
+        assert_eq!(depths(&index), [('(', 0), (']', 1), (')', 0)]);
+    }
+
+    #[test]
+    fn skips_strings_and_comments() {
+        let text = Rope::from("f(\"(\" // (\n)");
+        // Everything from the quote to the end of the first line is a string or a comment
+        let syntax = |offset: usize| !(2..10).contains(&offset);
+        let index = BracketIndex::new(&text, Some(&syntax));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.matching_bracket(1), Some(11));
+        assert_eq!(index.enclosing_pair(9), Some((1, 11)));
+
+        let index = BracketIndex::new(&text, None);
+        assert_eq!(index.matching_bracket(1), None);
+        assert_eq!(index.matching_bracket(11), Some(9));
+    }
+
+    #[test]
+    fn features() {
+        let text = Rope::from("f(\"(\")");
+        let syntax = |offset: usize| !(2..5).contains(&offset);
+        let index = BracketIndex::with_features(&text, Some(&syntax), BufferFeatures::ALL).unwrap();
+        assert_eq!(index.matching_bracket(1), Some(5));
+
+        let features = BufferFeatures {
+            syntax: false,
+            ..BufferFeatures::ALL
+        };
+        let index = BracketIndex::with_features(&text, Some(&syntax), features).unwrap();
+        assert_eq!(index.matching_bracket(1), None);
+
+        let index = BracketIndex::with_features(&text, Some(&syntax), BufferFeatures::LARGE_FILE);
+        assert!(index.is_none());
+    }
+
+    #[test]
+    fn overflows() {
+        let text = "()".repeat(MAX_BRACKETS / 2 + 1);
+        let index = BracketIndex::new(&Rope::from(&text), None);
+        assert!(index.is_overflowed());
+        assert_eq!(index.matching_bracket(0), None);
+        let index = BracketIndex::with_features(&Rope::from(&text), None, BufferFeatures::ALL);
+        assert!(index.is_none());
+
+        let mut buffer = Buffer::new(&text[2..]);
+        let mut index = BracketIndex::new(buffer.text(), None);
+        assert!(!index.is_overflowed());
+        assert_eq!(index.matching_bracket(0), Some(1));
+        let (_, delta, _) = buffer.edit(&[(Selection::caret(0), "()")], EditType::Other);
+        index.apply_delta(&delta, None);
+        assert!(index.is_overflowed());
+        let (_, delta, _) = buffer.edit(&[(Selection::region(0, 2), "")], EditType::Other);
+        index.apply_delta(&delta, None);
+        assert!(index.is_overflowed());
+        assert!(index.brackets(0..usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn follows_edits() {
+        let mut buffer = Buffer::new(CODE);
+        let mut index = BracketIndex::new(buffer.text(), None);
+        let mut edit = |buffer: &mut Buffer, range: std::ops::Range<usize>, content: &str| {
+            let selection = Selection::region(range.start, range.end);
+            let (_, delta, _) = buffer.edit(&[(selection, content)], EditType::Other);
+            index.apply_delta(&delta, None);
+            let fresh = BracketIndex::new(buffer.text(), None);
+            assert_eq!(index.brackets(0..usize::MAX), fresh.brackets(0..usize::MAX));
+            fresh
+        };
+
+        let offset = buffer.offset_of_line(1);
+        edit(&mut buffer, offset..offset, "    call(a, { b });\n");
+        let offset = buffer.offset_of_line(2) + 13;
+        edit(&mut buffer, offset..offset + 15, "");
+        edit(&mut buffer, 0..0, "[");
+        let index = edit(&mut buffer, 1..9, "");
+        assert_eq!(index.matching_bracket(0), None);
+        assert_eq!(index.matching_bracket(1), None);
+        assert_eq!(index.matching_bracket(3), Some(buffer.len() - 2));
+
+        // An edit may change what is in a string elsewhere
+        let text = "(\"(\")";
+        let in_string = |offset: usize| !(1..4).contains(&offset);
+        let mut index = BracketIndex::new(&Rope::from(text), Some(&in_string));
+        assert_eq!(index.matching_bracket(0), Some(4));
+        let mut buffer = Buffer::new(text);
+        let (_, delta, _) = buffer.edit(&[(Selection::region(1, 2), "")], EditType::Other);
+        let in_string = |offset: usize| offset != 2;
+        index.apply_delta(&delta, Some(&in_string));
+        assert_eq!(index.matching_bracket(0), None);
+        assert_eq!(index.matching_bracket(1), Some(3));
+    }
+
+    #[test]
+    fn pairs_again_only_around_the_edit() {
+        let mut buffer = Buffer::new(CODE);
+        let mut index = BracketIndex::new(buffer.text(), None);
+        let checked = Cell::new(Vec::new());
+        let syntax = |offset: usize| {
+            let mut offsets = checked.take();
+            offsets.push(offset);
+            checked.set(offsets);
+            true
+        };
+
+        // Within `(1, 2)`, only the pair is left to check
+        let (_, delta, _) = buffer.edit([(Selection::caret(26), "(0)")], EditType::Other);
+        index.apply_delta(&delta, Some(&syntax));
+        assert_eq!(checked.take(), [26, 28]);
+        let fresh = BracketIndex::new(buffer.text(), None);
+        assert_eq!(index.brackets(0..usize::MAX), fresh.brackets(0..usize::MAX));
+
+        // A closing bracket without its pair can close a pair around it
+        let (_, delta, _) = buffer.edit([(Selection::caret(26), "]")], EditType::Other);
+        index.apply_delta(&delta, Some(&syntax));
+        let fresh = BracketIndex::new(buffer.text(), None);
+        assert_eq!(index.brackets(0..usize::MAX), fresh.brackets(0..usize::MAX));
+        assert_eq!(index.matching_bracket(26), Some(24));
+    }
+
+    #[test]
+    fn follows_edits_at_several_places() {
+        let mut buffer = Buffer::new(CODE);
+        let mut index = BracketIndex::new(buffer.text(), None);
+        let edits: [&[(usize, usize, &str)]; 6] = [
+            &[(10, 10, "{"), (30, 31, "")],
+            &[(0, 0, "("), (5, 5, ")")],
+            &[(12, 20, "[x]"), (40, 41, "}")],
+            &[(22, 22, "}"), (23, 23, "{")],
+            &[(1, 4, ""), (50, 60, "((")],
+            &[(0, 2, "")],
+        ];
+        for edit in edits {
+            let regions: Vec<_> = edit
+                .iter()
+                .map(|&(start, end, content)| (Selection::region(start, end), content))
+                .collect();
+            let (_, delta, _) = buffer.edit(regions, EditType::Other);
+            index.apply_delta(&delta, None);
+            let fresh = BracketIndex::new(buffer.text(), None);
+            assert_eq!(
+                index.brackets(0..usize::MAX),
+                fresh.brackets(0..usize::MAX),
+                "after {edit:?} in {:?}",
+                buffer.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn updates_syntax_of_a_range() {
+        let text = Rope::from("f(a, \"(\") {}");
+        let mut index = BracketIndex::new(&text, None);
+        assert_eq!(index.matching_bracket(1), None);
+        let in_string = |offset: usize| !(5..8).contains(&offset);
+        index.update_syntax(5..8, Some(&in_string));
+        let fresh = BracketIndex::new(&text, Some(&in_string));
+        assert_eq!(index.brackets(0..usize::MAX), fresh.brackets(0..usize::MAX));
+        assert_eq!(index.matching_bracket(1), Some(8));
+    }
+}
@@ -244,3 +244,62 @@ pub fn rope_diff(
 
     Some(changes)
 }
+
+/// The edits that turn `old` into `new`, touching only the lines that differ according to
+/// [`rope_diff`]. The ranges are offsets into `old`.
+pub fn minimal_edits(old: &Rope, new: &Rope) -> Vec<(Range<usize>, String)> {
+    let line_starts = |text: &Rope| {
+        let mut starts = vec![0];
+        for line in text.lines_raw(..) {
+            starts.push(starts[starts.len() - 1] + line.len());
+        }
+        starts
+    };
+    let old_starts = line_starts(old);
+    let new_starts = line_starts(new);
+    let Some(changes) = rope_diff(
+        old.clone(),
+        new.clone(),
+        0,
+        Arc::new(AtomicU64::new(0)),
+        None,
+    ) else {
+        return Vec::new();
+    };
+    let new_lines =
+        |lines: Range<usize>| new.slice_to_cow(new_starts[lines.start]..new_starts[lines.end]);
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut push_edit = |range: Range<usize>, content: &str| match edits.last_mut() {
+        Some((last, last_content)) if last.end == range.start => {
+            last.end = range.end;
+            last_content.push_str(content);
+        }
+        _ => edits.push((range, content.to_string())),
+    };
+    let mut old_line = 0;
+    for change in changes {
+        match change {
+            DiffLines::Left(range) => {
+                push_edit(old_starts[range.start]..old_starts[range.end], "");
+                old_line = range.end;
+            }
+            DiffLines::Right(range) => {
+                let offset = old_starts[old_line];
+                push_edit(offset..offset, &new_lines(range));
+            }
+            DiffLines::Both(info) => {
+                // Lines with the same content can still differ in their line endings
+                for (left, right) in info.left.clone().zip(info.right) {
+                    let old_line = old_starts[left]..old_starts[left + 1];
+                    let new_line = new_lines(right..right + 1);
+                    if old.slice_to_cow(old_line.clone()) != new_line {
+                        push_edit(old_line, &new_line);
+                    }
+                }
+                old_line = info.left.end;
+            }
+        }
+    }
+    edits
+}
@@ -110,6 +110,12 @@ impl Buffer {
         // Get rid of lone Cr's as Rope does not treat them as line endings
         let text = line_ending.normalize_limited(&text);
 
+        Self::new_normalized(text, line_ending)
+    }
+
+    /// A buffer of `text` whose lone `\r` were already replaced with `line_ending`, such as
+    /// while loading it, so that it isn't scanned again.
+    pub fn new_normalized(text: Rope, line_ending: LineEnding) -> Self {
         let len = text.len();
         Self {
             text,
@@ -239,6 +245,10 @@ impl Buffer {
         self.indent_style
     }
 
+    pub fn set_indent_style(&mut self, indent_style: IndentStyle) {
+        self.indent_style = indent_style;
+    }
+
     // TODO: users of this function should often be using Styling::indent_style instead!
     pub fn indent_unit(&self) -> &'static str {
         self.indent_style.as_str()
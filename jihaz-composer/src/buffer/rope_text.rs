@@ -144,6 +144,19 @@ pub trait RopeText {
             .slice_to_cow(self.offset_of_line(line)..self.offset_of_line(line + 1))
     }
 
+    /// Get the start of the grapheme cluster containing `offset`, or `offset` itself if it is
+    /// already on a grapheme cluster boundary.
+    fn grapheme_start(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len());
+        let prev = self.prev_grapheme_offset(offset, 1, 0);
+        let next = self.next_grapheme_offset(prev, 1, self.len());
+        if next <= offset {
+            offset
+        } else {
+            prev
+        }
+    }
+
     /// Get the offset of the previous grapheme cluster.
     fn prev_grapheme_offset(&self, offset: usize, count: usize, limit: usize) -> usize {
         let offset = offset.min(self.len());
@@ -472,7 +485,7 @@ mod tests {
     use lapce_xi_rope::Rope;
 
     use super::RopeText;
-    use crate::buffer::rope_text::RopeTextVal;
+    use crate::{buffer::rope_text::RopeTextVal, mode::Mode};
 
     #[test]
     fn test_line_content() {
@@ -603,6 +616,41 @@ mod tests {
         assert_eq!(text.prev_grapheme_offset(2, 1, 1), 1);
     }
 
+    #[test]
+    fn test_grapheme_cluster_movement() {
+        // A family emoji joined with ZWJ, two flags, a Hangul syllable made of jamo, and an Arabic
+        // letter with a kasra
+        let text = Rope::from("👨\u{200d}👩\u{200d}👧🇸🇦🇺🇸\u{1100}\u{1161}\u{11A8}بِ");
+        let text = RopeTextVal::new(text);
+
+        let mut offsets = vec![0];
+        let mut offset = 0;
+        while offset < text.len() {
+            offset = text.move_right(offset, Mode::Insert, 1);
+            offsets.push(offset);
+        }
+        assert_eq!(offsets, [0, 18, 26, 34, 43, 47]);
+
+        let mut offset = text.len();
+        for expected in offsets.iter().rev().skip(1) {
+            offset = text.move_left(offset, Mode::Insert, 1);
+            assert_eq!(offset, *expected);
+        }
+    }
+
+    #[test]
+    fn test_grapheme_start() {
+        let text = Rope::from("aبِc");
+        let text = RopeTextVal::new(text);
+
+        assert_eq!(text.grapheme_start(0), 0);
+        assert_eq!(text.grapheme_start(1), 1);
+        // Between the letter and its kasra
+        assert_eq!(text.grapheme_start(3), 1);
+        assert_eq!(text.grapheme_start(5), 5);
+        assert_eq!(text.grapheme_start(6), 6);
+    }
+
     #[test]
     fn test_first_non_blank_character_on_line() {
         let text = Rope::from("");
@@ -0,0 +1,367 @@
+//! Case conversion and identifier-style transformations of text.
+
+use std::ops::Range;
+
+use lapce_xi_rope::{Cursor, Rope};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A transformation of the letter case of some text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CaseTransform {
+    /// `hello world` -> `HELLO WORLD`
+    Upper,
+    /// `Hello World` -> `hello world`
+    Lower,
+    /// `Hello World` -> `hELLO wORLD`
+    Toggle,
+    /// `hello wORLD` -> `Hello World`
+    Title,
+    /// `helloWorld` -> `hello_world`
+    Snake,
+    /// `hello_world` -> `helloWorld`
+    Camel,
+    /// `hello_world` -> `HelloWorld`
+    Pascal,
+    /// `helloWorld` -> `hello-world`
+    Kebab,
+    /// `helloWorld` -> `HELLO_WORLD`
+    ScreamingSnake,
+}
+
+impl CaseTransform {
+    /// Whether this transformation rewrites identifiers, i.e. splits them into words and joins
+    /// them back in a different style, rather than only changing the case of letters.
+    pub fn is_identifier_style(&self) -> bool {
+        matches!(
+            self,
+            CaseTransform::Snake
+                | CaseTransform::Camel
+                | CaseTransform::Pascal
+                | CaseTransform::Kebab
+                | CaseTransform::ScreamingSnake
+        )
+    }
+
+    /// Apply the transformation to `text`.
+    ///
+    /// The conversion is unicode aware, so a character may turn into several characters,
+    /// and the result can have a different length than `text`.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::case::CaseTransform;
+    /// assert_eq!(CaseTransform::Upper.apply("straße"), "STRASSE");
+    /// assert_eq!(CaseTransform::Toggle.apply("Hello"), "hELLO");
+    /// assert_eq!(CaseTransform::Snake.apply("parseHTTPRequest"), "parse_http_request");
+    /// assert_eq!(CaseTransform::Camel.apply("let some_value = other-value;"), "let someValue = otherValue;");
+    /// ```
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            CaseTransform::Upper => text.to_uppercase(),
+            CaseTransform::Lower => text.to_lowercase(),
+            CaseTransform::Toggle => toggle_case(text),
+            CaseTransform::Title => title_case(text),
+            CaseTransform::Snake
+            | CaseTransform::Camel
+            | CaseTransform::Pascal
+            | CaseTransform::Kebab
+            | CaseTransform::ScreamingSnake => map_identifiers(text, |ident| self.join(ident)),
+        }
+    }
+
+    /// Join the words of an identifier in the style of `self`.
+    fn join(&self, ident: &str) -> String {
+        let words = split_identifier(ident);
+        let mut result = String::with_capacity(ident.len() + words.len());
+        for (i, word) in words.iter().enumerate() {
+            match self {
+                CaseTransform::Snake => {
+                    if i > 0 {
+                        result.push('_');
+                    }
+                    result.push_str(&word.to_lowercase());
+                }
+                CaseTransform::ScreamingSnake => {
+                    if i > 0 {
+                        result.push('_');
+                    }
+                    result.push_str(&word.to_uppercase());
+                }
+                CaseTransform::Kebab => {
+                    if i > 0 {
+                        result.push('-');
+                    }
+                    result.push_str(&word.to_lowercase());
+                }
+                CaseTransform::Camel if i == 0 => result.push_str(&word.to_lowercase()),
+                CaseTransform::Camel | CaseTransform::Pascal => capitalize_into(word, &mut result),
+                _ => result.push_str(word),
+            }
+        }
+        result
+    }
+}
+
+/// Swap the case of every cased character, leaving the rest untouched.
+fn toggle_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_lowercase() {
+            result.extend(c.to_uppercase());
+        } else if c.is_uppercase() {
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Capitalize the first letter of every word and lower-case the rest of it.
+fn title_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word_start = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        let is_word_char = c.is_alphanumeric() || c == '\'' || c == '’';
+        if is_word_char && !in_word {
+            word_start = i;
+            in_word = true;
+        } else if !is_word_char && in_word {
+            capitalize_into(&text[word_start..i], &mut result);
+            in_word = false;
+        }
+        if !in_word {
+            result.push(c);
+        }
+    }
+    if in_word {
+        capitalize_into(&text[word_start..], &mut result);
+    }
+    result
+}
+
+/// Push `word` into `result` with its first character in title case and the rest in lower case.
+fn capitalize_into(word: &str, result: &mut String) {
+    let mut chars = word.chars();
+    if let Some(first) = chars.next() {
+        push_titlecase(first, result);
+        result.push_str(&chars.as_str().to_lowercase());
+    }
+}
+
+/// Push the title case form of `c`.
+///
+/// This differs from the upper case form only for the few digraph characters that have a
+/// dedicated title case form, such as `ǆ` whose title case is `ǅ` rather than `Ǆ`.
+fn push_titlecase(c: char, result: &mut String) {
+    match c {
+        'Ǆ' | 'ǅ' | 'ǆ' => result.push('ǅ'),
+        'Ǉ' | 'ǈ' | 'ǉ' => result.push('ǈ'),
+        'Ǌ' | 'ǋ' | 'ǌ' => result.push('ǋ'),
+        'Ǳ' | 'ǲ' | 'ǳ' => result.push('ǲ'),
+        _ => result.extend(c.to_uppercase()),
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// The range of the identifier-like run of `text` around `offset`, which may be empty.
+///
+/// Unlike a word, the run goes on over `_` and `-`, so that `some-value` is a single
+/// identifier to convert.
+pub(crate) fn identifier_range(text: &Rope, offset: usize) -> Range<usize> {
+    let mut cursor = Cursor::new(text, offset);
+    let mut start = offset;
+    while let Some(c) = cursor.prev_codepoint() {
+        if !is_identifier_char(c) {
+            break;
+        }
+        start -= c.len_utf8();
+    }
+    cursor.set(offset);
+    let mut end = offset;
+    while let Some(c) = cursor.next_codepoint() {
+        if !is_identifier_char(c) {
+            break;
+        }
+        end += c.len_utf8();
+    }
+    start..end
+}
+
+/// Rewrite every identifier-like run of `text` with `f`, leaving everything in between as is.
+///
+/// Leading and trailing separators of an identifier (e.g. the `_` of `_private`) are kept, and
+/// runs without any alphanumeric character (such as a lone `-`) are not considered identifiers.
+fn map_identifiers(text: &str, f: impl Fn(&str) -> String) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let start = rest.find(is_identifier_char).unwrap_or(rest.len());
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest
+            .find(|c: char| !is_identifier_char(c))
+            .unwrap_or(rest.len());
+        let run = &rest[..end];
+        rest = &rest[end..];
+
+        let core_start = run.find(char::is_alphanumeric);
+        let core_end = run
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_alphanumeric())
+            .map(|(i, c)| i + c.len_utf8());
+        match (core_start, core_end) {
+            (Some(core_start), Some(core_end)) => {
+                result.push_str(&run[..core_start]);
+                result.push_str(&f(&run[core_start..core_end]));
+                result.push_str(&run[core_end..]);
+            }
+            _ => result.push_str(run),
+        }
+    }
+    result
+}
+
+/// Split an identifier into its words.
+///
+/// Words are separated by `_` and `-`, by a lower case to upper case transition (`fooBar`) and
+/// by the end of an upper case acronym (`HTTPServer` is `HTTP` and `Server`). Digits stick to
+/// the word they follow.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::case::split_identifier;
+/// assert_eq!(split_identifier("parseHTTPRequest2d"), vec!["parse", "HTTP", "Request2d"]);
+/// assert_eq!(split_identifier("SOME__value-here"), vec!["SOME", "value", "here"]);
+/// ```
+pub fn split_identifier(ident: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let chars: Vec<(usize, char)> = ident.char_indices().collect();
+    let mut word_start: Option<usize> = None;
+
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(start) = word_start.take() {
+                words.push(&ident[start..offset]);
+            }
+            continue;
+        }
+
+        let Some(start) = word_start else {
+            word_start = Some(offset);
+            continue;
+        };
+
+        let prev = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let is_boundary = c.is_uppercase()
+            && (prev.is_lowercase()
+                || prev.is_numeric()
+                || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase())));
+        if is_boundary {
+            words.push(&ident[start..offset]);
+            word_start = Some(offset);
+        }
+    }
+
+    if let Some(start) = word_start {
+        words.push(&ident[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_identifier, CaseTransform};
+
+    #[test]
+    fn upper_and_lower_expand_characters() {
+        assert_eq!(CaseTransform::Upper.apply("straße"), "STRASSE");
+        assert_eq!(CaseTransform::Upper.apply("ﬁne"), "FINE");
+        assert_eq!(CaseTransform::Lower.apply("İ"), "i\u{307}");
+        // Final sigma is lower cased according to its position in the word
+        assert_eq!(CaseTransform::Lower.apply("ΟΔΟΣ ΟΔΟΣ"), "οδος οδος");
+    }
+
+    #[test]
+    fn toggle_case() {
+        assert_eq!(
+            CaseTransform::Toggle.apply("Hello World 42"),
+            "hELLO wORLD 42"
+        );
+        assert_eq!(CaseTransform::Toggle.apply("ßA"), "SSa");
+        // Caseless scripts are left untouched
+        assert_eq!(CaseTransform::Toggle.apply("مرحبا a"), "مرحبا A");
+    }
+
+    #[test]
+    fn title_case() {
+        assert_eq!(
+            CaseTransform::Title.apply("the QUICK brown-fox's tail"),
+            "The Quick Brown-Fox's Tail"
+        );
+        assert_eq!(CaseTransform::Title.apply("ǆungla"), "ǅungla");
+        assert_eq!(CaseTransform::Title.apply("ßa"), "SSa");
+    }
+
+    #[test]
+    fn split_identifiers() {
+        assert_eq!(split_identifier("fooBar"), vec!["foo", "Bar"]);
+        assert_eq!(split_identifier("FooBar"), vec!["Foo", "Bar"]);
+        assert_eq!(split_identifier("HTTPServer"), vec!["HTTP", "Server"]);
+        assert_eq!(split_identifier("foo_bar-baz"), vec!["foo", "bar", "baz"]);
+        assert_eq!(split_identifier("utf8Decode"), vec!["utf8", "Decode"]);
+        assert_eq!(
+            split_identifier("SCREAMING_CASE"),
+            vec!["SCREAMING", "CASE"]
+        );
+        assert_eq!(split_identifier("ÉtéÀParis"), vec!["Été", "À", "Paris"]);
+        assert!(split_identifier("__").is_empty());
+    }
+
+    #[test]
+    fn identifier_styles() {
+        let input = "parseHTTPRequest";
+        assert_eq!(CaseTransform::Snake.apply(input), "parse_http_request");
+        assert_eq!(CaseTransform::Camel.apply(input), "parseHttpRequest");
+        assert_eq!(CaseTransform::Pascal.apply(input), "ParseHttpRequest");
+        assert_eq!(CaseTransform::Kebab.apply(input), "parse-http-request");
+        assert_eq!(
+            CaseTransform::ScreamingSnake.apply(input),
+            "PARSE_HTTP_REQUEST"
+        );
+
+        assert_eq!(
+            CaseTransform::Camel.apply("some-kebab-name"),
+            "someKebabName"
+        );
+        assert_eq!(CaseTransform::Pascal.apply("straße_name"), "StraßeName");
+        assert_eq!(CaseTransform::ScreamingSnake.apply("straße"), "STRASSE");
+    }
+
+    #[test]
+    fn identifier_styles_keep_surroundings() {
+        assert_eq!(
+            CaseTransform::Snake.apply("let fooBar = bazQux(a - b);"),
+            "let foo_bar = baz_qux(a - b);"
+        );
+        assert_eq!(
+            CaseTransform::Camel.apply("_private_field_"),
+            "_privateField_"
+        );
+        assert_eq!(
+            CaseTransform::Kebab.apply("\tSomeValue\n"),
+            "\tsome-value\n"
+        );
+    }
+}
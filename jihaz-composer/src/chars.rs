@@ -6,6 +6,25 @@ pub fn char_is_line_ending(ch: char) -> bool {
     matches!(ch, '\u{000A}')
 }
 
+/// Determine whether a character is an Arabic diacritic, such as the harakat, tanween, shadda
+/// and sukun, or a Quranic annotation mark. These combine with the letter before them into a
+/// single grapheme cluster.
+#[inline]
+pub fn char_is_arabic_diacritic(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0610}'..='\u{061A}'
+            | '\u{064B}'..='\u{065F}'
+            | '\u{0670}'
+            | '\u{06D6}'..='\u{06DC}'
+            | '\u{06DF}'..='\u{06E4}'
+            | '\u{06E7}'..='\u{06E8}'
+            | '\u{06EA}'..='\u{06ED}'
+            | '\u{08D3}'..='\u{08E1}'
+            | '\u{08E3}'..='\u{08FF}'
+    )
+}
+
 /// Determine whether a character qualifies as (non-line-break)
 /// whitespace.
 #[inline]
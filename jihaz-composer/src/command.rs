@@ -1,3 +1,6 @@
+use std::str::FromStr;
+
+use strum::EnumMessage;
 use strum_macros::{Display, EnumIter, EnumMessage, EnumString, IntoStaticStr};
 
 use crate::movement::{LinePosition, Movement};
@@ -129,6 +132,68 @@ pub enum EditCommand {
     #[strum(message = "Normalize Line Endings")]
     #[strum(serialize = "normalize_line_endings")]
     NormalizeLineEndings,
+
+    #[strum(message = "Transform to Uppercase")]
+    #[strum(serialize = "upper_case")]
+    UpperCase,
+    #[strum(message = "Transform to Lowercase")]
+    #[strum(serialize = "lower_case")]
+    LowerCase,
+    #[strum(message = "Toggle Case")]
+    #[strum(serialize = "toggle_case")]
+    ToggleCase,
+    #[strum(message = "Transform to Title Case")]
+    #[strum(serialize = "title_case")]
+    TitleCase,
+    #[strum(message = "Transform to Snake Case")]
+    #[strum(serialize = "snake_case")]
+    SnakeCase,
+    #[strum(message = "Transform to Camel Case")]
+    #[strum(serialize = "camel_case")]
+    CamelCase,
+    #[strum(message = "Transform to Pascal Case")]
+    #[strum(serialize = "pascal_case")]
+    PascalCase,
+    #[strum(message = "Transform to Kebab Case")]
+    #[strum(serialize = "kebab_case")]
+    KebabCase,
+    #[strum(message = "Transform to Screaming Snake Case")]
+    #[strum(serialize = "screaming_snake_case")]
+    ScreamingSnakeCase,
+
+    #[strum(message = "Sort Lines")]
+    #[strum(serialize = "sort_lines")]
+    SortLines,
+    #[strum(message = "Sort Lines Naturally")]
+    #[strum(serialize = "sort_lines_natural")]
+    SortLinesNatural,
+    #[strum(message = "Sort Lines Case Insensitive")]
+    #[strum(serialize = "sort_lines_case_insensitive")]
+    SortLinesCaseInsensitive,
+    #[strum(message = "Sort Lines Descending")]
+    #[strum(serialize = "sort_lines_reverse")]
+    SortLinesReverse,
+    #[strum(message = "Delete Duplicate Lines")]
+    #[strum(serialize = "unique_lines")]
+    UniqueLines,
+    #[strum(message = "Reverse Lines")]
+    #[strum(serialize = "reverse_lines")]
+    ReverseLines,
+    #[strum(message = "Shuffle Lines")]
+    #[strum(serialize = "shuffle_lines")]
+    ShuffleLines,
+    #[strum(message = "Trim Trailing Whitespace")]
+    #[strum(serialize = "trim_trailing_whitespace")]
+    TrimTrailingWhitespace,
+    #[strum(message = "Align on Equals")]
+    #[strum(serialize = "align_on_equals")]
+    AlignOnEquals,
+    #[strum(message = "Align on Colon")]
+    #[strum(serialize = "align_on_colon")]
+    AlignOnColon,
+    #[strum(message = "Align on Comma")]
+    #[strum(serialize = "align_on_comma")]
+    AlignOnComma,
 }
 
 impl EditCommand {
@@ -384,6 +449,12 @@ pub enum FocusCommand {
     #[strum(message = "Go to Previous Difference")]
     #[strum(serialize = "previous_diff")]
     PreviousDiff,
+    #[strum(message = "Go to Next Diagnostic")]
+    #[strum(serialize = "next_error")]
+    NextError,
+    #[strum(message = "Go to Previous Diagnostic")]
+    #[strum(serialize = "previous_error")]
+    PreviousError,
     #[strum(message = "Toggle Code Lens")]
     #[strum(serialize = "toggle_code_lens")]
     ToggleCodeLens,
@@ -474,9 +545,18 @@ pub enum MotionModeCommand {
     #[strum(message = "Motion Mode Indent")]
     #[strum(serialize = "motion_mode_indent")]
     MotionModeIndent,
+    #[strum(message = "Motion Mode Lowercase")]
+    #[strum(serialize = "motion_mode_lower_case")]
+    MotionModeLowerCase,
     #[strum(message = "Motion Mode Outdent")]
     #[strum(serialize = "motion_mode_outdent")]
     MotionModeOutdent,
+    #[strum(message = "Motion Mode Toggle Case")]
+    #[strum(serialize = "motion_mode_toggle_case")]
+    MotionModeToggleCase,
+    #[strum(message = "Motion Mode Uppercase")]
+    #[strum(serialize = "motion_mode_upper_case")]
+    MotionModeUpperCase,
     #[strum(message = "Motion Mode Yank")]
     #[strum(serialize = "motion_mode_yank")]
     MotionModeYank,
@@ -514,3 +594,114 @@ pub enum MultiSelectionCommand {
     #[strum(serialize = "select_all")]
     SelectAll,
 }
+
+#[derive(
+    Display, EnumString, EnumIter, Clone, PartialEq, Eq, Debug, EnumMessage, IntoStaticStr,
+)]
+pub enum FoldCommand {
+    #[strum(message = "Create Fold")]
+    #[strum(serialize = "fold_create")]
+    CreateFold,
+    #[strum(message = "Open Fold")]
+    #[strum(serialize = "fold_open")]
+    OpenFold,
+    #[strum(message = "Close Fold")]
+    #[strum(serialize = "fold_close")]
+    CloseFold,
+    #[strum(message = "Toggle Fold")]
+    #[strum(serialize = "fold_toggle")]
+    ToggleFold,
+    #[strum(message = "Open All Folds")]
+    #[strum(serialize = "fold_open_all")]
+    OpenAllFolds,
+    #[strum(message = "Close All Folds")]
+    #[strum(serialize = "fold_close_all")]
+    CloseAllFolds,
+}
+
+/// A command of any of the kinds above, such as the target of a key binding.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CommandKind {
+    Edit(EditCommand),
+    Move(MoveCommand),
+    Scroll(ScrollCommand),
+    Focus(FocusCommand),
+    MotionMode(MotionModeCommand),
+    MultiSelection(MultiSelectionCommand),
+    Fold(FoldCommand),
+}
+
+impl CommandKind {
+    /// The serialized name of the command, e.g. `delete_backward`.
+    pub fn str(&self) -> &'static str {
+        match self {
+            CommandKind::Edit(cmd) => cmd.into(),
+            CommandKind::Move(cmd) => cmd.into(),
+            CommandKind::Scroll(cmd) => cmd.into(),
+            CommandKind::Focus(cmd) => cmd.into(),
+            CommandKind::MotionMode(cmd) => cmd.into(),
+            CommandKind::MultiSelection(cmd) => cmd.into(),
+            CommandKind::Fold(cmd) => cmd.into(),
+        }
+    }
+
+    /// The human readable description of the command, e.g. `Delete Backward`.
+    pub fn desc(&self) -> Option<&'static str> {
+        match self {
+            CommandKind::Edit(cmd) => cmd.get_message(),
+            CommandKind::Move(cmd) => cmd.get_message(),
+            CommandKind::Scroll(cmd) => cmd.get_message(),
+            CommandKind::Focus(cmd) => cmd.get_message(),
+            CommandKind::MotionMode(cmd) => cmd.get_message(),
+            CommandKind::MultiSelection(cmd) => cmd.get_message(),
+            CommandKind::Fold(cmd) => cmd.get_message(),
+        }
+    }
+}
+
+impl FromStr for CommandKind {
+    type Err = strum::ParseError;
+
+    /// Parse a command from its serialized name, whatever its kind.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EditCommand::from_str(s)
+            .map(CommandKind::Edit)
+            .or_else(|_| MoveCommand::from_str(s).map(CommandKind::Move))
+            .or_else(|_| ScrollCommand::from_str(s).map(CommandKind::Scroll))
+            .or_else(|_| FocusCommand::from_str(s).map(CommandKind::Focus))
+            .or_else(|_| MotionModeCommand::from_str(s).map(CommandKind::MotionMode))
+            .or_else(|_| MultiSelectionCommand::from_str(s).map(CommandKind::MultiSelection))
+            .or_else(|_| FoldCommand::from_str(s).map(CommandKind::Fold))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, str::FromStr};
+
+    use strum::IntoEnumIterator;
+
+    use super::{
+        CommandKind, EditCommand, FocusCommand, FoldCommand, MotionModeCommand, MoveCommand,
+        MultiSelectionCommand, ScrollCommand,
+    };
+
+    #[test]
+    fn command_names_are_unique_across_kinds() {
+        let names: Vec<&'static str> = EditCommand::iter()
+            .map(|cmd| CommandKind::Edit(cmd).str())
+            .chain(MoveCommand::iter().map(|cmd| CommandKind::Move(cmd).str()))
+            .chain(ScrollCommand::iter().map(|cmd| CommandKind::Scroll(cmd).str()))
+            .chain(FocusCommand::iter().map(|cmd| CommandKind::Focus(cmd).str()))
+            .chain(MotionModeCommand::iter().map(|cmd| CommandKind::MotionMode(cmd).str()))
+            .chain(MultiSelectionCommand::iter().map(|cmd| CommandKind::MultiSelection(cmd).str()))
+            .chain(FoldCommand::iter().map(|cmd| CommandKind::Fold(cmd).str()))
+            .collect();
+        let unique: HashSet<&str> = names.iter().copied().collect();
+        assert_eq!(names.len(), unique.len());
+
+        for name in names {
+            assert_eq!(CommandKind::from_str(name).unwrap().str(), name);
+        }
+    }
+}
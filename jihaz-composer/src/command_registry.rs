@@ -0,0 +1,332 @@
+//! A registry of all the composer commands, for listing and searching them in a command
+//! palette.
+
+use std::{borrow::Cow, collections::VecDeque};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    command::{
+        CommandKind, EditCommand, FocusCommand, FoldCommand, MotionModeCommand, MoveCommand,
+        MultiSelectionCommand, ScrollCommand,
+    },
+    fuzzy::{fuzzy_match, FuzzyMatch},
+    keymap::{KeyPress, Keymaps},
+};
+
+/// How much a command that was run most recently gains over others, which is about as much as
+/// two matched characters. Less recent commands gain less.
+const RECENCY_BONUS: i32 = 32;
+
+/// The kind of a command, for grouping commands in a palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandCategory {
+    Edit,
+    Move,
+    Scroll,
+    Focus,
+    MotionMode,
+    MultiSelection,
+    Fold,
+}
+
+impl CommandCategory {
+    pub fn title(&self) -> &'static str {
+        match self {
+            CommandCategory::Edit => "Edit",
+            CommandCategory::Move => "Move",
+            CommandCategory::Scroll => "Scroll",
+            CommandCategory::Focus => "Focus",
+            CommandCategory::MotionMode => "Motion Mode",
+            CommandCategory::MultiSelection => "Multiple Selections",
+            CommandCategory::Fold => "Folding",
+        }
+    }
+}
+
+impl From<&CommandKind> for CommandCategory {
+    fn from(command: &CommandKind) -> Self {
+        match command {
+            CommandKind::Edit(_) => CommandCategory::Edit,
+            CommandKind::Move(_) => CommandCategory::Move,
+            CommandKind::Scroll(_) => CommandCategory::Scroll,
+            CommandKind::Focus(_) => CommandCategory::Focus,
+            CommandKind::MotionMode(_) => CommandCategory::MotionMode,
+            CommandKind::MultiSelection(_) => CommandCategory::MultiSelection,
+            CommandKind::Fold(_) => CommandCategory::Fold,
+        }
+    }
+}
+
+/// A command along with what a palette shows of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandInfo {
+    pub command: CommandKind,
+    /// The serialized name of the command, e.g. `delete_backward`.
+    pub id: &'static str,
+    /// The title of the command, e.g. `Delete Backward`, which can be replaced by a translation.
+    pub title: Cow<'static, str>,
+    pub category: CommandCategory,
+    /// The key sequences bound to the command in the active keymap, the ones that take
+    /// precedence first.
+    pub bindings: Vec<Vec<KeyPress>>,
+}
+
+/// A command that matched a palette query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaletteItem<'a> {
+    pub info: &'a CommandInfo,
+    /// The fuzzy match of the query in the title, with the recency bonus included in the score.
+    pub matched: FuzzyMatch,
+}
+
+/// The commands that were run most recently, most recent first.
+#[derive(Clone, Debug)]
+pub struct RecentCommands {
+    ids: VecDeque<&'static str>,
+    capacity: usize,
+}
+
+impl Default for RecentCommands {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl RecentCommands {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ids: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record that the command `id` was run.
+    pub fn push(&mut self, id: &'static str) {
+        self.ids.retain(|recent| *recent != id);
+        self.ids.push_front(id);
+        self.ids.truncate(self.capacity);
+    }
+
+    /// How recently the command `id` was run, 0 being the most recent.
+    pub fn rank(&self, id: &str) -> Option<usize> {
+        self.ids.iter().position(|recent| *recent == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.ids.iter().copied()
+    }
+
+    fn bonus(&self, id: &str) -> i32 {
+        self.rank(id)
+            .map(|rank| RECENCY_BONUS / (rank as i32 + 1))
+            .unwrap_or(0)
+    }
+}
+
+/// All the composer commands.
+#[derive(Clone, Debug)]
+pub struct CommandRegistry {
+    commands: Vec<CommandInfo>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        let commands = EditCommand::iter()
+            .map(CommandKind::Edit)
+            .chain(MoveCommand::iter().map(CommandKind::Move))
+            .chain(ScrollCommand::iter().map(CommandKind::Scroll))
+            .chain(FocusCommand::iter().map(CommandKind::Focus))
+            .chain(MotionModeCommand::iter().map(CommandKind::MotionMode))
+            .chain(MultiSelectionCommand::iter().map(CommandKind::MultiSelection))
+            .chain(FoldCommand::iter().map(CommandKind::Fold))
+            .map(|command| CommandInfo {
+                id: command.str(),
+                title: Cow::Borrowed(command.desc().unwrap_or_else(|| command.str())),
+                category: CommandCategory::from(&command),
+                bindings: Vec::new(),
+                command,
+            })
+            .collect();
+        Self { commands }
+    }
+
+    /// A registry whose commands list their bindings in `keymaps`.
+    pub fn with_keymaps(keymaps: &Keymaps) -> Self {
+        let mut registry = Self::new();
+        registry.set_keymaps(keymaps);
+        registry
+    }
+
+    /// Take the bindings of the commands from `keymaps`, e.g. after the user changes them.
+    pub fn set_keymaps(&mut self, keymaps: &Keymaps) {
+        for info in &mut self.commands {
+            info.bindings.clear();
+            for keymap in keymaps.bindings_of(&info.command) {
+                if !info.bindings.contains(&keymap.keys) {
+                    info.bindings.push(keymap.keys.clone());
+                }
+            }
+        }
+    }
+
+    /// Replace the title of the command `id`, such as with its translation.
+    /// Returns false if there is no such command.
+    pub fn set_title(&mut self, id: &str, title: impl Into<Cow<'static, str>>) -> bool {
+        match self.commands.iter_mut().find(|info| info.id == id) {
+            Some(info) => {
+                info.title = title.into();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn commands(&self) -> &[CommandInfo] {
+        &self.commands
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CommandInfo> {
+        self.commands.iter().find(|info| info.id == id)
+    }
+
+    /// The commands whose titles match `query`, best first.
+    ///
+    /// Recently run commands rank higher, and with an empty query they come first, followed by
+    /// the rest of the commands. Among equally good matches, shorter titles come first.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::command_registry::{CommandRegistry, RecentCommands};
+    /// let registry = CommandRegistry::new();
+    /// let mut recent = RecentCommands::default();
+    ///
+    /// let items = registry.filter("del line", &recent);
+    /// assert_eq!(items[0].info.id, "delete_line");
+    ///
+    /// recent.push("delete_line_and_insert");
+    /// let items = registry.filter("del line", &recent);
+    /// assert_eq!(items[0].info.id, "delete_line_and_insert");
+    /// ```
+    pub fn filter(&self, query: &str, recent: &RecentCommands) -> Vec<PaletteItem<'_>> {
+        let mut items: Vec<PaletteItem> = self
+            .commands
+            .iter()
+            .filter_map(|info| {
+                let mut matched = fuzzy_match(query, &info.title)?;
+                matched.score += recent.bonus(info.id);
+                Some(PaletteItem { info, matched })
+            })
+            .collect();
+        items.sort_by(|a, b| {
+            b.matched
+                .score
+                .cmp(&a.matched.score)
+                .then_with(|| a.info.title.len().cmp(&b.info.title.len()))
+                .then_with(|| a.info.title.cmp(&b.info.title))
+        });
+        items
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CommandCategory, CommandRegistry, RecentCommands};
+    use crate::{
+        command::{CommandKind, EditCommand},
+        keymap::{KeyPress, KeymapSource, Keymaps},
+    };
+
+    #[test]
+    fn all_commands_are_registered() {
+        let registry = CommandRegistry::new();
+        let info = registry.get("delete_backward").unwrap();
+        assert_eq!(info.title, "Delete Backward");
+        assert_eq!(info.category, CommandCategory::Edit);
+        assert_eq!(info.command, CommandKind::Edit(EditCommand::DeleteBackward));
+
+        assert_eq!(
+            registry.get("insert_cursor_above").unwrap().category,
+            CommandCategory::MultiSelection
+        );
+        assert_eq!(
+            registry.get("page_down").unwrap().category,
+            CommandCategory::Scroll
+        );
+        assert!(registry.get("no_such_command").is_none());
+    }
+
+    #[test]
+    fn bindings_come_from_the_keymap() {
+        let mut keymaps = Keymaps::default_non_modal();
+        keymaps
+            .load_toml(
+                "[[keymaps]]\nkey = \"ctrl+alt+c\"\ncommand = \"toggle_line_comment\"",
+                KeymapSource::User,
+            )
+            .unwrap();
+        let registry = CommandRegistry::with_keymaps(&keymaps);
+        let bindings: Vec<String> = registry
+            .get("toggle_line_comment")
+            .unwrap()
+            .bindings
+            .iter()
+            .map(|keys| KeyPress::format_sequence(keys))
+            .collect();
+        assert_eq!(bindings, ["ctrl+alt+c", "ctrl+k ctrl+c", "ctrl+/"]);
+    }
+
+    #[test]
+    fn recent_commands() {
+        let mut recent = RecentCommands::new(2);
+        recent.push("undo");
+        recent.push("redo");
+        recent.push("undo");
+        assert_eq!(recent.iter().collect::<Vec<_>>(), ["undo", "redo"]);
+        recent.push("yank");
+        assert_eq!(recent.iter().collect::<Vec<_>>(), ["yank", "undo"]);
+        assert_eq!(recent.rank("undo"), Some(1));
+        assert_eq!(recent.rank("redo"), None);
+    }
+
+    #[test]
+    fn filter_ranks_and_highlights() {
+        let registry = CommandRegistry::new();
+        let recent = RecentCommands::default();
+
+        let items = registry.filter("undo", &recent);
+        assert_eq!(items[0].info.id, "undo");
+        assert_eq!(items[0].matched.highlights, vec![(0..4)]);
+        assert!(items.iter().any(|item| item.info.id == "select_undo"));
+
+        // An empty query lists everything, recent commands first
+        let mut recent = RecentCommands::default();
+        recent.push("sort_lines");
+        let items = registry.filter("", &recent);
+        assert_eq!(items.len(), registry.commands().len());
+        assert_eq!(items[0].info.id, "sort_lines");
+    }
+
+    #[test]
+    fn arabic_titles() {
+        let mut registry = CommandRegistry::new();
+        assert!(registry.set_title("delete_line", "حَذْفُ السَّطْرِ"));
+        assert!(registry.set_title("duplicate_line_down", "تكرار السطر للأسفل"));
+        assert!(!registry.set_title("no_such_command", "لا شيء"));
+
+        let items = registry.filter("حذف", &RecentCommands::default());
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].info.id, "delete_line");
+        assert_eq!(items[0].matched.highlights, vec![(0..12)]);
+
+        let items = registry.filter("السطر", &RecentCommands::default());
+        assert_eq!(items.len(), 2);
+    }
+}
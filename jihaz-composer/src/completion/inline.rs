@@ -0,0 +1,443 @@
+//! Inline completion: a suggestion shown as ghost text after the cursor, such as from an AI model
+//! or a language server, that is accepted whole or a word at a time.
+//!
+//! An [`InlineCompletion`] asks an [`InlineCompletionProvider`] for the suggestions at the cursor
+//! and keeps them while the user types what they suggest, so that the rest of the active one can
+//! still be shown. Any other edit, or moving the cursor away, drops them.
+
+use lapce_xi_rope::{Rope, RopeDelta};
+
+use super::{is_word_char, normalize};
+use crate::{
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    command::FocusCommand,
+    cursor::{Cursor, CursorMode},
+    editor::EditType,
+    selection::{InsertDrift, Selection},
+};
+
+/// A source of inline suggestions.
+pub trait InlineCompletionProvider {
+    /// The texts that could be inserted at `offset`, best first.
+    fn suggest(&mut self, text: &Rope, offset: usize) -> Vec<String>;
+}
+
+impl<F: FnMut(&Rope, usize) -> Vec<String>> InlineCompletionProvider for F {
+    fn suggest(&mut self, text: &Rope, offset: usize) -> Vec<String> {
+        self(text, offset)
+    }
+}
+
+/// The inline suggestions at the cursor, of which one, the active one, is shown.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     buffer::{rope_text::RopeText, Buffer},
+/// #     completion::inline::InlineCompletion,
+/// #     cursor::{Cursor, CursorMode},
+/// #     editor::EditType,
+/// #     selection::Selection,
+/// # };
+/// let mut buffer = Buffer::new("fn ");
+/// let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+/// let mut inline = InlineCompletion::default();
+/// let mut provider = |_: &_, _| vec!["main() {}".to_string()];
+/// inline.invoke(&buffer, 3, &mut provider);
+///
+/// // Typing what is suggested keeps the suggestion
+/// let (_, delta, _) = buffer.edit(&[(Selection::caret(3), "ma")], EditType::InsertChars);
+/// inline.apply_delta(buffer.text(), &delta);
+/// assert_eq!(inline.ghost_text(), Some((5, "in() {}")));
+///
+/// cursor.set_insert(Selection::caret(5));
+/// inline.accept_word(&mut buffer, &mut cursor);
+/// assert_eq!(buffer.text().to_string(), "fn main");
+/// inline.accept(&mut buffer, &mut cursor);
+/// assert_eq!(buffer.text().to_string(), "fn main() {}");
+/// assert_eq!(cursor.offset(), 12);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InlineCompletion {
+    suggestions: Vec<String>,
+    active: usize,
+    /// The offset that the suggestions were given for.
+    start: usize,
+    /// The text typed at `start` since, which all the suggestions begin with.
+    typed: String,
+}
+
+impl InlineCompletion {
+    pub fn is_active(&self) -> bool {
+        !self.suggestions.is_empty()
+    }
+
+    pub fn suggestions(&self) -> &[String] {
+        &self.suggestions
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// The offset where the ghost text is shown, and the text of the active suggestion that is
+    /// yet to be typed.
+    pub fn ghost_text(&self) -> Option<(usize, &str)> {
+        let suggestion = self.suggestions.get(self.active)?;
+        Some((
+            self.start + self.typed.len(),
+            &suggestion[self.typed.len()..],
+        ))
+    }
+
+    /// Ask `provider` for the suggestions at `offset`, replacing the current ones.
+    pub fn invoke(
+        &mut self,
+        buffer: &Buffer,
+        offset: usize,
+        provider: &mut dyn InlineCompletionProvider,
+    ) {
+        let mut suggestions: Vec<String> = Vec::new();
+        for suggestion in provider.suggest(buffer.text(), offset) {
+            // As the edit that accepts it would insert it
+            let suggestion = normalize(&suggestion, buffer.line_ending());
+            if !suggestion.is_empty() && !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        }
+        *self = Self {
+            suggestions,
+            active: 0,
+            start: offset,
+            typed: String::new(),
+        };
+    }
+
+    pub fn cancel(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn next(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.active = (self.active + 1) % self.suggestions.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.active = (self.active + self.suggestions.len() - 1) % self.suggestions.len();
+        }
+    }
+
+    /// Drop the suggestions if the cursor moved away from the ghost text.
+    pub fn cursor_moved(&mut self, offset: usize) {
+        if self
+            .ghost_text()
+            .is_some_and(|(ghost_offset, _)| ghost_offset != offset)
+        {
+            self.cancel();
+        }
+    }
+
+    /// Keep the suggestions through an edit of the text that types what they suggest, or delete
+    /// some of what was typed since they were given, and drop them on any other edit. `text` is
+    /// the text after the edit.
+    ///
+    /// The suggestions that don't begin with what was typed are dropped, and the active one
+    /// stays the same if it's kept.
+    pub fn apply_delta(&mut self, text: &Rope, delta: &RopeDelta) {
+        if !self.is_active() {
+            return;
+        }
+        let (interval, new_len) = delta.summary();
+        let at = self.start + self.typed.len();
+        if interval.is_empty() && interval.start == at && new_len > 0 {
+            let inserted = text.slice_to_cow(at..at + new_len);
+            self.typed.push_str(&inserted);
+            let active = self.suggestions.get(self.active).cloned();
+            let typed = &self.typed;
+            self.suggestions.retain(|suggestion| {
+                suggestion.len() > typed.len() && suggestion.starts_with(typed.as_str())
+            });
+            self.active = active
+                .and_then(|active| self.suggestions.iter().position(|s| *s == active))
+                .unwrap_or(0);
+            if self.suggestions.is_empty() {
+                self.cancel();
+            }
+        } else if new_len == 0
+            && interval.end == at
+            && interval.start >= self.start
+            && self.typed.is_char_boundary(interval.start - self.start)
+        {
+            self.typed.truncate(interval.start - self.start);
+        } else {
+            self.cancel();
+        }
+    }
+
+    /// Insert the rest of the active suggestion, and end the completion.
+    pub fn accept(
+        &mut self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let (_, rest) = self.ghost_text()?;
+        let rest = rest.to_string();
+        let edit = self.insert(buffer, cursor, &rest);
+        self.cancel();
+        edit
+    }
+
+    /// Insert the next word of the active suggestion, with the spaces and punctuation before it,
+    /// and keep the rest of it. A line break is accepted on its own.
+    pub fn accept_word(
+        &mut self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let (_, rest) = self.ghost_text()?;
+        let word = next_word(rest).to_string();
+        let edit = self.insert(buffer, cursor, &word)?;
+        self.apply_delta(buffer.text(), &edit.1);
+        Some(edit)
+    }
+
+    /// Run an inline completion command. Only [`FocusCommand::InlineCompletionSelect`] edits the
+    /// text, and its edit is returned.
+    pub fn do_command(
+        &mut self,
+        cmd: &FocusCommand,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        provider: &mut dyn InlineCompletionProvider,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        match cmd {
+            FocusCommand::InlineCompletionSelect => return self.accept(buffer, cursor),
+            FocusCommand::InlineCompletionNext => self.next(),
+            FocusCommand::InlineCompletionPrevious => self.previous(),
+            FocusCommand::InlineCompletionCancel => self.cancel(),
+            FocusCommand::InlineCompletionInvoke => self.invoke(buffer, cursor.offset(), provider),
+            _ => {}
+        }
+        None
+    }
+
+    fn insert(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        text: &str,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let (offset, _) = self.ghost_text()?;
+        let CursorMode::Insert(selection) = &cursor.mode else {
+            return None;
+        };
+        if cursor.offset() != offset || offset > buffer.len() {
+            return None;
+        }
+        let selection = selection.clone();
+        let (text, delta, inval_lines) =
+            buffer.edit(&[(Selection::caret(offset), text)], EditType::Completion);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        cursor.set_insert(selection);
+        Some((text, delta, inval_lines))
+    }
+}
+
+/// The leading text of `text` up to the end of its first word, or its first line break.
+fn next_word(text: &str) -> &str {
+    let mut chars = text.char_indices().peekable();
+    let mut end = 0;
+    // The spaces and punctuation before the word
+    while let Some((i, c)) = chars.next_if(|(_, c)| !is_word_char(*c) && *c != '\r' && *c != '\n') {
+        end = i + c.len_utf8();
+    }
+    while let Some((i, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+        end = i + c.len_utf8();
+    }
+    if end == 0 {
+        return if text.starts_with("\r\n") {
+            &text[..2]
+        } else {
+            &text[..text.chars().next().map_or(0, char::len_utf8)]
+        };
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::Rope;
+
+    use super::{next_word, InlineCompletion, InlineCompletionProvider};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        command::FocusCommand,
+        cursor::{Cursor, CursorMode},
+        editor::EditType,
+        selection::Selection,
+    };
+
+    /// Suggests its texts wherever it's asked, and counts how often.
+    struct FakeProvider {
+        suggestions: Vec<&'static str>,
+        requests: Vec<usize>,
+    }
+
+    impl InlineCompletionProvider for FakeProvider {
+        fn suggest(&mut self, _text: &Rope, offset: usize) -> Vec<String> {
+            self.requests.push(offset);
+            self.suggestions.iter().map(|s| s.to_string()).collect()
+        }
+    }
+
+    fn setup(
+        text: &str,
+        offset: usize,
+        suggestions: Vec<&'static str>,
+    ) -> (Buffer, Cursor, InlineCompletion, FakeProvider) {
+        let buffer = Buffer::new(text);
+        let cursor = Cursor::new(CursorMode::Insert(Selection::caret(offset)), None, None);
+        let provider = FakeProvider {
+            suggestions,
+            requests: Vec::new(),
+        };
+        (buffer, cursor, InlineCompletion::default(), provider)
+    }
+
+    fn type_text(
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        inline: &mut InlineCompletion,
+        text: &str,
+    ) {
+        let offset = cursor.offset();
+        let (_, delta, _) = buffer.edit(&[(Selection::caret(offset), text)], EditType::InsertChars);
+        cursor.set_insert(Selection::caret(offset + text.len()));
+        inline.apply_delta(buffer.text(), &delta);
+    }
+
+    #[test]
+    fn type_through_suggestions() {
+        let (mut buffer, mut cursor, mut inline, mut provider) =
+            setup("let x = ", 8, vec!["vec![1, 2]", "vec.len()", "value"]);
+        inline.do_command(
+            &FocusCommand::InlineCompletionInvoke,
+            &mut buffer,
+            &mut cursor,
+            &mut provider,
+        );
+        assert_eq!(provider.requests, [8]);
+        assert_eq!(inline.ghost_text(), Some((8, "vec![1, 2]")));
+
+        inline.do_command(
+            &FocusCommand::InlineCompletionNext,
+            &mut buffer,
+            &mut cursor,
+            &mut provider,
+        );
+        assert_eq!(inline.ghost_text(), Some((8, "vec.len()")));
+
+        // The active suggestion stays active while it matches
+        type_text(&mut buffer, &mut cursor, &mut inline, "ve");
+        assert_eq!(inline.suggestions().len(), 2);
+        assert_eq!(inline.ghost_text(), Some((10, "c.len()")));
+
+        // Deleting what was typed brings back the earlier ghost text
+        let (_, delta, _) = buffer.edit(&[(Selection::region(9, 10), "")], EditType::Delete);
+        inline.apply_delta(buffer.text(), &delta);
+        cursor.set_insert(Selection::caret(9));
+        assert_eq!(inline.ghost_text(), Some((9, "ec.len()")));
+
+        type_text(&mut buffer, &mut cursor, &mut inline, "ec!");
+        assert_eq!(inline.suggestions(), ["vec![1, 2]"]);
+        inline.do_command(
+            &FocusCommand::InlineCompletionPrevious,
+            &mut buffer,
+            &mut cursor,
+            &mut provider,
+        );
+        assert_eq!(inline.ghost_text(), Some((12, "[1, 2]")));
+
+        // Typing something else drops the suggestions
+        type_text(&mut buffer, &mut cursor, &mut inline, "(");
+        assert!(!inline.is_active());
+        assert_eq!(inline.ghost_text(), None);
+    }
+
+    #[test]
+    fn unrelated_edits_drop_suggestions() {
+        let (mut buffer, mut cursor, mut inline, mut provider) = setup("a\nb", 1, vec!["bc"]);
+        inline.invoke(&buffer, 1, &mut provider);
+        let (_, delta, _) = buffer.edit(&[(Selection::caret(3), "x")], EditType::InsertChars);
+        inline.apply_delta(buffer.text(), &delta);
+        assert!(!inline.is_active());
+
+        inline.invoke(&buffer, 1, &mut provider);
+        inline.cursor_moved(1);
+        assert!(inline.is_active());
+        inline.cursor_moved(0);
+        assert!(!inline.is_active());
+
+        inline.invoke(&buffer, 1, &mut provider);
+        inline.do_command(
+            &FocusCommand::InlineCompletionCancel,
+            &mut buffer,
+            &mut cursor,
+            &mut provider,
+        );
+        assert!(!inline.is_active());
+        assert!(inline.accept(&mut buffer, &mut cursor).is_none());
+    }
+
+    #[test]
+    fn accept_word_by_word() {
+        let (mut buffer, mut cursor, mut inline, mut provider) =
+            setup("\r\n", 0, vec!["fn main() {\n    مرحبا();\n}"]);
+        inline.invoke(&buffer, 0, &mut provider);
+        let mut accepted = Vec::new();
+        while inline.is_active() {
+            let (_, delta, _) = inline.accept_word(&mut buffer, &mut cursor).unwrap();
+            let (interval, len) = delta.summary();
+            accepted.push(
+                buffer
+                    .slice_to_cow(interval.start..interval.start + len)
+                    .into_owned(),
+            );
+        }
+        assert_eq!(
+            accepted,
+            [
+                "fn",
+                " main",
+                "() {",
+                "\r\n",
+                "    مرحبا",
+                "();",
+                "\r\n",
+                "}"
+            ]
+        );
+        assert_eq!(
+            buffer.text().to_string(),
+            "fn main() {\r\n    مرحبا();\r\n}\r\n"
+        );
+        assert_eq!(cursor.offset(), buffer.len() - 2);
+
+        // Nothing is accepted once the cursor is away from the suggestion
+        inline.invoke(&buffer, 0, &mut provider);
+        assert!(inline.accept(&mut buffer, &mut cursor).is_none());
+        assert_eq!(buffer.len(), 35);
+    }
+
+    #[test]
+    fn next_words() {
+        assert_eq!(next_word("foo.bar"), "foo");
+        assert_eq!(next_word(".bar baz"), ".bar");
+        assert_eq!(next_word("  ();"), "  ();");
+        assert_eq!(next_word("\n  x"), "\n");
+        assert_eq!(next_word("\r\nx"), "\r\n");
+    }
+}
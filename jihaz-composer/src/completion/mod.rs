@@ -0,0 +1,553 @@
+//! Completion of the text before the cursor, from pluggable [`CompletionProvider`]s.
+//!
+//! The providers of this module complete words of the buffer ([`WordIndex`]), words of the
+//! other open documents ([`DocumentWords`]), file paths ([`PathCompletion`]) and snippets
+//! ([`Snippets`]). A [`CompletionEngine`] gathers their items when completion is requested, such
+//! as by [`FocusCommand::GetCompletion`](crate::command::FocusCommand::GetCompletion), filters
+//! and ranks them as the user types, and applies the chosen one as a single
+//! [`EditType::Completion`] edit.
+//!
+//! Inline suggestions, shown as ghost text after the cursor, are kept by an
+//! [`InlineCompletion`](inline::InlineCompletion) instead.
+
+pub mod inline;
+pub mod paths;
+pub mod snippets;
+pub mod words;
+
+use std::collections::HashMap;
+
+use lapce_xi_rope::{Rope, RopeDelta, Transformer};
+
+use crate::{
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    cursor::{Cursor, CursorMode},
+    editor::EditType,
+    fuzzy::{fuzzy_match, FuzzyMatch},
+    line_ending::LineEnding,
+    selection::{SelRegion, Selection},
+    word::{get_char_property, CharClassification},
+};
+
+pub use paths::PathCompletion;
+pub use snippets::{Snippet, Snippets};
+pub use words::{DocumentWords, WordIndex};
+
+/// The score of an item at the cursor, which decreases with the log of its distance.
+const PROXIMITY_BONUS_MAX: i32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CompletionKind {
+    Word,
+    Path,
+    Snippet,
+}
+
+impl CompletionKind {
+    /// Whether `c` can be typed while completing an item of this kind. Typing another character
+    /// drops the item.
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            CompletionKind::Word | CompletionKind::Snippet => is_word_char(c),
+            CompletionKind::Path => paths::is_path_char(c),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The text that replaces the prefix.
+    pub insert_text: String,
+    /// Where the cursor goes within the inserted text, or at its end if `None`.
+    pub cursor: Option<usize>,
+    /// The length in bytes of the text before the cursor that the item completes.
+    pub prefix_len: usize,
+    /// The distance in bytes between the cursor and where the item was found, for items found
+    /// in the text being edited.
+    pub distance: Option<usize>,
+    pub detail: Option<String>,
+}
+
+impl CompletionItem {
+    pub fn new(
+        label: impl Into<String>,
+        kind: CompletionKind,
+        insert_text: impl Into<String>,
+        prefix_len: usize,
+    ) -> Self {
+        Self {
+            label: label.into(),
+            kind,
+            insert_text: insert_text.into(),
+            cursor: None,
+            prefix_len,
+            distance: None,
+            detail: None,
+        }
+    }
+}
+
+/// Where completion is requested.
+#[derive(Clone, Copy, Debug)]
+pub struct CompletionContext<'a> {
+    pub text: &'a Rope,
+    /// The offset of the cursor.
+    pub offset: usize,
+}
+
+impl CompletionContext<'_> {
+    /// The start of the word that ends at the cursor.
+    pub fn word_start(&self) -> usize {
+        prefix_start(self.text, self.offset, is_word_char)
+    }
+
+    /// The word that ends at the cursor, which is empty after a non-word character.
+    pub fn word_prefix(&self) -> String {
+        self.text
+            .slice_to_cow(self.word_start()..self.offset)
+            .into_owned()
+    }
+}
+
+/// The start of the run of characters before `offset` for which `accepts` holds.
+pub(crate) fn prefix_start(text: &Rope, offset: usize, accepts: impl Fn(char) -> bool) -> usize {
+    let mut start = offset;
+    let mut cursor = lapce_xi_rope::Cursor::new(text, offset);
+    while let Some(c) = cursor.prev_codepoint() {
+        if !accepts(c) {
+            break;
+        }
+        start = cursor.pos();
+    }
+    start
+}
+
+/// Whether `c` is part of a word: a letter, a digit, an underscore or any other non-ASCII
+/// character, as [`WordCursor`](crate::word::WordCursor) classifies them.
+pub(crate) fn is_word_char(c: char) -> bool {
+    get_char_property(c) == CharClassification::Other
+}
+
+/// A source of completion items.
+pub trait CompletionProvider {
+    /// Add the items for `context` to `items`, which the engine then filters by what the user
+    /// typed, so they need not match it.
+    fn complete(&self, context: &CompletionContext<'_>, items: &mut Vec<CompletionItem>);
+}
+
+/// An item that matches what was typed, ready to be shown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RankedItem {
+    pub item: CompletionItem,
+    pub matched: FuzzyMatch,
+    /// The offset of the start of the prefix that the item replaces.
+    pub start: usize,
+}
+
+/// The completion in progress: the items that were gathered and those of them that match what
+/// was typed since, with the one that is selected.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     buffer::{rope_text::RopeText, Buffer},
+/// #     completion::{CompletionEngine, CompletionProvider, WordIndex},
+/// #     cursor::{Cursor, CursorMode},
+/// #     selection::Selection,
+/// # };
+/// let mut buffer = Buffer::new("let counter = 0;\nco");
+/// let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(19)), None, None);
+/// let index = WordIndex::new(buffer.text());
+///
+/// let mut engine = CompletionEngine::default();
+/// engine.request(buffer.text(), 19, &[&index as &dyn CompletionProvider]);
+/// assert_eq!(engine.items()[0].item.label, "counter");
+///
+/// engine.apply(&mut buffer, &mut cursor);
+/// assert_eq!(buffer.text().to_string(), "let counter = 0;\ncounter");
+/// assert_eq!(cursor.offset(), 24);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CompletionEngine {
+    gathered: Vec<(usize, CompletionItem)>,
+    items: Vec<RankedItem>,
+    selected: usize,
+    /// The offset of the cursor when the items were last filtered, while completing.
+    offset: Option<usize>,
+}
+
+impl CompletionEngine {
+    pub fn is_active(&self) -> bool {
+        self.offset.is_some() && !self.items.is_empty()
+    }
+
+    /// The items that match what was typed, best first.
+    pub fn items(&self) -> &[RankedItem] {
+        &self.items
+    }
+
+    pub fn selected(&self) -> Option<&RankedItem> {
+        self.items.get(self.selected)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Gather the items of `providers` for the cursor at `offset`, and rank them.
+    ///
+    /// Items with the same label and text are merged, keeping the one found closest to the
+    /// cursor.
+    pub fn request(&mut self, text: &Rope, offset: usize, providers: &[&dyn CompletionProvider]) {
+        let context = CompletionContext { text, offset };
+        let mut items = Vec::new();
+        for provider in providers {
+            provider.complete(&context, &mut items);
+        }
+
+        let mut gathered: Vec<(usize, CompletionItem)> = Vec::with_capacity(items.len());
+        let mut indices: HashMap<(String, String, usize), usize> = HashMap::new();
+        for item in items {
+            if item.prefix_len > offset {
+                continue;
+            }
+            let key = (
+                item.label.clone(),
+                item.insert_text.clone(),
+                item.prefix_len,
+            );
+            match indices.get(&key) {
+                Some(&index) => {
+                    let existing = &mut gathered[index].1;
+                    if item.distance.is_some_and(|distance| {
+                        existing.distance.is_none_or(|existing| distance < existing)
+                    }) {
+                        existing.distance = item.distance;
+                    }
+                }
+                None => {
+                    indices.insert(key, gathered.len());
+                    gathered.push((offset - item.prefix_len, item));
+                }
+            }
+        }
+        *self = Self {
+            gathered,
+            offset: Some(offset),
+            ..Self::default()
+        };
+        self.update(text, offset);
+    }
+
+    /// Filter and rank the items again for the cursor at `offset`, such as after typing.
+    ///
+    /// Completion ends when the cursor moves before the start of the prefix, or when nothing
+    /// matches anymore.
+    pub fn update(&mut self, text: &Rope, offset: usize) {
+        if self.offset.is_none() {
+            return;
+        }
+        self.offset = Some(offset);
+        let selected = self.selected().map(|item| item.item.label.clone());
+
+        let mut items: Vec<RankedItem> = self
+            .gathered
+            .iter()
+            .filter(|(start, _)| *start <= offset && offset <= text.len())
+            .filter_map(|(start, item)| {
+                let typed = text.slice_to_cow(*start..offset);
+                if !typed.chars().all(|c| item.kind.accepts(c)) || typed == item.insert_text {
+                    return None;
+                }
+                let mut matched = fuzzy_match(&typed, &item.label)?;
+                if let Some(distance) = item.distance {
+                    matched.score += proximity_bonus(distance);
+                }
+                Some(RankedItem {
+                    item: item.clone(),
+                    matched,
+                    start: *start,
+                })
+            })
+            .collect();
+        items.sort_by(|a, b| {
+            b.matched
+                .score
+                .cmp(&a.matched.score)
+                .then_with(|| a.item.label.len().cmp(&b.item.label.len()))
+                .then_with(|| a.item.label.cmp(&b.item.label))
+        });
+
+        self.selected = selected
+            .and_then(|label| items.iter().position(|item| item.item.label == label))
+            .unwrap_or(0);
+        self.items = items;
+        if self.items.is_empty() {
+            self.cancel();
+        }
+    }
+
+    /// Apply the selected item, replacing the prefix it completes in every region of the
+    /// cursor, and end completion.
+    ///
+    /// The prefix typed before the cursor is replaced in the other regions where the
+    /// same text comes before them, and elsewhere the item is inserted as is. The lines of an
+    /// item after its first are indented as the line it is inserted on.
+    pub fn apply(
+        &mut self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let selected = self.selected()?.clone();
+        let offset = self.offset?;
+        self.cancel();
+        let CursorMode::Insert(selection) = &cursor.mode else {
+            return None;
+        };
+        if offset > buffer.len() {
+            return None;
+        }
+
+        let typed = buffer.slice_to_cow(selected.start..offset).into_owned();
+        let insert_text = normalize(&selected.item.insert_text, buffer.line_ending());
+        let cursor_in_text = match selected.item.cursor {
+            Some(c) => normalize(&selected.item.insert_text[..c], buffer.line_ending()).len(),
+            None => insert_text.len(),
+        };
+
+        // The lines of a multi-line item are indented as the line it is inserted on
+        let inserts: Vec<(Selection, String, usize)> = selection
+            .regions()
+            .iter()
+            .map(|region| {
+                let end = region.max();
+                let start = if region.is_caret() {
+                    end.checked_sub(typed.len())
+                        .filter(|start| buffer.slice_to_cow(*start..end) == typed)
+                        .unwrap_or(end)
+                } else {
+                    region.min()
+                };
+                let indent = buffer.indent_on_line(buffer.line_of_offset(start));
+                let (text, cursor) = indent_lines(&insert_text, &indent, cursor_in_text);
+                (Selection::region(start, end), text, cursor)
+            })
+            .collect();
+        let edits: Vec<(&Selection, &str)> = inserts
+            .iter()
+            .map(|(selection, text, _)| (selection, text.as_str()))
+            .collect();
+        let (text, delta, inval_lines) = buffer.edit(&edits, EditType::Completion);
+
+        let mut transformer = Transformer::new(&delta);
+        let mut new_selection = Selection::new();
+        for (edit, _, cursor) in &inserts {
+            let start = transformer.transform(edit.min_offset(), false);
+            new_selection.add_region(SelRegion::caret(start + cursor));
+        }
+        cursor.set_insert(new_selection);
+        Some((text, delta, inval_lines))
+    }
+}
+
+fn proximity_bonus(distance: usize) -> i32 {
+    PROXIMITY_BONUS_MAX - ((distance + 1).ilog2() as i32).min(PROXIMITY_BONUS_MAX)
+}
+
+/// `text` with `indent` before each of its lines but the first, except the empty ones, and the
+/// offset in it of `cursor`, an offset in `text`.
+fn indent_lines(text: &str, indent: &str, cursor: usize) -> (String, usize) {
+    let mut indented = String::with_capacity(text.len());
+    let mut new_cursor = None;
+    let mut start = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        if i > 0 && !line.trim_start_matches(['\r', '\n']).is_empty() {
+            indented.push_str(indent);
+        }
+        if new_cursor.is_none() && cursor < start + line.len() {
+            new_cursor = Some(indented.len() + cursor - start);
+        }
+        indented.push_str(line);
+        start += line.len();
+    }
+    let new_cursor = new_cursor.unwrap_or(indented.len());
+    (indented, new_cursor)
+}
+
+/// Give `text` the line endings of the buffer, as the edit will.
+fn normalize(text: &str, line_ending: LineEnding) -> String {
+    let text = text.replace("\r\n", "\n");
+    match line_ending {
+        LineEnding::Lf => text,
+        LineEnding::CrLf => text.replace('\n', "\r\n"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CompletionEngine, CompletionItem, CompletionKind, CompletionProvider, Snippet, Snippets,
+        WordIndex,
+    };
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        cursor::{Cursor, CursorMode},
+        editor::EditType,
+        selection::{SelRegion, Selection},
+    };
+
+    fn insert_cursor(offsets: &[usize]) -> Cursor {
+        let mut selection = Selection::new();
+        for offset in offsets {
+            selection.add_region(SelRegion::caret(*offset));
+        }
+        Cursor::new(CursorMode::Insert(selection), None, None)
+    }
+
+    fn labels(engine: &CompletionEngine) -> Vec<&str> {
+        engine
+            .items()
+            .iter()
+            .map(|item| item.item.label.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rank_by_score_and_proximity() {
+        let text = "value_far\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\nvalue_near va";
+        let buffer = Buffer::new(text);
+        let index = WordIndex::new(buffer.text());
+        let mut engine = CompletionEngine::default();
+        engine.request(buffer.text(), text.len(), &[&index]);
+        assert_eq!(labels(&engine), ["value_near", "value_far"]);
+
+        // A better match outranks proximity
+        let text = "abcdef\n\naxxbxxc abc";
+        let buffer = Buffer::new(text);
+        let index = WordIndex::new(buffer.text());
+        engine.request(buffer.text(), text.len(), &[&index]);
+        assert_eq!(labels(&engine), ["abcdef", "axxbxxc"]);
+    }
+
+    #[test]
+    fn filter_as_you_type() {
+        let mut buffer = Buffer::new("alpha alps beta b");
+        let index = WordIndex::new(buffer.text());
+        let mut engine = CompletionEngine::default();
+        engine.request(buffer.text(), 17, &[&index]);
+        assert_eq!(labels(&engine), ["beta"]);
+
+        // The items are filtered again from those that were gathered
+        buffer.edit(&[(Selection::region(16, 17), "a")], EditType::InsertChars);
+        engine.update(buffer.text(), 17);
+        assert_eq!(labels(&engine), ["alps", "alpha", "beta"]);
+        assert_eq!(engine.selected().unwrap().item.label, "beta");
+        engine.select_previous();
+        assert_eq!(engine.selected().unwrap().item.label, "alpha");
+
+        // The selection stays on the same item while it matches
+        buffer.edit(&[(Selection::caret(17), "l")], EditType::InsertChars);
+        engine.update(buffer.text(), 18);
+        assert_eq!(labels(&engine), ["alps", "alpha"]);
+        assert_eq!(engine.selected().unwrap().item.label, "alpha");
+
+        // A character that can't be part of a word ends completion
+        buffer.edit(&[(Selection::caret(18), " ")], EditType::InsertChars);
+        engine.update(buffer.text(), 19);
+        assert!(!engine.is_active());
+    }
+
+    #[test]
+    fn apply_in_every_region() {
+        let mut buffer = Buffer::new("println\npr\npr\nx");
+        let index = WordIndex::new(buffer.text());
+        let mut cursor = insert_cursor(&[10, 13, 15]);
+        let mut engine = CompletionEngine::default();
+        engine.request(buffer.text(), 10, &[&index]);
+        engine.apply(&mut buffer, &mut cursor).unwrap();
+        // The prefix is replaced where it was typed, and the item inserted elsewhere
+        assert_eq!(
+            buffer.text().to_string(),
+            "println\nprintln\nprintln\nxprintln"
+        );
+        assert_eq!(
+            cursor.mode,
+            CursorMode::Insert({
+                let mut selection = Selection::new();
+                for offset in [15, 23, 32] {
+                    selection.add_region(SelRegion::caret(offset));
+                }
+                selection
+            })
+        );
+        assert!(engine.apply(&mut buffer, &mut cursor).is_none());
+    }
+
+    #[test]
+    fn snippets_place_the_cursor() {
+        let mut buffer = Buffer::new("fn main() {\r\n    fo\r\n}");
+        let snippets = Snippets::new(vec![Snippet::new(
+            "for",
+            "for ${1:item} in ${2:iter} {\n    $0\n}",
+            "For loop",
+        )]);
+        let mut cursor = insert_cursor(&[19]);
+        let mut engine = CompletionEngine::default();
+        engine.request(buffer.text(), 19, &[&snippets]);
+        assert_eq!(engine.items()[0].item.kind, CompletionKind::Snippet);
+        engine.apply(&mut buffer, &mut cursor);
+        assert_eq!(
+            buffer.text().to_string(),
+            "fn main() {\r\n    for item in iter {\r\n        \r\n    }\r\n}"
+        );
+        assert_eq!(cursor.offset(), 45);
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        struct Fixed(Vec<CompletionItem>);
+        impl CompletionProvider for Fixed {
+            fn complete(
+                &self,
+                _context: &super::CompletionContext<'_>,
+                items: &mut Vec<CompletionItem>,
+            ) {
+                items.extend(self.0.iter().cloned());
+            }
+        }
+        let mut far = CompletionItem::new("word", CompletionKind::Word, "word", 1);
+        far.distance = Some(100);
+        let mut near = far.clone();
+        near.distance = Some(2);
+        let other = CompletionItem::new("world", CompletionKind::Word, "world", 1);
+
+        let buffer = Buffer::new("w");
+        let mut engine = CompletionEngine::default();
+        engine.request(
+            buffer.text(),
+            1,
+            &[&Fixed(vec![far, other]), &Fixed(vec![near])],
+        );
+        assert_eq!(labels(&engine), ["word", "world"]);
+        assert_eq!(engine.items()[0].item.distance, Some(2));
+    }
+}
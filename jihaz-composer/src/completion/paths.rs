@@ -0,0 +1,126 @@
+//! Completion of file paths, such as in imports and string literals.
+
+use std::path::{Path, PathBuf};
+
+use super::{
+    is_word_char, prefix_start, CompletionContext, CompletionItem, CompletionKind,
+    CompletionProvider,
+};
+
+/// Whether `c` can be part of a path being completed. Paths with spaces aren't completed.
+pub(crate) fn is_path_char(c: char) -> bool {
+    is_word_char(c) || matches!(c, '/' | '.' | '-' | '~' | '+' | '@')
+}
+
+/// Complete the entries of the directory of the path before the cursor, once it contains a
+/// `/`. Relative paths are resolved against `base_dir`, typically the directory of the document.
+///
+/// Directories are completed with a trailing `/`, so that their entries can be completed next.
+/// Hidden entries are only offered once a `.` was typed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathCompletion {
+    pub base_dir: PathBuf,
+}
+
+impl PathCompletion {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+        }
+    }
+
+    fn resolve(&self, dir: &str) -> Option<PathBuf> {
+        if let Some(rest) = dir.strip_prefix("~/") {
+            return std::env::var_os("HOME").map(|home| Path::new(&home).join(rest));
+        }
+        Some(self.base_dir.join(dir))
+    }
+}
+
+impl CompletionProvider for PathCompletion {
+    fn complete(&self, context: &CompletionContext<'_>, items: &mut Vec<CompletionItem>) {
+        let start = prefix_start(context.text, context.offset, is_path_char);
+        let path = context.text.slice_to_cow(start..context.offset);
+        let Some(separator) = path.rfind('/') else {
+            return;
+        };
+        let (dir, file) = path.split_at(separator + 1);
+        let Some(dir) = self.resolve(dir) else {
+            return;
+        };
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if name.starts_with('.') && !file.starts_with('.') {
+                continue;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let label = if is_dir { format!("{name}/") } else { name };
+            let mut item = CompletionItem::new(&label, CompletionKind::Path, &label, file.len());
+            item.detail = Some(dir.join(&label).to_string_lossy().into_owned());
+            items.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::Rope;
+
+    use super::PathCompletion;
+    use crate::completion::{CompletionContext, CompletionProvider};
+
+    fn complete(provider: &PathCompletion, text: &str) -> Vec<(String, usize)> {
+        let text = Rope::from(text);
+        let mut items = Vec::new();
+        provider.complete(
+            &CompletionContext {
+                text: &text,
+                offset: text.len(),
+            },
+            &mut items,
+        );
+        let mut items: Vec<_> = items
+            .into_iter()
+            .map(|item| (item.insert_text, item.prefix_len))
+            .collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn list_directories() {
+        let dir =
+            std::env::temp_dir().join(format!("jihaz-path-completion-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src/nested")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+        std::fs::write(dir.join("src/.hidden"), "").unwrap();
+        let provider = PathCompletion::new(&dir);
+
+        let entries = complete(&provider, "include \"src/li");
+        let hidden = complete(&provider, "mod src/.h");
+        let nested = complete(&provider, "./src/nested/");
+        let words = complete(&provider, "src");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            entries,
+            [("lib.rs".to_string(), 2), ("nested/".to_string(), 2)]
+        );
+        assert_eq!(
+            hidden,
+            [
+                (".hidden".to_string(), 2),
+                ("lib.rs".to_string(), 2),
+                ("nested/".to_string(), 2)
+            ]
+        );
+        assert!(nested.is_empty());
+        assert!(words.is_empty());
+    }
+}
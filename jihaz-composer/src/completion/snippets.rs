@@ -0,0 +1,141 @@
+//! Completion of snippets: templates inserted by typing their trigger.
+//!
+//! Bodies use the placeholders of the LSP snippet syntax. Tab stops are inserted with their
+//! default text, `${1:name}` as `name` and `$1` as nothing, and the cursor is placed at `$0`, or
+//! at the end of the snippet without one. `\$` is a literal `$`.
+
+use super::{CompletionContext, CompletionItem, CompletionKind, CompletionProvider};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snippet {
+    pub trigger: String,
+    pub body: String,
+    pub description: String,
+}
+
+impl Snippet {
+    pub fn new(
+        trigger: impl Into<String>,
+        body: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Self {
+            trigger: trigger.into(),
+            body: body.into(),
+            description: description.into(),
+        }
+    }
+
+    /// The text of the body with the placeholders expanded, and the offset of the cursor in it.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::completion::Snippet;
+    /// let snippet = Snippet::new("if", "if ${1:condition} {\n\t$0\n}", "If block");
+    /// assert_eq!(
+    ///     snippet.expand(),
+    ///     ("if condition {\n\t\n}".to_string(), Some(16))
+    /// );
+    /// ```
+    pub fn expand(&self) -> (String, Option<usize>) {
+        let mut text = String::with_capacity(self.body.len());
+        let mut cursor = None;
+        let mut chars = self.body.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'$') => {
+                    text.push('$');
+                    chars.next();
+                }
+                '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                    let mut stop = 0;
+                    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                        stop = stop * 10 + digit.to_digit(10).unwrap_or(0);
+                    }
+                    if stop == 0 {
+                        cursor = Some(text.len());
+                    }
+                }
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    let mut stop = 0;
+                    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                        stop = stop * 10 + digit.to_digit(10).unwrap_or(0);
+                    }
+                    if stop == 0 {
+                        cursor = Some(text.len());
+                    }
+                    if chars.next_if_eq(&':').is_some() {
+                        // The default text, which can't nest other placeholders
+                        for c in chars.by_ref() {
+                            if c == '}' {
+                                break;
+                            }
+                            text.push(c);
+                        }
+                    } else {
+                        chars.next_if_eq(&'}');
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        (text, cursor)
+    }
+}
+
+/// Complete the triggers of a set of snippets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snippets {
+    snippets: Vec<Snippet>,
+}
+
+impl Snippets {
+    pub fn new(snippets: Vec<Snippet>) -> Self {
+        Self { snippets }
+    }
+
+    pub fn snippets(&self) -> &[Snippet] {
+        &self.snippets
+    }
+
+    pub fn add(&mut self, snippet: Snippet) {
+        self.snippets.push(snippet);
+    }
+}
+
+impl CompletionProvider for Snippets {
+    fn complete(&self, context: &CompletionContext<'_>, items: &mut Vec<CompletionItem>) {
+        let prefix_len = context.offset - context.word_start();
+        for snippet in &self.snippets {
+            let (text, cursor) = snippet.expand();
+            let mut item =
+                CompletionItem::new(&snippet.trigger, CompletionKind::Snippet, text, prefix_len);
+            item.cursor = cursor;
+            item.detail = Some(snippet.description.clone());
+            items.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Snippet;
+
+    #[test]
+    fn expand() {
+        let expand = |body: &str| Snippet::new("t", body, "").expand();
+        assert_eq!(expand("plain"), ("plain".to_string(), None));
+        assert_eq!(expand("a$1b$0c"), ("abc".to_string(), Some(2)));
+        assert_eq!(
+            expand("fn ${1:name}(${2}) {$0}"),
+            ("fn name() {}".to_string(), Some(11))
+        );
+        assert_eq!(
+            expand("\\$HOME ${0:default}"),
+            ("$HOME default".to_string(), Some(6))
+        );
+        assert_eq!(expand("مرحبا $0!"), ("مرحبا !".to_string(), Some(11)));
+    }
+}
@@ -0,0 +1,306 @@
+//! Completion of the words of the text being edited and of the other open documents.
+//!
+//! A [`WordIndex`] keeps the words of a text with their offsets and is rebased through the
+//! deltas of the buffer, so that an edit only scans the words it touches.
+
+use std::collections::{HashMap, HashSet};
+
+use lapce_xi_rope::{DeltaElement, Rope, RopeDelta};
+
+use super::{
+    is_word_char, prefix_start, CompletionContext, CompletionItem, CompletionKind,
+    CompletionProvider,
+};
+
+/// The words of a text, with their offsets.
+///
+/// Words are runs of letters, digits, underscores and other non-ASCII characters, as
+/// [`WordCursor`](crate::word::WordCursor) classifies them, that don't start with a digit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WordIndex {
+    /// The words by offset.
+    words: Vec<(usize, String)>,
+}
+
+impl WordIndex {
+    pub fn new(text: &Rope) -> Self {
+        let mut index = Self::default();
+        scan(text, 0..text.len(), &mut index.words);
+        index
+    }
+
+    /// The words and their offsets, in order.
+    pub fn words(&self) -> impl Iterator<Item = (usize, &str)> + '_ {
+        self.words
+            .iter()
+            .map(|(offset, word)| (*offset, word.as_str()))
+    }
+
+    /// The distinct words, in no particular order.
+    pub fn unique_words(&self) -> impl Iterator<Item = &str> + '_ {
+        let mut seen = HashSet::new();
+        self.words
+            .iter()
+            .map(|(_, word)| word.as_str())
+            .filter(move |word| seen.insert(*word))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Rebase the index through an edit of the text, where `text` is the text after the edit.
+    ///
+    /// The words within the copied ranges are kept, and the text around the inserted text and
+    /// the deleted ranges is scanned again, since an edit can join or split the words there.
+    pub fn apply_delta(&mut self, text: &Rope, delta: &RopeDelta) {
+        let mut kept = Vec::with_capacity(self.words.len());
+        // The offsets of the new text to scan around
+        let mut dirty: Vec<(usize, usize)> = Vec::new();
+        let mut old = self.words.iter().peekable();
+        let mut pos = 0;
+        for el in &delta.els {
+            match el {
+                DeltaElement::Copy(start, end) => {
+                    while old.next_if(|(offset, _)| offset < start).is_some() {}
+                    while let Some((offset, word)) = old.next_if(|(offset, _)| offset < end) {
+                        if offset + word.len() <= *end {
+                            kept.push((pos + offset - start, word.clone()));
+                        }
+                    }
+                    dirty.push((pos, pos));
+                    pos += end - start;
+                    dirty.push((pos, pos));
+                }
+                DeltaElement::Insert(inserted) => {
+                    dirty.push((pos, pos + inserted.len()));
+                    pos += inserted.len();
+                }
+            }
+        }
+
+        // Widen the ranges to whole words, and merge those that overlap
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in dirty {
+            let start = prefix_start(text, start.min(text.len()), is_word_char);
+            let end = word_end(text, end.min(text.len()));
+            match ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        let mut words = Vec::with_capacity(kept.len());
+        let mut kept = kept.into_iter().peekable();
+        for (start, end) in ranges {
+            while let Some(word) = kept.next_if(|(offset, _)| *offset < start) {
+                if word.0 + word.1.len() < start {
+                    words.push(word);
+                }
+            }
+            while kept.next_if(|(offset, _)| *offset <= end).is_some() {}
+            scan(text, start..end, &mut words);
+        }
+        words.extend(kept);
+        self.words = words;
+    }
+}
+
+/// Complete the words of the text, ranking the words closer to the cursor higher.
+///
+/// The index should be that of `context.text`.
+impl CompletionProvider for WordIndex {
+    fn complete(&self, context: &CompletionContext<'_>, items: &mut Vec<CompletionItem>) {
+        let start = context.word_start();
+        let prefix_len = context.offset - start;
+        let mut nearest: HashMap<&str, usize> = HashMap::new();
+        for (offset, word) in &self.words {
+            let end = offset + word.len();
+            // The word being typed
+            if *offset <= context.offset && context.offset <= end {
+                continue;
+            }
+            let distance = if end < context.offset {
+                context.offset - end
+            } else {
+                offset - context.offset
+            };
+            nearest
+                .entry(word)
+                .and_modify(|nearest| *nearest = distance.min(*nearest))
+                .or_insert(distance);
+        }
+        items.extend(nearest.into_iter().map(|(word, distance)| {
+            let mut item = CompletionItem::new(word, CompletionKind::Word, word, prefix_len);
+            item.distance = Some(distance);
+            item
+        }));
+    }
+}
+
+/// Complete the words of other texts, such as the other open documents.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentWords<'a> {
+    documents: Vec<&'a WordIndex>,
+}
+
+impl<'a> DocumentWords<'a> {
+    pub fn new(documents: impl IntoIterator<Item = &'a WordIndex>) -> Self {
+        Self {
+            documents: documents.into_iter().collect(),
+        }
+    }
+}
+
+impl CompletionProvider for DocumentWords<'_> {
+    fn complete(&self, context: &CompletionContext<'_>, items: &mut Vec<CompletionItem>) {
+        let prefix_len = context.offset - context.word_start();
+        let mut seen = HashSet::new();
+        for word in self.documents.iter().flat_map(|index| index.unique_words()) {
+            if seen.insert(word) {
+                items.push(CompletionItem::new(
+                    word,
+                    CompletionKind::Word,
+                    word,
+                    prefix_len,
+                ));
+            }
+        }
+    }
+}
+
+/// The end of the word that `offset` is in or before.
+fn word_end(text: &Rope, offset: usize) -> usize {
+    let mut cursor = lapce_xi_rope::Cursor::new(text, offset);
+    let mut end = offset;
+    while let Some(c) = cursor.next_codepoint() {
+        if !is_word_char(c) {
+            break;
+        }
+        end = cursor.pos();
+    }
+    end
+}
+
+/// Add the words of `range` of the text, which should start and end at word boundaries.
+fn scan(text: &Rope, range: std::ops::Range<usize>, words: &mut Vec<(usize, String)>) {
+    let mut word: Option<(usize, String)> = None;
+    let mut offset = range.start;
+    for c in text.slice_to_cow(range).chars() {
+        if is_word_char(c) {
+            match &mut word {
+                Some((_, word)) => word.push(c),
+                None => word = Some((offset, c.to_string())),
+            }
+        } else if let Some(word) = word.take() {
+            push_word(word, words);
+        }
+        offset += c.len_utf8();
+    }
+    if let Some(word) = word {
+        push_word(word, words);
+    }
+}
+
+fn push_word(word: (usize, String), words: &mut Vec<(usize, String)>) {
+    if !word.1.starts_with(|c: char| c.is_ascii_digit()) {
+        words.push(word);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::Rope;
+
+    use super::{DocumentWords, WordIndex};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        completion::{CompletionContext, CompletionProvider},
+        editor::EditType,
+        selection::Selection,
+    };
+
+    #[test]
+    fn words() {
+        let index = WordIndex::new(&Rope::from("let x_1 = 42 + y2;\nقال مرحبا"));
+        assert_eq!(
+            index.words().collect::<Vec<_>>(),
+            [
+                (0, "let"),
+                (4, "x_1"),
+                (15, "y2"),
+                (19, "قال"),
+                (26, "مرحبا")
+            ]
+        );
+    }
+
+    #[test]
+    fn incremental_index() {
+        let mut buffer = Buffer::new("alpha beta\ngamma delta\nepsilon");
+        let mut index = WordIndex::new(buffer.text());
+        let edits: &[&[(usize, usize, &str)]] = &[
+            // Join two words
+            &[(10, 11, "")],
+            // Split a word
+            &[(2, 2, " ")],
+            // Several regions at once, inserting new lines and words
+            &[(0, 0, "new "), (12, 12, "\nmore words\n"), (20, 25, "")],
+            // Insert within a word
+            &[(5, 5, "xyz")],
+            // Delete everything
+            &[(0, usize::MAX, "")],
+            &[(0, 0, "again\nهنا")],
+        ];
+        for edit in edits {
+            let len = buffer.text().len();
+            let regions: Vec<(Selection, &str)> = edit
+                .iter()
+                .map(|(start, end, text)| (Selection::region(*start, (*end).min(len)), *text))
+                .collect();
+            let (_, delta, _) = buffer.edit(&regions, EditType::Other);
+            index.apply_delta(buffer.text(), &delta);
+            assert_eq!(index, WordIndex::new(buffer.text()), "{:?}", buffer.text());
+        }
+    }
+
+    #[test]
+    fn nearest_words() {
+        let text = Rope::from("foo bar fo baz foo");
+        let index = WordIndex::new(&text);
+        let mut items = Vec::new();
+        index.complete(
+            &CompletionContext {
+                text: &text,
+                offset: 10,
+            },
+            &mut items,
+        );
+        items.sort_by(|a, b| a.label.cmp(&b.label));
+        let items: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.distance, item.prefix_len))
+            .collect();
+        // The word being typed isn't offered
+        assert_eq!(
+            items,
+            [
+                ("bar", Some(3), 2),
+                ("baz", Some(1), 2),
+                ("foo", Some(5), 2)
+            ]
+        );
+
+        let other = WordIndex::new(&Rope::from("foo qux"));
+        let mut items = Vec::new();
+        DocumentWords::new([&index, &other]).complete(
+            &CompletionContext {
+                text: &text,
+                offset: 10,
+            },
+            &mut items,
+        );
+        assert_eq!(items.len(), 5);
+        assert!(items.iter().all(|item| item.distance.is_none()));
+    }
+}
@@ -1,9 +1,12 @@
-use lapce_xi_rope::{RopeDelta, Transformer};
+use lapce_xi_rope::{Rope, RopeDelta, Transformer};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    buffer::{rope_text::RopeText, Buffer},
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    editor::EditType,
+    ime::Preedit,
     mode::{Mode, MotionMode, VisualMode},
     register::RegisterData,
     selection::{InsertDrift, SelRegion, Selection},
@@ -26,6 +29,9 @@ pub struct Cursor {
     pub motion_mode: Option<MotionMode>,
     pub history_selections: Vec<Selection>,
     pub affinity: CursorAffinity,
+    /// The composition text of an input method, shown at every region until it is committed.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub preedit: Option<Preedit>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -165,6 +171,7 @@ impl Cursor {
             history_selections: Vec::new(),
             // It should appear before any inlay hints at the very first position
             affinity: CursorAffinity::Backward,
+            preedit: None,
         }
     }
 
@@ -312,7 +319,7 @@ impl Cursor {
         let (content, mode) = match &self.mode {
             CursorMode::Insert(selection) => {
                 let mut mode = VisualMode::Normal;
-                let mut content = "".to_string();
+                let mut fragments = Vec::with_capacity(selection.len());
                 for region in selection.regions() {
                     let region_content = if region.is_caret() {
                         mode = VisualMode::Linewise;
@@ -321,16 +328,9 @@ impl Cursor {
                     } else {
                         text.slice_to_cow(region.min()..region.max())
                     };
-                    if content.is_empty() {
-                        content = region_content.to_string();
-                    } else if content.ends_with('\n') {
-                        content += &region_content;
-                    } else {
-                        content += "\n";
-                        content += &region_content;
-                    }
+                    fragments.push(region_content.to_string());
                 }
-                (content, mode)
+                return RegisterData::from_fragments(fragments, mode);
             }
             CursorMode::Normal(offset) => {
                 let new_offset = text.next_grapheme_offset(*offset, 1, text.len());
@@ -385,7 +385,11 @@ impl Cursor {
                 }
             },
         };
-        RegisterData { content, mode }
+        RegisterData {
+            content,
+            mode,
+            fragments: Vec::new(),
+        }
     }
 
     /// Return the current selection start and end position for a
@@ -405,31 +409,32 @@ impl Cursor {
         }
     }
 
+    /// Get the line, the column and the offset of the cursor, for display in a status bar.
+    /// The column counts grapheme clusters from the start of the line, so that a character
+    /// made of several codepoints (an emoji sequence, a letter with its diacritics) counts once,
+    /// and the offset is snapped to the start of the cluster it is in.
+    /// Returns `None` when there are several selections.
     pub fn get_line_col_char(&self, buffer: &Buffer) -> Option<(usize, usize, usize)> {
-        match &self.mode {
-            CursorMode::Normal(offset) => {
-                let ln_col = buffer.offset_to_line_col(*offset);
-                Some((ln_col.0, ln_col.1, *offset))
-            }
-            CursorMode::Visual {
-                start,
-                end,
-                mode: _,
-            } => {
-                let v = buffer.offset_to_line_col(*start.min(end));
-                Some((v.0, v.1, *start))
-            }
+        let offset = match &self.mode {
+            CursorMode::Normal(offset) => *offset,
+            CursorMode::Visual { start, end, .. } => *start.min(end),
             CursorMode::Insert(selection) => {
                 if selection.regions().len() > 1 {
                     return None;
                 }
 
-                let x = selection.regions().first().unwrap();
-                let v = buffer.offset_to_line_col(x.start);
-
-                Some((v.0, v.1, x.start))
+                selection.regions().first()?.start
             }
-        }
+        };
+
+        let offset = buffer.grapheme_start(offset);
+        let line = buffer.line_of_offset(offset);
+        let line_start = buffer.offset_of_line(line);
+        let col = buffer
+            .slice_to_cow(line_start..offset)
+            .graphemes(true)
+            .count();
+        Some((line, col, offset))
     }
 
     pub fn get_selection_count(&self) -> usize {
@@ -551,6 +556,36 @@ impl Cursor {
             }
         }
     }
+
+    /// Show the composition text of an input method at every region, without editing the
+    /// buffer. An empty text ends the composition, as input methods clear it that way.
+    pub fn set_preedit(&mut self, preedit: Preedit) {
+        self.preedit = (!preedit.text.is_empty()).then_some(preedit);
+    }
+
+    /// Drop the composition text, leaving the buffer as it was.
+    pub fn cancel_preedit(&mut self) {
+        self.preedit = None;
+    }
+
+    /// End the composition with the `text` committed by the input method, inserted in place of
+    /// every region in one edit. Only insert mode takes the text of an input method.
+    pub fn commit_preedit(
+        &mut self,
+        buffer: &mut Buffer,
+        text: &str,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        self.preedit = None;
+        let CursorMode::Insert(selection) = &self.mode else {
+            return None;
+        };
+        if text.is_empty() {
+            return None;
+        }
+        let edit = buffer.edit([(selection, text)], EditType::InsertChars);
+        self.apply_delta(&edit.1);
+        Some(edit)
+    }
 }
 
 pub fn get_first_selection_after(
@@ -614,3 +649,30 @@ pub fn get_first_selection_after(
             Cursor::new(cursor_mode, None, None)
         })
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Cursor, CursorMode};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        selection::{SelRegion, Selection},
+    };
+
+    #[test]
+    fn line_col_counts_grapheme_clusters() {
+        // A flag, a letter with an accent, and an Arabic letter with shadda and fatha
+        let buffer = Buffer::new("x\n🇸🇦e\u{301}بَّz");
+
+        let cursor = Cursor::new(CursorMode::Normal(buffer.len() - 1), None, None);
+        assert_eq!(cursor.get_line_col_char(&buffer), Some((1, 3, 19)));
+
+        // Inside the flag, the cursor is reported at the start of the cluster
+        let cursor = Cursor::new(CursorMode::Normal(6), None, None);
+        assert_eq!(cursor.get_line_col_char(&buffer), Some((1, 0, 2)));
+
+        let mut selection = Selection::caret(2);
+        selection.add_region(SelRegion::caret(13));
+        let cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        assert_eq!(cursor.get_line_col_char(&buffer), None);
+    }
+}
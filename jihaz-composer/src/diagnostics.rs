@@ -0,0 +1,733 @@
+//! Diagnostics, such as the errors and warnings of a language server, kept on the ranges of the
+//! text they are about.
+//!
+//! The ranges are offsets into the current text and are rebased through every delta of the
+//! buffer, so that diagnostics stay on their text while it is edited until fresh ones come in. A
+//! diagnostic whose text is deleted is dropped, as it no longer points at anything.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use lapce_xi_rope::{DeltaElement, RopeDelta, Transformer};
+
+use crate::{
+    buffer::rope_text::RopeText,
+    command::FocusCommand,
+    cursor::Cursor,
+    encoding::offset_utf16_to_utf8,
+    viewport::{DisplayLines, Viewport},
+};
+
+/// How severe a diagnostic is, the most severe first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl DiagnosticSeverity {
+    /// Whether the severity is `min` or more severe.
+    pub fn is_at_least(self, min: DiagnosticSeverity) -> bool {
+        self <= min
+    }
+}
+
+impl From<lsp_types::DiagnosticSeverity> for DiagnosticSeverity {
+    fn from(severity: lsp_types::DiagnosticSeverity) -> Self {
+        match severity {
+            lsp_types::DiagnosticSeverity::WARNING => Self::Warning,
+            lsp_types::DiagnosticSeverity::INFORMATION => Self::Information,
+            lsp_types::DiagnosticSeverity::HINT => Self::Hint,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// Where related information points: into the same document, where the range is tracked like
+/// the diagnostic's, or into another one, where it is kept as the server sent it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelatedLocation {
+    Document(Range<usize>),
+    External {
+        uri: lsp_types::Url,
+        range: lsp_types::Range,
+    },
+}
+
+/// A location related to a diagnostic, such as the first definition of a duplicated name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelatedInformation {
+    pub location: RelatedLocation,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range<usize>,
+    pub severity: DiagnosticSeverity,
+    /// What produced the diagnostic, such as `rustc` or `clippy`.
+    pub source: Option<String>,
+    pub code: Option<String>,
+    pub message: String,
+    pub related: Vec<RelatedInformation>,
+}
+
+impl Diagnostic {
+    pub fn new(
+        range: Range<usize>,
+        severity: DiagnosticSeverity,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            range,
+            severity,
+            source: None,
+            code: None,
+            message: message.into(),
+            related: Vec::new(),
+        }
+    }
+
+    /// Convert a diagnostic of a language server for the document at `uri` whose text is `text`.
+    /// Positions are in UTF-16 code units and are clamped to the text. A missing severity is
+    /// taken to be an error, as the protocol leaves it to the client.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::{
+    /// #     buffer::Buffer,
+    /// #     diagnostics::{Diagnostic, DiagnosticSeverity},
+    /// # };
+    /// # use lsp_types::{Position, Range, Url};
+    /// let buffer = Buffer::new("let ñ = 1;\nlet x = ñ;\n");
+    /// let uri = Url::parse("file:///src/main.rs").unwrap();
+    /// let lsp = lsp_types::Diagnostic::new_simple(
+    ///     Range::new(Position::new(1, 8), Position::new(1, 9)),
+    ///     "unused variable".to_string(),
+    /// );
+    /// let diagnostic = Diagnostic::from_lsp(&buffer, &uri, &lsp);
+    /// // ñ is one UTF-16 code unit but two bytes
+    /// assert_eq!(diagnostic.range, 20..22);
+    /// assert_eq!(diagnostic.severity, DiagnosticSeverity::Error);
+    /// ```
+    pub fn from_lsp(
+        text: &impl RopeText,
+        uri: &lsp_types::Url,
+        diagnostic: &lsp_types::Diagnostic,
+    ) -> Self {
+        let code = diagnostic.code.as_ref().map(|code| match code {
+            lsp_types::NumberOrString::Number(number) => number.to_string(),
+            lsp_types::NumberOrString::String(code) => code.clone(),
+        });
+        let related = diagnostic
+            .related_information
+            .iter()
+            .flatten()
+            .map(|info| RelatedInformation {
+                location: if &info.location.uri == uri {
+                    RelatedLocation::Document(lsp_range_to_offsets(text, info.location.range))
+                } else {
+                    RelatedLocation::External {
+                        uri: info.location.uri.clone(),
+                        range: info.location.range,
+                    }
+                },
+                message: info.message.clone(),
+            })
+            .collect();
+        Self {
+            range: lsp_range_to_offsets(text, diagnostic.range),
+            severity: diagnostic
+                .severity
+                .map_or(DiagnosticSeverity::Error, DiagnosticSeverity::from),
+            source: diagnostic.source.clone(),
+            code,
+            message: diagnostic.message.clone(),
+            related,
+        }
+    }
+
+    /// Whether the diagnostic is on any of the text of `range`. An empty diagnostic is on the
+    /// range if it is within it or at its end.
+    fn overlaps(&self, range: &Range<usize>) -> bool {
+        if self.range.is_empty() {
+            range.contains(&self.range.start) || self.range.start == range.end
+        } else {
+            self.range.start < range.end && range.start < self.range.end
+        }
+    }
+}
+
+fn lsp_position_to_offset(text: &impl RopeText, position: lsp_types::Position) -> usize {
+    let line = position.line as usize;
+    if line >= text.num_lines() {
+        return text.len();
+    }
+    let start = text.offset_of_line(line);
+    let end = text.line_end_offset(line, true);
+    let col = offset_utf16_to_utf8(
+        text.char_indices_iter(start..end),
+        position.character as usize,
+    );
+    start + col.min(end - start)
+}
+
+fn lsp_range_to_offsets(text: &impl RopeText, range: lsp_types::Range) -> Range<usize> {
+    let start = lsp_position_to_offset(text, range.start);
+    let end = lsp_position_to_offset(text, range.end);
+    start..end.max(start)
+}
+
+/// Rebase `range` through `delta`, or `None` if all of its text was deleted. Text inserted at
+/// either end is left out of the range, and an empty range is dropped if the text around it
+/// was deleted.
+fn transform_range(delta: &RopeDelta, range: &Range<usize>) -> Option<Range<usize>> {
+    let kept = delta.els.iter().any(|el| match el {
+        DeltaElement::Copy(start, end) if range.is_empty() => {
+            *start <= range.start && range.start <= *end
+        }
+        DeltaElement::Copy(start, end) => *start < range.end && range.start < *end,
+        DeltaElement::Insert(_) => false,
+    });
+    if !kept {
+        return None;
+    }
+    let mut transformer = Transformer::new(delta);
+    let start = transformer.transform(range.start, !range.is_empty());
+    let end = transformer.transform(range.end, false).max(start);
+    Some(start..end)
+}
+
+/// The diagnostics of a document, ordered by where they start.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     buffer::Buffer,
+/// #     diagnostics::{Diagnostic, DiagnosticSeverity, DocumentDiagnostics},
+/// #     editor::EditType,
+/// #     selection::Selection,
+/// # };
+/// let mut buffer = Buffer::new("let x = y;\n");
+/// let mut diagnostics = DocumentDiagnostics::new(vec![Diagnostic::new(
+///     8..9,
+///     DiagnosticSeverity::Error,
+///     "cannot find value `y`",
+/// )]);
+///
+/// // The diagnostic moves with its text
+/// let (_, delta, _) = buffer.edit(&[(Selection::caret(0), "// y\n")], EditType::InsertChars);
+/// diagnostics.apply_delta(&delta);
+/// assert_eq!(diagnostics.iter().next().unwrap().range, 13..14);
+///
+/// // And goes away with it
+/// let (_, delta, _) = buffer.edit(&[(Selection::region(13, 14), "")], EditType::Delete);
+/// diagnostics.apply_delta(&delta);
+/// assert!(diagnostics.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocumentDiagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl DocumentDiagnostics {
+    pub fn new(mut diagnostics: Vec<Diagnostic>) -> Self {
+        diagnostics.sort_by(compare);
+        Self { diagnostics }
+    }
+
+    /// Convert the diagnostics that a language server published for the document at `uri`.
+    pub fn from_lsp(
+        text: &impl RopeText,
+        uri: &lsp_types::Url,
+        diagnostics: &[lsp_types::Diagnostic],
+    ) -> Self {
+        Self::new(
+            diagnostics
+                .iter()
+                .map(|diagnostic| Diagnostic::from_lsp(text, uri, diagnostic))
+                .collect(),
+        )
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &Diagnostic> + '_ {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// The number of diagnostics of each severity, as `[errors, warnings, information, hints]`.
+    pub fn counts(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for diagnostic in &self.diagnostics {
+            counts[diagnostic.severity as usize] += 1;
+        }
+        counts
+    }
+
+    /// Rebase the diagnostics through an edit of the text, dropping those whose text it deleted.
+    pub fn apply_delta(&mut self, delta: &RopeDelta) {
+        self.diagnostics.retain_mut(|diagnostic| {
+            let Some(range) = transform_range(delta, &diagnostic.range) else {
+                return false;
+            };
+            diagnostic.range = range;
+            diagnostic
+                .related
+                .retain_mut(|info| match &mut info.location {
+                    RelatedLocation::Document(range) => match transform_range(delta, range) {
+                        Some(new_range) => {
+                            *range = new_range;
+                            true
+                        }
+                        None => false,
+                    },
+                    RelatedLocation::External { .. } => true,
+                });
+            true
+        });
+        // Rebasing keeps the order of the starts, but not always of the ends
+        self.diagnostics.sort_by(compare);
+    }
+
+    /// The diagnostics on any of the text of `range`.
+    pub fn in_range(&self, range: Range<usize>) -> impl Iterator<Item = &Diagnostic> + '_ {
+        // Diagnostics are not nested, but they can be long, so those that start before the range
+        // are all checked
+        let end = self
+            .diagnostics
+            .partition_point(|diagnostic| diagnostic.range.start <= range.end);
+        self.diagnostics[..end]
+            .iter()
+            .filter(move |diagnostic| diagnostic.overlaps(&range))
+    }
+
+    /// The diagnostics on any of the buffer lines of `lines`.
+    pub fn in_lines(
+        &self,
+        text: &impl RopeText,
+        lines: Range<usize>,
+    ) -> impl Iterator<Item = &Diagnostic> + '_ {
+        let range = text.offset_of_line(lines.start)
+            ..text.line_end_offset(lines.end.max(lines.start + 1) - 1, true);
+        // Nothing is on no lines
+        self.in_range(range).filter(move |_| !lines.is_empty())
+    }
+
+    /// The diagnostics on a buffer line.
+    pub fn on_line(
+        &self,
+        text: &impl RopeText,
+        line: usize,
+    ) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.in_lines(text, line..line + 1)
+    }
+
+    /// The diagnostics on the display lines that `viewport` shows.
+    pub fn in_viewport(
+        &self,
+        text: &impl RopeText,
+        lines: &mut impl DisplayLines,
+        viewport: &Viewport,
+    ) -> impl Iterator<Item = &Diagnostic> + '_ {
+        let last_line = lines.num_display_lines(text) - 1;
+        let first = viewport.first_line.min(last_line);
+        let last = (viewport.lines().end.saturating_sub(1)).min(last_line);
+        let range =
+            lines.display_line_range(text, first).start..lines.display_line_range(text, last).end;
+        self.in_range(range)
+    }
+
+    /// The first diagnostic of `min_severity` or more severe that starts after `offset`, wrapping
+    /// around to the start of the document.
+    pub fn next(&self, offset: usize, min_severity: DiagnosticSeverity) -> Option<&Diagnostic> {
+        let mut diagnostics = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity.is_at_least(min_severity));
+        let first = diagnostics.clone().next();
+        diagnostics
+            .find(|diagnostic| diagnostic.range.start > offset)
+            .or(first)
+    }
+
+    /// The last diagnostic of `min_severity` or more severe that starts before `offset`,
+    /// wrapping around to the end of the document.
+    pub fn previous(&self, offset: usize, min_severity: DiagnosticSeverity) -> Option<&Diagnostic> {
+        let mut diagnostics = self
+            .diagnostics
+            .iter()
+            .rev()
+            .filter(|diagnostic| diagnostic.severity.is_at_least(min_severity));
+        let last = diagnostics.clone().next();
+        diagnostics
+            .find(|diagnostic| diagnostic.range.start < offset)
+            .or(last)
+    }
+
+    /// Run [`FocusCommand::NextError`] or [`FocusCommand::PreviousError`], moving the cursor to
+    /// the start of the diagnostic it goes to, which is returned.
+    pub fn do_command(
+        &self,
+        cmd: &FocusCommand,
+        cursor: &mut Cursor,
+        min_severity: DiagnosticSeverity,
+    ) -> Option<&Diagnostic> {
+        let diagnostic = match cmd {
+            FocusCommand::NextError => self.next(cursor.offset(), min_severity),
+            FocusCommand::PreviousError => self.previous(cursor.offset(), min_severity),
+            _ => None,
+        }?;
+        cursor.set_offset(diagnostic.range.start, false, false);
+        Some(diagnostic)
+    }
+}
+
+fn compare(a: &Diagnostic, b: &Diagnostic) -> Ordering {
+    a.range
+        .start
+        .cmp(&b.range.start)
+        .then(a.range.end.cmp(&b.range.end))
+        .then(a.severity.cmp(&b.severity))
+}
+
+/// The diagnostics of all the documents that have some.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    documents: HashMap<PathBuf, DocumentDiagnostics>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the diagnostics of a document, as when a language server publishes them.
+    pub fn set(&mut self, path: impl Into<PathBuf>, diagnostics: DocumentDiagnostics) {
+        let path = path.into();
+        if diagnostics.is_empty() {
+            self.documents.remove(&path);
+        } else {
+            self.documents.insert(path, diagnostics);
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&DocumentDiagnostics> {
+        self.documents.get(path)
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<DocumentDiagnostics> {
+        self.documents.remove(path)
+    }
+
+    /// Rebase the diagnostics of a document through an edit of its text.
+    pub fn apply_delta(&mut self, path: &Path, delta: &RopeDelta) {
+        if let Some(diagnostics) = self.documents.get_mut(path) {
+            diagnostics.apply_delta(delta);
+            if diagnostics.is_empty() {
+                self.documents.remove(path);
+            }
+        }
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = (&PathBuf, &DocumentDiagnostics)> + '_ {
+        self.documents.iter()
+    }
+
+    /// The number of diagnostics of each severity in all the documents, as
+    /// `[errors, warnings, information, hints]`.
+    pub fn counts(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for diagnostics in self.documents.values() {
+            for (count, document_count) in counts.iter_mut().zip(diagnostics.counts()) {
+                *count += document_count;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::{DiagnosticRelatedInformation, Location, NumberOrString, Position, Url};
+
+    use super::{
+        Diagnostic, DiagnosticSeverity, Diagnostics, DocumentDiagnostics, RelatedInformation,
+        RelatedLocation,
+    };
+    use crate::{
+        buffer::Buffer,
+        command::FocusCommand,
+        cursor::{Cursor, CursorMode},
+        editor::EditType,
+        selection::Selection,
+        viewport::{BufferLines, Viewport},
+    };
+
+    fn diagnostic(range: std::ops::Range<usize>, severity: DiagnosticSeverity) -> Diagnostic {
+        Diagnostic::new(range, severity, "")
+    }
+
+    fn ranges(diagnostics: &DocumentDiagnostics) -> Vec<(usize, usize)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start, d.range.end))
+            .collect()
+    }
+
+    #[test]
+    fn apply_delta() {
+        let mut buffer = Buffer::new("abcdefghij");
+        let mut diagnostics = DocumentDiagnostics::new(vec![
+            diagnostic(2..4, DiagnosticSeverity::Error),
+            diagnostic(6..6, DiagnosticSeverity::Warning),
+            diagnostic(8..10, DiagnosticSeverity::Hint),
+        ]);
+
+        // Text inserted at the ends of a range is not in it, but text inserted within it is
+        let (_, delta, _) = buffer.edit(
+            &[
+                (Selection::caret(2), "X"),
+                (Selection::caret(3), "Y"),
+                (Selection::caret(4), "Z"),
+            ],
+            EditType::InsertChars,
+        );
+        diagnostics.apply_delta(&delta);
+        assert_eq!(ranges(&diagnostics), [(3, 6), (9, 9), (11, 13)]);
+
+        // Deleting part of a range shrinks it
+        let (_, delta, _) = buffer.edit(&[(Selection::region(10, 12), "")], EditType::Delete);
+        diagnostics.apply_delta(&delta);
+        assert_eq!(ranges(&diagnostics), [(3, 6), (9, 9), (10, 11)]);
+
+        // Deleting around an empty range drops it, and so does deleting all of a range
+        let (_, delta, _) = buffer.edit(
+            &[
+                (Selection::region(8, 10), ""),
+                (Selection::region(3, 6), ""),
+            ],
+            EditType::Delete,
+        );
+        diagnostics.apply_delta(&delta);
+        assert_eq!(ranges(&diagnostics), [(5, 6)]);
+
+        // Replacing the text of a range drops it too
+        let (_, delta, _) = buffer.edit(&[(Selection::region(5, 6), "new")], EditType::InsertChars);
+        diagnostics.apply_delta(&delta);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn related_ranges() {
+        let mut buffer = Buffer::new("fn a() {}\nfn a() {}\n");
+        let mut first = diagnostic(13..14, DiagnosticSeverity::Error);
+        first.related.push(RelatedInformation {
+            location: RelatedLocation::Document(3..4),
+            message: "first defined here".to_string(),
+        });
+        let mut diagnostics = DocumentDiagnostics::new(vec![first]);
+
+        let (_, delta, _) = buffer.edit(&[(Selection::caret(0), "\n")], EditType::InsertChars);
+        diagnostics.apply_delta(&delta);
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(diagnostic.range, 14..15);
+        assert_eq!(
+            diagnostic.related[0].location,
+            RelatedLocation::Document(4..5)
+        );
+
+        // The related range goes away with its text, but the diagnostic stays
+        let (_, delta, _) = buffer.edit(&[(Selection::region(1, 11), "")], EditType::Delete);
+        diagnostics.apply_delta(&delta);
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(diagnostic.range, 4..5);
+        assert!(diagnostic.related.is_empty());
+    }
+
+    #[test]
+    fn queries() {
+        let text: String = (0..20).map(|i| format!("line {i:02}\n")).collect();
+        let buffer = Buffer::new(text.as_str());
+        // Lines are 8 bytes long
+        let diagnostics = DocumentDiagnostics::new(vec![
+            diagnostic(0..4, DiagnosticSeverity::Error),
+            diagnostic(10..30, DiagnosticSeverity::Warning),
+            diagnostic(40..40, DiagnosticSeverity::Hint),
+            diagnostic(47..47, DiagnosticSeverity::Hint),
+            diagnostic(100..104, DiagnosticSeverity::Error),
+        ]);
+
+        let on_line = |line| {
+            diagnostics
+                .on_line(&buffer, line)
+                .map(|d| (d.range.start, d.range.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(on_line(0), [(0, 4)]);
+        assert_eq!(on_line(2), [(10, 30)]);
+        // An empty diagnostic at the end of a line is on it
+        assert_eq!(on_line(5), [(40, 40), (47, 47)]);
+        assert!(on_line(6).is_empty());
+
+        let in_lines: Vec<_> = diagnostics.in_lines(&buffer, 3..5).collect();
+        assert_eq!(in_lines.len(), 1);
+        assert_eq!(diagnostics.in_lines(&buffer, 3..3).count(), 0);
+
+        let mut viewport = Viewport::new(5, 0);
+        viewport.first_line = 10;
+        let in_viewport: Vec<_> = diagnostics
+            .in_viewport(&buffer, &mut BufferLines, &viewport)
+            .map(|d| (d.range.start, d.range.end))
+            .collect();
+        assert_eq!(in_viewport, [(100, 104)]);
+        viewport.first_line = 30;
+        assert_eq!(
+            diagnostics
+                .in_viewport(&buffer, &mut BufferLines, &viewport)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn navigation() {
+        let diagnostics = DocumentDiagnostics::new(vec![
+            diagnostic(5..6, DiagnosticSeverity::Warning),
+            diagnostic(10..12, DiagnosticSeverity::Error),
+            diagnostic(20..21, DiagnosticSeverity::Hint),
+        ]);
+        let next = |offset, severity| diagnostics.next(offset, severity).map(|d| d.range.start);
+        let previous = |offset, severity| {
+            diagnostics
+                .previous(offset, severity)
+                .map(|d| d.range.start)
+        };
+
+        assert_eq!(next(0, DiagnosticSeverity::Hint), Some(5));
+        assert_eq!(next(5, DiagnosticSeverity::Hint), Some(10));
+        assert_eq!(next(20, DiagnosticSeverity::Hint), Some(5));
+        assert_eq!(next(0, DiagnosticSeverity::Error), Some(10));
+        assert_eq!(next(10, DiagnosticSeverity::Error), Some(10));
+        assert_eq!(previous(10, DiagnosticSeverity::Hint), Some(5));
+        assert_eq!(previous(5, DiagnosticSeverity::Hint), Some(20));
+        assert_eq!(previous(30, DiagnosticSeverity::Warning), Some(10));
+        assert_eq!(
+            DocumentDiagnostics::default().next(0, DiagnosticSeverity::Hint),
+            None
+        );
+
+        let mut cursor = Cursor::new(CursorMode::Normal(7), None, None);
+        diagnostics.do_command(
+            &FocusCommand::NextError,
+            &mut cursor,
+            DiagnosticSeverity::Hint,
+        );
+        assert_eq!(cursor.offset(), 10);
+        diagnostics.do_command(
+            &FocusCommand::PreviousError,
+            &mut cursor,
+            DiagnosticSeverity::Hint,
+        );
+        assert_eq!(cursor.offset(), 5);
+        assert!(diagnostics
+            .do_command(
+                &FocusCommand::SplitVertical,
+                &mut cursor,
+                DiagnosticSeverity::Hint
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn from_lsp() {
+        let buffer = Buffer::new("let 😀 = 1;\r\nfn a() {}\nfn a() {}\n");
+        let uri = Url::parse("file:///src/lib.rs").unwrap();
+        let other = Url::parse("file:///src/other.rs").unwrap();
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            lsp_types::Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        };
+        let mut lsp =
+            lsp_types::Diagnostic::new_simple(range((2, 3), (2, 4)), "duplicate".to_string());
+        lsp.severity = Some(lsp_types::DiagnosticSeverity::WARNING);
+        lsp.code = Some(NumberOrString::String("E0428".to_string()));
+        lsp.source = Some("rustc".to_string());
+        lsp.related_information = Some(vec![
+            DiagnosticRelatedInformation {
+                location: Location::new(uri.clone(), range((1, 3), (1, 4))),
+                message: "previous definition".to_string(),
+            },
+            DiagnosticRelatedInformation {
+                location: Location::new(other.clone(), range((0, 0), (0, 1))),
+                message: "imported here".to_string(),
+            },
+        ]);
+
+        let diagnostic = Diagnostic::from_lsp(&buffer, &uri, &lsp);
+        assert_eq!(diagnostic.range, 28..29);
+        assert_eq!(diagnostic.severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostic.code.as_deref(), Some("E0428"));
+        assert_eq!(diagnostic.source.as_deref(), Some("rustc"));
+        assert_eq!(
+            diagnostic.related[0].location,
+            RelatedLocation::Document(18..19)
+        );
+        assert_eq!(
+            diagnostic.related[1].location,
+            RelatedLocation::External {
+                uri: other,
+                range: range((0, 0), (0, 1)),
+            }
+        );
+
+        // The emoji is two UTF-16 code units and four bytes, and positions past the end of a
+        // line or of the text are clamped
+        let mut lsp = lsp_types::Diagnostic::new_simple(range((0, 6), (0, 100)), String::new());
+        lsp.code = Some(NumberOrString::Number(42));
+        let diagnostic = Diagnostic::from_lsp(&buffer, &uri, &lsp);
+        assert_eq!(diagnostic.range, 8..13);
+        assert_eq!(diagnostic.code.as_deref(), Some("42"));
+        let lsp = lsp_types::Diagnostic::new_simple(range((9, 0), (9, 1)), String::new());
+        assert_eq!(Diagnostic::from_lsp(&buffer, &uri, &lsp).range, 35..35);
+    }
+
+    #[test]
+    fn documents() {
+        let mut buffer = Buffer::new("abc");
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.set(
+            "a.rs",
+            DocumentDiagnostics::new(vec![
+                diagnostic(0..1, DiagnosticSeverity::Error),
+                diagnostic(1..2, DiagnosticSeverity::Hint),
+            ]),
+        );
+        diagnostics.set(
+            "b.rs",
+            DocumentDiagnostics::new(vec![diagnostic(0..1, DiagnosticSeverity::Error)]),
+        );
+        assert_eq!(diagnostics.counts(), [2, 0, 0, 1]);
+
+        let (_, delta, _) = buffer.edit(&[(Selection::region(0, 2), "")], EditType::Delete);
+        diagnostics.apply_delta("a.rs".as_ref(), &delta);
+        assert!(diagnostics.get("a.rs".as_ref()).is_none());
+        assert_eq!(diagnostics.counts(), [1, 0, 0, 0]);
+
+        diagnostics.set("b.rs", DocumentDiagnostics::default());
+        assert_eq!(diagnostics.documents().count(), 0);
+    }
+}
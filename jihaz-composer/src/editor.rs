@@ -1,12 +1,21 @@
-use std::{collections::HashSet, iter, ops::Range};
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    hash::BuildHasher,
+    iter,
+    ops::Range,
+};
 
 use itertools::Itertools;
-use lapce_xi_rope::{DeltaElement, Rope, RopeDelta};
+use lapce_xi_rope::{DeltaElement, Rope, RopeDelta, Transformer};
 
 use crate::{
-    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    bidi::BidiMovement,
+    buffer::{diff::minimal_edits, rope_text::RopeText, Buffer, InvalLines},
+    case::{identifier_range, CaseTransform},
+    chars::char_is_arabic_diacritic,
     command::EditCommand,
     cursor::{get_first_selection_after, Cursor, CursorMode},
+    line_transform::{AlignDelimiter, LineTransform, SortOrder},
     mode::{Mode, MotionMode, VisualMode},
     register::{Clipboard, Register, RegisterData, RegisterKind},
     selection::{InsertDrift, SelRegion, Selection},
@@ -63,6 +72,9 @@ pub enum EditType {
     DeleteToEndOfLineAndInsert,
     MotionDelete,
     NormalizeLineEndings,
+    ChangeCase,
+    TransformLines,
+    OnSave,
     Undo,
     Redo,
     Other,
@@ -81,6 +93,17 @@ pub struct EditConf<'a> {
     pub smart_tab: bool,
     pub keep_indent: bool,
     pub auto_indent: bool,
+    /// Whether backspace after a letter carrying Arabic diacritics (harakat) removes only the
+    /// last diacritic instead of the whole grapheme cluster, so that a mistyped haraka can be
+    /// corrected without retyping the letter.
+    pub backspace_deletes_diacritic: bool,
+    /// Whether the left and right arrow keys move in logical or visual order through lines
+    /// that mix right-to-left and left-to-right text. Edits themselves always work in logical
+    /// order; this is the value to pass to [`move_offset`](crate::movement::move_offset) when
+    /// moving the same cursor.
+    pub bidi_movement: BidiMovement,
+    /// The number of columns a tab takes, for aligning lines.
+    pub tab_width: usize,
 }
 
 pub struct Action {}
@@ -425,6 +448,7 @@ impl Action {
                         } else {
                             VisualMode::Normal
                         },
+                        fragments: Vec::new(),
                     },
                 );
                 let selection = Selection::region(range.start, range.end);
@@ -444,6 +468,7 @@ impl Action {
                         } else {
                             VisualMode::Normal
                         },
+                        fragments: Vec::new(),
                     },
                 );
             }
@@ -457,25 +482,54 @@ impl Action {
                 let (text, delta, inval_lines) = Self::do_outdent(buffer, selection);
                 deltas.push((text, delta, inval_lines));
             }
+            MotionMode::ChangeCase(transform) => {
+                let range = format_start_end(buffer, range, is_vertical, false, 1);
+                let content = buffer.slice_to_cow(range.clone());
+                let replacement = transform.apply(&content);
+                if replacement != content {
+                    let selection = Selection::region(range.start, range.end);
+                    let (text, delta, inval_lines) =
+                        buffer.edit([(&selection, replacement.as_str())], EditType::ChangeCase);
+                    if cursor.is_insert() {
+                        cursor.apply_delta(&delta);
+                    } else {
+                        cursor.mode = CursorMode::Normal(range.start);
+                    }
+                    deltas.push((text, delta, inval_lines));
+                }
+            }
         }
         deltas
     }
 
     /// Compute the result of pasting `content` into `selection`.
-    /// If the number of lines to be pasted is divisible by the number of [`SelRegion`]s in
-    /// `selection`, partition the content to be pasted into groups of equal numbers of lines and
+    /// If there are as many `fragments`, the text yanked from each region, as [`SelRegion`]s in
+    /// `selection`, paste one fragment at each [`SelRegion`].
+    /// Otherwise, if the number of lines to be pasted is divisible by the number of [`SelRegion`]s
+    /// in `selection`, partition the content to be pasted into groups of equal numbers of lines and
     /// paste one group at each [`SelRegion`].
     /// The way lines are counted and `content` is partitioned depends on `mode`.
     fn compute_paste_edit(
         buffer: &mut Buffer,
         selection: &Selection,
         content: &str,
+        fragments: &[String],
         mode: VisualMode,
     ) -> (Rope, RopeDelta, InvalLines) {
         if selection.len() > 1 {
             let line_ends: Vec<_> = content.match_indices('\n').map(|(idx, _)| idx).collect();
 
             match mode {
+                _ if fragments.len() == selection.len() => {
+                    let edits = selection
+                        .regions()
+                        .iter()
+                        .copied()
+                        .map(Selection::sel_region)
+                        .zip(fragments.iter().map(String::as_str));
+
+                    buffer.edit(edits, EditType::Paste)
+                }
                 // Consider lines to be separated by the line terminator.
                 // The number of lines == number of line terminators + 1.
                 // The final line in each group does not include the line terminator.
@@ -566,8 +620,13 @@ impl Action {
                     }
                 };
                 let after = cursor.is_insert() || !data.content.contains('\n');
-                let (text, delta, inval_lines) =
-                    Self::compute_paste_edit(buffer, &selection, &data.content, data.mode);
+                let (text, delta, inval_lines) = Self::compute_paste_edit(
+                    buffer,
+                    &selection,
+                    &data.content,
+                    &data.fragments,
+                    data.mode,
+                );
                 let selection = selection.apply_delta(&delta, after, InsertDrift::Default);
                 deltas.push((text, delta, inval_lines));
                 if !after {
@@ -612,8 +671,13 @@ impl Action {
                         (selection, data)
                     }
                 };
-                let (text, delta, inval_lines) =
-                    Self::compute_paste_edit(buffer, &selection, &content, data.mode);
+                let (text, delta, inval_lines) = Self::compute_paste_edit(
+                    buffer,
+                    &selection,
+                    &content,
+                    &data.fragments,
+                    data.mode,
+                );
                 let selection =
                     selection.apply_delta(&delta, cursor.is_insert(), InsertDrift::Default);
                 deltas.push((text, delta, inval_lines));
@@ -745,6 +809,199 @@ impl Action {
         vec![(text, delta, inval_lines)]
     }
 
+    /// Apply `transform` to the lines covered by the cursor's selection, or to every line of the
+    /// buffer when nothing is selected.
+    ///
+    /// Every selected block of lines is transformed on its own, and only the lines that actually
+    /// changed are edited so that cursors and marks on the other lines are kept.
+    fn transform_lines(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        transform: LineTransform,
+    ) -> Vec<(Rope, RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => Selection::new(),
+            CursorMode::Insert(selection) => {
+                let mut regions = Selection::new();
+                for region in selection.regions().iter().filter(|r| !r.is_caret()) {
+                    regions.add_region(*region);
+                }
+                regions
+            }
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+        };
+
+        let mut blocks: Vec<Range<usize>> = Vec::new();
+        if selection.is_empty() {
+            blocks.push(0..buffer.num_lines());
+        }
+        for region in selection.regions() {
+            let start_line = buffer.line_of_offset(region.min());
+            let mut end_line = buffer.line_of_offset(region.max());
+            if end_line > start_line && region.max() == buffer.offset_of_line(end_line) {
+                end_line -= 1;
+            }
+            match blocks.last_mut() {
+                Some(last) if start_line < last.end => last.end = last.end.max(end_line + 1),
+                _ => blocks.push(start_line..end_line + 1),
+            }
+        }
+
+        let line_ending = buffer.line_ending().get_chars();
+        let mut edits = Vec::new();
+        for mut block in blocks {
+            // The empty line after a final line ending is not part of any block
+            if block.len() > 1
+                && block.end == buffer.num_lines()
+                && buffer.line_len(block.end - 1) == 0
+            {
+                block.end -= 1;
+            }
+            let lines: Vec<String> = block
+                .clone()
+                .map(|line| {
+                    let content = buffer.line_content(line);
+                    content.trim_end_matches(['\r', '\n']).to_string()
+                })
+                .collect();
+            let new_lines = transform.apply(&lines);
+            edits.extend(minimal_line_edits(buffer, block, &new_lines, line_ending));
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let edits: Vec<(Selection, &str)> = edits
+            .iter()
+            .map(|(range, content)| (Selection::region(range.start, range.end), content.as_str()))
+            .collect();
+        let (text, delta, inval_lines) = buffer.edit(&edits, EditType::TransformLines);
+        if let CursorMode::Insert(selection) = &cursor.mode {
+            let selection = selection.apply_delta(&delta, true, InsertDrift::Inside);
+            cursor.mode = CursorMode::Insert(selection);
+            cursor.horiz = None;
+        } else {
+            cursor.apply_delta(&delta);
+        }
+
+        vec![(text, delta, inval_lines)]
+    }
+
+    /// Apply `transform` to the text of every region of the cursor's selection.
+    ///
+    /// In insert mode, a caret changes the case of the word it is in and stays at the same
+    /// position within that word, while non-empty regions keep covering their transformed text.
+    /// In normal mode the character under the cursor is changed and the cursor moves right, like
+    /// vim's `~`, and in visual mode the cursor returns to the start of the selection.
+    ///
+    /// An [identifier style](CaseTransform::is_identifier_style) applies to whole identifiers, so
+    /// a caret converts the identifier it is in, `-` included, and in normal mode the identifier
+    /// under the cursor is converted and the cursor goes to its start.
+    fn change_case(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        transform: CaseTransform,
+    ) -> Vec<(Rope, RopeDelta, InvalLines)> {
+        let identifier_style = transform.is_identifier_style();
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => {
+                let mut words = Selection::new();
+                for region in selection.regions() {
+                    if region.is_caret() {
+                        let (start, end) = if identifier_style {
+                            let range = identifier_range(buffer.text(), region.start);
+                            (range.start, range.end)
+                        } else {
+                            buffer.select_word(region.start)
+                        };
+                        words.add_region(SelRegion::new(start, end, None));
+                    } else {
+                        words.add_region(*region);
+                    }
+                }
+                words
+            }
+            CursorMode::Normal(offset) if identifier_style => {
+                let range = identifier_range(buffer.text(), *offset);
+                Selection::region(range.start, range.end)
+            }
+            _ => cursor.edit_selection(buffer),
+        };
+
+        let mut edits = Vec::new();
+        for region in selection.regions() {
+            let content = buffer.slice_to_cow(region.min()..region.max());
+            let replacement = transform.apply(&content);
+            if replacement != content {
+                edits.push((
+                    Selection::region(region.min(), region.max()),
+                    content.into_owned(),
+                    replacement,
+                ));
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let (text, delta, inval_lines) = buffer.edit(
+            edits
+                .iter()
+                .map(|(selection, _, replacement)| (selection, replacement.as_str())),
+            EditType::ChangeCase,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        match cursor.mode.clone() {
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let new_region = if region.is_caret() {
+                        let edit = edits.iter().find(|(s, _, _)| {
+                            s.min_offset() <= region.start && region.start <= s.max_offset()
+                        });
+                        let offset = match edit {
+                            Some((s, content, replacement)) => {
+                                transformer.transform(s.min_offset(), false)
+                                    + offset_in_replacement(
+                                        content,
+                                        replacement,
+                                        region.start - s.min_offset(),
+                                    )
+                            }
+                            None => transformer.transform(region.start, true),
+                        };
+                        SelRegion::caret(offset)
+                    } else {
+                        let forward = region.start < region.end;
+                        SelRegion::new(
+                            transformer.transform(region.start, !forward),
+                            transformer.transform(region.end, forward),
+                            None,
+                        )
+                    };
+                    new_selection.add_region(new_region);
+                }
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+            CursorMode::Normal(_) if identifier_style => {
+                let offset = transformer.transform(selection.min_offset(), false);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Normal(offset) => {
+                let offset = transformer.transform(offset, false);
+                cursor.mode = CursorMode::Normal(buffer.move_right(offset, Mode::Normal, 1));
+            }
+            CursorMode::Visual { start, end, .. } => {
+                let offset = transformer.transform(start.min(end), false);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+        }
+        cursor.horiz = None;
+
+        vec![(text, delta, inval_lines)]
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn do_edit<T: Clipboard>(
         cursor: &mut Cursor,
@@ -758,6 +1015,9 @@ impl Action {
             smart_tab,
             keep_indent,
             auto_indent,
+            backspace_deletes_diacritic,
+            bidi_movement: _,
+            tab_width,
         }: EditConf,
     ) -> Vec<(Rope, RopeDelta, InvalLines)> {
         use crate::command::EditCommand::*;
@@ -979,7 +1239,7 @@ impl Action {
             }
             ClipboardCopy => {
                 let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+                clipboard.put_register(&data);
 
                 match &cursor.mode {
                     CursorMode::Visual {
@@ -997,7 +1257,7 @@ impl Action {
             }
             ClipboardCut => {
                 let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+                clipboard.put_register(&data);
 
                 let selection = if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
                     for region in selection.regions_mut() {
@@ -1020,13 +1280,7 @@ impl Action {
                 vec![(text, delta, inval_lines)]
             }
             ClipboardPaste => {
-                if let Some(s) = clipboard.get_string() {
-                    let mode = if s.ends_with('\n') {
-                        VisualMode::Linewise
-                    } else {
-                        VisualMode::Normal
-                    };
-                    let data = RegisterData { content: s, mode };
+                if let Some(data) = clipboard.get_register() {
                     Self::do_paste(cursor, buffer, &data)
                 } else {
                     vec![]
@@ -1109,6 +1363,12 @@ impl Action {
                             let new_region = if region.is_caret() {
                                 if indent.starts_with('\t') {
                                     let new_end = buffer.move_left(region.end, Mode::Insert, 1);
+                                    let new_end = if backspace_deletes_diacritic {
+                                        last_diacritic_start(buffer, new_end..region.end)
+                                            .unwrap_or(new_end)
+                                    } else {
+                                        new_end
+                                    };
                                     SelRegion::new(region.start, new_end, None)
                                 } else {
                                     let line = buffer.line_of_offset(region.start);
@@ -1125,6 +1385,12 @@ impl Action {
                                         1
                                     };
                                     let new_end = buffer.move_left(region.end, Mode::Insert, count);
+                                    let new_end = if backspace_deletes_diacritic {
+                                        last_diacritic_start(buffer, new_end..region.end)
+                                            .unwrap_or(new_end)
+                                    } else {
+                                        new_end
+                                    };
                                     SelRegion::new(region.start, new_end, None)
                                 }
                             } else {
@@ -1473,6 +1739,59 @@ impl Action {
 
                 vec![(text, delta, inval)]
             }
+            UpperCase => Self::change_case(cursor, buffer, CaseTransform::Upper),
+            LowerCase => Self::change_case(cursor, buffer, CaseTransform::Lower),
+            ToggleCase => Self::change_case(cursor, buffer, CaseTransform::Toggle),
+            TitleCase => Self::change_case(cursor, buffer, CaseTransform::Title),
+            SnakeCase => Self::change_case(cursor, buffer, CaseTransform::Snake),
+            CamelCase => Self::change_case(cursor, buffer, CaseTransform::Camel),
+            PascalCase => Self::change_case(cursor, buffer, CaseTransform::Pascal),
+            KebabCase => Self::change_case(cursor, buffer, CaseTransform::Kebab),
+            ScreamingSnakeCase => Self::change_case(cursor, buffer, CaseTransform::ScreamingSnake),
+            SortLines => {
+                Self::transform_lines(cursor, buffer, LineTransform::Sort(SortOrder::Lexical))
+            }
+            SortLinesNatural => {
+                Self::transform_lines(cursor, buffer, LineTransform::Sort(SortOrder::Natural))
+            }
+            SortLinesCaseInsensitive => Self::transform_lines(
+                cursor,
+                buffer,
+                LineTransform::Sort(SortOrder::CaseInsensitive),
+            ),
+            SortLinesReverse => {
+                Self::transform_lines(cursor, buffer, LineTransform::Sort(SortOrder::Reverse))
+            }
+            UniqueLines => Self::transform_lines(cursor, buffer, LineTransform::Unique),
+            ReverseLines => Self::transform_lines(cursor, buffer, LineTransform::Reverse),
+            ShuffleLines => {
+                let seed = RandomState::new().hash_one(buffer.rev());
+                Self::transform_lines(cursor, buffer, LineTransform::Shuffle { seed })
+            }
+            TrimTrailingWhitespace => {
+                Self::transform_lines(cursor, buffer, LineTransform::TrimTrailingWhitespace)
+            }
+            AlignOnEquals => {
+                let align = LineTransform::Align {
+                    delimiter: AlignDelimiter::Equals,
+                    tab_width,
+                };
+                Self::transform_lines(cursor, buffer, align)
+            }
+            AlignOnColon => {
+                let align = LineTransform::Align {
+                    delimiter: AlignDelimiter::Colon,
+                    tab_width,
+                };
+                Self::transform_lines(cursor, buffer, align)
+            }
+            AlignOnComma => {
+                let align = LineTransform::Align {
+                    delimiter: AlignDelimiter::Comma,
+                    tab_width,
+                };
+                Self::transform_lines(cursor, buffer, align)
+            }
         }
     }
 }
@@ -1509,6 +1828,72 @@ fn apply_undo_redo(
     vec![(text, delta, inval_lines)]
 }
 
+/// Compute the edits that turn `lines` of `text` into `new_lines`, touching only the
+/// lines that differ according to [`minimal_edits`].
+///
+/// `new_lines` are line contents without line endings, and are terminated with `line_ending`,
+/// except for the last one when the last of `lines` is not terminated either.
+fn minimal_line_edits(
+    text: &impl RopeText,
+    lines: Range<usize>,
+    new_lines: &[String],
+    line_ending: &str,
+) -> Vec<(Range<usize>, String)> {
+    let start = text.offset_of_line(lines.start);
+    let old_text = text.text().slice(start..text.offset_of_line(lines.end));
+    let terminated = !old_text.is_empty() && old_text.byte_at(old_text.len() - 1) == b'\n';
+    let new_text = Rope::from(
+        new_lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let mut line = line.clone();
+                if i + 1 < new_lines.len() || terminated {
+                    line.push_str(line_ending);
+                }
+                line
+            })
+            .collect::<String>(),
+    );
+    minimal_edits(&old_text, &new_text)
+        .into_iter()
+        .map(|(range, content)| (start + range.start..start + range.end, content))
+        .collect()
+}
+
+/// Map the offset `offset` in `content` to the equivalent offset in `replacement`, the result of
+/// changing the case of `content`, by keeping the same number of alphanumeric characters before it.
+fn offset_in_replacement(content: &str, replacement: &str, offset: usize) -> usize {
+    if offset >= content.len() {
+        return replacement.len();
+    }
+    let count = content[..offset]
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .count();
+    if count == 0 {
+        return 0;
+    }
+    replacement
+        .char_indices()
+        .filter(|(_, c)| c.is_alphanumeric())
+        .nth(count - 1)
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(replacement.len())
+}
+
+/// If the grapheme cluster in `cluster` is a letter followed by Arabic diacritics, get the start
+/// of its last diacritic.
+fn last_diacritic_start(buffer: &Buffer, cluster: Range<usize>) -> Option<usize> {
+    let content = buffer.slice_to_cow(cluster.clone());
+    let mut chars = content.chars();
+    let last = chars.next_back()?;
+    if chars.as_str().is_empty() || !char_is_arabic_diacritic(last) {
+        return None;
+    }
+    Some(cluster.end - last.len_utf8())
+}
+
 enum DuplicateDirection {
     Up,
     Down,
@@ -1517,9 +1902,16 @@ enum DuplicateDirection {
 #[cfg(test)]
 mod test {
     use crate::{
+        bidi::BidiMovement,
         buffer::{rope_text::RopeText, Buffer},
+        case::CaseTransform,
+        command::EditCommand,
         cursor::{Cursor, CursorMode},
-        editor::{Action, DuplicateDirection},
+        editor::{Action, DuplicateDirection, EditConf},
+        line_transform::{AlignDelimiter, LineTransform, SortOrder},
+        mode::{Mode, MotionMode},
+        movement::{move_offset, Movement},
+        register::{Clipboard, MemoryClipboard, Register, RegisterData},
         selection::{SelRegion, Selection},
         word::WordCursor,
     };
@@ -1528,6 +1920,55 @@ mod test {
         WordCursor::new(buffer.text(), offset).previous_unmatched(c)
     }
 
+    fn edit(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        cmd: EditCommand,
+        backspace_deletes_diacritic: bool,
+    ) {
+        edit_with_clipboard(
+            cursor,
+            buffer,
+            cmd,
+            &mut MemoryClipboard::default(),
+            backspace_deletes_diacritic,
+        );
+    }
+
+    fn edit_with_clipboard(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        cmd: EditCommand,
+        clipboard: &mut impl Clipboard,
+        backspace_deletes_diacritic: bool,
+    ) {
+        Action::do_edit(
+            cursor,
+            buffer,
+            &cmd,
+            clipboard,
+            &mut Register::default(),
+            EditConf {
+                comment_token: "//",
+                modal: false,
+                smart_tab: true,
+                keep_indent: true,
+                auto_indent: false,
+                backspace_deletes_diacritic,
+                bidi_movement: BidiMovement::Logical,
+                tab_width: 4,
+            },
+        );
+    }
+
+    fn carets(offsets: &[usize]) -> Cursor {
+        let mut selection = Selection::new();
+        for &offset in offsets {
+            selection.add_region(SelRegion::caret(offset));
+        }
+        Cursor::new(CursorMode::Insert(selection), None, None)
+    }
+
     #[test]
     fn test_insert_simple() {
         let mut buffer = Buffer::new("abc");
@@ -1759,5 +2200,364 @@ mod test {
         assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
     }
 
+    #[test]
+    fn change_case_keeps_multiple_regions() {
+        let mut buffer = Buffer::new("straße fooBar\nbazQux");
+        let mut selection = Selection::new();
+        // `straße`, selected backwards
+        selection.add_region(SelRegion::new(7, 0, None));
+        // `fooBar`
+        selection.add_region(SelRegion::new(8, 14, None));
+        // `bazQux`
+        selection.add_region(SelRegion::new(15, 21, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Action::change_case(&mut cursor, &mut buffer, CaseTransform::ScreamingSnake);
+
+        assert_eq!(
+            "STRASSE FOO_BAR\nBAZ_QUX",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::new(7, 0, None));
+        end_selection.add_region(SelRegion::new(8, 15, None));
+        end_selection.add_region(SelRegion::new(16, 23, None));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
+    #[test]
+    fn change_case_of_words_under_carets() {
+        let mut buffer = Buffer::new("let fooBar = someValue;");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::caret(17));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Action::change_case(&mut cursor, &mut buffer, CaseTransform::Snake);
+
+        assert_eq!(
+            "let foo_bar = some_value;",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::caret(7));
+        end_selection.add_region(SelRegion::caret(18));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
+    #[test]
+    fn change_case_normal_mode_moves_right() {
+        let mut buffer = Buffer::new("abc");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        Action::change_case(&mut cursor, &mut buffer, CaseTransform::Toggle);
+
+        assert_eq!("Abc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Normal(1));
+    }
+
+    #[test]
+    fn identifier_styles_convert_whole_identifiers() {
+        // A caret converts the identifier it is in, not only the word `value`
+        let mut buffer = Buffer::new("let some-value = 1;");
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(11)), None, None);
+        Action::change_case(&mut cursor, &mut buffer, CaseTransform::Camel);
+        assert_eq!("let someValue = 1;", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Insert(Selection::caret(10)));
+
+        // In normal mode the identifier under the cursor is converted, not one character
+        let mut buffer = Buffer::new("x = fooBar;");
+        let mut cursor = Cursor::new(CursorMode::Normal(7), None, None);
+        Action::change_case(&mut cursor, &mut buffer, CaseTransform::Snake);
+        assert_eq!("x = foo_bar;", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(cursor.mode, CursorMode::Normal(4));
+    }
+
+    #[test]
+    fn change_case_motion() {
+        let mut buffer = Buffer::new("hello world\nnext line\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(8), None, None);
+        let mut register = Register::default();
+
+        Action::execute_motion_mode(
+            &mut cursor,
+            &mut buffer,
+            MotionMode::ChangeCase(CaseTransform::Upper),
+            0..8,
+            true,
+            &mut register,
+        );
+
+        assert_eq!(
+            "HELLO WORLD\nnext line\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(cursor.mode, CursorMode::Normal(0));
+    }
+
+    #[test]
+    fn transform_lines_of_whole_buffer() {
+        let mut buffer = Buffer::new("c\nb\na\n");
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+
+        Action::transform_lines(
+            &mut cursor,
+            &mut buffer,
+            LineTransform::Sort(SortOrder::Lexical),
+        );
+        assert_eq!("a\nb\nc\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("b\na\nb");
+        Action::transform_lines(&mut cursor, &mut buffer, LineTransform::Unique);
+        assert_eq!("b\na", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn transform_lines_only_edits_changed_lines() {
+        let mut buffer = Buffer::new("first\nb\na\nlast  \n");
+        let mut selection = Selection::new();
+        // `b` and `a`
+        selection.add_region(SelRegion::new(6, 10, None));
+        // A caret on the last line
+        selection.add_region(SelRegion::caret(14));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        let deltas = Action::transform_lines(
+            &mut cursor,
+            &mut buffer,
+            LineTransform::Sort(SortOrder::Lexical),
+        );
+
+        assert_eq!(
+            "first\na\nb\nlast  \n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let (_, delta, _) = &deltas[0];
+        let (interval, _) = delta.summary();
+        assert!(interval.start() >= 6 && interval.end() <= 10);
+        let mut end_selection = Selection::new();
+        end_selection.add_region(SelRegion::new(6, 10, None));
+        end_selection.add_region(SelRegion::caret(14));
+        assert_eq!(cursor.mode, CursorMode::Insert(end_selection));
+    }
+
+    #[test]
+    fn transform_lines_keeps_missing_final_line_ending() {
+        let mut buffer = Buffer::new("b = 1\nlong = 2");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        Action::transform_lines(&mut cursor, &mut buffer, LineTransform::Reverse);
+        assert_eq!("long = 2\nb = 1", buffer.slice_to_cow(0..buffer.len()));
+
+        Action::transform_lines(
+            &mut cursor,
+            &mut buffer,
+            LineTransform::Align {
+                delimiter: AlignDelimiter::Equals,
+                tab_width: 4,
+            },
+        );
+        assert_eq!("long = 2\nb    = 1", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn delete_whole_grapheme_clusters() {
+        // A family emoji joined with ZWJ, a flag, a Hangul syllable made of jamo, and an Arabic
+        // letter with a kasra
+        let text = "a👨\u{200d}👩\u{200d}👧🇸🇦\u{1100}\u{1161}\u{11A8}بِb";
+        let clusters = [
+            "a",
+            "👨\u{200d}👩\u{200d}👧",
+            "🇸🇦",
+            "\u{1100}\u{1161}\u{11A8}",
+            "بِ",
+            "b",
+        ];
+
+        let mut buffer = Buffer::new(text);
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(text.len())), None, None);
+        for i in (0..clusters.len()).rev() {
+            assert_eq!(
+                clusters[..=i].concat(),
+                buffer.slice_to_cow(0..buffer.len())
+            );
+            edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, false);
+        }
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new(text);
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        for i in 0..clusters.len() {
+            assert_eq!(clusters[i..].concat(), buffer.slice_to_cow(0..buffer.len()));
+            edit(&mut cursor, &mut buffer, EditCommand::DeleteForward, false);
+        }
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn backspace_deletes_last_diacritic() {
+        // Beh with a shadda and a fatha
+        let text = "ب\u{651}\u{64e}";
+
+        let mut buffer = Buffer::new(text);
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(text.len())), None, None);
+        edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, true);
+        assert_eq!("ب\u{651}", buffer.slice_to_cow(0..buffer.len()));
+        edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, true);
+        assert_eq!("ب", buffer.slice_to_cow(0..buffer.len()));
+        edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, true);
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+
+        // Other clusters are still deleted whole
+        let mut buffer = Buffer::new("e\u{301}");
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, true);
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+
+        // Without the option the whole cluster goes
+        let mut buffer = Buffer::new(text);
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(text.len())), None, None);
+        edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, false);
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn visual_arrows_on_rtl_text() {
+        // The start of a right-to-left line is at its right edge, so left moves into the line
+        let text = "سلام";
+        for (bidi_movement, expected) in
+            [(BidiMovement::Logical, text), (BidiMovement::Visual, "لام")]
+        {
+            let mut buffer = Buffer::new(text);
+            let mut cursor = carets(&[0]);
+            let (offset, _) = move_offset(
+                &buffer,
+                cursor.offset(),
+                None,
+                &Movement::Left,
+                1,
+                Mode::Insert,
+                bidi_movement,
+                &mut cursor.affinity,
+            );
+            cursor.mode = CursorMode::Insert(Selection::caret(offset));
+            edit(&mut cursor, &mut buffer, EditCommand::DeleteBackward, false);
+            assert_eq!(expected, buffer.slice_to_cow(0..buffer.len()));
+        }
+    }
+
+    #[test]
+    fn paste_one_region_per_cursor() {
+        let mut clipboard = MemoryClipboard::default();
+        let mut buffer = Buffer::new("one\ntwo\nthree\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(4, 11, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardCopy,
+            &mut clipboard,
+            false,
+        );
+        // Other applications get the regions on their own lines
+        assert_eq!(clipboard.content.as_deref(), Some("one\ntwo\nthr"));
+
+        // The second region has two lines, so the text can't be split evenly by lines
+        let mut buffer = Buffer::new("a b\n");
+        let mut cursor = carets(&[1, 3]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardPaste,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("aone btwo\nthr\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            cursor.regions_iter().collect::<Vec<_>>(),
+            [(4, 4), (13, 13)]
+        );
+
+        // With another number of cursors, the lines are spread as for text from elsewhere
+        let mut buffer = Buffer::new("ab");
+        let mut cursor = carets(&[0, 1, 2]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardPaste,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("oneatwobthr", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn cut_lines_of_carets() {
+        let mut clipboard = MemoryClipboard::default();
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut cursor = carets(&[0, 4]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardCut,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("b\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("x\ny\n");
+        let mut cursor = carets(&[1, 3]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardPaste,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("a\nx\nc\ny\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn paste_spreads_lines_over_cursors() {
+        // Copied from another application: one line per cursor
+        let mut clipboard = MemoryClipboard {
+            content: Some("x\ny".to_string()),
+            regions: None,
+        };
+        let mut buffer = Buffer::new("ab\ncd\n");
+        let mut cursor = carets(&[1, 4]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardPaste,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("axb\ncyd\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn stale_regions_are_ignored() {
+        // The plain text was replaced since the regions were copied
+        let regions =
+            RegisterData::from_fragments(vec!["a".into(), "b".into()], Default::default());
+        let mut clipboard = MemoryClipboard {
+            content: Some("p\nq\nr".to_string()),
+            regions: Some(regions.encode_fragments()),
+        };
+        let mut buffer = Buffer::new("-");
+        let mut cursor = carets(&[0, 1]);
+        edit_with_clipboard(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::ClipboardPaste,
+            &mut clipboard,
+            false,
+        );
+        assert_eq!("p\nq\nr-p\nq\nr", buffer.slice_to_cow(0..buffer.len()));
+    }
+
     // TODO(dbuga): add tests duplicating selections (multiple line blocks)
 }
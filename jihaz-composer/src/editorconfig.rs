@@ -0,0 +1,686 @@
+//! Support for [EditorConfig](https://editorconfig.org) files, which set the indentation, line
+//! endings and other properties of the files of a project.
+//!
+//! The properties of a file come from the `.editorconfig` files of its directory and of the
+//! directories above it, up to one that declares `root = true`. Files closer to the file take
+//! precedence, as do later sections within a file.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use lapce_xi_rope::{Rope, RopeDelta};
+
+use crate::{
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    editor::EditType,
+    indent::IndentStyle,
+    line_ending::LineEnding,
+    selection::Selection,
+};
+
+pub const EDITORCONFIG_FILE_NAME: &str = ".editorconfig";
+
+/// A parsed `.editorconfig` file.
+#[derive(Clone, Debug, Default)]
+pub struct EditorConfig {
+    /// Whether the search for more files stops at this one.
+    pub root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Clone, Debug)]
+struct Section {
+    glob: Vec<Token>,
+    properties: Vec<(String, String)>,
+}
+
+impl EditorConfig {
+    /// Parse the content of a `.editorconfig` file. Lines that can't be parsed are ignored, as
+    /// other EditorConfig implementations do.
+    pub fn parse(content: &str) -> Self {
+        let mut config = EditorConfig::default();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                config.sections.push(Section {
+                    glob: compile_section_glob(glob),
+                    properties: Vec::new(),
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+            match config.sections.last_mut() {
+                Some(section) => section.properties.push((key, value.to_string())),
+                // Only `root` is allowed before the first section
+                None if key == "root" => config.root = value.eq_ignore_ascii_case("true"),
+                None => {}
+            }
+        }
+        config
+    }
+
+    /// Add the properties of the sections matching `path` to `properties`. `path` is relative to
+    /// the directory of this file and uses `/` as its separator.
+    fn collect(&self, path: &str, properties: &mut HashMap<String, String>) {
+        for section in &self.sections {
+            if glob_matches(&section.glob, &path.chars().collect::<Vec<_>>()) {
+                for (key, value) in &section.properties {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    /// Resolve the properties of the file at `path`, reading the `.editorconfig` files of its
+    /// directories with `read`, which returns `None` for those that don't exist.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use std::path::Path;
+    /// # use jihaz_composer::editorconfig::{EditorConfig, EndOfLine};
+    /// let read = |path: &Path| {
+    ///     (path == Path::new("/project/.editorconfig"))
+    ///         .then(|| "root = true\n[*.rs]\nindent_size = 2\nend_of_line = lf".to_string())
+    /// };
+    /// let properties = EditorConfig::resolve(Path::new("/project/src/main.rs"), read);
+    /// assert_eq!(properties.indent_size, Some(2));
+    /// assert_eq!(properties.tab_width, Some(2));
+    /// assert_eq!(properties.end_of_line, Some(EndOfLine::Lf));
+    /// ```
+    pub fn resolve(
+        path: &Path,
+        mut read: impl FnMut(&Path) -> Option<String>,
+    ) -> EditorConfigProperties {
+        let mut configs: Vec<(PathBuf, EditorConfig)> = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let Some(content) = read(&dir.join(EDITORCONFIG_FILE_NAME)) else {
+                continue;
+            };
+            let config = EditorConfig::parse(&content);
+            let root = config.root;
+            configs.push((dir.to_path_buf(), config));
+            if root {
+                break;
+            }
+        }
+
+        let mut properties = HashMap::new();
+        for (dir, config) in configs.iter().rev() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            config.collect(&relative.join("/"), &mut properties);
+        }
+        EditorConfigProperties::from_raw(&properties)
+    }
+
+    /// Resolve the properties of the file at `path` from the `.editorconfig` files on disk.
+    pub fn resolve_from_fs(path: &Path) -> EditorConfigProperties {
+        Self::resolve(path, |path| std::fs::read_to_string(path).ok())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentKind {
+    Tab,
+    Space,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndOfLine {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    Latin1,
+    Utf8,
+    Utf8Bom,
+    Utf16Be,
+    Utf16Le,
+}
+
+/// The resolved properties of a file. A property is `None` when no file sets it, when it is set
+/// to `unset`, or when its value isn't valid.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EditorConfigProperties {
+    pub indent_style: Option<IndentKind>,
+    /// The number of columns of an indentation level. Set to `tab` it is the tab width.
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<EndOfLine>,
+    pub charset: Option<Charset>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+    /// Set to `off` it is `None`.
+    pub max_line_length: Option<usize>,
+}
+
+impl EditorConfigProperties {
+    fn from_raw(raw: &HashMap<String, String>) -> Self {
+        let value = |key: &str| {
+            raw.get(key)
+                .map(|value| value.to_lowercase())
+                .filter(|value| value != "unset")
+        };
+        let number = |key: &str| value(key)?.parse::<usize>().ok().filter(|n| *n > 0);
+        let boolean = |key: &str| match value(key)?.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+
+        let indent_style = match value("indent_style").as_deref() {
+            Some("tab") => Some(IndentKind::Tab),
+            Some("space") => Some(IndentKind::Space),
+            _ => None,
+        };
+        let indent_size_is_tab = value("indent_size").as_deref() == Some("tab")
+            || (indent_style == Some(IndentKind::Tab) && value("indent_size").is_none());
+        let mut tab_width = number("tab_width");
+        let mut indent_size = number("indent_size");
+        if indent_size_is_tab {
+            indent_size = tab_width;
+        } else if tab_width.is_none() {
+            tab_width = indent_size;
+        }
+
+        Self {
+            indent_style,
+            indent_size,
+            tab_width,
+            end_of_line: match value("end_of_line").as_deref() {
+                Some("lf") => Some(EndOfLine::Lf),
+                Some("crlf") => Some(EndOfLine::CrLf),
+                Some("cr") => Some(EndOfLine::Cr),
+                _ => None,
+            },
+            charset: match value("charset").as_deref() {
+                Some("latin1") => Some(Charset::Latin1),
+                Some("utf-8") => Some(Charset::Utf8),
+                Some("utf-8-bom") => Some(Charset::Utf8Bom),
+                Some("utf-16be") => Some(Charset::Utf16Be),
+                Some("utf-16le") => Some(Charset::Utf16Le),
+                _ => None,
+            },
+            trim_trailing_whitespace: boolean("trim_trailing_whitespace"),
+            insert_final_newline: boolean("insert_final_newline"),
+            max_line_length: number("max_line_length"),
+        }
+    }
+
+    /// The indentation these properties ask for. Spaces need `indent_size`, up to 8 of them.
+    pub fn indent_style(&self) -> Option<IndentStyle> {
+        match self.indent_style? {
+            IndentKind::Tab => Some(IndentStyle::Tabs),
+            IndentKind::Space => {
+                let size = self.indent_size?.min(IndentStyle::LONGEST_INDENT.len());
+                Some(IndentStyle::Spaces(size as u8))
+            }
+        }
+    }
+
+    /// The line ending these properties ask for. A buffer can't use `cr` line endings, which
+    /// give `None`.
+    pub fn line_ending(&self) -> Option<LineEnding> {
+        match self.end_of_line? {
+            EndOfLine::Lf => Some(LineEnding::Lf),
+            EndOfLine::CrLf => Some(LineEnding::CrLf),
+            EndOfLine::Cr => None,
+        }
+    }
+
+    /// Set the indentation and the line ending of `buffer`, in place of what was detected from
+    /// its content. The line endings already in the buffer are converted when it is saved.
+    pub fn apply(&self, buffer: &mut Buffer) {
+        if let Some(indent_style) = self.indent_style() {
+            buffer.set_indent_style(indent_style);
+        }
+        if let Some(line_ending) = self.line_ending() {
+            buffer.set_line_ending(line_ending);
+        }
+    }
+
+    /// Edit `buffer` before it is saved: trim trailing whitespace, add or remove the final
+    /// newline and convert the line endings, as the properties ask. The changes are a single edit
+    /// so that they are undone together. Returns `None` if nothing needed to change.
+    pub fn on_save(&self, buffer: &mut Buffer) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let trim = self.trim_trailing_whitespace == Some(true);
+        let convert = self.line_ending();
+        if let Some(line_ending) = convert {
+            buffer.set_line_ending(line_ending);
+        }
+        let line_ending = buffer.line_ending().get_chars();
+
+        let mut edits: Vec<(Selection, &str)> = Vec::new();
+        let mut offset = 0;
+        for line in buffer.text().lines_raw(..) {
+            let content = line.trim_end_matches(['\r', '\n']);
+            let ending = &line[content.len()..];
+            let start = if trim {
+                content.trim_end().len()
+            } else {
+                content.len()
+            };
+            let convert = convert.is_some() && !ending.is_empty() && ending != line_ending;
+            if start < content.len() || convert {
+                let end = if convert { line.len() } else { content.len() };
+                let replacement = if convert { line_ending } else { "" };
+                edits.push((Selection::region(offset + start, offset + end), replacement));
+            }
+            offset += line.len();
+        }
+
+        let len = buffer.len();
+        let last = buffer.slice_to_cow(len.saturating_sub(2)..len);
+        let final_ending = if last.ends_with("\r\n") {
+            2
+        } else if last.ends_with('\n') {
+            1
+        } else {
+            0
+        };
+        match self.insert_final_newline {
+            Some(true) if len > 0 && final_ending == 0 => {
+                edits.push((Selection::caret(len), line_ending));
+            }
+            Some(false) if final_ending > 0 => {
+                // Only the final line ending goes, not the blank lines before it. It may already
+                // be converted, so that edit is replaced by its removal.
+                let start = len - final_ending;
+                edits.retain(|(selection, _)| selection.min_offset() < start);
+                edits.push((Selection::region(start, len), ""));
+            }
+            _ => {}
+        }
+
+        if edits.is_empty() {
+            return None;
+        }
+        Some(buffer.edit(&edits, EditType::OnSave))
+    }
+}
+
+/// A part of a glob.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`, any character but `/`.
+    AnyChar,
+    /// `*`, any characters but `/`.
+    Any,
+    /// `**`, any characters. In `**/` it also matches no directory at all.
+    AnyPath,
+    /// `[abc]`, `[a-z]` or `[!abc]`, a character of a set or not in it, but `/`.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    /// `{a,b}`, any of the alternatives.
+    Alternatives(Vec<Vec<Token>>),
+    /// `{1..10}`, an integer within the range.
+    Number(i64, i64),
+}
+
+/// Compile the glob of a section, which matches in any directory unless it contains a `/`.
+fn compile_section_glob(glob: &str) -> Vec<Token> {
+    let glob = if let Some(glob) = glob.strip_prefix('/') {
+        glob.to_string()
+    } else if glob.contains('/') {
+        glob.to_string()
+    } else {
+        format!("**/{glob}")
+    };
+    let chars: Vec<char> = glob.chars().collect();
+    let mut pos = 0;
+    compile(&chars, &mut pos, false)
+}
+
+/// Compile `chars` from `pos`, up to the end or, within braces, up to a `,` or `}`.
+fn compile(chars: &[char], pos: &mut usize, in_braces: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    while let Some(&c) = chars.get(*pos) {
+        match c {
+            ',' | '}' if in_braces => break,
+            '\\' if *pos + 1 < chars.len() => {
+                tokens.push(Token::Char(chars[*pos + 1]));
+                *pos += 2;
+            }
+            '?' => {
+                tokens.push(Token::AnyChar);
+                *pos += 1;
+            }
+            '*' if chars.get(*pos + 1) == Some(&'*') => {
+                tokens.push(Token::AnyPath);
+                *pos += 2;
+            }
+            '*' => {
+                tokens.push(Token::Any);
+                *pos += 1;
+            }
+            '[' => match compile_class(chars, *pos) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    *pos = end;
+                }
+                None => {
+                    tokens.push(Token::Char('['));
+                    *pos += 1;
+                }
+            },
+            '{' => match compile_braces(chars, *pos) {
+                Some((mut braces, end)) => {
+                    tokens.append(&mut braces);
+                    *pos = end;
+                }
+                None => {
+                    tokens.push(Token::Char('{'));
+                    *pos += 1;
+                }
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                *pos += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Compile the class starting at the `[` at `start`, returning it with the position after it.
+fn compile_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut pos = start + 1;
+    let negated = chars.get(pos) == Some(&'!');
+    if negated {
+        pos += 1;
+    }
+    let mut ranges = Vec::new();
+    while let Some(&c) = chars.get(pos) {
+        if c == ']' && !ranges.is_empty() {
+            return Some((Token::Class { negated, ranges }, pos + 1));
+        }
+        if c == '/' {
+            return None;
+        }
+        let c = if c == '\\' {
+            pos += 1;
+            *chars.get(pos)?
+        } else {
+            c
+        };
+        match (chars.get(pos + 1), chars.get(pos + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((c, end));
+                pos += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                pos += 1;
+            }
+        }
+    }
+    None
+}
+
+/// Compile the braces starting at the `{` at `start`, returning them with the position after
+/// them. Braces without alternatives are literal, as in `{a}`.
+fn compile_braces(chars: &[char], start: usize) -> Option<(Vec<Token>, usize)> {
+    let close = start + chars[start..].iter().position(|c| *c == '}')?;
+    let inner: String = chars[start + 1..close].iter().collect();
+    if let Some((from, to)) = inner.split_once("..") {
+        if let (Ok(from), Ok(to)) = (from.parse::<i64>(), to.parse::<i64>()) {
+            return Some((vec![Token::Number(from.min(to), from.max(to))], close + 1));
+        }
+    }
+
+    let mut pos = start + 1;
+    let mut alternatives = Vec::new();
+    loop {
+        alternatives.push(compile(chars, &mut pos, true));
+        match chars.get(pos) {
+            Some(',') => pos += 1,
+            Some('}') => break,
+            _ => return None,
+        }
+    }
+    if alternatives.len() == 1 {
+        let mut tokens = vec![Token::Char('{')];
+        tokens.append(&mut alternatives.remove(0));
+        tokens.push(Token::Char('}'));
+        return Some((tokens, pos + 1));
+    }
+    Some((vec![Token::Alternatives(alternatives)], pos + 1))
+}
+
+fn glob_matches(tokens: &[Token], path: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+    match token {
+        Token::Char(c) => path.first() == Some(c) && glob_matches(rest, &path[1..]),
+        Token::AnyChar => path.first().is_some_and(|c| *c != '/') && glob_matches(rest, &path[1..]),
+        Token::Any => {
+            let max = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=max).any(|len| glob_matches(rest, &path[len..]))
+        }
+        Token::AnyPath => {
+            (rest.first() == Some(&Token::Char('/')) && glob_matches(&rest[1..], path))
+                || (0..=path.len()).any(|len| glob_matches(rest, &path[len..]))
+        }
+        Token::Class { negated, ranges } => match path.first() {
+            Some(c) if *c != '/' => {
+                let in_class = ranges.iter().any(|(start, end)| (start..=end).contains(&c));
+                in_class != *negated && glob_matches(rest, &path[1..])
+            }
+            _ => false,
+        },
+        Token::Alternatives(alternatives) => alternatives.iter().any(|alternative| {
+            let tokens = [alternative.as_slice(), rest].concat();
+            glob_matches(&tokens, path)
+        }),
+        Token::Number(from, to) => {
+            let sign = usize::from(path.first() == Some(&'-'));
+            let digits = path[sign..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            (1..=digits).any(|len| {
+                let number: String = path[..sign + len].iter().collect();
+                number
+                    .parse::<i64>()
+                    .is_ok_and(|n| (*from..=*to).contains(&n))
+                    && glob_matches(rest, &path[sign + len..])
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::Path};
+
+    use super::{
+        compile_section_glob, glob_matches, Charset, EditorConfig, EditorConfigProperties,
+        EndOfLine, IndentKind,
+    };
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        indent::IndentStyle,
+        line_ending::LineEnding,
+    };
+
+    fn matches(glob: &str, path: &str) -> bool {
+        glob_matches(
+            &compile_section_glob(glob),
+            &path.chars().collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn globs() {
+        assert!(matches("*", "main.rs"));
+        assert!(matches("*", "src/main.rs"));
+        assert!(matches("*.rs", "src/bin/main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("*.{js,ts}", "app/index.ts"));
+        assert!(!matches("*.{js,ts}", "app/index.rs"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(!matches("src/*.rs", "crate/src/lib.rs"));
+        assert!(matches("/src/**.rs", "src/bin/main.rs"));
+        assert!(matches("src/**/*.rs", "src/lib.rs"));
+        assert!(matches("Makefile", "sub/Makefile"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(matches("[Mm]akefile", "makefile"));
+        assert!(matches("*.[!o]", "main.c"));
+        assert!(!matches("*.[!o]", "main.o"));
+        assert!(matches("test{1..12}.txt", "test10.txt"));
+        assert!(!matches("test{1..12}.txt", "test13.txt"));
+        assert!(matches("{single}.txt", "{single}.txt"));
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+        assert!(matches("{src/{a,b},lib}/*.rs", "src/b/x.rs"));
+    }
+
+    #[test]
+    fn parse_and_cascade() {
+        let files: HashMap<&str, &str> = HashMap::from([
+            (
+                "/home/.editorconfig",
+                "[*]\nindent_style = tab\ncharset = latin1",
+            ),
+            (
+                "/home/project/.editorconfig",
+                "# Top level\nroot = true\n\n[*]\nindent_style = space\nindent_size = 4\n\
+                 trim_trailing_whitespace = true\ninsert_final_newline = true\n\
+                 ; Windows scripts\n[*.{bat,cmd}]\nend_of_line = CRLF\n\n[Makefile]\n\
+                 indent_style = tab\n",
+            ),
+            (
+                "/home/project/docs/.editorconfig",
+                "[*.md]\ntrim_trailing_whitespace = false\nmax_line_length = off\n\
+                 [*]\nindent_size = unset\ncharset = utf-8-bom\nmax_line_length = 80",
+            ),
+        ]);
+        let resolve = |path: &str| {
+            EditorConfig::resolve(Path::new(path), |path| {
+                files.get(path.to_str().unwrap()).map(|s| s.to_string())
+            })
+        };
+
+        let properties = resolve("/home/project/src/main.rs");
+        assert_eq!(
+            properties,
+            EditorConfigProperties {
+                indent_style: Some(IndentKind::Space),
+                indent_size: Some(4),
+                tab_width: Some(4),
+                trim_trailing_whitespace: Some(true),
+                insert_final_newline: Some(true),
+                // The root file stops the cascade before `/home`
+                charset: None,
+                ..Default::default()
+            }
+        );
+        assert_eq!(properties.indent_style(), Some(IndentStyle::Spaces(4)));
+
+        let properties = resolve("/home/project/scripts/build.CMD");
+        assert_eq!(properties.end_of_line, None);
+        let properties = resolve("/home/project/scripts/build.cmd");
+        assert_eq!(properties.end_of_line, Some(EndOfLine::CrLf));
+        assert_eq!(properties.line_ending(), Some(LineEnding::CrLf));
+
+        let properties = resolve("/home/project/Makefile");
+        assert_eq!(properties.indent_style(), Some(IndentStyle::Tabs));
+
+        // Closer files override, and later sections override earlier ones
+        let properties = resolve("/home/project/docs/guide.md");
+        assert_eq!(properties.trim_trailing_whitespace, Some(false));
+        assert_eq!(properties.indent_size, None);
+        assert_eq!(properties.indent_style(), None);
+        assert_eq!(properties.charset, Some(Charset::Utf8Bom));
+        assert_eq!(properties.max_line_length, Some(80));
+
+        let properties = resolve("/home/other/notes.txt");
+        assert_eq!(properties.indent_style(), Some(IndentStyle::Tabs));
+        assert_eq!(properties.charset, Some(Charset::Latin1));
+    }
+
+    #[test]
+    fn apply_to_buffer() {
+        let properties = EditorConfigProperties {
+            indent_style: Some(IndentKind::Tab),
+            end_of_line: Some(EndOfLine::CrLf),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new("fn main() {\n  body();\n}\n");
+        properties.apply(&mut buffer);
+        assert_eq!(buffer.indent_style(), IndentStyle::Tabs);
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+    }
+
+    #[test]
+    fn save_hooks() {
+        let mut properties = EditorConfigProperties {
+            trim_trailing_whitespace: Some(true),
+            insert_final_newline: Some(true),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new("a  \nb\t\n\nc ");
+        assert!(properties.on_save(&mut buffer).is_some());
+        assert_eq!(buffer.text().to_string(), "a\nb\n\nc\n");
+        assert!(properties.on_save(&mut buffer).is_none());
+        // The changes are undone together
+        buffer.do_undo();
+        assert_eq!(buffer.text().to_string(), "a  \nb\t\n\nc ");
+
+        properties.end_of_line = Some(EndOfLine::CrLf);
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a\r\nb\r\n\r\nc\r\n");
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+
+        properties.end_of_line = Some(EndOfLine::Lf);
+        properties.insert_final_newline = Some(false);
+        let mut buffer = Buffer::new("a \r\nb\r\n\r\n");
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a\nb\n");
+        assert_eq!(buffer.len(), 4);
+    }
+
+    #[test]
+    fn remove_only_the_final_newline() {
+        let properties = EditorConfigProperties {
+            insert_final_newline: Some(false),
+            ..Default::default()
+        };
+        // The trailing blank lines are kept
+        let mut buffer = Buffer::new("a\n\n\n");
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a\n\n");
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a\n");
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a");
+        assert!(properties.on_save(&mut buffer).is_none());
+
+        let mut buffer = Buffer::new("a\r\n\r\n");
+        properties.on_save(&mut buffer);
+        assert_eq!(buffer.text().to_string(), "a\r\n");
+    }
+}
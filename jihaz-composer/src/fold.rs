@@ -0,0 +1,684 @@
+//! Code folding.
+//!
+//! A [`FoldProvider`] finds the ranges of lines that can be folded, whether from indentation,
+//! bracket pairs or a tree-sitter syntax tree. A [`FoldState`] keeps the folds that are closed,
+//! rebases them through the edits of the buffer and maps between buffer lines and the visible
+//! lines that remain once the closed folds are hidden. As the display lines of a view, these are
+//! the lines that [`move_offset_in`](crate::movement::move_offset_in) moves up and down through.
+
+use std::ops::Range;
+
+use lapce_xi_rope::{Rope, RopeDelta, Transformer};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tree_sitter::Tree;
+
+use crate::{
+    buffer::rope_text::{RopeText, RopeTextRef},
+    command::FoldCommand,
+    cursor::{Cursor, CursorMode},
+    large_file::BufferFeatures,
+    syntax_util::{matching_char, matching_pair_direction},
+    word::WordCursor,
+};
+
+/// A range of lines that can be folded. The first line stays visible when the range is folded
+/// and the lines after it up to `end_line`, included, are hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FoldRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl FoldRange {
+    pub fn new(start_line: usize, end_line: usize) -> Self {
+        Self {
+            start_line,
+            end_line,
+        }
+    }
+
+    /// Whether `line` is the first line of the range or one of the lines it hides.
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start_line..=self.end_line).contains(&line)
+    }
+
+    /// The lines that are hidden when the range is folded.
+    pub fn hidden_lines(&self) -> Range<usize> {
+        self.start_line + 1..self.end_line + 1
+    }
+}
+
+/// Sort fold ranges by their first line, keeping only the largest of those that start on the same
+/// line and dropping those that wouldn't hide anything.
+pub fn merge_fold_ranges(ranges: impl IntoIterator<Item = FoldRange>) -> Vec<FoldRange> {
+    let mut ranges: Vec<FoldRange> = ranges
+        .into_iter()
+        .filter(|range| range.end_line > range.start_line)
+        .collect();
+    ranges.sort_by(|a, b| {
+        a.start_line
+            .cmp(&b.start_line)
+            .then(b.end_line.cmp(&a.end_line))
+    });
+    ranges.dedup_by_key(|range| range.start_line);
+    ranges
+}
+
+/// A source of the ranges that can be folded in a text.
+pub trait FoldProvider {
+    /// The fold ranges of `text`, as given by [`merge_fold_ranges`].
+    fn fold_ranges(&self, text: &Rope) -> Vec<FoldRange>;
+}
+
+/// Folds a line together with the lines after it that are indented deeper, ignoring blank lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndentFolds {
+    pub tab_width: usize,
+}
+
+impl Default for IndentFolds {
+    fn default() -> Self {
+        Self { tab_width: 4 }
+    }
+}
+
+impl IndentFolds {
+    /// The width of the indentation of `line`, or `None` if it is blank.
+    fn indent_width(&self, line: &str) -> Option<usize> {
+        let mut width = 0;
+        for c in line.chars() {
+            match c {
+                ' ' => width += 1,
+                '\t' => width += self.tab_width - width % self.tab_width.max(1),
+                '\r' | '\n' => return None,
+                _ => return Some(width),
+            }
+        }
+        None
+    }
+}
+
+impl FoldProvider for IndentFolds {
+    fn fold_ranges(&self, text: &Rope) -> Vec<FoldRange> {
+        let text = RopeTextRef::new(text);
+        let mut ranges = Vec::new();
+        // The lines that start a fold which hasn't ended yet, with their indentation
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut last_non_blank = 0;
+        for line in 0..text.num_lines() {
+            let Some(indent) = self.indent_width(&text.line_content(line)) else {
+                continue;
+            };
+            while let Some(&(start, start_indent)) = open.last() {
+                if start_indent < indent {
+                    break;
+                }
+                open.pop();
+                ranges.push(FoldRange::new(start, last_non_blank));
+            }
+            open.push((line, indent));
+            last_non_blank = line;
+        }
+        for (start, _) in open {
+            ranges.push(FoldRange::new(start, last_non_blank));
+        }
+        merge_fold_ranges(ranges)
+    }
+}
+
+/// Folds the lines between an opening bracket and its matching closing bracket, found with
+/// [`WordCursor::match_pairs`]. The line of the closing bracket stays visible when nothing comes
+/// before the bracket on it, as in `} else {`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BracketFolds;
+
+impl FoldProvider for BracketFolds {
+    fn fold_ranges(&self, rope: &Rope) -> Vec<FoldRange> {
+        let text = RopeTextRef::new(rope);
+        let mut ranges = Vec::new();
+        for line in 0..text.num_lines() {
+            let line_start = text.offset_of_line(line);
+            // Only the brackets that aren't closed on their own line can start a fold
+            let mut unclosed: Vec<(usize, char)> = Vec::new();
+            for (i, c) in text.line_content(line).char_indices() {
+                match matching_pair_direction(c) {
+                    Some(true) => unclosed.push((line_start + i, c)),
+                    Some(false) if unclosed.last().map(|(_, open)| *open) == matching_char(c) => {
+                        unclosed.pop();
+                    }
+                    _ => {}
+                }
+            }
+            for (open, _) in unclosed {
+                let Some(close) = WordCursor::new(rope, open).match_pairs() else {
+                    continue;
+                };
+                ranges.push(FoldRange::new(line, last_folded_line(&text, close)));
+            }
+        }
+        merge_fold_ranges(ranges)
+    }
+}
+
+/// Folds the named nodes of a syntax tree that span several lines.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeSitterFolds<'a> {
+    tree: &'a Tree,
+}
+
+impl<'a> TreeSitterFolds<'a> {
+    pub fn new(tree: &'a Tree) -> Self {
+        Self { tree }
+    }
+
+    /// The folds of `tree`, unless `features` leave syntax out, in which case another provider
+    /// such as [`IndentFolds`] is to be used.
+    pub fn with_features(tree: &'a Tree, features: BufferFeatures) -> Option<Self> {
+        features.syntax.then(|| Self::new(tree))
+    }
+}
+
+impl FoldProvider for TreeSitterFolds<'_> {
+    fn fold_ranges(&self, rope: &Rope) -> Vec<FoldRange> {
+        let text = RopeTextRef::new(rope);
+        let mut ranges = Vec::new();
+        let mut cursor = self.tree.walk();
+        loop {
+            let node = cursor.node();
+            let start_line = node.start_position().row;
+            let end = node.end_position();
+            // A node that ends with a line ending ends on the line before
+            let end_line = if end.column == 0 {
+                end.row.saturating_sub(1)
+            } else {
+                end.row
+            };
+
+            // The children of a node on a single line are on a single line too
+            if end_line > start_line {
+                if node.is_named() {
+                    let end_line = match rope.prev_codepoint_offset(node.end_byte()) {
+                        Some(close) if is_closing_bracket(rope, close) => {
+                            last_folded_line(&text, close)
+                        }
+                        _ => end_line,
+                    };
+                    ranges.push(FoldRange::new(start_line, end_line));
+                }
+                if cursor.goto_first_child() {
+                    continue;
+                }
+            }
+
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return merge_fold_ranges(ranges);
+                }
+            }
+        }
+    }
+}
+
+fn is_closing_bracket(rope: &Rope, offset: usize) -> bool {
+    // Brackets are ascii, so any other byte isn't one
+    matching_pair_direction(rope.byte_at(offset) as char) == Some(false)
+}
+
+/// The last line a fold that ends with the closing bracket at `close` hides.
+fn last_folded_line(text: &impl RopeText, close: usize) -> usize {
+    let line = text.line_of_offset(close);
+    let before = text.slice_to_cow(text.offset_of_line(line)..close);
+    if before.trim().is_empty() {
+        line.saturating_sub(1)
+    } else {
+        line
+    }
+}
+
+/// The folds that are closed in a buffer.
+///
+/// Folds are kept as the offset of the start of their first line and the offset just past their
+/// last line, so that lines inserted or deleted around them move them along, while lines
+/// inserted or deleted inside them grow or shrink them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FoldState {
+    folds: Vec<(usize, usize)>,
+}
+
+impl FoldState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    fn to_range(text: &impl RopeText, (start, end): (usize, usize)) -> Option<FoldRange> {
+        let range = FoldRange::new(
+            text.line_of_offset(start),
+            text.line_of_offset(end.saturating_sub(1)),
+        );
+        (start < end && range.end_line > range.start_line).then_some(range)
+    }
+
+    fn to_offsets(text: &impl RopeText, range: FoldRange) -> (usize, usize) {
+        (
+            text.offset_of_line(range.start_line),
+            text.offset_of_line(range.end_line + 1),
+        )
+    }
+
+    /// The closed folds, ordered by their first line and then outermost first.
+    pub fn folds(&self, text: &impl RopeText) -> Vec<FoldRange> {
+        self.folds
+            .iter()
+            .filter_map(|fold| Self::to_range(text, *fold))
+            .collect()
+    }
+
+    pub fn is_folded(&self, text: &impl RopeText, range: FoldRange) -> bool {
+        self.folds.contains(&Self::to_offsets(text, range))
+    }
+
+    /// Close `range`. Ranges that wouldn't hide anything are ignored.
+    pub fn fold(&mut self, text: &impl RopeText, range: FoldRange) {
+        let offsets = Self::to_offsets(text, range);
+        if Self::to_range(text, offsets).is_none() {
+            return;
+        }
+        if let Err(index) = self
+            .folds
+            .binary_search_by(|fold| Self::cmp(*fold, offsets))
+        {
+            self.folds.insert(index, offsets);
+        }
+    }
+
+    /// Open `range`. Returns false if it wasn't closed.
+    pub fn unfold(&mut self, text: &impl RopeText, range: FoldRange) -> bool {
+        let offsets = Self::to_offsets(text, range);
+        let len = self.folds.len();
+        self.folds.retain(|fold| *fold != offsets);
+        self.folds.len() != len
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
+    /// Folds starting first come first, and of those starting together the larger one.
+    fn cmp(a: (usize, usize), b: (usize, usize)) -> std::cmp::Ordering {
+        a.0.cmp(&b.0).then(b.1.cmp(&a.1))
+    }
+
+    /// Rebase the folds through an edit. `text` is the text after the edit.
+    ///
+    /// Folds whose lines are all deleted, or that are left with nothing to hide, are dropped.
+    pub fn apply_delta(&mut self, text: &impl RopeText, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        let mut folds: Vec<(usize, usize)> = self
+            .folds
+            .iter()
+            .map(|&(start, end)| {
+                (
+                    transformer.transform(start, true),
+                    transformer.transform(end, false),
+                )
+            })
+            // An edit that joins lines can leave the offsets in the middle of a line
+            .filter_map(|fold| Self::to_range(text, fold))
+            .map(|range| Self::to_offsets(text, range))
+            .collect();
+        folds.sort_by(|a, b| Self::cmp(*a, *b));
+        folds.dedup();
+        self.folds = folds;
+    }
+
+    /// The lines hidden by the closed folds, as sorted ranges that neither overlap nor touch.
+    /// The line before each range is always visible.
+    fn hidden_lines(&self, text: &impl RopeText) -> Vec<Range<usize>> {
+        let mut hidden: Vec<Range<usize>> = Vec::new();
+        for range in self.folds(text) {
+            let lines = range.hidden_lines();
+            match hidden.last_mut() {
+                Some(last) if lines.start <= last.end => last.end = last.end.max(lines.end),
+                _ => hidden.push(lines),
+            }
+        }
+        hidden
+    }
+
+    pub fn is_line_hidden(&self, text: &impl RopeText, line: usize) -> bool {
+        self.hidden_lines(text)
+            .iter()
+            .any(|hidden| hidden.contains(&line))
+    }
+
+    /// The outermost closed fold that contains `line`, either as its first line or as one of
+    /// the lines it hides.
+    pub fn closed_fold_at(&self, text: &impl RopeText, line: usize) -> Option<FoldRange> {
+        self.folds(text)
+            .into_iter()
+            .filter(|range| range.contains(line))
+            .max_by_key(|range| range.end_line - range.start_line)
+    }
+
+    pub fn num_visible_lines(&self, text: &impl RopeText) -> usize {
+        let hidden: usize = self
+            .hidden_lines(text)
+            .iter()
+            .map(|lines| lines.len())
+            .sum();
+        text.num_lines() - hidden
+    }
+
+    /// The visible line that shows the buffer line `line`, which for a hidden line is the first
+    /// line of the fold that hides it.
+    pub fn visible_line(&self, text: &impl RopeText, line: usize) -> usize {
+        let mut visible = line;
+        for hidden in self.hidden_lines(text) {
+            if hidden.start > line {
+                break;
+            }
+            if hidden.contains(&line) {
+                return visible - (line - hidden.start) - 1;
+            }
+            visible -= hidden.len();
+        }
+        visible
+    }
+
+    /// The buffer line shown at the visible line `visible_line`.
+    pub fn buffer_line(&self, text: &impl RopeText, visible_line: usize) -> usize {
+        let mut line = visible_line;
+        for hidden in self.hidden_lines(text) {
+            if hidden.start > line {
+                break;
+            }
+            line += hidden.len();
+        }
+        line.min(text.last_line())
+    }
+
+    /// Run a fold command at the cursor, given the fold ranges of the text from a
+    /// [`FoldProvider`].
+    ///
+    /// [`FoldCommand::CreateFold`] folds the selected lines, like `zf` in visual mode. Such a fold
+    /// is forgotten once it is opened. Afterwards a cursor that ended up on a hidden line is moved
+    /// to the first line of the fold that hides it.
+    pub fn do_command(
+        &mut self,
+        cmd: &FoldCommand,
+        text: &impl RopeText,
+        cursor: &mut Cursor,
+        ranges: &[FoldRange],
+    ) {
+        let line = text.line_of_offset(cursor.offset());
+        match cmd {
+            FoldCommand::CreateFold => {
+                let regions: Vec<(usize, usize)> = cursor.regions_iter().collect();
+                for (start, end) in regions {
+                    let range = FoldRange::new(
+                        text.line_of_offset(start.min(end)),
+                        text.line_of_offset(start.max(end)),
+                    );
+                    self.fold(text, range);
+                }
+                if let CursorMode::Visual { start, end, .. } = cursor.mode {
+                    cursor.set_mode(CursorMode::Normal(start.min(end)));
+                }
+            }
+            FoldCommand::OpenFold => {
+                if let Some(range) = self.closed_fold_at(text, line) {
+                    self.unfold(text, range);
+                }
+            }
+            FoldCommand::CloseFold => self.close_innermost(text, line, ranges),
+            FoldCommand::ToggleFold => match self.closed_fold_at(text, line) {
+                Some(range) => {
+                    self.unfold(text, range);
+                }
+                None => self.close_innermost(text, line, ranges),
+            },
+            FoldCommand::OpenAllFolds => self.unfold_all(),
+            FoldCommand::CloseAllFolds => {
+                for range in ranges {
+                    self.fold(text, *range);
+                }
+            }
+        }
+
+        let line = text.line_of_offset(cursor.offset());
+        if self.is_line_hidden(text, line) {
+            let line = self.buffer_line(text, self.visible_line(text, line));
+            let offset = text.first_non_blank_character_on_line(line);
+            cursor.set_offset(offset, false, false);
+        }
+    }
+
+    /// Close the innermost of `ranges` around `line` that is open and not hidden, so that
+    /// closing again closes the range around it.
+    fn close_innermost(&mut self, text: &impl RopeText, line: usize, ranges: &[FoldRange]) {
+        let range = ranges
+            .iter()
+            .filter(|range| range.contains(line))
+            .filter(|range| !self.is_folded(text, **range))
+            .filter(|range| !self.is_line_hidden(text, range.start_line))
+            .min_by_key(|range| range.end_line - range.start_line);
+        if let Some(range) = range {
+            self.fold(text, *range);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::Rope;
+
+    use super::{BracketFolds, FoldProvider, FoldRange, FoldState, IndentFolds};
+    use crate::{
+        bidi::BidiMovement,
+        buffer::{rope_text::RopeText, Buffer},
+        command::FoldCommand,
+        cursor::{Cursor, CursorAffinity, CursorMode},
+        editor::EditType,
+        mode::{Mode, VisualMode},
+        movement::{move_offset_in, Movement},
+        selection::Selection,
+    };
+
+    const CODE: &str = "\
+fn main() {
+    if ok {
+        one();
+    } else {
+        two();
+    }
+}
+";
+
+    #[test]
+    fn indent_ranges() {
+        let text = Rope::from("a:\n  b:\n    c\n\n  d\n\ne\n\tf\n");
+        assert_eq!(
+            IndentFolds::default().fold_ranges(&text),
+            [
+                FoldRange::new(0, 4),
+                FoldRange::new(1, 2),
+                FoldRange::new(6, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn bracket_ranges() {
+        let text = Rope::from(CODE);
+        assert_eq!(
+            BracketFolds.fold_ranges(&text),
+            [
+                FoldRange::new(0, 5),
+                FoldRange::new(1, 2),
+                FoldRange::new(3, 4)
+            ]
+        );
+
+        // Brackets closed on their own line don't fold, nor do ranges with nothing to hide
+        let text = Rope::from("call(a, [b]) + {\n} + (c\n)");
+        assert!(BracketFolds.fold_ranges(&text).is_empty());
+        let text = Rope::from("call(a,\n  b) {\n  c\n}");
+        assert_eq!(
+            BracketFolds.fold_ranges(&text),
+            [FoldRange::new(0, 1), FoldRange::new(1, 2)]
+        );
+    }
+
+    #[test]
+    fn visible_lines() {
+        let text = Buffer::new(CODE);
+        let mut folds = FoldState::new();
+        folds.fold(&text, FoldRange::new(3, 4));
+        folds.fold(&text, FoldRange::new(1, 2));
+        assert_eq!(
+            folds.folds(&text),
+            [FoldRange::new(1, 2), FoldRange::new(3, 4)]
+        );
+        assert_eq!(folds.num_visible_lines(&text), 6);
+
+        let visible: Vec<usize> = (0..8).map(|line| folds.visible_line(&text, line)).collect();
+        assert_eq!(visible, [0, 1, 1, 2, 2, 3, 4, 5]);
+        let lines: Vec<usize> = (0..6).map(|line| folds.buffer_line(&text, line)).collect();
+        assert_eq!(lines, [0, 1, 3, 5, 6, 7]);
+
+        // A fold around the others hides them too
+        folds.fold(&text, FoldRange::new(0, 5));
+        assert_eq!(folds.num_visible_lines(&text), 3);
+        assert_eq!(folds.visible_line(&text, 4), 0);
+        assert_eq!(folds.buffer_line(&text, 1), 6);
+        assert_eq!(folds.closed_fold_at(&text, 2), Some(FoldRange::new(0, 5)));
+    }
+
+    #[test]
+    fn vertical_movement_skips_folds() {
+        let text = Buffer::new(CODE);
+        let mut folds = FoldState::new();
+        folds.fold(&text, FoldRange::new(1, 2));
+        let mut move_line = |line: usize, movement: Movement, count: usize| {
+            let offset = text.offset_of_line(line);
+            let (offset, _) = move_offset_in(
+                &text,
+                &mut folds,
+                offset,
+                None,
+                &movement,
+                count,
+                Mode::Insert,
+                BidiMovement::Logical,
+                &mut CursorAffinity::Forward,
+            );
+            text.line_of_offset(offset)
+        };
+        assert_eq!(move_line(1, Movement::Down, 1), 3);
+        assert_eq!(move_line(3, Movement::Up, 1), 1);
+        assert_eq!(move_line(0, Movement::Down, 2), 3);
+        assert_eq!(move_line(6, Movement::Down, 5), 7);
+    }
+
+    #[test]
+    fn folds_follow_edits() {
+        let mut buffer = Buffer::new(CODE);
+        let mut folds = FoldState::new();
+        folds.fold(&buffer, FoldRange::new(3, 4));
+
+        let mut edit = |buffer: &mut Buffer, offset: usize, len: usize, content: &str| {
+            let selection = Selection::region(offset, offset + len);
+            let (_, delta, _) = buffer.edit(&[(selection, content)], EditType::Other);
+            folds.apply_delta(buffer, &delta);
+            folds.folds(buffer)
+        };
+
+        // Lines inserted above move the fold down
+        let folded = edit(&mut buffer, 0, 0, "// main\n");
+        assert_eq!(folded, [FoldRange::new(4, 5)]);
+        // A line opened above its first line stays out of it
+        let offset = buffer.offset_of_line(4);
+        let folded = edit(&mut buffer, offset, 0, "    // else\n");
+        assert_eq!(folded, [FoldRange::new(5, 6)]);
+        // Lines inserted inside it grow it
+        let offset = buffer.offset_of_line(6);
+        let folded = edit(&mut buffer, offset, 0, "        three();\n");
+        assert_eq!(folded, [FoldRange::new(5, 7)]);
+        // A line opened below it stays out of it
+        let offset = buffer.offset_of_line(8);
+        let folded = edit(&mut buffer, offset, 0, "    four();\n");
+        assert_eq!(folded, [FoldRange::new(5, 7)]);
+        // Deleting its last line shrinks it
+        let (start, end) = (buffer.offset_of_line(7), buffer.offset_of_line(8));
+        let folded = edit(&mut buffer, start, end - start, "");
+        assert_eq!(folded, [FoldRange::new(5, 6)]);
+        // Deleting all it hides drops it
+        let (start, end) = (buffer.offset_of_line(6), buffer.offset_of_line(7));
+        let folded = edit(&mut buffer, start, end - start, "");
+        assert!(folded.is_empty());
+        assert!(folds.is_empty());
+    }
+
+    #[test]
+    fn commands() {
+        let text = Buffer::new(CODE);
+        let ranges = BracketFolds.fold_ranges(text.text());
+        let mut folds = FoldState::new();
+        let mut cursor = Cursor::new(CursorMode::Normal(text.offset_of_line(2) + 8), None, None);
+        let run = |folds: &mut FoldState, cursor: &mut Cursor, cmd: FoldCommand| {
+            folds.do_command(&cmd, &text, cursor, &ranges);
+            folds.folds(&text)
+        };
+
+        // Closing closes the innermost range and moves the cursor out of it
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::CloseFold),
+            [FoldRange::new(1, 2)]
+        );
+        assert_eq!(cursor.offset(), text.offset_of_line(1) + 4);
+        // Closing again closes the range around it
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::CloseFold),
+            [FoldRange::new(0, 5), FoldRange::new(1, 2)]
+        );
+        assert_eq!(cursor.offset(), 0);
+        // Opening opens one level
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::OpenFold),
+            [FoldRange::new(1, 2)]
+        );
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::ToggleFold),
+            [FoldRange::new(0, 5), FoldRange::new(1, 2)]
+        );
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::ToggleFold),
+            [FoldRange::new(1, 2)]
+        );
+        assert!(run(&mut folds, &mut cursor, FoldCommand::OpenAllFolds).is_empty());
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::CloseAllFolds),
+            ranges
+        );
+        folds.unfold_all();
+
+        // Creating a fold folds the selected lines and leaves visual mode
+        cursor.set_mode(CursorMode::Visual {
+            start: text.offset_of_line(4),
+            end: text.offset_of_line(2) + 3,
+            mode: VisualMode::Linewise,
+        });
+        assert_eq!(
+            run(&mut folds, &mut cursor, FoldCommand::CreateFold),
+            [FoldRange::new(2, 4)]
+        );
+        assert_eq!(cursor.mode, CursorMode::Normal(text.offset_of_line(2) + 3));
+    }
+}
@@ -0,0 +1,456 @@
+//! Formatting with an external command, such as `rustfmt`, `prettier` or `black`.
+//!
+//! The text is piped through the command, which reads it from its standard input and writes the
+//! formatted text to its standard output:
+//!
+//! - `rustfmt --emit stdout --edition 2021`
+//! - `prettier --stdin-filepath src/index.ts`
+//! - `black --quiet -`
+//!
+//! Only the lines that the command changed are edited, in one edit, so that cursors, marks and
+//! folds on the other lines stay where they are and a single undo reverts the formatting.
+
+use std::{
+    fmt,
+    io::{Read, Write},
+    ops::Range,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use lapce_xi_rope::{Rope, RopeDelta};
+
+use crate::{
+    buffer::{diff::minimal_edits, rope_text::RopeText, Buffer, InvalLines},
+    command::FocusCommand,
+    cursor::Cursor,
+    editor::EditType,
+    selection::Selection,
+};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The command couldn't be started, usually because it isn't installed.
+    Spawn(String),
+    /// The command didn't finish within its timeout, and was killed.
+    Timeout,
+    /// The command exited with a failure, usually because the text has syntax errors.
+    Failed { code: Option<i32>, stderr: String },
+    /// The output of the command is not UTF-8.
+    InvalidOutput,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Spawn(err) => write!(f, "Couldn't run the formatter: {err}"),
+            FormatError::Timeout => write!(f, "The formatter timed out"),
+            FormatError::Failed {
+                code: Some(code),
+                stderr,
+            } => write!(
+                f,
+                "The formatter failed with code {code}: {}",
+                stderr.trim()
+            ),
+            FormatError::Failed { code: None, stderr } => {
+                write!(f, "The formatter failed: {}", stderr.trim())
+            }
+            FormatError::InvalidOutput => write!(f, "The formatter output is not UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// A formatting command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Formatter {
+    pub program: String,
+    pub args: Vec<String>,
+    /// The directory to run the command in, where formatters look for their configuration.
+    pub working_dir: Option<PathBuf>,
+    pub timeout: Duration,
+    /// Whether the document is formatted before it is saved.
+    pub format_on_save: bool,
+}
+
+impl Formatter {
+    pub fn new<I, S>(program: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            working_dir: None,
+            timeout: DEFAULT_TIMEOUT,
+            format_on_save: false,
+        }
+    }
+
+    /// Pipe `input` through the command and return its output.
+    pub fn run(&self, input: &str) -> Result<String, FormatError> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|err| FormatError::Spawn(err.to_string()))?;
+
+        // The pipes are written and read on their own threads so that a command that writes
+        // before it has read everything can't block on a full pipe
+        let mut stdin = child.stdin.take();
+        let input = input.to_string();
+        thread::spawn(move || {
+            if let Some(stdin) = &mut stdin {
+                let _ = stdin.write_all(input.as_bytes());
+            }
+        });
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(FormatError::Timeout);
+                }
+                Err(err) => return Err(FormatError::Spawn(err.to_string())),
+            }
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        if !status.success() {
+            let stderr = stderr.join().unwrap_or_default();
+            return Err(FormatError::Failed {
+                code: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            });
+        }
+        String::from_utf8(stdout).map_err(|_| FormatError::InvalidOutput)
+    }
+
+    /// Format the whole document.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::{
+    /// #     buffer::Buffer,
+    /// #     cursor::{Cursor, CursorMode},
+    /// #     formatter::Formatter,
+    /// # };
+    /// # #[cfg(unix)]
+    /// # {
+    /// // A formatter that removes trailing spaces
+    /// let formatter = Formatter::new("sed", ["s/ *$//"]);
+    /// let mut buffer = Buffer::new("fn main() {  \n    let x = 1;\n}  \n");
+    /// let mut cursor = Cursor::new(CursorMode::Normal(20), None, None);
+    ///
+    /// formatter.format(&mut buffer, &mut cursor).unwrap();
+    /// assert_eq!(buffer.to_string(), "fn main() {\n    let x = 1;\n}\n");
+    /// // The cursor is still on the `x`
+    /// assert_eq!(cursor.offset(), 18);
+    /// # }
+    /// ```
+    pub fn format(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, FormatError> {
+        self.format_text(buffer, cursor, 0..buffer.len(), EditType::Other)
+    }
+
+    /// Format the document before it is saved, if the formatter is set to.
+    pub fn format_on_save(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, FormatError> {
+        if !self.format_on_save {
+            return Ok(None);
+        }
+        self.format_text(buffer, cursor, 0..buffer.len(), EditType::OnSave)
+    }
+
+    /// Format only the lines of `lines`, with their common indentation removed for the command
+    /// and added back to its output, so that a nested block is formatted like a top-level one.
+    ///
+    /// The lines should be a whole construct, such as a function or a statement, for the command
+    /// to be able to parse them.
+    pub fn format_lines(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        lines: Range<usize>,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, FormatError> {
+        let range = buffer.offset_of_line(lines.start)..buffer.offset_of_line(lines.end);
+        if range.is_empty() {
+            return Ok(None);
+        }
+        let text = buffer.slice_to_cow(range.clone());
+        let indent = common_indent(&text);
+        let input: String = text
+            .split_inclusive('\n')
+            .map(|line| line.strip_prefix(indent.as_str()).unwrap_or(line))
+            .collect();
+        let output = self.run(&input)?;
+        let mut formatted: String = output
+            .split_inclusive('\n')
+            .map(|line| {
+                if line.trim().is_empty() {
+                    line.to_string()
+                } else {
+                    format!("{indent}{line}")
+                }
+            })
+            .collect();
+        // Without its line ending, the last line stays joined to what follows it
+        if !text.ends_with('\n') && formatted.ends_with('\n') {
+            formatted.pop();
+            if formatted.ends_with('\r') {
+                formatted.pop();
+            }
+        }
+        Ok(self.apply(buffer, cursor, range, &formatted, EditType::Other))
+    }
+
+    /// Run [`FocusCommand::FormatDocument`].
+    pub fn do_command(
+        &self,
+        cmd: &FocusCommand,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, FormatError> {
+        match cmd {
+            FocusCommand::FormatDocument => self.format(buffer, cursor),
+            _ => Ok(None),
+        }
+    }
+
+    fn format_text(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        range: Range<usize>,
+        edit_type: EditType,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, FormatError> {
+        let output = self.run(&buffer.slice_to_cow(range.clone()))?;
+        Ok(self.apply(buffer, cursor, range, &output, edit_type))
+    }
+
+    fn apply(
+        &self,
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        range: Range<usize>,
+        formatted: &str,
+        edit_type: EditType,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let old_text = buffer.text().slice(range.clone());
+        let edits = minimal_edits(&old_text, &Rope::from(formatted));
+        if edits.is_empty() {
+            return None;
+        }
+        let edits: Vec<(Selection, &str)> = edits
+            .iter()
+            .map(|(edit_range, content)| {
+                (
+                    Selection::region(range.start + edit_range.start, range.start + edit_range.end),
+                    content.as_str(),
+                )
+            })
+            .collect();
+        let edit = buffer.edit(&edits, edit_type);
+        cursor.apply_delta(&edit.1);
+        Some(edit)
+    }
+}
+
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// The whitespace that starts all the lines of `text` that aren't blank.
+fn common_indent(text: &str) -> String {
+    let mut indent: Option<&str> = None;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let line_indent = &line[..line.len() - line.trim_start().len()];
+        indent = Some(match indent {
+            None => line_indent,
+            Some(indent) => {
+                let len = indent
+                    .char_indices()
+                    .zip(line_indent.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((i, c), _)| i + c.len_utf8());
+                &indent[..len]
+            }
+        });
+    }
+    indent.unwrap_or_default().to_string()
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Duration};
+
+    use super::{common_indent, FormatError, Formatter};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        command::FocusCommand,
+        cursor::{Cursor, CursorMode},
+        selection::{SelRegion, Selection},
+    };
+
+    /// A fake formatter that puts one space around `=` and indents with four spaces instead of
+    /// two, failing on unbalanced braces like a real formatter on a syntax error.
+    fn fake_formatter(name: &str) -> Formatter {
+        let dir = std::env::temp_dir().join(format!("jihaz-formatter-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join(name);
+        std::fs::write(
+            &path,
+            "#!/bin/sh\n\
+             input=$(cat; echo .)\n\
+             input=${input%.}\n\
+             open=$(printf '%s' \"$input\" | tr -cd '{' | wc -c)\n\
+             close=$(printf '%s' \"$input\" | tr -cd '}' | wc -c)\n\
+             if [ \"$open\" -ne \"$close\" ]; then echo 'unbalanced braces' >&2; exit 2; fi\n\
+             printf '%s' \"$input\" | sed -e 's/ *= */ = /g' -e 's/^  \\([^ ]\\)/    \\1/'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Formatter::new(path.to_string_lossy(), Vec::<String>::new())
+    }
+
+    #[test]
+    fn format() {
+        let formatter = fake_formatter("format");
+        let text = "fn main() {\n  let x=1;\n    let y = 2;\n  let z =3;\n}\n";
+        let mut buffer = Buffer::new(text);
+        // Selecting `main`, and on the `y`
+        let mut selection = Selection::region(3, 7);
+        selection.add_region(SelRegion::caret(31));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        let (_, delta, _) = formatter.format(&mut buffer, &mut cursor).unwrap().unwrap();
+        assert_eq!(
+            buffer.to_string(),
+            "fn main() {\n    let x = 1;\n    let y = 2;\n    let z = 3;\n}\n"
+        );
+        // Only the changed lines were edited
+        assert_eq!(delta.summary().0.start, 12);
+        let regions: Vec<_> = cursor.regions_iter().collect();
+        assert_eq!(regions, [(3, 7), (35, 35)]);
+
+        // Formatting is undone at once, and formatting formatted text does nothing
+        assert!(formatter
+            .format(&mut buffer, &mut cursor)
+            .unwrap()
+            .is_none());
+        buffer.do_undo();
+        assert_eq!(buffer.to_string(), text);
+    }
+
+    #[test]
+    fn format_lines() {
+        let formatter = fake_formatter("format_lines");
+        let text = "impl A {\n    fn a() {\n      let x=1;\n    }\n}";
+        let mut buffer = Buffer::new(text);
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        // The common indentation is kept
+        formatter
+            .format_lines(&mut buffer, &mut cursor, 1..4)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            buffer.to_string(),
+            "impl A {\n    fn a() {\n        let x = 1;\n    }\n}"
+        );
+
+        // The last line has no line ending, and gets none
+        let mut buffer = Buffer::new("a=1");
+        formatter
+            .format_lines(&mut buffer, &mut cursor, 0..1)
+            .unwrap();
+        assert_eq!(buffer.to_string(), "a = 1");
+    }
+
+    #[test]
+    fn on_save() {
+        let mut formatter = fake_formatter("on_save");
+        let mut buffer = Buffer::new("a=1\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        assert!(formatter
+            .format_on_save(&mut buffer, &mut cursor)
+            .unwrap()
+            .is_none());
+        formatter.format_on_save = true;
+        assert!(formatter
+            .format_on_save(&mut buffer, &mut cursor)
+            .unwrap()
+            .is_some());
+        assert_eq!(buffer.to_string(), "a = 1\n");
+
+        let mut buffer = Buffer::new("b=2\n");
+        formatter
+            .do_command(&FocusCommand::FormatDocument, &mut buffer, &mut cursor)
+            .unwrap();
+        assert_eq!(buffer.to_string(), "b = 2\n");
+    }
+
+    #[test]
+    fn errors() {
+        let mut buffer = Buffer::new("fn main() {\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        let formatter = fake_formatter("errors");
+        assert_eq!(
+            formatter.format(&mut buffer, &mut cursor).err(),
+            Some(FormatError::Failed {
+                code: Some(2),
+                stderr: "unbalanced braces\n".to_string(),
+            })
+        );
+        assert_eq!(buffer.len(), 12);
+
+        let missing = Formatter::new("jihaz-missing-formatter", Vec::<String>::new());
+        assert!(matches!(missing.run(""), Err(FormatError::Spawn(_))));
+
+        let mut slow = Formatter::new("sleep", ["10"]);
+        slow.timeout = Duration::from_millis(50);
+        assert_eq!(slow.run(""), Err(FormatError::Timeout));
+    }
+
+    #[test]
+    fn indent() {
+        assert_eq!(common_indent("    a\n\n  b\n"), "  ");
+        assert_eq!(common_indent("\ta\n\t\tb"), "\t");
+        assert_eq!(common_indent("a\n  b"), "");
+        assert_eq!(common_indent("\n \n"), "");
+    }
+}
@@ -0,0 +1,255 @@
+//! Fuzzy matching of a query against candidate strings, e.g. command titles in a palette.
+//!
+//! A candidate matches when the characters of the query appear in it in order. Matches are
+//! scored so that characters at word starts, consecutive characters and early matches rank
+//! higher, and the best scoring alignment is the one that is highlighted.
+//!
+//! Matching ignores case, and for Arabic it ignores the harakat and the tatweel and treats the
+//! different forms of a letter alike, e.g. `أ`, `إ` and `آ` all match `ا`.
+
+use std::ops::Range;
+
+use crate::chars::char_is_arabic_diacritic;
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL: i32 = 6;
+const BONUS_CONSECUTIVE: i32 = 4;
+/// Matching the first character at the start of a word counts that much more.
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+const PENALTY_GAP: i32 = 1;
+const PENALTY_LEADING_GAP_MAX: i32 = 8;
+
+/// A successful match of a query in a candidate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// How well the query matches; higher is better.
+    pub score: i32,
+    /// The byte ranges of the candidate that matched the query, merged when adjacent.
+    /// A matched letter's range includes the diacritics that follow it.
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// A character of a candidate or query after normalization, with the byte range it covers in
+/// the original string.
+#[derive(Clone, Debug)]
+struct Unit {
+    c: char,
+    range: Range<usize>,
+    is_upper: bool,
+}
+
+/// Normalize a character for matching, or return `None` if it is ignored.
+fn fold(c: char) -> Option<char> {
+    if char_is_arabic_diacritic(c) || c == '\u{0640}' {
+        return None;
+    }
+    let c = match c {
+        'أ' | 'إ' | 'آ' | 'ٱ' => 'ا',
+        'ة' => 'ه',
+        'ى' | 'ئ' => 'ي',
+        'ؤ' => 'و',
+        c => c,
+    };
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => Some(lower),
+        _ => Some(c),
+    }
+}
+
+fn units(text: &str) -> Vec<Unit> {
+    let mut units: Vec<Unit> = Vec::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        match fold(c) {
+            Some(folded) => units.push(Unit {
+                c: folded,
+                range: i..i + c.len_utf8(),
+                is_upper: c.is_uppercase(),
+            }),
+            // Ignored characters belong to the character before them
+            None => {
+                if let Some(last) = units.last_mut() {
+                    last.range.end = i + c.len_utf8();
+                }
+            }
+        }
+    }
+    units
+}
+
+/// The bonus for matching the unit at `index`, given what comes before it.
+fn bonus(units: &[Unit], index: usize) -> i32 {
+    let Some(prev) = index.checked_sub(1).map(|i| &units[i]) else {
+        return BONUS_BOUNDARY;
+    };
+    let current = &units[index];
+    if !prev.c.is_alphanumeric() && current.c.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if current.is_upper && !prev.is_upper && prev.c.is_alphabetic() {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+/// The higher scoring of two alignments, preferring `a` on ties.
+fn best_of(a: Option<(i32, usize)>, b: Option<(i32, usize)>) -> Option<(i32, usize)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Match `query` against `candidate`. Whitespace in the query is ignored.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::fuzzy::fuzzy_match;
+/// let m = fuzzy_match("dl", "Delete Line").unwrap();
+/// assert_eq!(m.highlights, [0..1, 7..8]);
+/// assert!(fuzzy_match("ld", "Delete Line").is_none());
+///
+/// // Harakat and letter forms are ignored
+/// let m = fuzzy_match("اسم", "الإسْمُ").unwrap();
+/// assert_eq!(m.highlights, [0..2, 6..14]);
+/// ```
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .filter_map(fold)
+        .collect();
+    let units = units(candidate);
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            highlights: Vec::new(),
+        });
+    }
+    if query.len() > units.len() {
+        return None;
+    }
+
+    let n = units.len();
+    // scores[i][j] is the best score of matching query[..=i] with query[i] at unit j, and
+    // from[i][j] the unit that query[i - 1] matched in that alignment
+    let mut scores = vec![vec![None::<i32>; n]; query.len()];
+    let mut from = vec![vec![0usize; n]; query.len()];
+
+    for (i, &q) in query.iter().enumerate() {
+        // The best score of an alignment of query[..i] ending at least two units before j,
+        // with the gap to j already deducted
+        let mut running: Option<(i32, usize)> = None;
+        for j in 0..n {
+            if i > 0 && j >= 2 {
+                let further = running.map(|(score, k)| (score - PENALTY_GAP, k));
+                let closest = scores[i - 1][j - 2].map(|score| (score - PENALTY_GAP, j - 2));
+                running = best_of(closest, further);
+            }
+            if units[j].c != q {
+                continue;
+            }
+
+            let bonus = bonus(&units, j);
+            if i == 0 {
+                let leading_gap = (j as i32).min(PENALTY_LEADING_GAP_MAX);
+                scores[i][j] =
+                    Some(SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER - leading_gap);
+                continue;
+            }
+
+            let consecutive = j
+                .checked_sub(1)
+                .and_then(|k| scores[i - 1][k])
+                .map(|score| (score + SCORE_MATCH + bonus.max(BONUS_CONSECUTIVE), j - 1));
+            let gapped = running.map(|(score, k)| (score + SCORE_MATCH + bonus, k));
+            let best = best_of(consecutive, gapped);
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let last = query.len() - 1;
+    let (mut j, score) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+
+    let mut highlights: Vec<Range<usize>> = Vec::new();
+    for position in positions {
+        let range = units[position].range.clone();
+        match highlights.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => highlights.push(range),
+        }
+    }
+
+    Some(FuzzyMatch { score, highlights })
+}
+
+#[cfg(test)]
+mod test {
+    use super::fuzzy_match;
+
+    fn score(query: &str, candidate: &str) -> i32 {
+        fuzzy_match(query, candidate).unwrap().score
+    }
+
+    #[test]
+    fn subsequences_match() {
+        assert!(fuzzy_match("dwb", "Delete Word Backward").is_some());
+        assert!(fuzzy_match("DELETE", "delete line").is_some());
+        assert!(fuzzy_match("del line", "Delete Line").is_some());
+        assert!(fuzzy_match("xyz", "Delete Line").is_none());
+        assert!(fuzzy_match("linee", "Line").is_none());
+        assert!(fuzzy_match("", "Undo").unwrap().highlights.is_empty());
+    }
+
+    #[test]
+    fn word_starts_and_runs_rank_higher() {
+        // Initials of words beat scattered letters
+        assert!(score("dl", "Delete Line") > score("dl", "Paddle"));
+        // A consecutive run beats the same letters spread out
+        assert!(score("line", "Line End") > score("line", "Lower Indent Next"));
+        // Earlier matches beat later ones
+        assert!(score("undo", "Undo") > score("undo", "Select Undo"));
+    }
+
+    #[test]
+    fn highlights_prefer_word_starts() {
+        let m = fuzzy_match("sel", "Select Line").unwrap();
+        assert_eq!(m.highlights, vec![(0..3)]);
+        let m = fuzzy_match("sl", "Select Line").unwrap();
+        assert_eq!(m.highlights, [0..1, 7..8]);
+        let m = fuzzy_match("ml", "moveLine").unwrap();
+        assert_eq!(m.highlights, [0..1, 4..5]);
+    }
+
+    #[test]
+    fn arabic() {
+        // Harakat in the candidate or the query don't get in the way
+        assert!(fuzzy_match("كتب", "كَتَبَ").is_some());
+        assert!(fuzzy_match("كَتَبَ", "كتب").is_some());
+        // Nor do letter forms or the tatweel
+        assert!(fuzzy_match("احمد", "أحمد").is_some());
+        assert!(fuzzy_match("مكتبه", "مكتبة").is_some());
+        assert!(fuzzy_match("علي", "علـــى").is_some());
+
+        // Highlights cover the letter and its harakat
+        let m = fuzzy_match("حذ", "احذف السطر").unwrap();
+        assert_eq!(m.highlights, vec![(2..6)]);
+        let m = fuzzy_match("حس", "حَذْف السطر").unwrap();
+        assert_eq!(m.highlights, [0..4, 15..17]);
+    }
+}
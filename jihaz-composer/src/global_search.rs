@@ -0,0 +1,638 @@
+//! Search and replace across the files of a directory tree, for
+//! [`FocusCommand::GlobalSearchRefresh`](crate::command::FocusCommand::GlobalSearchRefresh).
+//!
+//! Files are searched in parallel. By default, the files that `.gitignore`, `.ignore` and
+//! `.git/info/exclude` ignore are skipped, as are hidden files and directories, and files that
+//! look binary because they have a NUL byte near their start, or that aren't UTF-8. Files in
+//! [large-file mode](crate::large_file) are skipped too, since each file is read whole.
+
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder, WalkState};
+use lapce_xi_rope::{Rope, RopeDelta};
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    buffer::{rope_text::RopeText, Buffer, InvalLines},
+    editor::EditType,
+    large_file::LargeFileConfig,
+    selection::Selection,
+};
+
+/// How far into a file to look for a NUL byte to decide that it is binary, as git does.
+const BINARY_DETECTION_LEN: usize = 8000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchError {
+    InvalidPattern(String),
+    InvalidGlob(String),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::InvalidPattern(err) => write!(f, "Invalid search pattern: {err}"),
+            SearchError::InvalidGlob(err) => write!(f, "Invalid glob: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Whether the pattern is a regular expression, rather than text to find as is.
+    pub is_regex: bool,
+}
+
+impl SearchQuery {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            case_sensitive: false,
+            whole_word: false,
+            is_regex: false,
+        }
+    }
+
+    fn regex(&self) -> Result<Regex, SearchError> {
+        let pattern = if self.is_regex {
+            self.pattern.clone()
+        } else {
+            regex::escape(&self.pattern)
+        };
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{pattern})\b")
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|err| SearchError::InvalidPattern(err.to_string()))
+    }
+}
+
+/// A match of a search in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub path: PathBuf,
+    /// The line that the match starts on.
+    pub line: usize,
+    /// The offsets of the match in the file.
+    pub range: Range<usize>,
+    /// The text of the line, without its line ending.
+    pub line_text: String,
+    /// The offsets of the match in `line_text`, up to its end for a match over several lines.
+    pub line_range: Range<usize>,
+    /// The lines before and after the line of the match, as many as the search asks for.
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// Stops a search that is running on other threads.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, atomic::Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(atomic::Ordering::Acquire)
+    }
+}
+
+/// A search of the files under a directory.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use std::sync::Mutex;
+/// # use jihaz_composer::global_search::{CancelToken, GlobalSearch, SearchQuery};
+/// let root = std::env::temp_dir().join(format!("jihaz-global-search-doc-{}", std::process::id()));
+/// std::fs::create_dir_all(root.join("src")).unwrap();
+/// std::fs::write(root.join("src/lib.rs"), "fn main() {\n    todo!()\n}\n").unwrap();
+/// std::fs::write(root.join("notes.md"), "- todo: docs\n").unwrap();
+///
+/// let mut search = GlobalSearch::new(&root, SearchQuery::new("todo"));
+/// search.include = vec!["*.rs".to_string()];
+/// let matches = Mutex::new(Vec::new());
+/// search
+///     .run(&CancelToken::new(), &|found| matches.lock().unwrap().push(found))
+///     .unwrap();
+///
+/// let matches = matches.into_inner().unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].line, 1);
+/// assert_eq!(matches[0].line_text, "    todo!()");
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlobalSearch {
+    pub root: PathBuf,
+    pub query: SearchQuery,
+    /// Globs of the files to search, relative to the root, or all the files when empty.
+    pub include: Vec<String>,
+    /// Globs of the files and directories to leave out.
+    pub exclude: Vec<String>,
+    /// Whether hidden files and directories are searched.
+    pub hidden: bool,
+    /// Whether the files that ignore files list are left out.
+    pub respect_ignore: bool,
+    /// The number of lines of context before and after the line of each match.
+    pub context_lines: usize,
+    /// Which files are in large-file mode, where
+    /// [`BufferFeatures::search_index`](crate::large_file::BufferFeatures::search_index) is off
+    /// and they are not searched.
+    pub large_file: LargeFileConfig,
+}
+
+impl GlobalSearch {
+    pub fn new(root: impl Into<PathBuf>, query: SearchQuery) -> Self {
+        Self {
+            root: root.into(),
+            query,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hidden: false,
+            respect_ignore: true,
+            context_lines: 0,
+            large_file: LargeFileConfig::default(),
+        }
+    }
+
+    /// Search the files, calling `on_match` for each match as it is found, from several threads
+    /// and in no particular order. Returns once all the files were searched or `cancel` was
+    /// cancelled.
+    pub fn run(
+        &self,
+        cancel: &CancelToken,
+        on_match: &(dyn Fn(SearchMatch) + Sync),
+    ) -> Result<(), SearchError> {
+        let regex = self.query.regex()?;
+        self.walk(cancel, &|path| {
+            search_file(path, &regex, self.context_lines, cancel, on_match);
+        })
+    }
+
+    /// Search the files on another thread, sending the matches through the returned channel,
+    /// which is closed when the search is over.
+    pub fn spawn(self, cancel: CancelToken) -> Result<mpsc::Receiver<SearchMatch>, SearchError> {
+        // Fail early on an invalid search rather than with an empty channel
+        self.query.regex()?;
+        self.walker()?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = self.run(&cancel, &|found| {
+                if sender.send(found).is_err() {
+                    // Nobody is listening anymore
+                    cancel.cancel();
+                }
+            });
+        });
+        Ok(receiver)
+    }
+
+    /// Replace the matches in `buffer` with `replacement`, as one edit. For a regular expression,
+    /// `$1` or `${name}` in `replacement` stand for the groups of the match.
+    pub fn replace_in_buffer(
+        &self,
+        buffer: &mut Buffer,
+        replacement: &str,
+    ) -> Result<Option<(Rope, RopeDelta, InvalLines)>, SearchError> {
+        let regex = self.query.regex()?;
+        Ok(self.replace_matches(&regex, buffer, replacement))
+    }
+
+    fn replace_matches(
+        &self,
+        regex: &Regex,
+        buffer: &mut Buffer,
+        replacement: &str,
+    ) -> Option<(Rope, RopeDelta, InvalLines)> {
+        let text = buffer.text().to_string();
+        let mut edits = Vec::new();
+        for captures in regex.captures_iter(&text) {
+            let found = captures.get(0).unwrap();
+            let mut content = String::new();
+            if self.query.is_regex {
+                captures.expand(replacement, &mut content);
+            } else {
+                content.push_str(replacement);
+            }
+            edits.push((Selection::region(found.start(), found.end()), content));
+        }
+        if edits.is_empty() {
+            return None;
+        }
+        let edits: Vec<(Selection, &str)> = edits
+            .iter()
+            .map(|(selection, content)| (selection.clone(), content.as_str()))
+            .collect();
+        Some(buffer.edit(&edits, EditType::Other))
+    }
+
+    /// Replace all the matches with `replacement`, returning the files that had some as buffers
+    /// with the replacement as an edit that can be undone. The files themselves are not written.
+    pub fn replace_all(
+        &self,
+        replacement: &str,
+        cancel: &CancelToken,
+    ) -> Result<Vec<(PathBuf, Buffer)>, SearchError> {
+        let regex = self.query.regex()?;
+        let buffers = std::sync::Mutex::new(Vec::new());
+        self.walk(cancel, &|path| {
+            let Some(content) = read_text(path) else {
+                return;
+            };
+            if !regex.is_match(&content) {
+                return;
+            }
+            let mut buffer = Buffer::new(content.as_str());
+            if self
+                .replace_matches(&regex, &mut buffer, replacement)
+                .is_some()
+            {
+                buffers.lock().unwrap().push((path.to_path_buf(), buffer));
+            }
+        })?;
+        let mut buffers = buffers.into_inner().unwrap();
+        buffers.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(buffers)
+    }
+
+    fn walker(&self) -> Result<WalkBuilder, SearchError> {
+        let mut overrides = OverrideBuilder::new(&self.root);
+        let globs = self
+            .include
+            .iter()
+            .cloned()
+            .chain(self.exclude.iter().map(|glob| format!("!{glob}")));
+        for glob in globs {
+            overrides
+                .add(&glob)
+                .map_err(|err| SearchError::InvalidGlob(err.to_string()))?;
+        }
+        let overrides = overrides
+            .build()
+            .map_err(|err| SearchError::InvalidGlob(err.to_string()))?;
+
+        let mut walker = WalkBuilder::new(&self.root);
+        walker
+            .hidden(!self.hidden)
+            .ignore(self.respect_ignore)
+            .git_ignore(self.respect_ignore)
+            .git_exclude(self.respect_ignore)
+            .git_global(self.respect_ignore)
+            // A project that isn't a git repository can still have a `.gitignore`
+            .require_git(false)
+            .overrides(overrides);
+        Ok(walker)
+    }
+
+    /// Call `visit` with each file to search, in parallel.
+    fn walk(
+        &self,
+        cancel: &CancelToken,
+        visit: &(dyn Fn(&Path) + Sync),
+    ) -> Result<(), SearchError> {
+        self.walker()?.build_parallel().run(|| {
+            Box::new(|entry| {
+                if cancel.is_cancelled() {
+                    return WalkState::Quit;
+                }
+                if let Ok(entry) = entry {
+                    let searched = entry.metadata().is_ok_and(|metadata| {
+                        metadata.is_file() && self.large_file.features(metadata.len()).search_index
+                    });
+                    if searched {
+                        visit(entry.path());
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+        Ok(())
+    }
+}
+
+/// The content of a file, unless it can't be read or looks binary.
+fn read_text(path: &Path) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    if content[..content.len().min(BINARY_DETECTION_LEN)].contains(&0) {
+        return None;
+    }
+    String::from_utf8(content).ok()
+}
+
+fn search_file(
+    path: &Path,
+    regex: &Regex,
+    context_lines: usize,
+    cancel: &CancelToken,
+    on_match: &(dyn Fn(SearchMatch) + Sync),
+) {
+    let Some(content) = read_text(path) else {
+        return;
+    };
+    let mut line_starts: Option<Vec<usize>> = None;
+    for found in regex.find_iter(&content) {
+        if cancel.is_cancelled() {
+            return;
+        }
+        // Most files have no match, so their lines are only found for those that do
+        let line_starts = line_starts.get_or_insert_with(|| {
+            std::iter::once(0)
+                .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+                .collect()
+        });
+        let line_text = |line: usize| {
+            let start = line_starts[line];
+            let end = line_starts
+                .get(line + 1)
+                .map_or(content.len(), |next| next - 1);
+            content[start..end].trim_end_matches('\r').to_string()
+        };
+        let line = line_starts.partition_point(|start| *start <= found.start()) - 1;
+        let text = line_text(line);
+        // A match can start or end within the line ending, which the line text leaves out
+        let start = (found.start() - line_starts[line]).min(text.len());
+        let end = (found.end() - line_starts[line]).min(text.len());
+        // The empty line after a final line ending isn't context
+        let last_line = line_starts.len() - 1 - usize::from(content.ends_with('\n'));
+        on_match(SearchMatch {
+            path: path.to_path_buf(),
+            line,
+            range: found.range(),
+            line_range: start..end.max(start),
+            before: (line.saturating_sub(context_lines)..line)
+                .map(line_text)
+                .collect(),
+            after: (line + 1..(line + 1 + context_lines).min(last_line + 1))
+                .map(line_text)
+                .collect(),
+            line_text: text,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Mutex};
+
+    use super::{CancelToken, GlobalSearch, SearchError, SearchMatch, SearchQuery};
+    use crate::buffer::rope_text::RopeText;
+
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[(&str, &[u8])]) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("jihaz-global-search-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            for (path, content) in files {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            }
+            Self(root)
+        }
+
+        fn search(&self, search: &GlobalSearch) -> Vec<SearchMatch> {
+            let matches = Mutex::new(Vec::new());
+            search
+                .run(&CancelToken::new(), &|found| {
+                    matches.lock().unwrap().push(found)
+                })
+                .unwrap();
+            let mut matches = matches.into_inner().unwrap();
+            matches.sort_by(|a, b| (&a.path, a.range.start).cmp(&(&b.path, b.range.start)));
+            matches
+        }
+
+        fn paths(&self, search: &GlobalSearch) -> Vec<String> {
+            let mut paths: Vec<String> = self
+                .search(search)
+                .iter()
+                .map(|found| {
+                    let path = found.path.strip_prefix(&self.0).unwrap();
+                    path.to_string_lossy().replace('\\', "/")
+                })
+                .collect();
+            paths.dedup();
+            paths
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn files() {
+        let tree = Tree::new(
+            "files",
+            &[
+                (".gitignore", b"target/\n*.log\n"),
+                ("src/main.rs", b"// needle\n"),
+                ("src/lib.rs", b"// needle\n"),
+                ("src/gen/out.rs", b"// needle\n"),
+                ("target/debug/build.rs", b"// needle\n"),
+                ("run.log", b"needle\n"),
+                (".hidden/config", b"needle\n"),
+                ("image.bin", b"needle\0\x01\x02"),
+                ("latin1.txt", b"needle \xe9\n"),
+                ("README.md", b"Needle\n"),
+            ],
+        );
+        let mut search = GlobalSearch::new(&tree.0, SearchQuery::new("needle"));
+        assert_eq!(
+            tree.paths(&search),
+            ["README.md", "src/gen/out.rs", "src/lib.rs", "src/main.rs"]
+        );
+
+        search.include = vec!["*.rs".to_string()];
+        search.exclude = vec!["src/gen".to_string()];
+        assert_eq!(tree.paths(&search), ["src/lib.rs", "src/main.rs"]);
+
+        search.include.clear();
+        search.exclude.clear();
+        search.hidden = true;
+        search.respect_ignore = false;
+        search.query.case_sensitive = true;
+        assert_eq!(
+            tree.paths(&search),
+            [
+                ".hidden/config",
+                "run.log",
+                "src/gen/out.rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "target/debug/build.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn large_files_are_skipped() {
+        let tree = Tree::new(
+            "large",
+            &[
+                (
+                    "small.txt",
+                    b"needle
+",
+                ),
+                ("large.log", &[b'n'; 100]),
+            ],
+        );
+        let mut search = GlobalSearch::new(&tree.0, SearchQuery::new("n"));
+        assert_eq!(tree.paths(&search), ["large.log", "small.txt"]);
+
+        search.large_file.threshold = 100;
+        assert_eq!(tree.paths(&search), ["small.txt"]);
+    }
+
+    #[test]
+    fn matches() {
+        let tree = Tree::new(
+            "matches",
+            &[(
+                "a.txt",
+                "one\r\nfoo two foobar\r\nthree\r\nFOO\r\n".as_bytes(),
+            )],
+        );
+        let mut search = GlobalSearch::new(&tree.0, SearchQuery::new("foo"));
+        search.context_lines = 1;
+        let matches = tree.search(&search);
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].line, 1);
+        assert_eq!(matches[0].range, 5..8);
+        assert_eq!(matches[0].line_text, "foo two foobar");
+        assert_eq!(matches[0].line_range, 0..3);
+        assert_eq!(matches[0].before, ["one"]);
+        assert_eq!(matches[0].after, ["three"]);
+        assert_eq!(matches[1].line_range, 8..11);
+        // There is no context after the last line
+        assert_eq!(matches[2].line, 3);
+        assert_eq!(matches[2].after, Vec::<String>::new());
+
+        search.query.whole_word = true;
+        search.query.case_sensitive = true;
+        assert_eq!(tree.search(&search).len(), 1);
+
+        search.query = SearchQuery::new(r"t\w+");
+        search.query.is_regex = true;
+        let matches = tree.search(&search);
+        let found: Vec<_> = matches
+            .iter()
+            .map(|found| &found.line_text[found.line_range.clone()])
+            .collect();
+        assert_eq!(found, ["two", "three"]);
+
+        // Matches in a line ending are past the end of the line text without its `\r`
+        for (pattern, range) in [(r"\r\n", 3..5), (r"\n", 4..5)] {
+            search.query.pattern = pattern.to_string();
+            let matches = tree.search(&search);
+            assert_eq!(matches[0].range, range);
+            assert_eq!(matches[0].line_text, "one");
+            assert_eq!(matches[0].line_range, 3..3);
+        }
+
+        search.query.pattern = "(".to_string();
+        assert!(matches!(
+            search.run(&CancelToken::new(), &|_| {}),
+            Err(SearchError::InvalidPattern(_))
+        ));
+        search.query.pattern = "a".to_string();
+        search.include = vec!["[".to_string()];
+        assert!(matches!(
+            search.clone().spawn(CancelToken::new()),
+            Err(SearchError::InvalidGlob(_))
+        ));
+    }
+
+    #[test]
+    fn channel() {
+        let files: Vec<(String, Vec<u8>)> = (0..50)
+            .map(|i| (format!("{i}.txt"), b"needle\nneedle\n".to_vec()))
+            .collect();
+        let files: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_slice()))
+            .collect();
+        let tree = Tree::new("channel", &files);
+        let search = GlobalSearch::new(&tree.0, SearchQuery::new("needle"));
+
+        let receiver = search.clone().spawn(CancelToken::new()).unwrap();
+        assert_eq!(receiver.iter().count(), 100);
+
+        // A cancelled search stops sending
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let receiver = search.spawn(cancel).unwrap();
+        assert_eq!(receiver.iter().count(), 0);
+    }
+
+    #[test]
+    fn replace() {
+        let tree = Tree::new(
+            "replace",
+            &[
+                ("a.rs", b"let old_name = 1;\nold_name + old_name\n"),
+                ("b.rs", b"nothing here\n"),
+                ("c.rs", b"fn old_name() {}\r\n"),
+            ],
+        );
+        let mut search = GlobalSearch::new(&tree.0, SearchQuery::new("old_name"));
+        let mut buffers = search.replace_all("new_name", &CancelToken::new()).unwrap();
+        assert_eq!(buffers.len(), 2);
+        let (path, buffer) = &mut buffers[0];
+        assert!(path.ends_with("a.rs"));
+        assert_eq!(
+            buffer.to_string(),
+            "let new_name = 1;\nnew_name + new_name\n"
+        );
+        assert_eq!(buffers[1].1.to_string(), "fn new_name() {}\r\n");
+
+        // Each replacement is one edit, and the files are left as they were
+        buffers[0].1.do_undo();
+        assert_eq!(
+            buffers[0].1.to_string(),
+            "let old_name = 1;\nold_name + old_name\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(tree.0.join("a.rs")).unwrap(),
+            "let old_name = 1;\nold_name + old_name\n"
+        );
+
+        // Regular expressions can use the groups of the match
+        search.query = SearchQuery::new(r"(\w+)_name");
+        search.query.is_regex = true;
+        let (_, buffer) = &mut buffers[0];
+        search.replace_in_buffer(buffer, "${1}Name").unwrap();
+        assert_eq!(buffer.to_string(), "let oldName = 1;\noldName + oldName\n");
+        assert_eq!(buffer.len(), 35);
+    }
+}
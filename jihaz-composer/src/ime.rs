@@ -0,0 +1,214 @@
+//! Input method composition, such as of Arabic, CJK or dead-key input.
+//!
+//! While the user composes, the input method sends the composition text, the preedit, again on
+//! every key, and the text it commits at the end. The preedit is kept on the [`Cursor`] and only
+//! drawn, so the buffer and its undo history never see the intermediate text: committing inserts
+//! the committed text as one [`EditType::InsertChars`](crate::editor::EditType::InsertChars) edit
+//! at every cursor region, and cancelling drops the preedit.
+
+use std::ops::Range;
+
+use crate::{buffer::rope_text::RopeText, cursor::Cursor};
+
+/// The composition text of an input method, shown at every cursor region until it is committed
+/// or cancelled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preedit {
+    pub text: String,
+    /// The cursor of the input method in `text`, as a range of bytes, or `None` if it's hidden.
+    pub cursor: Option<Range<usize>>,
+}
+
+impl Preedit {
+    /// A preedit with its cursor moved back to character boundaries of `text`.
+    pub fn new(text: impl Into<String>, cursor: Option<Range<usize>>) -> Self {
+        let text = text.into();
+        let boundary = |mut offset: usize| {
+            offset = offset.min(text.len());
+            while !text.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            offset
+        };
+        let cursor = cursor.map(|cursor| {
+            let start = boundary(cursor.start);
+            start..boundary(cursor.end).max(start)
+        });
+        Self { text, cursor }
+    }
+}
+
+/// A line as it is drawn while composing, with the preedit shown at every cursor region on it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreeditLine {
+    /// The content of the line without its line ending, with the preedit inserted.
+    pub text: String,
+    /// The ranges of `text` that are preedit, to underline.
+    pub preedit: Vec<Range<usize>>,
+    /// The ranges of `text` where the cursor of the input method is, one per preedit if shown.
+    pub cursors: Vec<Range<usize>>,
+    /// The columns of the line where the preedit is inserted.
+    columns: Vec<usize>,
+    preedit_len: usize,
+}
+
+impl PreeditLine {
+    /// The column of `text` that shows the column `col` of the line. The text at a column where
+    /// the preedit is inserted is shown after it.
+    pub fn display_column(&self, col: usize) -> usize {
+        let before = self.columns.iter().filter(|column| **column <= col).count();
+        col + before * self.preedit_len
+    }
+}
+
+/// The line `line` as it is drawn with the preedit of `cursor`, or `None` if there is no preedit
+/// on it. The preedit is shown at the start of every region, where the committed text will go;
+/// the selected text is only replaced when it is committed.
+pub fn preedit_line(text: &impl RopeText, cursor: &Cursor, line: usize) -> Option<PreeditLine> {
+    let preedit = cursor.preedit.as_ref()?;
+    let line_start = text.offset_of_line(line);
+    let line_end = text.line_end_offset(line, true);
+    let mut columns: Vec<usize> = cursor
+        .regions_iter()
+        .map(|(start, end)| start.min(end))
+        .filter(|offset| (line_start..=line_end).contains(offset))
+        .map(|offset| offset - line_start)
+        .collect();
+    if columns.is_empty() {
+        return None;
+    }
+    columns.sort_unstable();
+    columns.dedup();
+
+    let content = text.slice_to_cow(line_start..line_end);
+    let mut line = PreeditLine {
+        text: String::with_capacity(content.len() + columns.len() * preedit.text.len()),
+        preedit: Vec::with_capacity(columns.len()),
+        cursors: Vec::new(),
+        columns,
+        preedit_len: preedit.text.len(),
+    };
+    let mut last = 0;
+    for &column in &line.columns {
+        line.text.push_str(&content[last..column]);
+        let start = line.text.len();
+        line.text.push_str(&preedit.text);
+        line.preedit.push(start..line.text.len());
+        if let Some(cursor) = &preedit.cursor {
+            line.cursors.push(start + cursor.start..start + cursor.end);
+        }
+        last = column;
+    }
+    line.text.push_str(&content[last..]);
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preedit_line, Preedit};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        cursor::{Cursor, CursorMode},
+        selection::{SelRegion, Selection},
+    };
+
+    fn cursor(regions: &[(usize, usize)]) -> Cursor {
+        let mut selection = Selection::new();
+        for &(start, end) in regions {
+            selection.add_region(SelRegion::new(start, end, None));
+        }
+        Cursor::new(CursorMode::Insert(selection), None, None)
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn preedit_cursor_on_char_boundaries() {
+        let preedit = Preedit::new("مر", Some(1..3));
+        assert_eq!(preedit.cursor, Some(0..2));
+        let preedit = Preedit::new("ab", Some(2..1));
+        assert_eq!(preedit.cursor, Some(2..2));
+        let preedit = Preedit::new("ab", Some(5..9));
+        assert_eq!(preedit.cursor, Some(2..2));
+    }
+
+    #[test]
+    fn overlay() {
+        let buffer = Buffer::new("abc\r\ndef\nghi");
+        let mut cursor = cursor(&[(1, 1), (3, 3), (10, 12)]);
+        assert_eq!(preedit_line(&buffer, &cursor, 0), None);
+
+        cursor.set_preedit(Preedit::new("xy", Some(1..1)));
+        let line = preedit_line(&buffer, &cursor, 0).unwrap();
+        assert_eq!(line.text, "axybcxy");
+        assert_eq!(line.preedit, [1..3, 5..7]);
+        assert_eq!(line.cursors, [2..2, 6..6]);
+        assert_eq!(line.display_column(0), 0);
+        assert_eq!(line.display_column(1), 3);
+        assert_eq!(line.display_column(3), 7);
+
+        assert_eq!(preedit_line(&buffer, &cursor, 1), None);
+        // The selected text is still shown, after the preedit
+        let line = preedit_line(&buffer, &cursor, 2).unwrap();
+        assert_eq!(line.text, "gxyhi");
+        assert_eq!(buffer.line_content(2), "ghi");
+    }
+
+    #[test]
+    fn hidden_cursor() {
+        let buffer = Buffer::new("ab");
+        let mut cursor = cursor(&[(2, 2)]);
+        cursor.set_preedit(Preedit::new("ç", None));
+        let line = preedit_line(&buffer, &cursor, 0).unwrap();
+        assert_eq!(line.text, "abç");
+        assert_eq!(line.preedit.len(), 1);
+        assert_eq!(line.preedit[0], 2..4);
+        assert!(line.cursors.is_empty());
+    }
+
+    #[test]
+    fn commit() {
+        let mut buffer = Buffer::new("ab\ncd");
+        let mut cursor = cursor(&[(1, 1), (3, 5)]);
+        cursor.set_preedit(Preedit::new("ك", None));
+        cursor.set_preedit(Preedit::new("كت", None));
+        assert_eq!(buffer.to_string(), "ab\ncd");
+
+        let (_, _, inval_lines) = cursor.commit_preedit(&mut buffer, "كتب").unwrap();
+        assert_eq!(buffer.to_string(), "aكتبb\nكتب");
+        assert_eq!(inval_lines.start_line, 0);
+        assert_eq!(cursor.preedit, None);
+        assert_eq!(
+            cursor.regions_iter().collect::<Vec<_>>(),
+            [(7, 7), (15, 15)]
+        );
+
+        // A single undo reverts it
+        buffer.do_undo();
+        assert_eq!(buffer.to_string(), "ab\ncd");
+    }
+
+    #[test]
+    fn cancel() {
+        let mut buffer = Buffer::new("ab");
+        let mut cursor = cursor(&[(1, 1)]);
+        let rev = buffer.rev();
+        cursor.set_preedit(Preedit::new("´", Some(0..2)));
+        cursor.cancel_preedit();
+        assert_eq!(cursor.preedit, None);
+        assert!(cursor.commit_preedit(&mut buffer, "").is_none());
+        assert_eq!(buffer.rev(), rev);
+        assert!(buffer.do_undo().is_none());
+
+        cursor.set_preedit(Preedit::new("x", None));
+        cursor.set_preedit(Preedit::new("", None));
+        assert_eq!(cursor.preedit, None);
+    }
+
+    #[test]
+    fn commit_only_in_insert_mode() {
+        let mut buffer = Buffer::new("ab");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        assert!(cursor.commit_preedit(&mut buffer, "x").is_none());
+        assert_eq!(buffer.to_string(), "ab");
+    }
+}
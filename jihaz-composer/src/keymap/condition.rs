@@ -0,0 +1,188 @@
+//! The `when` conditions of key bindings, e.g. `editor_focus && !list_focus`.
+
+use std::{fmt, str::FromStr};
+
+use super::KeymapError;
+
+/// A boolean expression over named context flags, which restricts when a key binding applies.
+///
+/// Flags are made of letters, digits, `_` and `.`, and can be combined with `!`, `&&`, `||` and
+/// parentheses, where `&&` binds tighter than `||`.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::keymap::Condition;
+/// let condition: Condition = "editor_focus && !(completion_visible || snippet_active)".parse().unwrap();
+/// assert!(condition.eval(&|flag| flag == "editor_focus"));
+/// assert!(!condition.eval(&|flag| flag == "editor_focus" || flag == "snippet_active"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Flag(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Evaluate the condition, with `is_set` telling whether a flag is set in the current
+    /// context.
+    pub fn eval(&self, is_set: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Condition::Flag(flag) => is_set(flag),
+            Condition::Not(condition) => !condition.eval(is_set),
+            Condition::And(a, b) => a.eval(is_set) && b.eval(is_set),
+            Condition::Or(a, b) => a.eval(is_set) || b.eval(is_set),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input: s, rest: s };
+        let condition = parser.or()?;
+        parser.skip_whitespace();
+        if !parser.rest.is_empty() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(condition)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Flag(flag) => f.write_str(flag),
+            Condition::Not(condition) => match condition.as_ref() {
+                Condition::Flag(_) | Condition::Not(_) => write!(f, "!{condition}"),
+                _ => write!(f, "!({condition})"),
+            },
+            Condition::And(a, b) => {
+                for (i, operand) in [a, b].into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" && ")?;
+                    }
+                    match operand.as_ref() {
+                        Condition::Or(..) => write!(f, "({operand})")?,
+                        _ => write!(f, "{operand}")?,
+                    }
+                }
+                Ok(())
+            }
+            Condition::Or(a, b) => write!(f, "{a} || {b}"),
+        }
+    }
+}
+
+/// A recursive descent parser of conditions.
+struct Parser<'a> {
+    input: &'a str,
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> KeymapError {
+        KeymapError::InvalidCondition {
+            condition: self.input.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, KeymapError> {
+        let mut condition = self.and()?;
+        while self.eat("||") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, KeymapError> {
+        let mut condition = self.unary()?;
+        while self.eat("&&") {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, KeymapError> {
+        if self.eat("!") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let condition = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("expected `)`"));
+            }
+            return Ok(condition);
+        }
+
+        self.skip_whitespace();
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(self.rest.len());
+        if len == 0 {
+            return Err(self.error("expected a flag"));
+        }
+        let flag = &self.rest[..len];
+        self.rest = &self.rest[len..];
+        Ok(Condition::Flag(flag.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Condition;
+
+    fn eval(condition: &str, flags: &[&str]) -> bool {
+        condition
+            .parse::<Condition>()
+            .unwrap()
+            .eval(&|flag| flags.contains(&flag))
+    }
+
+    #[test]
+    fn precedence() {
+        // `&&` binds tighter than `||`
+        assert!(eval("a || b && c", &["a"]));
+        assert!(!eval("(a || b) && c", &["a"]));
+        assert!(eval("!a && b", &["b"]));
+        assert!(!eval("!(a || b)", &["b"]));
+        assert!(eval("!!a", &["a"]));
+        assert!(eval("editor.focus", &["editor.focus"]));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for condition in ["a || b && c", "(a || b) && !c", "!(a && b)", "!!a"] {
+            let parsed: Condition = condition.parse().unwrap();
+            assert_eq!(parsed.to_string(), condition);
+            assert_eq!(parsed.to_string().parse::<Condition>().unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        for condition in ["", "a &&", "(a", "a b", "a & b", "!"] {
+            assert!(condition.parse::<Condition>().is_err(), "{condition}");
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Loading key bindings from TOML and JSON documents.
+//!
+//! Both formats describe a list of bindings under `keymaps`, and JSON also accepts the list on
+//! its own:
+//!
+//! ```toml
+//! [[keymaps]]
+//! key = "ctrl+k ctrl+c"
+//! command = "toggle_line_comment"
+//!
+//! [[keymaps]]
+//! key = "g g"
+//! command = "go_to_line_default_first"
+//! mode = "nv"
+//!
+//! [[keymaps]]
+//! key = "tab"
+//! command = "-insert_tab"
+//! when = "completion_visible"
+//! ```
+//!
+//! A command prefixed with `-` removes the bindings of that command to that key instead of
+//! adding one.
+
+use toml::Value;
+
+use super::{Condition, KeyPress, Keymap, KeymapEntry, KeymapError, KeymapSource};
+use crate::mode::Modes;
+
+pub(super) fn load_toml(
+    content: &str,
+    source: KeymapSource,
+) -> Result<Vec<KeymapEntry>, KeymapError> {
+    let value: Value =
+        toml::from_str(content).map_err(|err| KeymapError::Syntax(err.to_string()))?;
+    load_value(value, source)
+}
+
+pub(super) fn load_json(
+    content: &str,
+    source: KeymapSource,
+) -> Result<Vec<KeymapEntry>, KeymapError> {
+    let value: Value =
+        serde_json::from_str(content).map_err(|err| KeymapError::Syntax(err.to_string()))?;
+    load_value(value, source)
+}
+
+fn load_value(value: Value, source: KeymapSource) -> Result<Vec<KeymapEntry>, KeymapError> {
+    let entries = match value {
+        Value::Array(entries) => entries,
+        Value::Table(mut table) => match table.remove("keymaps") {
+            Some(Value::Array(entries)) => entries,
+            Some(_) => return Err(KeymapError::Syntax("`keymaps` is not a list".to_string())),
+            None => Vec::new(),
+        },
+        _ => {
+            return Err(KeymapError::Syntax(
+                "expected a list of keymaps".to_string(),
+            ))
+        }
+    };
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| load_entry(index, entry, source))
+        .collect()
+}
+
+fn load_entry(
+    index: usize,
+    entry: &Value,
+    source: KeymapSource,
+) -> Result<KeymapEntry, KeymapError> {
+    let field = |name: &'static str| -> Result<Option<&str>, KeymapError> {
+        match entry.get(name) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(KeymapError::InvalidField { index, field: name }),
+        }
+    };
+    let required =
+        |name: &'static str| field(name)?.ok_or(KeymapError::MissingField { index, field: name });
+
+    let keys = KeyPress::parse_sequence(required("key")?)?;
+    let command = required("command")?;
+    let modes = field("mode")?.map(Modes::parse).unwrap_or(Modes::empty());
+    let when = field("when")?
+        .map(|when| when.parse::<Condition>())
+        .transpose()?;
+
+    let (command, remove) = match command.strip_prefix('-') {
+        Some(command) => (command, true),
+        None => (command, false),
+    };
+    let command = command
+        .parse()
+        .map_err(|_| KeymapError::UnknownCommand(command.to_string()))?;
+
+    let keymap = Keymap {
+        keys,
+        modes,
+        when,
+        command,
+        source,
+    };
+    Ok(if remove {
+        KeymapEntry::Unbind(keymap)
+    } else {
+        KeymapEntry::Bind(keymap)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_json, load_toml};
+    use crate::{
+        command::{CommandKind, EditCommand, MoveCommand},
+        keymap::{KeyPress, KeymapEntry, KeymapError, KeymapSource},
+        mode::Modes,
+    };
+
+    #[test]
+    fn toml_and_json_agree() {
+        let toml = r#"
+            [[keymaps]]
+            key = "g g"
+            command = "go_to_line_default_first"
+            mode = "nv"
+
+            [[keymaps]]
+            key = "tab"
+            command = "-insert_tab"
+            when = "completion_visible"
+        "#;
+        let json = r#"[
+            { "key": "g g", "command": "go_to_line_default_first", "mode": "nv" },
+            { "key": "tab", "command": "-insert_tab", "when": "completion_visible" }
+        ]"#;
+
+        let entries = load_toml(toml, KeymapSource::User).unwrap();
+        assert_eq!(entries, load_json(json, KeymapSource::User).unwrap());
+
+        let KeymapEntry::Bind(keymap) = &entries[0] else {
+            panic!("expected a binding");
+        };
+        assert_eq!(keymap.keys, KeyPress::parse_sequence("g g").unwrap());
+        assert_eq!(keymap.modes, Modes::NORMAL | Modes::VISUAL);
+        assert_eq!(
+            keymap.command,
+            CommandKind::Move(MoveCommand::GotoLineDefaultFirst)
+        );
+
+        let KeymapEntry::Unbind(keymap) = &entries[1] else {
+            panic!("expected a removal");
+        };
+        assert_eq!(keymap.command, CommandKind::Edit(EditCommand::InsertTab));
+        assert!(keymap.modes.is_empty());
+        assert!(keymap.when.is_some());
+    }
+
+    #[test]
+    fn errors() {
+        let load = |toml: &str| load_toml(toml, KeymapSource::User).unwrap_err();
+
+        assert!(matches!(load("[[keymaps]"), KeymapError::Syntax(_)));
+        assert_eq!(
+            load("[[keymaps]]\nkey = \"x\""),
+            KeymapError::MissingField {
+                index: 0,
+                field: "command"
+            }
+        );
+        assert_eq!(
+            load("[[keymaps]]\nkey = \"x\"\ncommand = \"undo\"\n[[keymaps]]\nkey = 1\ncommand = \"undo\""),
+            KeymapError::InvalidField {
+                index: 1,
+                field: "key"
+            }
+        );
+        assert_eq!(
+            load("[[keymaps]]\nkey = \"x\"\ncommand = \"fly\""),
+            KeymapError::UnknownCommand("fly".to_string())
+        );
+        assert_eq!(
+            load("[[keymaps]]\nkey = \"hyper+x\"\ncommand = \"undo\""),
+            KeymapError::InvalidKey("hyper+x".to_string())
+        );
+        assert!(matches!(
+            load("[[keymaps]]\nkey = \"x\"\ncommand = \"undo\"\nwhen = \"a &&\""),
+            KeymapError::InvalidCondition { .. }
+        ));
+    }
+}
@@ -0,0 +1,444 @@
+//! Key bindings from key chords and sequences to commands.
+//!
+//! A [`Keymaps`] holds layers of bindings, the default vim or non-modal bindings first and the
+//! user's after them, loaded from TOML or JSON. Later bindings take precedence over earlier
+//! ones, so user bindings override the defaults. A [`KeyPressState`] feeds key presses through
+//! the bindings, keeping track of pending sequences and count prefixes.
+
+mod condition;
+mod loader;
+mod press;
+mod state;
+
+use std::fmt;
+
+pub use condition::Condition;
+pub use press::{Key, KeyPress, Modifiers, NamedKey};
+pub use state::{KeyPressState, KeymapEvent, DEFAULT_TIMEOUT};
+
+use crate::{
+    command::CommandKind,
+    mode::{Mode, Modes},
+};
+
+/// Where a binding comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeymapSource {
+    Default,
+    User,
+}
+
+/// A binding of a key sequence to a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    pub keys: Vec<KeyPress>,
+    /// The modes the binding applies in, or all modes if empty.
+    pub modes: Modes,
+    pub when: Option<Condition>,
+    pub command: CommandKind,
+    pub source: KeymapSource,
+}
+
+impl Keymap {
+    /// Whether the binding applies in `mode` when the context flags are given by `is_set`.
+    pub fn applies(&self, mode: Mode, is_set: &dyn Fn(&str) -> bool) -> bool {
+        (self.modes.is_empty() || self.modes.contains(Modes::from(mode)))
+            && self.when.as_ref().is_none_or(|when| when.eval(is_set))
+    }
+
+    /// Whether there is a mode in which both bindings apply.
+    fn modes_overlap(&self, other: &Keymap) -> bool {
+        self.modes.is_empty() || other.modes.is_empty() || self.modes.intersects(other.modes)
+    }
+}
+
+/// An entry of a keymap file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeymapEntry {
+    /// Add a binding.
+    Bind(Keymap),
+    /// Remove the earlier bindings of the same command to the same keys, in the given modes.
+    /// Written with the command prefixed with `-`.
+    Unbind(Keymap),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    /// The document isn't valid TOML or JSON, or isn't a list of keymaps.
+    Syntax(String),
+    /// A keymap lacks a required field.
+    MissingField {
+        index: usize,
+        field: &'static str,
+    },
+    /// A field of a keymap is not a string.
+    InvalidField {
+        index: usize,
+        field: &'static str,
+    },
+    InvalidKey(String),
+    UnknownCommand(String),
+    InvalidCondition {
+        condition: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Syntax(err) => write!(f, "Invalid keymap file: {err}"),
+            KeymapError::MissingField { index, field } => {
+                write!(f, "Keymap {index} has no `{field}`")
+            }
+            KeymapError::InvalidField { index, field } => {
+                write!(f, "The `{field}` of keymap {index} is not a string")
+            }
+            KeymapError::InvalidKey(key) => write!(f, "Invalid key `{key}`"),
+            KeymapError::UnknownCommand(command) => write!(f, "Unknown command `{command}`"),
+            KeymapError::InvalidCondition { condition, reason } => {
+                write!(f, "Invalid condition `{condition}`: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both bindings have the same keys, so only the later one can ever be used.
+    SameKeys,
+    /// The keys of the first binding are a prefix of the keys of the second one, so the first
+    /// binding only runs after the pending sequence times out.
+    Prefix,
+}
+
+/// Two bindings that get in each other's way in some mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeymapConflict<'a> {
+    pub kind: ConflictKind,
+    pub first: &'a Keymap,
+    pub second: &'a Keymap,
+}
+
+/// The result of looking up a key sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Lookup<'a> {
+    /// The binding of exactly these keys, if any.
+    pub exact: Option<&'a Keymap>,
+    /// Whether there are bindings of longer sequences that start with these keys.
+    pub has_longer: bool,
+}
+
+/// The key bindings in effect, in order of increasing precedence.
+#[derive(Clone, Debug, Default)]
+pub struct Keymaps {
+    keymaps: Vec<Keymap>,
+}
+
+impl Keymaps {
+    /// The default bindings of the modal, vim-like editor.
+    pub fn default_vim() -> Self {
+        Self::default_from(include_str!("defaults/vim.toml"))
+    }
+
+    /// The default bindings of the non-modal editor.
+    pub fn default_non_modal() -> Self {
+        Self::default_from(include_str!("defaults/non_modal.toml"))
+    }
+
+    fn default_from(content: &str) -> Self {
+        let mut keymaps = Self::default();
+        keymaps
+            .load_toml(content, KeymapSource::Default)
+            .expect("the default keymaps are valid");
+        keymaps
+    }
+
+    pub fn keymaps(&self) -> &[Keymap] {
+        &self.keymaps
+    }
+
+    /// Load bindings from a TOML document, on top of the existing ones.
+    /// Nothing is loaded if the document has an error.
+    pub fn load_toml(&mut self, content: &str, source: KeymapSource) -> Result<(), KeymapError> {
+        let entries = loader::load_toml(content, source)?;
+        self.extend(entries);
+        Ok(())
+    }
+
+    /// Load bindings from a JSON document, on top of the existing ones.
+    /// Nothing is loaded if the document has an error.
+    pub fn load_json(&mut self, content: &str, source: KeymapSource) -> Result<(), KeymapError> {
+        let entries = loader::load_json(content, source)?;
+        self.extend(entries);
+        Ok(())
+    }
+
+    /// Apply `entries` on top of the existing bindings.
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = KeymapEntry>) {
+        for entry in entries {
+            match entry {
+                KeymapEntry::Bind(keymap) => self.keymaps.push(keymap),
+                KeymapEntry::Unbind(removal) => self.unbind(&removal),
+            }
+        }
+    }
+
+    fn unbind(&mut self, removal: &Keymap) {
+        self.keymaps.retain_mut(|keymap| {
+            if keymap.keys != removal.keys || keymap.command != removal.command {
+                return true;
+            }
+            if removal.modes.is_empty() {
+                return false;
+            }
+            if keymap.modes.is_empty() {
+                keymap.modes = Modes::all();
+            }
+            keymap.modes.remove(removal.modes);
+            !keymap.modes.is_empty()
+        });
+    }
+
+    /// Look up the binding of `keys` in `mode`, and whether longer sequences start with them.
+    pub fn lookup(
+        &self,
+        keys: &[KeyPress],
+        mode: Mode,
+        is_set: &dyn Fn(&str) -> bool,
+    ) -> Lookup<'_> {
+        let mut lookup = Lookup::default();
+        for keymap in self.keymaps.iter().rev() {
+            if !keymap.keys.starts_with(keys) || !keymap.applies(mode, is_set) {
+                continue;
+            }
+            if keymap.keys.len() == keys.len() {
+                lookup.exact = lookup.exact.or(Some(keymap));
+            } else {
+                lookup.has_longer = true;
+            }
+        }
+        lookup
+    }
+
+    /// The bindings of `command`, with the ones that take precedence first.
+    pub fn bindings_of<'a>(
+        &'a self,
+        command: &'a CommandKind,
+    ) -> impl Iterator<Item = &'a Keymap> + 'a {
+        self.keymaps
+            .iter()
+            .rev()
+            .filter(move |keymap| &keymap.command == command)
+    }
+
+    /// Find the bindings that get in each other's way.
+    ///
+    /// Bindings of the same keys only conflict when they come from the same source, since a
+    /// user binding is meant to override a default one. Bindings whose `when` conditions differ
+    /// are assumed to apply in different contexts.
+    pub fn conflicts(&self) -> Vec<KeymapConflict<'_>> {
+        let mut conflicts = Vec::new();
+        for (i, first) in self.keymaps.iter().enumerate() {
+            for second in &self.keymaps[i + 1..] {
+                if !first.modes_overlap(second) || first.when != second.when {
+                    continue;
+                }
+                let kind = if first.keys == second.keys {
+                    if first.source != second.source || first.command == second.command {
+                        continue;
+                    }
+                    ConflictKind::SameKeys
+                } else if second.keys.starts_with(&first.keys) {
+                    ConflictKind::Prefix
+                } else if first.keys.starts_with(&second.keys) {
+                    conflicts.push(KeymapConflict {
+                        kind: ConflictKind::Prefix,
+                        first: second,
+                        second: first,
+                    });
+                    continue;
+                } else {
+                    continue;
+                };
+                conflicts.push(KeymapConflict {
+                    kind,
+                    first,
+                    second,
+                });
+            }
+        }
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConflictKind, KeyPress, KeymapSource, Keymaps};
+    use crate::{
+        command::{CommandKind, EditCommand, MoveCommand},
+        mode::{Mode, VisualMode},
+    };
+
+    fn keys(keys: &str) -> Vec<KeyPress> {
+        KeyPress::parse_sequence(keys).unwrap()
+    }
+
+    fn no_flags(_: &str) -> bool {
+        false
+    }
+
+    #[test]
+    fn default_keymaps_have_no_conflicting_keys() {
+        for keymaps in [Keymaps::default_vim(), Keymaps::default_non_modal()] {
+            let conflicts: Vec<_> = keymaps
+                .conflicts()
+                .into_iter()
+                .filter(|conflict| conflict.kind == ConflictKind::SameKeys)
+                .collect();
+            assert!(conflicts.is_empty(), "{conflicts:#?}");
+        }
+    }
+
+    #[test]
+    fn lookup_respects_modes() {
+        let keymaps = Keymaps::default_vim();
+
+        let lookup = keymaps.lookup(&keys("j"), Mode::Normal, &no_flags);
+        assert_eq!(
+            lookup.exact.unwrap().command,
+            CommandKind::Move(MoveCommand::Down)
+        );
+        assert!(keymaps
+            .lookup(&keys("j"), Mode::Insert, &no_flags)
+            .exact
+            .is_none());
+
+        let lookup = keymaps.lookup(&keys("g"), Mode::Visual(VisualMode::Normal), &no_flags);
+        assert!(lookup.exact.is_none());
+        assert!(lookup.has_longer);
+    }
+
+    #[test]
+    fn user_bindings_override_defaults() {
+        let mut keymaps = Keymaps::default_non_modal();
+        keymaps
+            .load_toml(
+                r#"
+                [[keymaps]]
+                key = "ctrl+z"
+                command = "redo"
+
+                [[keymaps]]
+                key = "tab"
+                command = "-insert_tab"
+
+                [[keymaps]]
+                key = "ctrl+k ctrl+j"
+                command = "duplicate_line_down"
+                when = "!read_only"
+                "#,
+                KeymapSource::User,
+            )
+            .unwrap();
+
+        let lookup = keymaps.lookup(&keys("ctrl+z"), Mode::Insert, &no_flags);
+        assert_eq!(
+            lookup.exact.unwrap().command,
+            CommandKind::Edit(EditCommand::Redo)
+        );
+        assert!(keymaps
+            .lookup(&keys("tab"), Mode::Insert, &no_flags)
+            .exact
+            .is_none());
+
+        let lookup = keymaps.lookup(&keys("ctrl+k ctrl+j"), Mode::Insert, &no_flags);
+        assert!(lookup.exact.is_some());
+        let lookup = keymaps.lookup(&keys("ctrl+k ctrl+j"), Mode::Insert, &|flag| {
+            flag == "read_only"
+        });
+        assert!(lookup.exact.is_none());
+
+        // Overriding a default isn't a conflict
+        assert!(keymaps
+            .conflicts()
+            .iter()
+            .all(|conflict| conflict.kind != ConflictKind::SameKeys));
+    }
+
+    #[test]
+    fn unbind_in_some_modes() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .load_toml(
+                r#"
+                [[keymaps]]
+                key = "x"
+                command = "delete_forward"
+                mode = "nv"
+
+                [[keymaps]]
+                key = "x"
+                command = "-delete_forward"
+                mode = "v"
+                "#,
+                KeymapSource::User,
+            )
+            .unwrap();
+        assert!(keymaps
+            .lookup(&keys("x"), Mode::Normal, &no_flags)
+            .exact
+            .is_some());
+        assert!(keymaps
+            .lookup(&keys("x"), Mode::Visual(VisualMode::Linewise), &no_flags)
+            .exact
+            .is_none());
+    }
+
+    #[test]
+    fn detect_conflicts() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .load_toml(
+                r#"
+                [[keymaps]]
+                key = "g"
+                command = "undo"
+                mode = "n"
+
+                [[keymaps]]
+                key = "g g"
+                command = "go_to_line_default_first"
+
+                [[keymaps]]
+                key = "ctrl+x"
+                command = "clipboard_cut"
+                mode = "i"
+
+                [[keymaps]]
+                key = "ctrl+x"
+                command = "delete_line"
+                mode = "in"
+
+                [[keymaps]]
+                key = "ctrl+x"
+                command = "delete_forward"
+                mode = "v"
+                "#,
+                KeymapSource::User,
+            )
+            .unwrap();
+
+        let conflicts = keymaps.conflicts();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].kind, ConflictKind::Prefix);
+        assert_eq!(conflicts[0].first.keys, keys("g"));
+        assert_eq!(conflicts[1].kind, ConflictKind::SameKeys);
+        assert_eq!(
+            conflicts[1].second.command,
+            CommandKind::Edit(EditCommand::DeleteLine)
+        );
+    }
+}
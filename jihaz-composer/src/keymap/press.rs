@@ -0,0 +1,290 @@
+//! Key presses and their textual form, e.g. `ctrl+k` or `G`.
+
+use std::{fmt, str::FromStr};
+
+use bitflags::bitflags;
+
+use super::KeymapError;
+
+bitflags! {
+    /// The modifier keys held during a key press.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct Modifiers: u8 {
+        const CTRL = 0x1;
+        const ALT = 0x2;
+        const SHIFT = 0x4;
+        const META = 0x8;
+    }
+}
+
+/// A key that doesn't produce a character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NamedKey {
+    Escape,
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// A function key, `F1` to `F24`.
+    F(u8),
+}
+
+impl NamedKey {
+    fn parse(name: &str) -> Option<Self> {
+        let key = match name.to_lowercase().as_str() {
+            "esc" | "escape" => NamedKey::Escape,
+            "enter" | "return" => NamedKey::Enter,
+            "tab" => NamedKey::Tab,
+            "backspace" => NamedKey::Backspace,
+            "del" | "delete" => NamedKey::Delete,
+            "insert" => NamedKey::Insert,
+            "up" | "arrowup" => NamedKey::Up,
+            "down" | "arrowdown" => NamedKey::Down,
+            "left" | "arrowleft" => NamedKey::Left,
+            "right" | "arrowright" => NamedKey::Right,
+            "home" => NamedKey::Home,
+            "end" => NamedKey::End,
+            "pageup" => NamedKey::PageUp,
+            "pagedown" => NamedKey::PageDown,
+            name => {
+                let n = name.strip_prefix('f')?.parse().ok()?;
+                if !(1..=24).contains(&n) {
+                    return None;
+                }
+                NamedKey::F(n)
+            }
+        };
+        Some(key)
+    }
+}
+
+impl fmt::Display for NamedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamedKey::Escape => f.write_str("esc"),
+            NamedKey::Enter => f.write_str("enter"),
+            NamedKey::Tab => f.write_str("tab"),
+            NamedKey::Backspace => f.write_str("backspace"),
+            NamedKey::Delete => f.write_str("delete"),
+            NamedKey::Insert => f.write_str("insert"),
+            NamedKey::Up => f.write_str("up"),
+            NamedKey::Down => f.write_str("down"),
+            NamedKey::Left => f.write_str("left"),
+            NamedKey::Right => f.write_str("right"),
+            NamedKey::Home => f.write_str("home"),
+            NamedKey::End => f.write_str("end"),
+            NamedKey::PageUp => f.write_str("pageup"),
+            NamedKey::PageDown => f.write_str("pagedown"),
+            NamedKey::F(n) => write!(f, "f{n}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A key that produces a character, as it is produced, so `G` rather than `shift+g`.
+    Char(char),
+    Named(NamedKey),
+}
+
+/// A key along with the modifiers held when it was pressed.
+///
+/// Presses of character keys don't keep the shift modifier, since it is already reflected in
+/// the character, which makes `shift+g` and `G` the same press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub key: Key,
+    pub mods: Modifiers,
+}
+
+impl KeyPress {
+    pub fn new(key: Key, mods: Modifiers) -> Self {
+        let mut key = key;
+        let mut mods = mods;
+        if let Key::Char(c) = key {
+            if c != ' ' && mods.contains(Modifiers::SHIFT) {
+                if c.is_lowercase() {
+                    let mut upper = c.to_uppercase();
+                    if let (Some(upper), None) = (upper.next(), upper.next()) {
+                        key = Key::Char(upper);
+                    }
+                }
+                mods.remove(Modifiers::SHIFT);
+            }
+        }
+        Self { key, mods }
+    }
+
+    /// A press of a character key without any modifier.
+    pub fn char(c: char) -> Self {
+        Self::new(Key::Char(c), Modifiers::empty())
+    }
+
+    /// A press of a named key without any modifier.
+    pub fn named(key: NamedKey) -> Self {
+        Self::new(Key::Named(key), Modifiers::empty())
+    }
+
+    /// The digit of this press, if it is a digit key pressed without modifiers.
+    pub fn digit(&self) -> Option<u32> {
+        match self.key {
+            Key::Char(c) if self.mods.is_empty() => c.to_digit(10),
+            _ => None,
+        }
+    }
+
+    /// Parse a sequence of presses separated by spaces, e.g. `ctrl+k ctrl+c` or `g g`.
+    pub fn parse_sequence(keys: &str) -> Result<Vec<KeyPress>, KeymapError> {
+        let presses = keys
+            .split_whitespace()
+            .map(KeyPress::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if presses.is_empty() {
+            return Err(KeymapError::InvalidKey(keys.to_string()));
+        }
+        Ok(presses)
+    }
+
+    /// Format a sequence of presses the way [`KeyPress::parse_sequence`] parses it.
+    pub fn format_sequence(presses: &[KeyPress]) -> String {
+        presses
+            .iter()
+            .map(|press| press.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl FromStr for KeyPress {
+    type Err = KeymapError;
+
+    /// Parse a single press, such as `ctrl+shift+p`, `alt++` or `space`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || KeymapError::InvalidKey(s.to_string());
+
+        // The key itself may be a `+`, as in `ctrl++`
+        let (mods_str, key_str) = match s.strip_suffix('+') {
+            Some(rest) if rest.is_empty() || rest.ends_with('+') => {
+                (rest.strip_suffix('+').unwrap_or(rest), "+")
+            }
+            Some(_) => return Err(invalid()),
+            None => match s.rsplit_once('+') {
+                Some((mods, key)) => (mods, key),
+                None => ("", s),
+            },
+        };
+
+        let mut mods = Modifiers::empty();
+        if !mods_str.is_empty() {
+            for name in mods_str.split('+') {
+                let modifier = match name.to_lowercase().as_str() {
+                    "ctrl" | "control" => Modifiers::CTRL,
+                    "alt" | "option" => Modifiers::ALT,
+                    "shift" => Modifiers::SHIFT,
+                    "meta" | "cmd" | "super" | "win" => Modifiers::META,
+                    _ => return Err(invalid()),
+                };
+                mods.insert(modifier);
+            }
+        }
+
+        let mut chars = key_str.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c),
+            _ if key_str.eq_ignore_ascii_case("space") => Key::Char(' '),
+            _ if key_str.eq_ignore_ascii_case("plus") => Key::Char('+'),
+            _ => Key::Named(NamedKey::parse(key_str).ok_or_else(invalid)?),
+        };
+        Ok(KeyPress::new(key, mods))
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (Modifiers::CTRL, "ctrl+"),
+            (Modifiers::ALT, "alt+"),
+            (Modifiers::SHIFT, "shift+"),
+            (Modifiers::META, "meta+"),
+        ];
+        for (modifier, name) in names {
+            if self.mods.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.key {
+            Key::Char(' ') => f.write_str("space"),
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Named(key) => write!(f, "{key}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::{Key, KeyPress, Modifiers, NamedKey};
+
+    #[test]
+    fn parse_presses() {
+        assert_eq!(
+            KeyPress::from_str("ctrl+k").unwrap(),
+            KeyPress::new(Key::Char('k'), Modifiers::CTRL)
+        );
+        assert_eq!(
+            KeyPress::from_str("Ctrl+Alt+Delete").unwrap(),
+            KeyPress::new(
+                Key::Named(NamedKey::Delete),
+                Modifiers::CTRL | Modifiers::ALT
+            )
+        );
+        assert_eq!(KeyPress::from_str("shift+g").unwrap(), KeyPress::char('G'));
+        assert_eq!(KeyPress::from_str("G").unwrap(), KeyPress::char('G'));
+        assert_eq!(
+            KeyPress::from_str("shift+tab").unwrap(),
+            KeyPress::new(Key::Named(NamedKey::Tab), Modifiers::SHIFT)
+        );
+        assert_eq!(
+            KeyPress::from_str("ctrl++").unwrap(),
+            KeyPress::new(Key::Char('+'), Modifiers::CTRL)
+        );
+        assert_eq!(KeyPress::from_str("+").unwrap(), KeyPress::char('+'));
+        assert_eq!(KeyPress::from_str("space").unwrap(), KeyPress::char(' '));
+        assert_eq!(
+            KeyPress::from_str("f12").unwrap(),
+            KeyPress::named(NamedKey::F(12))
+        );
+        assert_eq!(KeyPress::from_str("ش").unwrap(), KeyPress::char('ش'));
+
+        assert!(KeyPress::from_str("hyper+k").is_err());
+        assert!(KeyPress::from_str("ctrl+").is_err());
+        assert!(KeyPress::from_str("f25").is_err());
+        assert!(KeyPress::from_str("nothing").is_err());
+    }
+
+    #[test]
+    fn sequences_round_trip() {
+        for keys in [
+            "g g",
+            "ctrl+k ctrl+c",
+            "ctrl+shift+tab",
+            "alt++ space",
+            "z f24",
+        ] {
+            let presses = KeyPress::parse_sequence(keys).unwrap();
+            assert_eq!(KeyPress::format_sequence(&presses), keys);
+        }
+        assert_eq!(KeyPress::parse_sequence("g  g").unwrap().len(), 2);
+        assert!(KeyPress::parse_sequence(" ").is_err());
+    }
+}
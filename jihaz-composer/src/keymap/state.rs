@@ -0,0 +1,400 @@
+//! Matching key presses against key bindings as they come.
+
+use std::time::{Duration, Instant};
+
+use super::{Key, KeyPress, Keymaps, Lookup, Modifiers};
+use crate::{command::CommandKind, mode::Mode};
+
+/// How long a pending sequence waits for its next key, like vim's `timeoutlen`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeymapEvent {
+    /// Run a command, with the count typed before it, if any.
+    Command {
+        command: CommandKind,
+        count: Option<usize>,
+    },
+    /// A press that isn't bound, such as a character to insert.
+    Unhandled(KeyPress),
+}
+
+/// The state of the key presses that were typed but haven't resolved to a command yet: the
+/// presses of an incomplete sequence, and a count prefix such as the `3` of `3j` in the modal
+/// modes.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use std::time::Instant;
+/// # use jihaz_composer::command::{CommandKind, MoveCommand};
+/// # use jihaz_composer::keymap::{KeyPress, KeyPressState, KeymapEvent, Keymaps};
+/// # use jihaz_composer::mode::Mode;
+/// let keymaps = Keymaps::default_vim();
+/// let mut state = KeyPressState::default();
+/// let mut events = Vec::new();
+/// for c in "12gg".chars() {
+///     events = state.key_down(&keymaps, KeyPress::char(c), Mode::Normal, &|_| false, Instant::now());
+/// }
+/// assert_eq!(
+///     events,
+///     [KeymapEvent::Command {
+///         command: CommandKind::Move(MoveCommand::GotoLineDefaultFirst),
+///         count: Some(12)
+///     }]
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct KeyPressState {
+    pending: Vec<KeyPress>,
+    /// The command bound to the pending presses themselves, which runs if the sequence isn't
+    /// continued.
+    pending_match: Option<CommandKind>,
+    count: Option<usize>,
+    last_press: Option<Instant>,
+    timeout: Duration,
+}
+
+impl Default for KeyPressState {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl KeyPressState {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Vec::new(),
+            pending_match: None,
+            count: None,
+            last_press: None,
+            timeout,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The presses of the sequence that is waiting for more keys.
+    pub fn pending(&self) -> &[KeyPress] {
+        &self.pending
+    }
+
+    /// The count typed so far.
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+
+    /// When the pending sequence times out, at which point [`KeyPressState::expire`] should be
+    /// called.
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        self.last_press.map(|last_press| last_press + self.timeout)
+    }
+
+    /// Forget the pending presses and the count.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_match = None;
+        self.count = None;
+    }
+
+    /// End the pending sequence, running the command bound to it if there is one, or else
+    /// giving its presses back as unhandled.
+    pub fn expire(&mut self) -> Vec<KeymapEvent> {
+        let pending = std::mem::take(&mut self.pending);
+        let count = self.count.take();
+        match self.pending_match.take() {
+            Some(command) => vec![KeymapEvent::Command { command, count }],
+            None => pending.into_iter().map(KeymapEvent::Unhandled).collect(),
+        }
+    }
+
+    /// Handle a key press at the time `now`, in `mode` and in the context whose flags are given
+    /// by `is_set`.
+    ///
+    /// Returns the events that the press resolved, which is empty when the press is part of
+    /// a pending sequence or a count.
+    pub fn key_down(
+        &mut self,
+        keymaps: &Keymaps,
+        press: KeyPress,
+        mode: Mode,
+        is_set: &dyn Fn(&str) -> bool,
+        now: Instant,
+    ) -> Vec<KeymapEvent> {
+        let mut events = Vec::new();
+        if self.deadline().is_some_and(|deadline| now >= deadline) {
+            events.extend(self.expire());
+        }
+        self.last_press = Some(now);
+        self.press(keymaps, press, mode, is_set, &mut events);
+        events
+    }
+
+    fn press(
+        &mut self,
+        keymaps: &Keymaps,
+        press: KeyPress,
+        mode: Mode,
+        is_set: &dyn Fn(&str) -> bool,
+        events: &mut Vec<KeymapEvent>,
+    ) {
+        if self.pending.is_empty() && matches!(mode, Mode::Normal | Mode::Visual(_)) {
+            if let Some(digit) = press.digit() {
+                // A `0` that doesn't continue a count is a key of its own
+                let lookup = keymaps.lookup(&[press], mode, is_set);
+                if self.count.is_some() || (lookup.exact.is_none() && !lookup.has_longer) {
+                    let count = self.count.unwrap_or(0);
+                    self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                    return;
+                }
+            }
+        }
+
+        let (keys, lookup) = self.lookup(keymaps, press, mode, is_set);
+        if lookup.has_longer {
+            self.pending_match = lookup.exact.map(|keymap| keymap.command.clone());
+            self.pending = keys;
+            return;
+        }
+        if let Some(keymap) = lookup.exact {
+            events.push(KeymapEvent::Command {
+                command: keymap.command.clone(),
+                count: self.count.take(),
+            });
+            self.pending.clear();
+            self.pending_match = None;
+            return;
+        }
+
+        if self.pending.is_empty() {
+            self.count = None;
+            events.push(KeymapEvent::Unhandled(press));
+            return;
+        }
+        // The press doesn't continue the pending sequence, so end it and start over
+        events.extend(self.expire());
+        self.press(keymaps, press, mode, is_set, events);
+    }
+
+    /// Look up the pending presses followed by `press`.
+    /// A shifted named key, such as `shift+left`, falls back to the binding of the unshifted
+    /// key, leaving it to the caller to extend the selection.
+    fn lookup<'a>(
+        &self,
+        keymaps: &'a Keymaps,
+        press: KeyPress,
+        mode: Mode,
+        is_set: &dyn Fn(&str) -> bool,
+    ) -> (Vec<KeyPress>, Lookup<'a>) {
+        let mut keys = self.pending.clone();
+        keys.push(press);
+        let lookup = keymaps.lookup(&keys, mode, is_set);
+        if lookup.exact.is_some()
+            || lookup.has_longer
+            || !press.mods.contains(Modifiers::SHIFT)
+            || !matches!(press.key, Key::Named(_))
+        {
+            return (keys, lookup);
+        }
+
+        let unshifted = KeyPress::new(press.key, press.mods - Modifiers::SHIFT);
+        *keys.last_mut().unwrap() = unshifted;
+        let unshifted_lookup = keymaps.lookup(&keys, mode, is_set);
+        if unshifted_lookup.exact.is_none() && !unshifted_lookup.has_longer {
+            *keys.last_mut().unwrap() = press;
+            return (keys, lookup);
+        }
+        (keys, unshifted_lookup)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{KeyPressState, KeymapEvent};
+    use crate::{
+        command::{CommandKind, EditCommand, MotionModeCommand, MoveCommand},
+        keymap::{Key, KeyPress, KeymapSource, Keymaps, Modifiers, NamedKey},
+        mode::Mode,
+    };
+
+    struct Typist {
+        keymaps: Keymaps,
+        state: KeyPressState,
+        now: Instant,
+    }
+
+    impl Typist {
+        fn new(keymaps: Keymaps) -> Self {
+            Self {
+                keymaps,
+                state: KeyPressState::new(Duration::from_millis(500)),
+                now: Instant::now(),
+            }
+        }
+
+        fn type_keys(&mut self, keys: &str, mode: Mode) -> Vec<KeymapEvent> {
+            let mut events = Vec::new();
+            for press in KeyPress::parse_sequence(keys).unwrap() {
+                self.now += Duration::from_millis(100);
+                events.extend(self.state.key_down(
+                    &self.keymaps,
+                    press,
+                    mode,
+                    &|_| false,
+                    self.now,
+                ));
+            }
+            events
+        }
+
+        fn wait(&mut self, millis: u64) {
+            self.now += Duration::from_millis(millis);
+        }
+    }
+
+    fn command(command: CommandKind, count: Option<usize>) -> KeymapEvent {
+        KeymapEvent::Command { command, count }
+    }
+
+    #[test]
+    fn counts() {
+        let mut typist = Typist::new(Keymaps::default_vim());
+        assert_eq!(
+            typist.type_keys("3 j", Mode::Normal),
+            [command(CommandKind::Move(MoveCommand::Down), Some(3))]
+        );
+        // `0` is a motion unless it continues a count
+        assert_eq!(
+            typist.type_keys("0", Mode::Normal),
+            [command(CommandKind::Move(MoveCommand::LineStart), None)]
+        );
+        assert_eq!(
+            typist.type_keys("1 0 0 G", Mode::Normal),
+            [command(
+                CommandKind::Move(MoveCommand::GotoLineDefaultLast),
+                Some(100)
+            )]
+        );
+        // Digits are characters to insert in insert mode
+        assert_eq!(
+            typist.type_keys("3", Mode::Insert),
+            [KeymapEvent::Unhandled(KeyPress::char('3'))]
+        );
+        assert_eq!(typist.state.count(), None);
+    }
+
+    #[test]
+    fn sequences() {
+        let mut typist = Typist::new(Keymaps::default_vim());
+        assert!(typist.type_keys("g", Mode::Normal).is_empty());
+        assert_eq!(typist.state.pending(), [KeyPress::char('g')]);
+        assert_eq!(
+            typist.type_keys("g", Mode::Normal),
+            [command(
+                CommandKind::Move(MoveCommand::GotoLineDefaultFirst),
+                None
+            )]
+        );
+        assert_eq!(
+            typist.type_keys("2 g U", Mode::Normal),
+            [command(
+                CommandKind::MotionMode(MotionModeCommand::MotionModeUpperCase),
+                Some(2)
+            )]
+        );
+
+        // A sequence that isn't bound gives its presses back
+        assert_eq!(
+            typist.type_keys("g q", Mode::Normal),
+            [
+                KeymapEvent::Unhandled(KeyPress::char('g')),
+                KeymapEvent::Unhandled(KeyPress::char('q'))
+            ]
+        );
+        // And the press that broke it is matched on its own
+        assert_eq!(
+            typist.type_keys("g j", Mode::Normal),
+            [
+                KeymapEvent::Unhandled(KeyPress::char('g')),
+                command(CommandKind::Move(MoveCommand::Down), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn ambiguous_sequences_time_out() {
+        let mut keymaps = Keymaps::default_non_modal();
+        keymaps
+            .load_toml(
+                r#"
+                [[keymaps]]
+                key = "j k"
+                command = "normal_mode"
+                mode = "i"
+
+                [[keymaps]]
+                key = "ctrl+k"
+                command = "delete_to_end_of_line"
+                "#,
+                KeymapSource::User,
+            )
+            .unwrap();
+        let mut typist = Typist::new(keymaps);
+
+        assert_eq!(
+            typist.type_keys("j k", Mode::Insert),
+            [command(CommandKind::Edit(EditCommand::NormalMode), None)]
+        );
+        // Typing something else gives the `j` back to be inserted
+        assert_eq!(
+            typist.type_keys("j a", Mode::Insert),
+            [
+                KeymapEvent::Unhandled(KeyPress::char('j')),
+                KeymapEvent::Unhandled(KeyPress::char('a'))
+            ]
+        );
+        // So does waiting too long for the `k`
+        assert!(typist.type_keys("j", Mode::Insert).is_empty());
+        typist.wait(600);
+        assert_eq!(
+            typist.type_keys("k", Mode::Insert),
+            [
+                KeymapEvent::Unhandled(KeyPress::char('j')),
+                KeymapEvent::Unhandled(KeyPress::char('k'))
+            ]
+        );
+
+        // `ctrl+k` is bound on its own and as the start of `ctrl+k ctrl+c`, so it runs once
+        // the sequence times out
+        assert!(typist.type_keys("ctrl+k", Mode::Insert).is_empty());
+        let deadline = typist.state.deadline().unwrap();
+        assert_eq!(deadline, typist.now + Duration::from_millis(500));
+        assert_eq!(
+            typist.state.expire(),
+            [command(
+                CommandKind::Edit(EditCommand::DeleteToEndOfLine),
+                None
+            )]
+        );
+        assert_eq!(typist.state.deadline(), None);
+    }
+
+    #[test]
+    fn shifted_named_keys_fall_back() {
+        let mut typist = Typist::new(Keymaps::default_non_modal());
+        let press = KeyPress::new(Key::Named(NamedKey::Left), Modifiers::SHIFT);
+        assert_eq!(
+            typist
+                .state
+                .key_down(&typist.keymaps, press, Mode::Insert, &|_| false, typist.now),
+            [command(CommandKind::Move(MoveCommand::Left), None)]
+        );
+    }
+}
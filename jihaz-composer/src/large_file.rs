@@ -0,0 +1,423 @@
+//! Loading of large files, such as multi-hundred-megabyte logs.
+//!
+//! Files are read in chunks, in the background if wanted, and the rope is built as they come
+//! in. Instead of passes over the whole text, the line ending and the indentation are detected
+//! from a sample of the start of the file. Files above a size threshold are in large-file mode,
+//! in which the features that need the whole text are turned off, see [`BufferFeatures`].
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use lapce_xi_rope::{tree::TreeBuilder, Rope};
+
+use crate::{
+    buffer::Buffer,
+    indent::{auto_detect_indent_style, IndentStyle},
+    line_ending::{LineEnding, LineEndingDetermination},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LargeFileConfig {
+    /// The size in bytes from which a file is in large-file mode.
+    pub threshold: u64,
+    /// How many bytes are read at a time.
+    pub chunk_size: usize,
+    /// How many bytes from the start of the file the line ending and indentation are detected
+    /// from.
+    pub sample_len: usize,
+}
+
+impl Default for LargeFileConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 32 * 1024 * 1024,
+            chunk_size: 1024 * 1024,
+            sample_len: 64 * 1024,
+        }
+    }
+}
+
+impl LargeFileConfig {
+    pub fn is_large(&self, len: u64) -> bool {
+        len >= self.threshold
+    }
+
+    /// The features to enable for a text of `len` bytes.
+    pub fn features(&self, len: u64) -> BufferFeatures {
+        if self.is_large(len) {
+            BufferFeatures::LARGE_FILE
+        } else {
+            BufferFeatures::ALL
+        }
+    }
+}
+
+/// The features that are worth their cost for a buffer, which for large files they are not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferFeatures {
+    /// Reading the whole file to search it along with the others, in a
+    /// [`GlobalSearch`](crate::global_search::GlobalSearch).
+    pub search_index: bool,
+    /// Keeping a [`BracketIndex`](crate::bracket_index::BracketIndex) of the text, see
+    /// [`BracketIndex::with_features`](crate::bracket_index::BracketIndex::with_features).
+    pub bracket_index: bool,
+    /// Using a syntax tree of the text, for the brackets of a bracket index and for
+    /// [`TreeSitterFolds::with_features`](crate::fold::TreeSitterFolds::with_features).
+    pub syntax: bool,
+}
+
+impl BufferFeatures {
+    pub const ALL: Self = Self {
+        search_index: true,
+        bracket_index: true,
+        syntax: true,
+    };
+
+    pub const LARGE_FILE: Self = Self {
+        search_index: false,
+        bracket_index: false,
+        syntax: false,
+    };
+}
+
+/// How far loading a file is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// The number of bytes read.
+    pub loaded: u64,
+    /// The size of the file, if known.
+    pub total: Option<u64>,
+}
+
+impl LoadProgress {
+    /// The fraction of the file that was read, from 0 to 1.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total? {
+            0 => Some(1.0),
+            total => Some((self.loaded as f64 / total as f64).min(1.0)),
+        }
+    }
+}
+
+/// The text of a loaded file, along with what was detected from it.
+#[derive(Clone, Debug)]
+pub struct LoadedText {
+    /// The text, whose lone `\r` are replaced with `line_ending`, and whose invalid UTF-8 is
+    /// replaced with `U+FFFD`.
+    pub text: Rope,
+    pub line_ending: LineEnding,
+    /// The indentation detected from the sample, if any.
+    pub indent_style: Option<IndentStyle>,
+    pub features: BufferFeatures,
+}
+
+impl LoadedText {
+    /// Read the text from `reader`, calling `progress` after each chunk. `total` is the size of
+    /// the text if known, e.g. from the file's metadata. A memory-mapped file can be read from
+    /// its byte slice.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::{large_file::{LargeFileConfig, LoadedText}, line_ending::LineEnding};
+    /// let config = LargeFileConfig { chunk_size: 4, ..Default::default() };
+    /// let mut updates = 0;
+    /// let loaded =
+    ///     LoadedText::read(&b"one\r\ntwo\r\n"[..], Some(10), &config, |_| updates += 1).unwrap();
+    /// assert_eq!(String::from(&loaded.text), "one\r\ntwo\r\n");
+    /// assert_eq!(loaded.line_ending, LineEnding::CrLf);
+    /// assert_eq!(updates, 3);
+    /// ```
+    pub fn read(
+        reader: impl Read,
+        total: Option<u64>,
+        config: &LargeFileConfig,
+        progress: impl FnMut(LoadProgress),
+    ) -> io::Result<Self> {
+        read_chunks(reader, total, config, &AtomicBool::new(false), progress)
+    }
+
+    /// Read the file at `path`. See [`LoadedText::read`].
+    pub fn read_file(
+        path: &Path,
+        config: &LargeFileConfig,
+        progress: impl FnMut(LoadProgress),
+    ) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let total = file.metadata()?.len();
+        Self::read(file, Some(total), config, progress)
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.features == BufferFeatures::LARGE_FILE
+    }
+
+    /// A buffer of the text, with the detected line ending and indentation.
+    pub fn into_buffer(self, default_indent: impl FnOnce() -> IndentStyle) -> Buffer {
+        let mut buffer = Buffer::new_normalized(self.text, self.line_ending);
+        buffer.set_indent_style(self.indent_style.unwrap_or_else(default_indent));
+        buffer
+    }
+}
+
+/// The loading of a file on a background thread.
+#[derive(Debug)]
+pub struct LargeFileLoader {
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<LoadedText>>,
+}
+
+impl LargeFileLoader {
+    /// Start loading the file at `path`. `progress` is called from the loading thread.
+    pub fn spawn(
+        path: impl Into<PathBuf>,
+        config: LargeFileConfig,
+        progress: impl FnMut(LoadProgress) + Send + 'static,
+    ) -> Self {
+        let path = path.into();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let cancelled = cancelled.clone();
+            move || {
+                let file = File::open(&path)?;
+                let total = file.metadata()?.len();
+                read_chunks(file, Some(total), &config, &cancelled, progress)
+            }
+        });
+        Self { cancelled, handle }
+    }
+
+    /// Stop loading after the current chunk, which makes [`LargeFileLoader::join`] return an
+    /// [`io::ErrorKind::Interrupted`] error.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait for the file to be loaded.
+    pub fn join(self) -> io::Result<LoadedText> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the loading thread panicked")))
+    }
+}
+
+fn read_chunks(
+    mut reader: impl Read,
+    total: Option<u64>,
+    config: &LargeFileConfig,
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(LoadProgress),
+) -> io::Result<LoadedText> {
+    let chunk_size = config.chunk_size.max(1);
+    let mut chunk = vec![0; chunk_size];
+    // The bytes read but not yet in the rope, which are the sample until the line ending is
+    // known, and after that any incomplete character or `\r` at the end of a chunk
+    let mut pending: Vec<u8> = Vec::with_capacity(chunk_size + 4);
+    let mut detected: Option<(LineEnding, Option<IndentStyle>)> = None;
+    let mut text = TreeBuilder::new();
+    let mut loaded = 0;
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "loading was cancelled",
+            ));
+        }
+        let read = match reader.read(&mut chunk) {
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        let eof = read == 0;
+        pending.extend_from_slice(&chunk[..read]);
+        loaded += read as u64;
+
+        if detected.is_none() && (eof || pending.len() >= config.sample_len) {
+            let sample = Rope::from(String::from_utf8_lossy(&pending));
+            let line_ending = LineEndingDetermination::determine(&sample).unwrap_or(LineEnding::Lf);
+            detected = Some((line_ending, auto_detect_indent_style(&sample)));
+        }
+        if let Some((line_ending, _)) = detected {
+            let end = if eof {
+                pending.len()
+            } else {
+                complete_len(&pending)
+            };
+            if end > 0 {
+                let decoded = Rope::from(String::from_utf8_lossy(&pending[..end]));
+                text.push(line_ending.normalize_limited(&decoded));
+                pending.drain(..end);
+            }
+        }
+
+        if !eof {
+            progress(LoadProgress { loaded, total });
+            continue;
+        }
+
+        let (line_ending, indent_style) = detected.unwrap_or((LineEnding::Lf, None));
+        let text: Rope = text.build();
+        let features = config.features(text.len() as u64);
+        return Ok(LoadedText {
+            text,
+            line_ending,
+            indent_style,
+            features,
+        });
+    }
+}
+
+/// The length of `bytes` without a character cut off at its end or a `\r` that may be followed
+/// by `\n`, which are left for the next chunk.
+fn complete_len(bytes: &[u8]) -> usize {
+    if bytes.last() == Some(&b'\r') {
+        return bytes.len() - 1;
+    }
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        if byte & 0xC0 == 0x80 {
+            // A continuation byte
+            continue;
+        }
+        let char_len = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if char_len > back {
+            bytes.len() - back
+        } else {
+            bytes.len()
+        };
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, sync::atomic::AtomicBool};
+
+    use super::{
+        complete_len, read_chunks, BufferFeatures, LargeFileConfig, LargeFileLoader, LoadedText,
+    };
+    use crate::{buffer::rope_text::RopeText, indent::IndentStyle, line_ending::LineEnding};
+
+    #[test]
+    fn incomplete_chunk_ends() {
+        let text = "سلام\r\n".as_bytes();
+        assert_eq!(complete_len(text), text.len());
+        assert_eq!(complete_len(&text[..text.len() - 1]), text.len() - 2);
+        assert_eq!(complete_len(&text[..3]), 2);
+        assert_eq!(complete_len(&text[..4]), 4);
+        assert_eq!(complete_len("🙂".as_bytes()), 4);
+        assert_eq!(complete_len(&"🙂".as_bytes()[..3]), 0);
+        assert_eq!(complete_len(b""), 0);
+    }
+
+    #[test]
+    fn read_in_chunks() {
+        let text = "fn main() {\r\n    println!(\"مرحبا\");\r\n}\r\nlone\rcr\r\n";
+        let config = LargeFileConfig {
+            chunk_size: 3,
+            sample_len: 40,
+            threshold: 1024,
+        };
+        let mut progress = Vec::new();
+        let loaded = LoadedText::read(text.as_bytes(), Some(text.len() as u64), &config, |p| {
+            progress.push(p)
+        })
+        .unwrap();
+
+        // Characters cut between chunks are joined, and the lone `\r` takes the line ending
+        assert_eq!(
+            String::from(&loaded.text),
+            text.replace("lone\rcr", "lone\r\ncr")
+        );
+        assert_eq!(loaded.line_ending, LineEnding::CrLf);
+        assert_eq!(loaded.indent_style, Some(IndentStyle::Spaces(4)));
+        assert_eq!(loaded.features, BufferFeatures::ALL);
+
+        assert_eq!(progress.len(), text.len().div_ceil(3));
+        assert!(progress.windows(2).all(|w| w[0].loaded < w[1].loaded));
+        let last = progress.last().unwrap();
+        assert_eq!(last.loaded, text.len() as u64);
+        assert_eq!(last.fraction(), Some(1.0));
+
+        let buffer = loaded.into_buffer(|| IndentStyle::Tabs);
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.indent_style(), IndentStyle::Spaces(4));
+        assert_eq!(buffer.num_lines(), 6);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let bytes = [b'a', 0xFF, b'b', 0xE2, 0x82];
+        let loaded =
+            LoadedText::read(&bytes[..], None, &LargeFileConfig::default(), |_| {}).unwrap();
+        assert_eq!(String::from(&loaded.text), "a\u{FFFD}b\u{FFFD}");
+        assert_eq!(loaded.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn large_files_disable_features() {
+        let config = LargeFileConfig {
+            threshold: 100,
+            chunk_size: 16,
+            sample_len: 32,
+        };
+        let text = "line\n".repeat(20) + "{\n\tline\n}\n";
+        let loaded = LoadedText::read(text.as_bytes(), None, &config, |_| {}).unwrap();
+        assert!(loaded.is_large());
+        assert_eq!(loaded.features, BufferFeatures::LARGE_FILE);
+        // Detection only looked at the sample
+        assert_eq!(loaded.indent_style, None);
+        assert_eq!(loaded.text.len(), text.len());
+    }
+
+    #[test]
+    fn cancel() {
+        let cancelled = AtomicBool::new(true);
+        let config = LargeFileConfig::default();
+        let err = read_chunks(&b"text"[..], None, &config, &cancelled, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn load_in_the_background() {
+        let path = std::env::temp_dir().join(format!(
+            "jihaz-composer-large-file-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "مرحبا\n".repeat(1000)).unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let config = LargeFileConfig {
+            chunk_size: 1000,
+            ..Default::default()
+        };
+        let loader = LargeFileLoader::spawn(&path, config, move |progress| {
+            sender.send(progress).unwrap();
+        });
+        let loaded = loader.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.text.len(), "مرحبا\n".len() * 1000);
+        let progress: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(progress.len(), 11);
+        assert_eq!(progress[0].total, Some(11000));
+    }
+}
@@ -1,23 +1,47 @@
 //! Elements and tasks that help with composing text
 
+pub mod accessibility;
+pub mod base_diff;
+pub mod bidi;
+pub mod bracket_index;
 pub mod buffer;
+pub mod case;
 pub mod char_buffer;
 pub mod chars;
 pub mod command;
+pub mod command_registry;
+pub mod completion;
 pub mod cursor;
+pub mod diagnostics;
 pub mod editor;
+pub mod editorconfig;
 pub mod encoding;
+pub mod fold;
+pub mod formatter;
+pub mod fuzzy;
+pub mod global_search;
+pub mod ime;
 pub mod indent;
+pub mod keymap;
+pub mod large_file;
 pub mod lens;
 pub mod line_ending;
+pub mod line_transform;
 pub mod mode;
 pub mod movement;
 pub mod paragraph;
 pub mod register;
 pub mod selection;
+pub mod session;
+#[cfg(feature = "serde")]
+pub mod session_state;
 pub mod soft_tab;
+pub mod spell;
+pub mod split;
 pub mod syntax_util;
 pub mod util;
+pub mod viewport;
+pub mod visual_line;
 pub mod word;
 
 pub use lapce_xi_rope as xi_rope;
\ No newline at end of file
@@ -92,6 +92,14 @@ impl LineEnding {
             LineEnding::Lf => "LF",
         }
     }
+
+    /// Get the characters of the line ending
+    pub fn get_chars(&self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -0,0 +1,431 @@
+//! Transformations of whole blocks of lines, such as sorting and aligning them.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use unicode_width::UnicodeWidthStr;
+
+/// The order in which [`LineTransform::Sort`] sorts lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SortOrder {
+    /// Compare lines by their characters.
+    Lexical,
+    /// Compare runs of digits by their numeric value, so that `file2` comes before `file10`.
+    Natural,
+    /// Compare lines by their characters, ignoring case.
+    CaseInsensitive,
+    /// Compare lines by their characters, in descending order.
+    Reverse,
+}
+
+impl SortOrder {
+    /// Compare two lines according to this order.
+    /// Lines that are equal according to the order are compared lexically, to keep the result
+    /// deterministic.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            SortOrder::Lexical => a.cmp(b),
+            SortOrder::Natural => natural_cmp(a, b).then_with(|| a.cmp(b)),
+            SortOrder::CaseInsensitive => a
+                .to_lowercase()
+                .cmp(&b.to_lowercase())
+                .then_with(|| a.cmp(b)),
+            SortOrder::Reverse => b.cmp(a),
+        }
+    }
+}
+
+/// The delimiter that [`LineTransform::Align`] lines up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AlignDelimiter {
+    /// Line up the `=` of assignments, `a    = 1`.
+    Equals,
+    /// Line up the values after `:`, `a:    1`.
+    Colon,
+    /// Line up the values after `,`, `a,    1`.
+    Comma,
+}
+
+impl AlignDelimiter {
+    pub fn as_char(&self) -> char {
+        match self {
+            AlignDelimiter::Equals => '=',
+            AlignDelimiter::Colon => ':',
+            AlignDelimiter::Comma => ',',
+        }
+    }
+
+    /// The byte index of the first occurrence of the delimiter in `line` that stands alone,
+    /// rather than being part of an operator such as `==`, `=>`, `+=` or `::`.
+    fn find_in(&self, line: &str) -> Option<usize> {
+        let delimiter = self.as_char();
+        let (before, after): (&[char], &[char]) = match self {
+            AlignDelimiter::Equals => (&OPERATOR_CHARS, &['=', '>', '~']),
+            AlignDelimiter::Colon => (&[':'], &[':', '=']),
+            AlignDelimiter::Comma => (&[], &[]),
+        };
+        let mut prev = None;
+        let mut chars = line.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            if c == delimiter {
+                let next = chars.peek().map(|(_, next)| *next);
+                let joined = prev.is_some_and(|prev| before.contains(&prev))
+                    || next.is_some_and(|next| after.contains(&next));
+                if !joined {
+                    return Some(index);
+                }
+            }
+            prev = Some(c);
+        }
+        None
+    }
+}
+
+/// The characters that combine with a following `=` into an operator, such as `!=`, `<=`, `+=`
+/// or `:=`.
+const OPERATOR_CHARS: [char; 17] = [
+    '=', '!', '<', '>', '+', '-', '*', '/', '%', '&', '|', '^', '~', ':', '?', '.', '@',
+];
+
+/// A transformation of a block of lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LineTransform {
+    /// Sort the lines in the given order.
+    Sort(SortOrder),
+    /// Remove every line that is a repetition of a line before it.
+    Unique,
+    /// Reverse the order of the lines.
+    Reverse,
+    /// Shuffle the lines, deterministically for a given seed.
+    Shuffle { seed: u64 },
+    /// Remove the whitespace at the end of every line.
+    TrimTrailingWhitespace,
+    /// Line up the first occurrence of the delimiter in every line that has it, using display
+    /// width columns so that wide characters are accounted for and tabs reach the next multiple
+    /// of `tab_width`. A delimiter that is part of an operator, such as `==` or `::`, is not one.
+    Align {
+        delimiter: AlignDelimiter,
+        tab_width: usize,
+    },
+}
+
+impl LineTransform {
+    /// Apply the transformation to `lines`, which are the contents of lines without their line
+    /// endings.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::line_transform::{AlignDelimiter, LineTransform, SortOrder};
+    /// let lines = ["b10", "b2", "a"];
+    /// assert_eq!(LineTransform::Sort(SortOrder::Natural).apply(&lines), ["a", "b2", "b10"]);
+    ///
+    /// let lines = ["x = 1", "long_name = 2"];
+    /// let align = LineTransform::Align {
+    ///     delimiter: AlignDelimiter::Equals,
+    ///     tab_width: 4,
+    /// };
+    /// assert_eq!(align.apply(&lines), ["x         = 1", "long_name = 2"]);
+    /// ```
+    pub fn apply<S: AsRef<str>>(&self, lines: &[S]) -> Vec<String> {
+        let lines = lines.iter().map(|l| l.as_ref());
+        match self {
+            LineTransform::Sort(order) => {
+                let mut lines: Vec<&str> = lines.collect();
+                lines.sort_by(|a, b| order.compare(a, b));
+                lines.into_iter().map(String::from).collect()
+            }
+            LineTransform::Unique => {
+                let mut seen = HashSet::new();
+                lines
+                    .filter(|line| seen.insert(*line))
+                    .map(String::from)
+                    .collect()
+            }
+            LineTransform::Reverse => lines.rev().map(String::from).collect(),
+            LineTransform::Shuffle { seed } => {
+                let mut lines: Vec<&str> = lines.collect();
+                shuffle(&mut lines, *seed);
+                lines.into_iter().map(String::from).collect()
+            }
+            LineTransform::TrimTrailingWhitespace => {
+                lines.map(|line| line.trim_end().to_string()).collect()
+            }
+            LineTransform::Align {
+                delimiter,
+                tab_width,
+            } => align(&lines.collect::<Vec<_>>(), *delimiter, *tab_width),
+        }
+    }
+}
+
+/// Compare two strings, comparing runs of ascii digits by their numeric value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a;
+    let mut b = b;
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let a_len = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_len = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let a_num = a[..a_len].trim_start_matches('0');
+                let b_num = b[..b_len].trim_start_matches('0');
+                let ordering = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a = &a[ca.len_utf8()..];
+                b = &b[cb.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Fisher-Yates shuffle driven by a xorshift generator.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    // xorshift gets stuck on zero
+    let mut state = seed | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let j = (state % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// The display width of `text` from the start of a line, with tabs up to the next multiple of
+/// `tab_width`.
+fn columns(text: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    let mut column = 0;
+    for (i, part) in text.split('\t').enumerate() {
+        if i > 0 {
+            column += tab_width - column % tab_width;
+        }
+        column += part.width();
+    }
+    column
+}
+
+fn align(lines: &[&str], delimiter: AlignDelimiter, tab_width: usize) -> Vec<String> {
+    let delimiter_char = delimiter.as_char();
+    let splits: Vec<Option<(&str, &str)>> = lines
+        .iter()
+        .map(|line| {
+            let index = delimiter.find_in(line)?;
+            let (left, right) = (&line[..index], &line[index + delimiter_char.len_utf8()..]);
+            Some((left.trim_end(), right.trim_start()))
+        })
+        .collect();
+    let Some(column) = splits
+        .iter()
+        .flatten()
+        .map(|(left, _)| columns(left, tab_width))
+        .max()
+    else {
+        return lines.iter().map(|line| line.to_string()).collect();
+    };
+
+    lines
+        .iter()
+        .zip(splits)
+        .map(|(line, split)| {
+            let Some((left, right)) = split else {
+                return line.to_string();
+            };
+            let padding = " ".repeat(column - columns(left, tab_width));
+            let aligned = match delimiter {
+                AlignDelimiter::Equals => format!("{left}{padding} {delimiter_char} {right}"),
+                AlignDelimiter::Colon | AlignDelimiter::Comma => {
+                    format!("{left}{delimiter_char}{padding} {right}")
+                }
+            };
+            aligned.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AlignDelimiter, LineTransform, SortOrder};
+
+    fn align(delimiter: AlignDelimiter) -> LineTransform {
+        LineTransform::Align {
+            delimiter,
+            tab_width: 4,
+        }
+    }
+
+    #[test]
+    fn sort_orders() {
+        let lines = ["b", "B", "a10", "a9", "A"];
+        assert_eq!(
+            LineTransform::Sort(SortOrder::Lexical).apply(&lines),
+            ["A", "B", "a10", "a9", "b"]
+        );
+        assert_eq!(
+            LineTransform::Sort(SortOrder::Natural).apply(&lines),
+            ["A", "B", "a9", "a10", "b"]
+        );
+        assert_eq!(
+            LineTransform::Sort(SortOrder::CaseInsensitive).apply(&lines),
+            ["A", "a10", "a9", "B", "b"]
+        );
+        assert_eq!(
+            LineTransform::Sort(SortOrder::Reverse).apply(&lines),
+            ["b", "a9", "a10", "B", "A"]
+        );
+    }
+
+    #[test]
+    fn natural_sort_leading_zeros() {
+        let lines = ["v1.10", "v1.02", "v1.2", "v1.9"];
+        assert_eq!(
+            LineTransform::Sort(SortOrder::Natural).apply(&lines),
+            ["v1.02", "v1.2", "v1.9", "v1.10"]
+        );
+    }
+
+    #[test]
+    fn unique_and_reverse() {
+        let lines = ["a", "b", "a", "c", "b"];
+        assert_eq!(LineTransform::Unique.apply(&lines), ["a", "b", "c"]);
+        assert_eq!(
+            LineTransform::Reverse.apply(&lines),
+            ["b", "c", "a", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn shuffle_is_a_deterministic_permutation() {
+        let lines = ["1", "2", "3", "4", "5", "6"];
+        let shuffled = LineTransform::Shuffle { seed: 42 }.apply(&lines);
+        assert_eq!(shuffled, LineTransform::Shuffle { seed: 42 }.apply(&lines));
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        assert_eq!(sorted, lines);
+    }
+
+    #[test]
+    fn trim_trailing_whitespace() {
+        let lines = ["a  ", "\tb\t", "  "];
+        assert_eq!(
+            LineTransform::TrimTrailingWhitespace.apply(&lines),
+            ["a", "\tb", ""]
+        );
+    }
+
+    #[test]
+    fn align_on_delimiters() {
+        let lines = ["a = 1", "    long_name=2", "no delimiter", "b  =  3"];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            [
+                "a             = 1",
+                "    long_name = 2",
+                "no delimiter",
+                "b             = 3"
+            ]
+        );
+
+        let lines = ["key: value", "longer_key:other", "k:"];
+        assert_eq!(
+            align(AlignDelimiter::Colon).apply(&lines),
+            ["key:        value", "longer_key: other", "k:"]
+        );
+
+        let lines = ["1, one", "100,hundred"];
+        assert_eq!(
+            align(AlignDelimiter::Comma).apply(&lines),
+            ["1,   one", "100, hundred"]
+        );
+    }
+
+    #[test]
+    fn align_skips_operators() {
+        let lines = [
+            "a == b",
+            "n += 1",
+            "long != 2",
+            "m => y",
+            "a <= b",
+            "x = c >= d",
+            "value = 1",
+        ];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            [
+                "a == b",
+                "n += 1",
+                "long != 2",
+                "m => y",
+                "a <= b",
+                "x     = c >= d",
+                "value = 1",
+            ]
+        );
+
+        // The first `=` that stands alone may come after an operator
+        let lines = ["if a == b { x = 1 }", "y = 2"];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            ["if a == b { x = 1 }", "y             = 2"]
+        );
+
+        let lines = [
+            "use std::io;",
+            "key: std::io::Result",
+            "k := 1",
+            "longer: 2",
+        ];
+        assert_eq!(
+            align(AlignDelimiter::Colon).apply(&lines),
+            [
+                "use std::io;",
+                "key:    std::io::Result",
+                "k := 1",
+                "longer: 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn align_expands_tabs() {
+        let lines = ["\tx = 1", "    long = 2", "\t\tab: c = 3"];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            ["\tx         = 1", "    long      = 2", "\t\tab: c = 3"]
+        );
+        // A tab in the middle of a line goes to the next tab stop
+        let lines = ["a\tb = 1", "abcdef = 2"];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            ["a\tb  = 1", "abcdef = 2"]
+        );
+    }
+
+    #[test]
+    fn align_uses_display_width() {
+        // Each of these characters takes two columns
+        let lines = ["日本 = 1", "abcde = 2"];
+        assert_eq!(
+            align(AlignDelimiter::Equals).apply(&lines),
+            ["日本  = 1", "abcde = 2"]
+        );
+    }
+}
@@ -4,6 +4,8 @@ use bitflags::bitflags;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::case::CaseTransform;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MotionMode {
@@ -11,6 +13,7 @@ pub enum MotionMode {
     Yank { count: usize },
     Indent,
     Outdent,
+    ChangeCase(CaseTransform),
 }
 
 impl MotionMode {
@@ -20,6 +23,7 @@ impl MotionMode {
             MotionMode::Yank { count } => *count,
             MotionMode::Indent => 1,
             MotionMode::Outdent => 1,
+            MotionMode::ChangeCase(_) => 1,
         }
     }
 }
@@ -34,6 +38,7 @@ pub enum VisualMode {
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Copy, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Mode {
     Normal,
     Insert,
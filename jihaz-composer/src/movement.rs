@@ -1,3 +1,14 @@
+use std::ops::Range;
+
+use crate::{
+    bidi::{move_horizontal, BidiMovement, HorizontalDirection},
+    buffer::{rope_text::RopeText, Buffer},
+    cursor::{ColPosition, CursorAffinity},
+    mode::Mode,
+    viewport::{BufferLines, DisplayLines},
+    word::WordCursor,
+};
+
 #[derive(Clone, Debug)]
 pub enum LinePosition {
     First,
@@ -92,6 +103,286 @@ impl Movement {
     }
 }
 
+/// Move `offset` by `movement`, `count` times, in `mode`, returning the new offset along with
+/// the column that following vertical movements should keep to.
+///
+/// `horiz` is the column kept by the previous vertical movements, if any. Columns are counted in
+/// grapheme clusters. In normal mode the offset stays on a character rather than past the end of
+/// its line.
+///
+/// [`Movement::Left`] and [`Movement::Right`] move as [`move_horizontal`] does with
+/// `bidi_movement`, which updates the `affinity` of the cursor.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     bidi::BidiMovement,
+/// #     buffer::Buffer,
+/// #     cursor::CursorAffinity,
+/// #     mode::Mode,
+/// #     movement::{move_offset, Movement},
+/// # };
+/// let buffer = Buffer::new("long line\nab\nanother line");
+/// let bidi = BidiMovement::Logical;
+/// let mut affinity = CursorAffinity::Forward;
+/// let (offset, horiz) =
+///     move_offset(&buffer, 7, None, &Movement::Down, 1, Mode::Normal, bidi, &mut affinity);
+/// assert_eq!(offset, 11);
+/// let horiz = horiz.as_ref();
+/// let (offset, _) =
+///     move_offset(&buffer, offset, horiz, &Movement::Down, 1, Mode::Normal, bidi, &mut affinity);
+/// assert_eq!(offset, 20);
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn move_offset(
+    buffer: &Buffer,
+    offset: usize,
+    horiz: Option<&ColPosition>,
+    movement: &Movement,
+    count: usize,
+    mode: Mode,
+    bidi_movement: BidiMovement,
+    affinity: &mut CursorAffinity,
+) -> (usize, Option<ColPosition>) {
+    let caret = mode != Mode::Normal;
+    let line = buffer.line_of_offset(offset);
+    let (new_offset, horiz) = match movement {
+        Movement::Left | Movement::Right => {
+            let direction = if *movement == Movement::Left {
+                HorizontalDirection::Left
+            } else {
+                HorizontalDirection::Right
+            };
+            let (offset, new_affinity) = move_horizontal(
+                buffer,
+                offset,
+                *affinity,
+                direction,
+                mode,
+                count,
+                bidi_movement,
+            );
+            *affinity = new_affinity;
+            (offset, None)
+        }
+        Movement::Up | Movement::Down => {
+            let (offset, horiz) = move_vertical(
+                buffer,
+                &mut BufferLines,
+                offset,
+                horiz,
+                movement,
+                count,
+                caret,
+            );
+            (offset, Some(horiz))
+        }
+        Movement::DocumentStart => (0, None),
+        Movement::DocumentEnd => (buffer.offset_line_end(buffer.len(), caret), None),
+        Movement::FirstNonBlank => (
+            buffer.first_non_blank_character_on_line(line),
+            Some(ColPosition::FirstNonBlank),
+        ),
+        Movement::StartOfLine => (buffer.offset_of_line(line), Some(ColPosition::Start)),
+        Movement::EndOfLine => (buffer.line_end_offset(line, caret), Some(ColPosition::End)),
+        Movement::Line(position) => {
+            let line = match position {
+                LinePosition::First => 0,
+                LinePosition::Last => buffer.last_line(),
+                // Lines are numbered from 1
+                LinePosition::Line(n) => n.saturating_sub(1).min(buffer.last_line()),
+            };
+            (
+                buffer.first_non_blank_character_on_line(line),
+                Some(ColPosition::FirstNonBlank),
+            )
+        }
+        Movement::Offset(offset) => ((*offset).min(buffer.len()), None),
+        Movement::WordForward => (buffer.move_n_words_forward(offset, count), None),
+        Movement::WordEndForward => (
+            buffer.move_n_wordends_forward(offset, count, mode == Mode::Insert),
+            None,
+        ),
+        Movement::WordBackward => (buffer.move_n_words_backward(offset, count, mode), None),
+        Movement::NextUnmatched(c) => {
+            let mut cursor = WordCursor::new(buffer.text(), offset);
+            let mut new_offset = offset;
+            for _ in 0..count {
+                match cursor.next_unmatched(*c) {
+                    // Onto the character rather than after it
+                    Some(offset) => new_offset = offset - c.len_utf8(),
+                    None => break,
+                }
+            }
+            (new_offset, None)
+        }
+        Movement::PreviousUnmatched(c) => {
+            let mut cursor = WordCursor::new(buffer.text(), offset);
+            let mut new_offset = offset;
+            for _ in 0..count {
+                match cursor.previous_unmatched(*c) {
+                    Some(offset) => new_offset = offset,
+                    None => break,
+                }
+            }
+            (new_offset, None)
+        }
+        Movement::MatchPairs => (
+            WordCursor::new(buffer.text(), offset)
+                .match_pairs()
+                .unwrap_or(offset),
+            None,
+        ),
+        Movement::ParagraphForward => (buffer.move_n_paragraphs_forward(offset, count), None),
+        Movement::ParagraphBackward => (buffer.move_n_paragraphs_backward(offset, count), None),
+    };
+
+    let new_offset = if mode == Mode::Normal {
+        new_offset.min(buffer.offset_line_end(new_offset, false))
+    } else {
+        new_offset
+    };
+    (new_offset, horiz)
+}
+
+/// Move `offset` like [`move_offset`], except that [`Movement::Up`] and [`Movement::Down`] move
+/// by the display lines of `lines`, so that they skip the lines hidden in closed folds or move
+/// within a soft-wrapped line. Columns are then counted from the start of the display line.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     bidi::BidiMovement,
+/// #     buffer::Buffer,
+/// #     cursor::CursorAffinity,
+/// #     fold::{FoldRange, FoldState},
+/// #     mode::Mode,
+/// #     movement::{move_offset_in, Movement},
+/// # };
+/// let buffer = Buffer::new("fn main() {\n    one();\n    two();\n}\n");
+/// let mut folds = FoldState::new();
+/// folds.fold(&buffer, FoldRange::new(0, 2));
+/// let (offset, _) = move_offset_in(
+///     &buffer,
+///     &mut folds,
+///     0,
+///     None,
+///     &Movement::Down,
+///     1,
+///     Mode::Normal,
+///     BidiMovement::Logical,
+///     &mut CursorAffinity::Forward,
+/// );
+/// assert_eq!(offset, 34);
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn move_offset_in(
+    buffer: &Buffer,
+    lines: &mut impl DisplayLines,
+    offset: usize,
+    horiz: Option<&ColPosition>,
+    movement: &Movement,
+    count: usize,
+    mode: Mode,
+    bidi_movement: BidiMovement,
+    affinity: &mut CursorAffinity,
+) -> (usize, Option<ColPosition>) {
+    match movement {
+        Movement::Up | Movement::Down => {
+            let caret = mode != Mode::Normal;
+            let (offset, horiz) =
+                move_vertical(buffer, lines, offset, horiz, movement, count, caret);
+            (offset, Some(horiz))
+        }
+        _ => move_offset(
+            buffer,
+            offset,
+            horiz,
+            movement,
+            count,
+            mode,
+            bidi_movement,
+            affinity,
+        ),
+    }
+}
+
+fn move_vertical(
+    buffer: &Buffer,
+    lines: &mut impl DisplayLines,
+    offset: usize,
+    horiz: Option<&ColPosition>,
+    movement: &Movement,
+    count: usize,
+    caret: bool,
+) -> (usize, ColPosition) {
+    let line = lines.display_line_of_offset(buffer, offset);
+    let horiz = horiz.cloned().unwrap_or_else(|| {
+        let range = lines.display_line_range(buffer, line);
+        ColPosition::Col(column_of_offset(buffer, range.start, offset) as f64)
+    });
+    let line = movement.update_index(line, lines.num_display_lines(buffer), count, false);
+    let range = lines.display_line_range(buffer, line);
+    let last = last_cursor_offset(buffer, &range, caret);
+    let offset = match horiz {
+        ColPosition::Start => range.start,
+        ColPosition::End => last,
+        ColPosition::FirstNonBlank => {
+            let line = buffer.line_of_offset(range.start);
+            if range.start == buffer.offset_of_line(line) {
+                buffer.first_non_blank_character_on_line(line).min(last)
+            } else {
+                range.start
+            }
+        }
+        ColPosition::Col(column) => offset_of_column(buffer, range.start, column as usize, last),
+    };
+    (offset, horiz)
+}
+
+/// The column of `offset` from `start`, in grapheme clusters.
+pub(crate) fn column_of_offset(text: &impl RopeText, start: usize, offset: usize) -> usize {
+    let mut current = start;
+    let mut column = 0;
+    while current < offset {
+        current = text.next_grapheme_offset(current, 1, offset);
+        column += 1;
+    }
+    column
+}
+
+/// The offset `column` grapheme clusters after `start`, or `last` if that is before it.
+pub(crate) fn offset_of_column(
+    text: &impl RopeText,
+    start: usize,
+    column: usize,
+    last: usize,
+) -> usize {
+    let mut offset = start;
+    for _ in 0..column {
+        let next = text.next_grapheme_offset(offset, 1, last);
+        if next == offset {
+            break;
+        }
+        offset = next;
+    }
+    offset
+}
+
+/// The last offset a cursor can be at in the display line `range`: the end of the line for
+/// a caret, or else the start of its last character. A cursor is never at a wrap point, which is
+/// the start of the following visual line.
+pub(crate) fn last_cursor_offset(text: &impl RopeText, range: &Range<usize>, caret: bool) -> usize {
+    let line_end = text.line_end_offset(text.line_of_offset(range.start), true);
+    if caret && range.end == line_end {
+        range.end
+    } else {
+        text.prev_grapheme_offset(range.end, 1, range.start)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::movement::Movement;
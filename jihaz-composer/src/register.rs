@@ -1,11 +1,34 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 use crate::mode::VisualMode;
 
 #[cfg(feature = "jihaz")]
 pub use jihaz::sys_task::handle::SystemTaskHandle;
+#[cfg(feature = "jihaz")]
+use jihaz::sys_task::ClipboardFormat;
+
+/// The private clipboard format that holds the text of each region of a copy from several
+/// regions, next to the plain text that other applications see.
+#[cfg(target_os = "macos")]
+pub const REGIONS_FORMAT: &str = "org.jihaz.composer.regions";
+#[cfg(not(target_os = "macos"))]
+pub const REGIONS_FORMAT: &str = "application/x-jihaz-composer-regions";
 
 pub trait Clipboard {
     fn get_string(&mut self) -> Option<String>;
     fn put_string(&mut self, s: impl AsRef<str>);
+
+    /// Put copied text on the clipboard. A clipboard that can hold more than plain text should
+    /// keep its fragments too, so that they can be pasted back one per cursor.
+    fn put_register(&mut self, data: &RegisterData) {
+        self.put_string(&data.content);
+    }
+
+    /// The text on the clipboard, with its fragments if it was copied from several regions.
+    fn get_register(&mut self) -> Option<RegisterData> {
+        self.get_string().map(RegisterData::from_text)
+    }
 }
 
 #[cfg(feature = "jihaz")]
@@ -17,20 +40,141 @@ impl Clipboard for SystemTaskHandle {
     fn put_string(&mut self, s: impl AsRef<str>) {
         SystemTaskHandle::global().clipboard().put_string(s)
     }
+
+    fn put_register(&mut self, data: &RegisterData) {
+        let mut clipboard = SystemTaskHandle::global().clipboard();
+        if data.fragments.is_empty() {
+            clipboard.put_string(&data.content);
+        } else {
+            clipboard.put_formats(&[
+                ClipboardFormat::new(ClipboardFormat::TEXT, data.content.as_bytes()),
+                ClipboardFormat::new(REGIONS_FORMAT, data.encode_fragments()),
+            ]);
+        }
+    }
+
+    fn get_register(&mut self) -> Option<RegisterData> {
+        let clipboard = SystemTaskHandle::global().clipboard();
+        let data = RegisterData::from_text(clipboard.get_string()?);
+        Some(match clipboard.get_format(REGIONS_FORMAT) {
+            Some(fragments) => data.with_fragments(&fragments),
+            None => data,
+        })
+    }
+}
+
+/// A clipboard that keeps its content in memory, for when there is no system clipboard, such as
+/// in tests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryClipboard {
+    pub content: Option<String>,
+    /// The content of [`REGIONS_FORMAT`], as a system clipboard would hold it.
+    pub regions: Option<Vec<u8>>,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_string(&mut self) -> Option<String> {
+        self.content.clone()
+    }
+
+    fn put_string(&mut self, s: impl AsRef<str>) {
+        self.content = Some(s.as_ref().to_string());
+        self.regions = None;
+    }
+
+    fn put_register(&mut self, data: &RegisterData) {
+        self.put_string(&data.content);
+        if !data.fragments.is_empty() {
+            self.regions = Some(data.encode_fragments());
+        }
+    }
+
+    fn get_register(&mut self) -> Option<RegisterData> {
+        let data = RegisterData::from_text(self.content.clone()?);
+        Some(match &self.regions {
+            Some(fragments) => data.with_fragments(fragments),
+            None => data,
+        })
+    }
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RegisterData {
     pub content: String,
     pub mode: VisualMode,
+    /// The text of each region when it was yanked from several regions, in order, which
+    /// `content` joins. Pasting with as many regions puts one in each.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fragments: Vec<String>,
+}
+
+impl RegisterData {
+    /// Text from outside the editor, which is pasted by lines if it ends with a line break.
+    pub fn from_text(content: String) -> Self {
+        let mode = if content.ends_with('\n') {
+            VisualMode::Linewise
+        } else {
+            VisualMode::Normal
+        };
+        Self {
+            content,
+            mode,
+            fragments: Vec::new(),
+        }
+    }
+
+    /// The text of regions yanked together, joined by line breaks where they have none.
+    pub fn from_fragments(fragments: Vec<String>, mode: VisualMode) -> Self {
+        let mut content = String::new();
+        for fragment in &fragments {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(fragment);
+        }
+        let fragments = if fragments.len() > 1 {
+            fragments
+        } else {
+            Vec::new()
+        };
+        Self {
+            content,
+            mode,
+            fragments,
+        }
+    }
+
+    /// The fragments in the data of [`REGIONS_FORMAT`].
+    pub fn encode_fragments(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.fragments).unwrap_or_default()
+    }
+
+    /// Takes the fragments from the data of [`REGIONS_FORMAT`] if they still make up the
+    /// content, which they don't if another application replaced only the plain text.
+    pub fn with_fragments(self, data: &[u8]) -> Self {
+        match serde_json::from_slice::<Vec<String>>(data) {
+            Ok(fragments) => {
+                let joined = Self::from_fragments(fragments, self.mode);
+                if joined.content == self.content {
+                    joined
+                } else {
+                    self
+                }
+            }
+            Err(_) => self,
+        }
+    }
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Register {
     pub unnamed: RegisterData,
     last_yank: RegisterData,
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RegisterKind {
     Delete,
     Yank,
@@ -0,0 +1,164 @@
+//! Text with the cursor marked in it, such as `fn |main()` or `let [x] = 1;`.
+//!
+//! `|` marks a caret, and `[` and `]` the ends of a selection, whose cursor is at its `]` unless
+//! a `|` follows its `[`. In visual mode the selection includes the character under the cursor,
+//! and the `|` goes before that character. A `\` escapes the character after it, so `\|` is
+//! a literal `|`.
+
+use super::ScriptError;
+
+/// A marked caret or selection, in byte offsets of the text without the marks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mark {
+    /// The start and end of the marked range, which are the same for a caret.
+    pub start: usize,
+    pub end: usize,
+    /// Where the `|` is, if there is one.
+    pub cursor: Option<usize>,
+}
+
+/// Split marked text into the text and its marks.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::session::marked::{parse_marked, Mark};
+/// let (text, marks) = parse_marked("a|b [cd] \\|").unwrap();
+/// assert_eq!(text, "ab cd |");
+/// assert_eq!(
+///     marks,
+///     [
+///         Mark { start: 1, end: 1, cursor: Some(1) },
+///         Mark { start: 3, end: 5, cursor: None },
+///     ]
+/// );
+/// ```
+pub fn parse_marked(marked: &str) -> Result<(String, Vec<Mark>), ScriptError> {
+    let invalid = |reason| ScriptError::InvalidMarkedText {
+        text: marked.to_string(),
+        reason,
+    };
+
+    let mut text = String::with_capacity(marked.len());
+    let mut marks = Vec::new();
+    let mut open: Option<Mark> = None;
+    let mut chars = marked.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next().ok_or_else(|| invalid("it ends with a `\\`"))?),
+            '|' => match &mut open {
+                Some(Mark {
+                    cursor: Some(_), ..
+                }) => return Err(invalid("a selection has more than one `|`")),
+                Some(mark) => mark.cursor = Some(text.len()),
+                None => marks.push(Mark {
+                    start: text.len(),
+                    end: text.len(),
+                    cursor: Some(text.len()),
+                }),
+            },
+            '[' if open.is_some() => return Err(invalid("a selection is within another")),
+            '[' => {
+                open = Some(Mark {
+                    start: text.len(),
+                    end: text.len(),
+                    cursor: None,
+                })
+            }
+            ']' => {
+                let mut mark = open.take().ok_or_else(|| invalid("a `]` has no `[`"))?;
+                mark.end = text.len();
+                marks.push(mark);
+            }
+            c => text.push(c),
+        }
+    }
+    if open.is_some() {
+        return Err(invalid("a `[` has no `]`"));
+    }
+    Ok((text, marks))
+}
+
+/// The kind of a mark to render, in the order of marks at the same offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum MarkKind {
+    Close,
+    Open,
+    Cursor,
+}
+
+/// Render `text` with `marks` in it, escaping the characters that would be read as marks.
+pub(crate) fn render_marked(text: &str, mut marks: Vec<(usize, MarkKind)>) -> String {
+    marks.sort();
+    let mut marks = marks.into_iter().peekable();
+    let mut marked = String::with_capacity(text.len() + 8);
+    for (offset, c) in text.char_indices().chain([(text.len(), '\0')]) {
+        while let Some((_, kind)) = marks.next_if(|(mark, _)| *mark <= offset) {
+            marked.push(match kind {
+                MarkKind::Close => ']',
+                MarkKind::Open => '[',
+                MarkKind::Cursor => '|',
+            });
+        }
+        if offset == text.len() {
+            break;
+        }
+        if matches!(c, '|' | '[' | ']' | '\\') {
+            marked.push('\\');
+        }
+        marked.push(c);
+    }
+    marked
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_marked, render_marked, Mark, MarkKind};
+
+    #[test]
+    fn invalid_marks() {
+        for marked in ["[a", "a]", "[a[b]]", "[|a|]", "a\\"] {
+            assert!(parse_marked(marked).is_err(), "{marked}");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let (text, marks) = parse_marked("[|سلام] \\[x\\] [ab|c]|").unwrap();
+        assert_eq!(text, "سلام [x] abc");
+        assert_eq!(
+            marks,
+            [
+                Mark {
+                    start: 0,
+                    end: 8,
+                    cursor: Some(0)
+                },
+                Mark {
+                    start: 13,
+                    end: 16,
+                    cursor: Some(15)
+                },
+                Mark {
+                    start: 16,
+                    end: 16,
+                    cursor: Some(16)
+                },
+            ]
+        );
+
+        let rendered = render_marked(
+            &text,
+            vec![
+                (16, MarkKind::Cursor),
+                (0, MarkKind::Cursor),
+                (0, MarkKind::Open),
+                (8, MarkKind::Close),
+                (13, MarkKind::Open),
+                (15, MarkKind::Cursor),
+                (16, MarkKind::Close),
+            ],
+        );
+        assert_eq!(rendered, "[|سلام] \\[x\\] [ab|c]|");
+    }
+}
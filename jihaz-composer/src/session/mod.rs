@@ -0,0 +1,847 @@
+//! A headless editor session: a buffer along with its cursor, registers, clipboard and keymaps,
+//! driven by key presses the way an editor view drives them, but without a view.
+//!
+//! Sessions are meant for testing editing behavior. Their cursor is read and written as marked
+//! text, see [`marked`], and they can be driven by a [`script`].
+//!
+//! **Example:**
+//!
+//! ```rust
+//! # use jihaz_composer::session::EditorSession;
+//! let mut session = EditorSession::modal("|foo bar").unwrap();
+//! session.run_script("press dw\nexpect |bar\ntype ix\nexpect x|bar").unwrap();
+//! ```
+
+pub mod marked;
+pub mod script;
+
+use std::{str::FromStr, time::Instant};
+
+use lapce_xi_rope::{Rope, RopeDelta};
+
+use crate::{
+    bidi::BidiMovement,
+    buffer::{
+        rope_text::{RopeText, RopeTextVal},
+        Buffer, InvalLines,
+    },
+    case::CaseTransform,
+    command::{CommandKind, MotionModeCommand},
+    cursor::{ColPosition, Cursor, CursorMode},
+    editor::{Action, EditConf},
+    fold::{FoldProvider, FoldState, IndentFolds},
+    keymap::{Key, KeyPress, KeyPressState, KeymapEvent, Keymaps, Modifiers, NamedKey},
+    mode::{Mode, MotionMode, VisualMode},
+    movement::{move_offset, move_offset_in, Movement},
+    register::{MemoryClipboard, Register},
+    selection::{SelRegion, Selection},
+    word::WordCursor,
+};
+
+use marked::{parse_marked, render_marked, MarkKind};
+pub use script::{Script, ScriptError, Step};
+
+/// The editing options of a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub modal: bool,
+    pub comment_token: String,
+    pub smart_tab: bool,
+    pub keep_indent: bool,
+    pub auto_indent: bool,
+    pub backspace_deletes_diacritic: bool,
+    pub bidi_movement: BidiMovement,
+    pub tab_width: usize,
+    pub auto_closing_matching_pairs: bool,
+    pub auto_surround: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            modal: false,
+            comment_token: "//".to_string(),
+            smart_tab: true,
+            keep_indent: true,
+            auto_indent: false,
+            backspace_deletes_diacritic: false,
+            bidi_movement: BidiMovement::Logical,
+            tab_width: 4,
+            auto_closing_matching_pairs: false,
+            auto_surround: false,
+        }
+    }
+}
+
+pub struct EditorSession {
+    pub buffer: Buffer,
+    pub cursor: Cursor,
+    pub register: Register,
+    pub clipboard: MemoryClipboard,
+    pub keymaps: Keymaps,
+    pub config: SessionConfig,
+    /// The closed folds, which vertical movements skip. The fold commands fold by indentation.
+    pub folds: FoldState,
+    keys: KeyPressState,
+    /// The time of every key press, so that pending sequences don't time out in the middle of
+    /// a script.
+    now: Instant,
+}
+
+impl EditorSession {
+    /// A session of the marked text `marked`, with the default keymaps of its mode.
+    pub fn new(marked: &str, config: SessionConfig) -> Result<Self, ScriptError> {
+        let (text, marks) = parse_marked(marked)?;
+        let buffer = Buffer::new(text.as_str());
+        let invalid = |reason| ScriptError::InvalidMarkedText {
+            text: marked.to_string(),
+            reason,
+        };
+
+        let mode = if config.modal {
+            match marks.as_slice() {
+                [] => CursorMode::Normal(0),
+                [mark] if mark.start == mark.end => CursorMode::Normal(mark.start),
+                [mark] => {
+                    // The selection includes the character under the cursor
+                    let last = buffer.prev_grapheme_offset(mark.end, 1, mark.start);
+                    let (start, end) = match mark.cursor {
+                        None => (mark.start, last),
+                        Some(cursor) if cursor == mark.start => (last, mark.start),
+                        Some(cursor) if cursor < mark.end => (mark.start, cursor),
+                        Some(_) => return Err(invalid("the `|` of a selection is past its end")),
+                    };
+                    CursorMode::Visual {
+                        start,
+                        end,
+                        mode: VisualMode::Normal,
+                    }
+                }
+                _ => return Err(invalid("modal sessions have a single cursor")),
+            }
+        } else {
+            let mut selection = Selection::new();
+            for mark in &marks {
+                let region = match mark.cursor {
+                    Some(cursor) if cursor == mark.start => SelRegion::new(mark.end, cursor, None),
+                    Some(cursor) if cursor != mark.end => {
+                        return Err(invalid("the `|` of a selection is not at one of its ends"))
+                    }
+                    _ => SelRegion::new(mark.start, mark.end, None),
+                };
+                selection.add_region(region);
+            }
+            if selection.is_empty() {
+                selection.add_region(SelRegion::caret(0));
+            }
+            CursorMode::Insert(selection)
+        };
+
+        let keymaps = if config.modal {
+            Keymaps::default_vim()
+        } else {
+            Keymaps::default_non_modal()
+        };
+        Ok(Self {
+            buffer,
+            cursor: Cursor::new(mode, None, None),
+            register: Register::default(),
+            clipboard: MemoryClipboard::default(),
+            keymaps,
+            config,
+            folds: FoldState::new(),
+            keys: KeyPressState::default(),
+            now: Instant::now(),
+        })
+    }
+
+    /// A session with the vim keymaps.
+    pub fn modal(marked: &str) -> Result<Self, ScriptError> {
+        Self::new(
+            marked,
+            SessionConfig {
+                modal: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A session with the non-modal keymaps.
+    pub fn non_modal(marked: &str) -> Result<Self, ScriptError> {
+        Self::new(marked, SessionConfig::default())
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.text().to_string()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.cursor.get_mode()
+    }
+
+    /// The name of the mode, as vim shows it, e.g. `visual line`.
+    pub fn mode_name(&self) -> &'static str {
+        match self.mode() {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::Visual(VisualMode::Normal) => "visual",
+            Mode::Visual(VisualMode::Linewise) => "visual line",
+            Mode::Visual(VisualMode::Blockwise) => "visual block",
+            Mode::Terminal => "terminal",
+        }
+    }
+
+    /// The text with the cursor marked in it.
+    pub fn marked_text(&self) -> String {
+        let mut marks = Vec::new();
+        match &self.cursor.mode {
+            CursorMode::Normal(offset) => marks.push((*offset, MarkKind::Cursor)),
+            CursorMode::Visual { start, end, mode } => {
+                let (min, max) = (*start.min(end), *start.max(end));
+                let (min, max) = match mode {
+                    VisualMode::Linewise => (
+                        self.buffer.offset_of_line(self.buffer.line_of_offset(min)),
+                        self.buffer
+                            .offset_of_line(self.buffer.line_of_offset(max) + 1),
+                    ),
+                    _ => (
+                        min,
+                        self.buffer.next_grapheme_offset(max, 1, self.buffer.len()),
+                    ),
+                };
+                marks.push((min, MarkKind::Open));
+                marks.push((*end.max(&min), MarkKind::Cursor));
+                marks.push((max, MarkKind::Close));
+            }
+            CursorMode::Insert(selection) => {
+                for region in selection.regions() {
+                    if region.is_caret() {
+                        marks.push((region.end, MarkKind::Cursor));
+                        continue;
+                    }
+                    marks.push((region.min(), MarkKind::Open));
+                    marks.push((region.max(), MarkKind::Close));
+                    if region.end < region.start {
+                        marks.push((region.end, MarkKind::Cursor));
+                    }
+                }
+            }
+        }
+        render_marked(&self.text(), marks)
+    }
+
+    /// The marked text, followed in modal sessions by the mode, e.g. for snapshots.
+    pub fn snapshot(&self) -> String {
+        if self.config.modal {
+            format!(
+                "{}\n-- {} --",
+                self.marked_text(),
+                self.mode_name().to_uppercase()
+            )
+        } else {
+            self.marked_text()
+        }
+    }
+
+    /// Handle a key press: run the command it completes, or insert its character in insert mode.
+    pub fn press(&mut self, press: KeyPress) {
+        let mode = self.mode();
+        let events = self
+            .keys
+            .key_down(&self.keymaps, press, mode, &|_| false, self.now);
+        // A shifted named key that isn't bound extends the selection with the unshifted binding
+        let modify = press.mods.contains(Modifiers::SHIFT) && matches!(press.key, Key::Named(_));
+        for event in events {
+            self.handle_event(event, modify);
+        }
+    }
+
+    /// Press the keys written as in vim, such as `dw` or `<Esc>`.
+    pub fn press_keys(&mut self, keys: &str) -> Result<(), ScriptError> {
+        for press in script::parse_keys(keys)? {
+            self.press(press);
+        }
+        Ok(())
+    }
+
+    /// Type `text`, pressing a key for each of its characters.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(match c {
+                '\n' => KeyPress::named(NamedKey::Enter),
+                '\t' => KeyPress::named(NamedKey::Tab),
+                c => KeyPress::char(c),
+            });
+        }
+    }
+
+    /// End a pending key sequence, running the command bound to it if there is one.
+    pub fn flush(&mut self) {
+        for event in self.keys.expire() {
+            self.handle_event(event, false);
+        }
+    }
+
+    fn handle_event(&mut self, event: KeymapEvent, modify: bool) {
+        match event {
+            KeymapEvent::Command { command, count } => {
+                self.run_command_with(&command, count, modify);
+            }
+            KeymapEvent::Unhandled(press) => {
+                if let (Key::Char(c), true) = (press.key, press.mods.is_empty()) {
+                    if self.cursor.is_insert() {
+                        self.insert(&c.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, s: &str) {
+        let prev_unmatched = |buffer: &Buffer, c: char, offset: usize| {
+            WordCursor::new(buffer.text(), offset).previous_unmatched(c)
+        };
+        let deltas = Action::insert(
+            &mut self.cursor,
+            &mut self.buffer,
+            s,
+            &prev_unmatched,
+            self.config.auto_closing_matching_pairs,
+            self.config.auto_surround,
+        );
+        self.apply_deltas(deltas);
+    }
+
+    /// Rebase the folds through the edits of a command.
+    fn apply_deltas(&mut self, deltas: Vec<(Rope, RopeDelta, InvalLines)>) {
+        if self.folds.is_empty() {
+            return;
+        }
+        for (text, delta, _) in deltas {
+            let text = RopeTextVal::new(delta.apply(&text));
+            self.folds.apply_delta(&text, &delta);
+        }
+    }
+
+    /// Run `command` `count` times, or once if `None`.
+    ///
+    /// Returns false for the commands a session can't run, such as scrolling, which needs a view.
+    pub fn run_command(&mut self, command: &CommandKind, count: Option<usize>) -> bool {
+        self.run_command_with(command, count, false)
+    }
+
+    /// Run the command named `name`, such as `delete_line`.
+    pub fn run_command_by_name(&mut self, name: &str) -> Result<bool, ScriptError> {
+        let command = CommandKind::from_str(name)
+            .map_err(|_| ScriptError::UnknownCommand(name.to_string()))?;
+        Ok(self.run_command(&command, None))
+    }
+
+    fn run_command_with(
+        &mut self,
+        command: &CommandKind,
+        count: Option<usize>,
+        modify: bool,
+    ) -> bool {
+        match command {
+            CommandKind::Edit(command) => {
+                self.cursor.motion_mode = None;
+                for _ in 0..count.unwrap_or(1) {
+                    let conf = EditConf {
+                        comment_token: &self.config.comment_token,
+                        modal: self.config.modal,
+                        smart_tab: self.config.smart_tab,
+                        keep_indent: self.config.keep_indent,
+                        auto_indent: self.config.auto_indent,
+                        backspace_deletes_diacritic: self.config.backspace_deletes_diacritic,
+                        bidi_movement: self.config.bidi_movement,
+                        tab_width: self.config.tab_width,
+                    };
+                    let deltas = Action::do_edit(
+                        &mut self.cursor,
+                        &mut self.buffer,
+                        command,
+                        &mut self.clipboard,
+                        &mut self.register,
+                        conf,
+                    );
+                    self.apply_deltas(deltas);
+                }
+            }
+            CommandKind::Move(command) => {
+                let movement = command.to_movement(count);
+                // The count of a jump to a line is the line rather than a repetition
+                let count = match movement {
+                    Movement::Line(_) => 1,
+                    _ => count.unwrap_or(1),
+                };
+                self.move_cursor(&movement, count, modify);
+            }
+            CommandKind::MotionMode(command) => {
+                let count = count.unwrap_or(1);
+                let motion_mode = match command {
+                    MotionModeCommand::MotionModeDelete => MotionMode::Delete { count },
+                    MotionModeCommand::MotionModeYank => MotionMode::Yank { count },
+                    MotionModeCommand::MotionModeIndent => MotionMode::Indent,
+                    MotionModeCommand::MotionModeOutdent => MotionMode::Outdent,
+                    MotionModeCommand::MotionModeLowerCase => {
+                        MotionMode::ChangeCase(CaseTransform::Lower)
+                    }
+                    MotionModeCommand::MotionModeUpperCase => {
+                        MotionMode::ChangeCase(CaseTransform::Upper)
+                    }
+                    MotionModeCommand::MotionModeToggleCase => {
+                        MotionMode::ChangeCase(CaseTransform::Toggle)
+                    }
+                };
+                self.run_motion_mode(motion_mode);
+            }
+            CommandKind::Fold(command) => {
+                let ranges = IndentFolds::default().fold_ranges(self.buffer.text());
+                self.folds
+                    .do_command(command, &self.buffer, &mut self.cursor, &ranges);
+            }
+            CommandKind::Scroll(_) | CommandKind::Focus(_) | CommandKind::MultiSelection(_) => {
+                return false
+            }
+        }
+        self.clamp_normal_offset();
+        true
+    }
+
+    fn move_cursor(&mut self, movement: &Movement, count: usize, modify: bool) {
+        if let Some(motion_mode) = self.cursor.motion_mode.take() {
+            self.move_with_motion_mode(motion_mode, movement, count);
+            return;
+        }
+
+        match self.cursor.mode.clone() {
+            CursorMode::Normal(offset) => {
+                let horiz = self.cursor.horiz;
+                let (offset, horiz) =
+                    self.move_offset(offset, horiz.as_ref(), movement, count, Mode::Normal);
+                self.cursor.mode = CursorMode::Normal(offset);
+                self.cursor.horiz = horiz;
+            }
+            CursorMode::Visual { start, end, mode } => {
+                let horiz = self.cursor.horiz;
+                let (offset, horiz) =
+                    self.move_offset(end, horiz.as_ref(), movement, count, Mode::Visual(mode));
+                self.cursor.mode = CursorMode::Visual {
+                    start,
+                    end: offset,
+                    mode,
+                };
+                self.cursor.horiz = horiz;
+            }
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let collapse = !modify
+                        && !region.is_caret()
+                        && matches!(movement, Movement::Left | Movement::Right);
+                    let region = if collapse {
+                        // An arrow collapses a selection to its side instead of moving
+                        let offset = if *movement == Movement::Left {
+                            region.min()
+                        } else {
+                            region.max()
+                        };
+                        SelRegion::caret(offset)
+                    } else {
+                        let (offset, horiz) = self.move_offset(
+                            region.end,
+                            region.horiz.as_ref(),
+                            movement,
+                            count,
+                            Mode::Insert,
+                        );
+                        let start = if modify { region.start } else { offset };
+                        SelRegion::new(start, offset, horiz)
+                    };
+                    new_selection.add_region(region);
+                }
+                self.cursor.set_insert(new_selection);
+            }
+        }
+    }
+
+    /// Move `offset` as the cursor moves: up and down over the closed folds, and left and right
+    /// in visual order if [`SessionConfig::bidi_movement`] says so.
+    fn move_offset(
+        &mut self,
+        offset: usize,
+        horiz: Option<&ColPosition>,
+        movement: &Movement,
+        count: usize,
+        mode: Mode,
+    ) -> (usize, Option<ColPosition>) {
+        move_offset_in(
+            &self.buffer,
+            &mut self.folds,
+            offset,
+            horiz,
+            movement,
+            count,
+            mode,
+            self.config.bidi_movement,
+            &mut self.cursor.affinity,
+        )
+    }
+
+    /// Run the pending `motion_mode` over the range of `movement`, as `dw` deletes a word.
+    fn move_with_motion_mode(
+        &mut self,
+        motion_mode: MotionMode,
+        movement: &Movement,
+        count: usize,
+    ) {
+        let offset = self.cursor.offset();
+        let count = count * motion_mode.count();
+        // An inclusive movement stops on its last character, which the range includes
+        let mode = if movement.is_inclusive() {
+            Mode::Normal
+        } else {
+            Mode::Insert
+        };
+        let (mut end, _) = move_offset(
+            &self.buffer,
+            offset,
+            None,
+            movement,
+            count,
+            mode,
+            BidiMovement::Logical,
+            &mut self.cursor.affinity,
+        );
+        if movement.is_inclusive() {
+            end = self.buffer.next_grapheme_offset(end, 1, self.buffer.len());
+        }
+        if *movement == Movement::WordForward {
+            // As in vim, `dw` on the last word of a line stops at the end of the line
+            let line_end = self.buffer.offset_line_end(offset, true);
+            if offset < line_end {
+                end = end.min(line_end);
+            }
+        }
+        let deltas = Action::execute_motion_mode(
+            &mut self.cursor,
+            &mut self.buffer,
+            motion_mode,
+            offset..end,
+            movement.is_vertical(),
+            &mut self.register,
+        );
+        self.apply_deltas(deltas);
+    }
+
+    /// Start `motion_mode`, or run it over whole lines if it is already pending, as `dd` deletes
+    /// a line.
+    fn run_motion_mode(&mut self, motion_mode: MotionMode) {
+        let Some(pending) = self.cursor.motion_mode.take() else {
+            self.cursor.motion_mode = Some(motion_mode);
+            return;
+        };
+        let same = match (&pending, &motion_mode) {
+            (MotionMode::Delete { .. }, MotionMode::Delete { .. })
+            | (MotionMode::Yank { .. }, MotionMode::Yank { .. }) => true,
+            (pending, motion_mode) => pending == motion_mode,
+        };
+        // A different operator cancels the pending one
+        if !same {
+            return;
+        }
+
+        let lines = pending.count() * motion_mode.count();
+        let offset = self.cursor.offset();
+        let line = self.buffer.line_of_offset(offset);
+        let last_line = (line + lines - 1).min(self.buffer.last_line());
+        let end = self.buffer.offset_of_line(last_line);
+        let deltas = Action::execute_motion_mode(
+            &mut self.cursor,
+            &mut self.buffer,
+            pending,
+            offset..end,
+            true,
+            &mut self.register,
+        );
+        self.apply_deltas(deltas);
+    }
+
+    /// Keep the normal mode cursor on a character, as edits may leave it past the end of a line.
+    fn clamp_normal_offset(&mut self) {
+        if let CursorMode::Normal(offset) = self.cursor.mode {
+            let offset = offset.min(self.buffer.offset_line_end(offset, false));
+            self.cursor.mode = CursorMode::Normal(offset);
+        }
+    }
+
+    /// Run `script`, stopping at the first step that fails.
+    pub fn run_script(&mut self, script: &str) -> Result<(), ScriptError> {
+        self.run_steps(script, |_, _| {})
+    }
+
+    /// Run `script`, recording each step followed by the snapshot of the session after it,
+    /// e.g. for `insta` snapshots.
+    pub fn transcript(&mut self, script: &str) -> Result<String, ScriptError> {
+        let mut transcript = self.snapshot();
+        self.run_steps(script, |session, line| {
+            transcript.push_str("\n\n> ");
+            transcript.push_str(line);
+            transcript.push('\n');
+            transcript.push_str(&session.snapshot());
+        })?;
+        Ok(transcript)
+    }
+
+    fn run_steps(
+        &mut self,
+        script: &str,
+        mut after_step: impl FnMut(&Self, &str),
+    ) -> Result<(), ScriptError> {
+        let lines: Vec<&str> = script.lines().collect();
+        for (line, step) in Script::from_str(script)?.steps {
+            match step {
+                Step::Type(text) => self.type_text(&text),
+                Step::Press(presses) => {
+                    for press in presses {
+                        self.press(press);
+                    }
+                }
+                Step::Run(command) => {
+                    self.run_command(&command, None);
+                }
+                Step::Expect(expected) => {
+                    // Parse the expectation so that equivalent markings compare equal
+                    let (text, marks) = parse_marked(&expected)?;
+                    let actual = self.marked_text();
+                    if parse_marked(&actual)? != (text, marks) {
+                        return Err(ScriptError::Mismatch {
+                            line,
+                            expected,
+                            actual,
+                        });
+                    }
+                    continue;
+                }
+                Step::Mode(expected) => {
+                    if self.mode_name() != expected {
+                        return Err(ScriptError::Mismatch {
+                            line,
+                            expected,
+                            actual: self.mode_name().to_string(),
+                        });
+                    }
+                    continue;
+                }
+            }
+            after_step(self, lines[line - 1].trim());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EditorSession, ScriptError, SessionConfig};
+    use crate::bidi::BidiMovement;
+    use crate::{buffer::rope_text::RopeText, mode::Mode};
+
+    #[test]
+    fn marked_cursors() {
+        let session = EditorSession::non_modal("a|b [cd] [|ef]").unwrap();
+        assert_eq!(session.text(), "ab cd ef");
+        assert_eq!(session.marked_text(), "a|b [cd] [|ef]");
+
+        let session = EditorSession::modal("ab [c|d] ef").unwrap();
+        assert_eq!(
+            session.mode(),
+            Mode::Visual(crate::mode::VisualMode::Normal)
+        );
+        assert_eq!(session.marked_text(), "ab [c|d] ef");
+        let session = EditorSession::modal("ab [cd] ef").unwrap();
+        assert_eq!(session.marked_text(), "ab [c|d] ef");
+        let session = EditorSession::modal("ab [|cd] ef").unwrap();
+        assert_eq!(session.marked_text(), "ab [|cd] ef");
+
+        assert!(EditorSession::modal("|a|b").is_err());
+        assert!(EditorSession::non_modal("[a|b]").is_err());
+    }
+
+    #[test]
+    fn vim_editing() {
+        let mut session = EditorSession::modal("|foo bar\nbaz").unwrap();
+        session
+            .run_script(
+                "press dw
+                 expect |bar\\nbaz
+                 type ix
+                 mode insert
+                 expect x|bar\\nbaz
+                 press <Esc>
+                 expect |xbar\\nbaz
+                 press dd
+                 expect |baz
+                 press u
+                 expect xbar\\nba|z
+                 press kA!<Esc>j0
+                 expect xbar!\\n|baz
+                 press 2x
+                 expect xbar!\\n|z",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn vim_motions_and_counts() {
+        let mut session = EditorSession::modal("|one two three\n  four\nfive").unwrap();
+        session
+            .run_script(
+                "press 2w
+                 expect one two |three\\n  four\\nfive
+                 press $j
+                 expect one two three\\n  fou|r\\nfive
+                 press gg^
+                 expect |one two three\\n  four\\nfive
+                 press 2G
+                 expect one two three\\n  |four\\nfive
+                 press de
+                 expect one two three\\n | \\nfive
+                 press yyP
+                 expect one two three\\n | \\n  \\nfive",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn visual_mode() {
+        let mut session = EditorSession::modal("|foo bar").unwrap();
+        session
+            .run_script(
+                "press vll
+                 mode visual
+                 expect [fo|o] bar
+                 press d
+                 mode normal
+                 expect | bar",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn non_modal_editing() {
+        let mut session = EditorSession::non_modal("hello |world").unwrap();
+        session
+            .run_script(
+                "press <S-Right><S-Right>
+                 expect hello [wo]rld
+                 type W
+                 expect hello W|rld
+                 press <Home>
+                 expect |hello Wrld
+                 press <C-z>
+                 expect hello [wo]rld",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn arabic_text() {
+        let mut session = EditorSession::modal("|مرحبا بالعالم\nسلام").unwrap();
+        session
+            .run_script(
+                "press wj
+                 expect مرحبا بالعالم\\nسلا|م
+                 press x
+                 expect مرحبا بالعالم\\nسل|ا",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn moving_over_a_closed_fold() {
+        let mut session =
+            EditorSession::modal("|fn main() {\n    one();\n    two();\n}\n").unwrap();
+        session
+            .run_script(
+                "press zcj
+                 expect fn main() {\\n    one();\\n    two();\\n|}\\n
+                 press k
+                 expect |fn main() {\\n    one();\\n    two();\\n}\\n
+                 press zoj
+                 expect fn main() {\\n|    one();\\n    two();\\n}\\n",
+            )
+            .unwrap();
+
+        // The fold follows the edits before it
+        session.run_script("press kzcOuse a;<Esc>jj").unwrap();
+        assert!(session.folds.is_line_hidden(&session.buffer, 3));
+        assert_eq!(session.buffer.line_of_offset(session.cursor.offset()), 4);
+    }
+
+    #[test]
+    fn visual_arrows() {
+        let config = SessionConfig {
+            bidi_movement: BidiMovement::Visual,
+            ..Default::default()
+        };
+        let mut session = EditorSession::new("|سلام\nabc", config).unwrap();
+        // The start of a right-to-left line is at its right edge
+        session
+            .run_script(
+                "press <Left>
+                 expect س|لام\\nabc
+                 press <Right><Right>
+                 expect |سلام\\nabc
+                 press <Left><Left><Left><Left><Left>
+                 expect سلام\\n|abc",
+            )
+            .unwrap();
+
+        let mut session = EditorSession::non_modal("|سلام").unwrap();
+        session.run_script("press <Left>\nexpect |سلام").unwrap();
+    }
+
+    #[test]
+    fn failed_expectations() {
+        let mut session = EditorSession::non_modal("|abc").unwrap();
+        let err = session.run_script("type x\nexpect |xabc").unwrap_err();
+        assert_eq!(
+            err,
+            ScriptError::Mismatch {
+                line: 2,
+                expected: "|xabc".to_string(),
+                actual: "x|abc".to_string()
+            }
+        );
+        assert!(session.run_script("mode normal").is_err());
+    }
+
+    #[test]
+    fn transcripts() {
+        let mut session = EditorSession::modal("fn |main() {}").unwrap();
+        let transcript = session
+            .transcript("press wi\ntype start_\npress <Esc>0gUe\npress $x")
+            .unwrap();
+        insta::assert_snapshot!(transcript, @r"
+        fn |main() {}
+        -- NORMAL --
+
+        > press wi
+        fn main|() {}
+        -- INSERT --
+
+        > type start_
+        fn mainstart_|() {}
+        -- INSERT --
+
+        > press <Esc>0gUe
+        |FN mainstart_() {}
+        -- NORMAL --
+
+        > press $x
+        FN mainstart_() |{
+        -- NORMAL --
+        ");
+    }
+}
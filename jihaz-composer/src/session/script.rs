@@ -0,0 +1,282 @@
+//! Scripts that drive an [`EditorSession`](super::EditorSession), one step per line:
+//!
+//! ```text
+//! # Comments start with `#`
+//! type foo        types the rest of the line, as key presses
+//! press <Esc>dw   presses keys, written as in vim
+//! run delete_line runs a command by its name
+//! expect |bar     checks the marked text, see the `marked` module
+//! mode normal     checks the mode
+//! ```
+//!
+//! In `type` and `expect`, `\n` and `\t` stand for a newline and a tab. In `press`, keys other
+//! than characters are written within `<>`, such as `<Esc>`, `<CR>`, `<BS>`, `<C-r>` or
+//! `<S-Left>`, and `<lt>` is a `<`.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    command::CommandKind,
+    keymap::{Key, KeyPress, Modifiers, NamedKey},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    /// Marked text that isn't well formed, such as with an unclosed `[`.
+    InvalidMarkedText {
+        text: String,
+        reason: &'static str,
+    },
+    InvalidKeys(String),
+    UnknownCommand(String),
+    UnknownStep {
+        line: usize,
+        step: String,
+    },
+    /// An `expect` or `mode` step that doesn't hold.
+    Mismatch {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::InvalidMarkedText { text, reason } => {
+                write!(f, "Invalid marked text `{text}`: {reason}")
+            }
+            ScriptError::InvalidKeys(keys) => write!(f, "Invalid keys `{keys}`"),
+            ScriptError::UnknownCommand(command) => write!(f, "Unknown command `{command}`"),
+            ScriptError::UnknownStep { line, step } => {
+                write!(f, "Unknown step `{step}` on line {line}")
+            }
+            ScriptError::Mismatch {
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Expectation on line {line} failed\nexpected: {expected}\n  actual: {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Type(String),
+    Press(Vec<KeyPress>),
+    Run(CommandKind),
+    /// Check the marked text.
+    Expect(String),
+    /// Check the mode, by the name [`EditorSession::mode_name`](super::EditorSession::mode_name)
+    /// gives it.
+    Mode(String),
+}
+
+/// A parsed script, with the line number of each step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Script {
+    pub steps: Vec<(usize, Step)>,
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let step = match name {
+                "type" => Step::Type(unescape(argument)),
+                "press" => Step::Press(parse_keys(argument.trim_end())?),
+                "run" => {
+                    let name = argument.trim();
+                    Step::Run(
+                        CommandKind::from_str(name)
+                            .map_err(|_| ScriptError::UnknownCommand(name.to_string()))?,
+                    )
+                }
+                "expect" => Step::Expect(unescape_newlines(argument.trim_end())),
+                "mode" => Step::Mode(argument.trim().to_lowercase()),
+                _ => {
+                    return Err(ScriptError::UnknownStep {
+                        line: line_number,
+                        step: line.to_string(),
+                    })
+                }
+            };
+            steps.push((line_number, step));
+        }
+        Ok(Script { steps })
+    }
+}
+
+/// Replace `\n`, `\t` and `\\` with the characters they stand for.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(escaped) => {
+                if escaped != '\\' {
+                    unescaped.push('\\');
+                }
+                unescaped.push(escaped);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Replace `\n` and `\t`, leaving the other escapes to the marked text.
+fn unescape_newlines(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(escaped) => {
+                unescaped.push('\\');
+                unescaped.push(escaped);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Parse keys written as in vim, such as `dw`, `<Esc>` or `<C-k><C-c>`.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{keymap::KeyPress, session::script::parse_keys};
+/// let keys = parse_keys("2d<S-Right><lt>").unwrap();
+/// assert_eq!(KeyPress::format_sequence(&keys), "2 d shift+right <");
+/// ```
+pub fn parse_keys(keys: &str) -> Result<Vec<KeyPress>, ScriptError> {
+    let invalid = || ScriptError::InvalidKeys(keys.to_string());
+    let mut presses = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        if c != '<' {
+            presses.push(KeyPress::char(c));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let end = rest.find('>').ok_or_else(invalid)?;
+        let name = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let mut parts: Vec<&str> = name.split('-').collect();
+        // The key may itself be a `-`, as in `<C-->`
+        if name.ends_with("--") {
+            parts.truncate(parts.len() - 2);
+            parts.push("-");
+        }
+        let key = parts.pop().ok_or_else(invalid)?;
+        let mut mods = Modifiers::empty();
+        for part in parts {
+            mods.insert(match part.to_lowercase().as_str() {
+                "c" | "ctrl" => Modifiers::CTRL,
+                "s" | "shift" => Modifiers::SHIFT,
+                "a" | "m" | "alt" => Modifiers::ALT,
+                "d" | "cmd" | "meta" => Modifiers::META,
+                _ => return Err(invalid()),
+            });
+        }
+        let key = match key.to_lowercase().as_str() {
+            "cr" => Key::Named(NamedKey::Enter),
+            "bs" => Key::Named(NamedKey::Backspace),
+            "lt" => Key::Char('<'),
+            "space" => Key::Char(' '),
+            _ => KeyPress::from_str(key).map_err(|_| invalid())?.key,
+        };
+        presses.push(KeyPress::new(key, mods));
+    }
+    Ok(presses)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::{parse_keys, unescape, unescape_newlines, Script, ScriptError, Step};
+    use crate::{
+        command::{CommandKind, EditCommand},
+        keymap::KeyPress,
+    };
+
+    #[test]
+    fn parse_script() {
+        let script = Script::from_str(
+            "# Delete a word\ntype foo \\n\n  press <Esc>dw\nrun delete_line\n\nexpect |a\\|\\nb\n\
+             mode Normal",
+        )
+        .unwrap();
+        assert_eq!(
+            script.steps,
+            [
+                (2, Step::Type("foo \n".to_string())),
+                (3, Step::Press(parse_keys("<Esc>dw").unwrap())),
+                (4, Step::Run(CommandKind::Edit(EditCommand::DeleteLine))),
+                (6, Step::Expect("|a\\|\nb".to_string())),
+                (7, Step::Mode("normal".to_string())),
+            ]
+        );
+
+        assert_eq!(
+            Script::from_str("type a\npush b"),
+            Err(ScriptError::UnknownStep {
+                line: 2,
+                step: "push b".to_string()
+            })
+        );
+        assert_eq!(
+            Script::from_str("run no_such_command"),
+            Err(ScriptError::UnknownCommand("no_such_command".to_string()))
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape("a\\nb\\\\n\\t"), "a\nb\\n\t");
+        assert_eq!(unescape_newlines("\\[a\\n\\\\\\]"), "\\[a\n\\\\\\]");
+    }
+
+    #[test]
+    fn keys() {
+        let keys = |keys: &str| KeyPress::format_sequence(&parse_keys(keys).unwrap());
+        assert_eq!(keys("<Esc>gg"), "esc g g");
+        assert_eq!(keys("<C-k><C-c>"), "ctrl+k ctrl+c");
+        assert_eq!(keys("<CR><BS><Tab><Del>"), "enter backspace tab delete");
+        assert_eq!(keys("a b<Space>"), "a space b space");
+        assert_eq!(keys("<C-->"), "ctrl+-");
+        assert_eq!(keys("<C-S-p>"), "ctrl+P");
+        assert!(parse_keys("<Esc").is_err());
+        assert!(parse_keys("<X-a>").is_err());
+        assert!(parse_keys("<nosuchkey>").is_err());
+    }
+}
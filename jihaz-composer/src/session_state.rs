@@ -0,0 +1,329 @@
+//! The state of the editor views that is kept across launches: the open documents, with the
+//! cursor, selections, scroll position, folds and marks of each, and the register.
+//!
+//! The state is saved with [`jihaz_primal::serde`] on exit and loaded on launch. Since a file
+//! can change while the editor is closed, the offsets of a document are validated against its
+//! current content when it is restored, see [`DocumentState::restore`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use jihaz_primal::serde::{de, ser, DataFormat, SerdeError};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffer::{rope_text::RopeText, Buffer},
+    cursor::{Cursor, CursorMode},
+    fold::{FoldRange, FoldState},
+    register::Register,
+    selection::{SelRegion, Selection},
+};
+
+pub use crate::split::ScrollPosition;
+
+/// The file name of the saved state, within the data directory of the application.
+pub const SESSION_STATE_FILE_NAME: &str = "session.json";
+
+/// The view state of an open document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DocumentState {
+    pub path: PathBuf,
+    pub cursor: Cursor,
+    pub scroll: ScrollPosition,
+    /// The closed folds, by line.
+    pub folds: Vec<FoldRange>,
+    /// Named positions, such as vim marks.
+    pub marks: BTreeMap<char, usize>,
+}
+
+/// The view state of a document restored by [`DocumentState::restore`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestoredDocument {
+    pub cursor: Cursor,
+    pub scroll: ScrollPosition,
+    pub folds: FoldState,
+    pub marks: BTreeMap<char, usize>,
+}
+
+impl DocumentState {
+    pub fn capture(
+        path: impl Into<PathBuf>,
+        buffer: &Buffer,
+        cursor: &Cursor,
+        scroll: ScrollPosition,
+        folds: &FoldState,
+        marks: &BTreeMap<char, usize>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            cursor: cursor.clone(),
+            scroll,
+            folds: folds.folds(buffer),
+            marks: marks.clone(),
+        }
+    }
+
+    /// The view state for `buffer`, the current content of the document.
+    ///
+    /// Offsets past the end of the text, or within a character, are moved back to the closest
+    /// character boundary, and folds and scroll lines past the last line are dropped or clamped.
+    /// The pending motion and the selection history aren't restored.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use std::collections::BTreeMap;
+    /// # use jihaz_composer::{
+    /// #     buffer::Buffer,
+    /// #     cursor::{Cursor, CursorMode},
+    /// #     fold::FoldState,
+    /// #     session_state::{DocumentState, ScrollPosition},
+    /// # };
+    /// let buffer = Buffer::new("one\ntwo\nthree");
+    /// let cursor = Cursor::new(CursorMode::Normal(10), None, None);
+    /// let state = DocumentState::capture(
+    ///     "notes.txt",
+    ///     &buffer,
+    ///     &cursor,
+    ///     ScrollPosition { line: 2, x: 0.0 },
+    ///     &FoldState::new(),
+    ///     &BTreeMap::new(),
+    /// );
+    ///
+    /// // The file was shortened while the editor was closed
+    /// let restored = state.restore(&Buffer::new("one"));
+    /// assert_eq!(restored.cursor.mode, CursorMode::Normal(2));
+    /// assert_eq!(restored.scroll.line, 0);
+    /// ```
+    pub fn restore(&self, buffer: &Buffer) -> RestoredDocument {
+        let offset = |offset: usize| {
+            let offset = offset.min(buffer.len());
+            buffer
+                .text()
+                .at_or_prev_codepoint_boundary(offset)
+                .unwrap_or(0)
+        };
+
+        let mode = match &self.cursor.mode {
+            CursorMode::Normal(o) => {
+                let o = offset(*o);
+                CursorMode::Normal(o.min(buffer.offset_line_end(o, false)))
+            }
+            CursorMode::Visual { start, end, mode } => CursorMode::Visual {
+                start: offset(*start),
+                end: offset(*end),
+                mode: *mode,
+            },
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    new_selection.add_region(SelRegion::new(
+                        offset(region.start),
+                        offset(region.end),
+                        region.horiz,
+                    ));
+                }
+                if new_selection.is_empty() {
+                    new_selection.add_region(SelRegion::caret(0));
+                }
+                CursorMode::Insert(new_selection)
+            }
+        };
+        let cursor = Cursor {
+            mode,
+            horiz: self.cursor.horiz,
+            motion_mode: None,
+            history_selections: Vec::new(),
+            affinity: self.cursor.affinity,
+            preedit: None,
+        };
+
+        let last_line = buffer.last_line();
+        let mut folds = FoldState::new();
+        for range in &self.folds {
+            if range.end_line <= last_line {
+                folds.fold(buffer, *range);
+            }
+        }
+
+        let x = self.scroll.x;
+        RestoredDocument {
+            cursor,
+            scroll: ScrollPosition {
+                line: self.scroll.line.min(last_line),
+                x: if x.is_finite() { x.max(0.0) } else { 0.0 },
+            },
+            folds,
+            marks: self
+                .marks
+                .iter()
+                .map(|(name, o)| (*name, offset(*o)))
+                .collect(),
+        }
+    }
+}
+
+/// The state of all the open documents, and of the register they share.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SessionState {
+    pub documents: Vec<DocumentState>,
+    /// The index of the document that had focus.
+    pub active: Option<usize>,
+    pub register: Register,
+}
+
+impl SessionState {
+    pub fn document(&self, path: &Path) -> Option<&DocumentState> {
+        self.documents.iter().find(|document| document.path == path)
+    }
+
+    /// Add the state of a document, replacing any earlier state of the same path.
+    pub fn update(&mut self, document: DocumentState) {
+        match self.documents.iter_mut().find(|d| d.path == document.path) {
+            Some(existing) => *existing = document,
+            None => self.documents.push(document),
+        }
+    }
+
+    /// Drop the documents whose files no longer exist.
+    pub fn forget_missing(&mut self) {
+        let active = self
+            .active
+            .and_then(|index| self.documents.get(index))
+            .map(|document| document.path.clone());
+        self.documents.retain(|document| document.path.exists());
+        self.active = active.and_then(|path| {
+            self.documents
+                .iter()
+                .position(|document| document.path == path)
+        });
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SerdeError> {
+        ser::serialize_into_path(self, path, DataFormat::JsonPretty)
+    }
+
+    /// Load the state saved at `path`, dropping the documents that no longer exist.
+    pub fn load(path: &Path) -> Result<Self, SerdeError> {
+        let mut state: Self = de::deserialize_from_path(path, DataFormat::JsonPretty)?;
+        if state
+            .active
+            .is_some_and(|index| index >= state.documents.len())
+        {
+            state.active = None;
+        }
+        state.forget_missing();
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{DocumentState, ScrollPosition, SessionState};
+    use crate::{
+        buffer::Buffer,
+        cursor::{ColPosition, Cursor, CursorMode},
+        fold::{FoldRange, FoldState},
+        register::RegisterData,
+        selection::{SelRegion, Selection},
+    };
+
+    fn selection(regions: &[(usize, usize)]) -> Selection {
+        let mut selection = Selection::new();
+        for (start, end) in regions {
+            selection.add_region(SelRegion::new(*start, *end, None));
+        }
+        selection
+    }
+
+    #[test]
+    fn restore_validates_offsets() {
+        let buffer = Buffer::new("fn main() {\n    let x = 1;\n}\n");
+        let mut folds = FoldState::new();
+        folds.fold(&buffer, FoldRange::new(0, 2));
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(selection(&[(3, 7), (20, 24)])),
+            None,
+            None,
+        );
+        cursor.horiz = Some(ColPosition::Col(4.0));
+        let state = DocumentState::capture(
+            "main.rs",
+            &buffer,
+            &cursor,
+            ScrollPosition { line: 1, x: 12.0 },
+            &folds,
+            &BTreeMap::from([('a', 16), ('b', 28)]),
+        );
+
+        let restored = state.restore(&buffer);
+        assert_eq!(restored.cursor, cursor);
+        assert_eq!(restored.folds, folds);
+        assert_eq!(restored.marks, state.marks);
+
+        // "سلام" takes two bytes a character, so offset 3 is within its second character
+        let restored = state.restore(&Buffer::new("سلام"));
+        assert_eq!(
+            restored.cursor.mode,
+            CursorMode::Insert(selection(&[(2, 6), (8, 8)]))
+        );
+        assert!(restored.folds.is_empty());
+        assert_eq!(restored.scroll, ScrollPosition { line: 0, x: 12.0 });
+        assert_eq!(restored.marks, BTreeMap::from([('a', 8), ('b', 8)]));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("jihaz-session-state-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "hello\nworld").unwrap();
+
+        let buffer = Buffer::new("hello\nworld");
+        let mut state = SessionState::default();
+        state.update(DocumentState::capture(
+            dir.join("removed.txt"),
+            &buffer,
+            &Cursor::origin(true),
+            ScrollPosition::default(),
+            &FoldState::new(),
+            &BTreeMap::new(),
+        ));
+        state.update(DocumentState::capture(
+            &file,
+            &buffer,
+            &Cursor::new(
+                CursorMode::Visual {
+                    start: 1,
+                    end: 8,
+                    mode: Default::default(),
+                },
+                None,
+                None,
+            ),
+            ScrollPosition { line: 1, x: 0.0 },
+            &FoldState::new(),
+            &BTreeMap::from([('m', 7)]),
+        ));
+        state.active = Some(1);
+        state.register.add_yank(RegisterData {
+            content: "hello".to_string(),
+            mode: Default::default(),
+            fragments: Vec::new(),
+        });
+
+        let path = dir.join(super::SESSION_STATE_FILE_NAME);
+        state.save(&path).unwrap();
+        let loaded = SessionState::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.documents, state.documents[1..]);
+        assert_eq!(loaded.active, Some(0));
+        assert_eq!(loaded.document(&file), state.document(&file));
+        assert_eq!(loaded.register.unnamed.content, "hello");
+    }
+}
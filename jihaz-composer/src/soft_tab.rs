@@ -79,15 +79,21 @@ fn snap_to_soft_tab_logic(
 }
 
 /// Count the number of spaces found after a certain offset.
+/// A space that forms a single grapheme cluster with the character after it (such as a space
+/// followed by a combining mark) is not counted, so snapping never splits that cluster.
 fn count_spaces_from(text: &Rope, from_offset: usize) -> usize {
     let mut cursor = lapce_xi_rope::Cursor::new(text, from_offset);
     let mut space_count = 0usize;
-    while let Some(next) = cursor.next_codepoint() {
-        if next != ' ' {
-            break;
-        }
+    while let Some(' ') = cursor.next_codepoint() {
         space_count += 1;
     }
+    // Only the last space can share a cluster with what follows it.
+    if space_count > 0 {
+        let last_space = from_offset + space_count - 1;
+        if text.next_grapheme_offset(last_space) != Some(last_space + 1) {
+            space_count -= 1;
+        }
+    }
     space_count
 }
 
@@ -111,6 +117,15 @@ mod tests {
         assert_eq!(count_spaces_from(&text, 17), 0);
     }
 
+    #[test]
+    fn test_count_spaces_from_combining_mark() {
+        // The last space carries a combining acute accent, so it is not a plain space
+        let text = Rope::from("    \u{301}abc");
+        assert_eq!(count_spaces_from(&text, 0), 3);
+        // Snapping right would otherwise land between the space and its accent
+        assert_eq!(snap_to_soft_tab(&text, 2, SnapDirection::Right, 4), 2);
+    }
+
     #[test]
     fn test_snap_to_soft_tab() {
         let text = Rope::from("          abc\n      def\n    ghi\nklm\n        opq");
@@ -0,0 +1,98 @@
+//! The Arabic specifics of spell checking: the vowel marks (harakat) that are optional in
+//! writing, and the proclitics that attach to the start of words, which dictionaries don't
+//! always list.
+
+use std::{borrow::Cow, cmp::Reverse};
+
+/// Whether `c` is a haraka, such as a fatha or a shadda, or a tatweel, which stretches a word
+/// without changing it.
+pub fn is_haraka(c: char) -> bool {
+    matches!(c, '\u{064B}'..='\u{065F}' | '\u{0670}' | '\u{0640}')
+}
+
+pub fn is_arabic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0600}'..='\u{06FF}'
+            | '\u{0750}'..='\u{077F}'
+            | '\u{08A0}'..='\u{08FF}'
+            | '\u{FB50}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+    )
+}
+
+/// `word` without its harakat.
+pub fn strip_harakat(word: &str) -> Cow<'_, str> {
+    if word.chars().any(is_haraka) {
+        Cow::Owned(word.chars().filter(|c| !is_haraka(*c)).collect())
+    } else {
+        Cow::Borrowed(word)
+    }
+}
+
+const CONJUNCTIONS: [&str; 3] = ["", "و", "ف"];
+const PREPOSITIONS: [&str; 6] = ["", "ب", "ك", "ل", "س", "لل"];
+const ARTICLE: &str = "ال";
+
+/// The ways that `word` splits into proclitics and a stem of at least two letters, the longest
+/// proclitics first: a conjunction (و، ف), then a preposition (ب، ك، ل), the future particle
+/// (س) or the contraction لل, then the article (ال), each optional.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::spell::arabic::split_proclitics;
+/// assert_eq!(
+///     split_proclitics("وبالكتاب"),
+///     [("وبال", "كتاب"), ("وب", "الكتاب"), ("و", "بالكتاب")]
+/// );
+/// ```
+pub fn split_proclitics(word: &str) -> Vec<(&str, &str)> {
+    let mut splits = Vec::new();
+    for conjunction in CONJUNCTIONS {
+        for preposition in PREPOSITIONS {
+            for article in ["", ARTICLE] {
+                // The future particle comes before verbs, and لل already contains the article
+                if !article.is_empty() && matches!(preposition, "س" | "لل") {
+                    continue;
+                }
+                let proclitics = format!("{conjunction}{preposition}{article}");
+                if proclitics.is_empty() || !word.starts_with(&proclitics) {
+                    continue;
+                }
+                let len = proclitics.len();
+                let stem = &word[len..];
+                if stem.chars().count() >= 2 {
+                    splits.push((&word[..len], stem));
+                }
+            }
+        }
+    }
+    splits.sort_by_key(|(proclitics, _)| Reverse(proclitics.len()));
+    splits.dedup();
+    splits
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_proclitics, strip_harakat};
+
+    #[test]
+    fn harakat() {
+        assert_eq!(strip_harakat("كَتَبَ"), "كتب");
+        assert_eq!(strip_harakat("مـــرحبا"), "مرحبا");
+        assert_eq!(strip_harakat("word"), "word");
+    }
+
+    #[test]
+    fn proclitics() {
+        assert_eq!(
+            split_proclitics("فللمدرسة"),
+            [("فلل", "مدرسة"), ("فل", "لمدرسة"), ("ف", "للمدرسة")]
+        );
+        assert_eq!(split_proclitics("سيكتب"), [("س", "يكتب")]);
+        // The stem must keep two letters
+        assert_eq!(split_proclitics("بل"), []);
+        assert_eq!(split_proclitics("كتاب"), [("ك", "تاب")]);
+    }
+}
@@ -0,0 +1,647 @@
+//! A reader of Hunspell dictionaries: the `.aff` file of affix rules and the `.dic` file of
+//! stems with the flags of the rules that apply to them.
+//!
+//! The subset of the format that spelling dictionaries commonly use is supported: the `SET`,
+//! `FLAG`, `TRY`, `IGNORE`, `REP`, `PFX` and `SFX` directives, and the `FORBIDDENWORD`,
+//! `NEEDAFFIX` and `NOSUGGEST` flags. A prefix and a suffix combine when both allow cross
+//! products. Compounding and the continuation classes of affixes are not supported, so words
+//! that only they form are reported as misspelled.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+/// The most suggestions given for a word.
+pub const MAX_SUGGESTIONS: usize = 8;
+
+type Flag = u32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DictionaryError {
+    Io(String),
+    /// The `SET` of the `.aff` file names an encoding other than UTF-8 and ISO8859-1.
+    UnsupportedEncoding(String),
+    /// A directive of the `.aff` file is malformed.
+    InvalidDirective {
+        line: usize,
+        directive: String,
+    },
+}
+
+impl fmt::Display for DictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryError::Io(err) => write!(f, "Failed to read the dictionary: {err}"),
+            DictionaryError::UnsupportedEncoding(encoding) => {
+                write!(f, "Unsupported dictionary encoding `{encoding}`")
+            }
+            DictionaryError::InvalidDirective { line, directive } => {
+                write!(f, "Invalid `{directive}` on line {line} of the affix file")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DictionaryError {}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum FlagType {
+    /// A character per flag.
+    #[default]
+    Char,
+    /// Two characters per flag.
+    Long,
+    /// Comma separated numbers.
+    Numeric,
+}
+
+impl FlagType {
+    fn parse(&self, flags: &str) -> Vec<Flag> {
+        match self {
+            FlagType::Char => flags.chars().map(|c| c as Flag).collect(),
+            FlagType::Long => flags
+                .chars()
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|pair| {
+                    pair.iter()
+                        .fold(0, |flag, c| (flag << 16) | (*c as Flag & 0xffff))
+                })
+                .collect(),
+            FlagType::Numeric => flags
+                .split(',')
+                .filter_map(|flag| flag.trim().parse().ok())
+                .collect(),
+        }
+    }
+
+    fn parse_one(&self, flag: &str) -> Option<Flag> {
+        self.parse(flag).first().copied()
+    }
+}
+
+/// A character of an affix condition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ConditionChar {
+    Any,
+    Char(char),
+    Set { chars: Vec<char>, negated: bool },
+}
+
+impl ConditionChar {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ConditionChar::Any => true,
+            ConditionChar::Char(expected) => c == *expected,
+            ConditionChar::Set { chars, negated } => chars.contains(&c) != *negated,
+        }
+    }
+}
+
+/// The characters that a stem must start with for a prefix, or end with for a suffix, such as
+/// `[^aeiou]y`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Condition(Vec<ConditionChar>);
+
+impl Condition {
+    fn parse(condition: &str) -> Option<Self> {
+        if condition == "." {
+            return Some(Self::default());
+        }
+        let mut parts = Vec::new();
+        let mut chars = condition.chars();
+        while let Some(c) = chars.next() {
+            parts.push(match c {
+                '.' => ConditionChar::Any,
+                '[' => {
+                    let mut set = Vec::new();
+                    let mut negated = false;
+                    loop {
+                        match chars.next()? {
+                            ']' => break,
+                            '^' if set.is_empty() && !negated => negated = true,
+                            c => set.push(c),
+                        }
+                    }
+                    ConditionChar::Set {
+                        chars: set,
+                        negated,
+                    }
+                }
+                c => ConditionChar::Char(c),
+            });
+        }
+        Some(Self(parts))
+    }
+
+    fn matches_start(&self, stem: &str) -> bool {
+        let mut chars = stem.chars();
+        self.0
+            .iter()
+            .all(|part| chars.next().is_some_and(|c| part.matches(c)))
+    }
+
+    fn matches_end(&self, stem: &str) -> bool {
+        let mut chars = stem.chars().rev();
+        self.0
+            .iter()
+            .rev()
+            .all(|part| chars.next().is_some_and(|c| part.matches(c)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Affix {
+    flag: Flag,
+    cross_product: bool,
+    /// The text removed from the stem before adding the affix.
+    strip: String,
+    affix: String,
+    condition: Condition,
+}
+
+impl Affix {
+    /// The stem that `word` is formed from with this prefix.
+    fn prefix_stem(&self, word: &str) -> Option<String> {
+        let rest = word.strip_prefix(self.affix.as_str())?;
+        if rest.is_empty() && self.strip.is_empty() {
+            return None;
+        }
+        let stem = format!("{}{rest}", self.strip);
+        self.condition.matches_start(&stem).then_some(stem)
+    }
+
+    /// The stem that `word` is formed from with this suffix.
+    fn suffix_stem(&self, word: &str) -> Option<String> {
+        let rest = word.strip_suffix(self.affix.as_str())?;
+        if rest.is_empty() && self.strip.is_empty() {
+            return None;
+        }
+        let stem = format!("{rest}{}", self.strip);
+        self.condition.matches_end(&stem).then_some(stem)
+    }
+}
+
+/// A Hunspell dictionary.
+#[derive(Clone, Debug, Default)]
+pub struct Dictionary {
+    /// The flags of each stem, a set for each of its homonyms.
+    stems: HashMap<String, Vec<Vec<Flag>>>,
+    prefixes: Vec<Affix>,
+    suffixes: Vec<Affix>,
+    /// The characters to try in suggestions, the most common first.
+    try_chars: Vec<char>,
+    /// Characters removed from words before looking them up, such as vowel marks.
+    ignore: Vec<char>,
+    /// Common misspellings, for suggestions.
+    replacements: Vec<(String, String)>,
+    forbidden: Option<Flag>,
+    need_affix: Option<Flag>,
+    no_suggest: Option<Flag>,
+}
+
+impl Dictionary {
+    /// Load the dictionary of the `.aff` and `.dic` files at `path` with either extension.
+    pub fn load(path: &Path) -> Result<Self, DictionaryError> {
+        let read = |extension| {
+            fs::read(path.with_extension(extension))
+                .map_err(|err| DictionaryError::Io(err.to_string()))
+        };
+        Self::from_bytes(&read("aff")?, &read("dic")?)
+    }
+
+    /// Read a dictionary from the content of its files, in the encoding that the `SET` of the
+    /// `.aff` file names.
+    pub fn from_bytes(aff: &[u8], dic: &[u8]) -> Result<Self, DictionaryError> {
+        let encoding = aff
+            .split(|b| *b == b'\n')
+            .map(|line| String::from_utf8_lossy(line).trim().to_string())
+            .find_map(|line| line.strip_prefix("SET ").map(|set| set.trim().to_string()));
+        let decode = |bytes: &[u8]| match encoding.as_deref() {
+            None | Some("UTF-8" | "utf-8") => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            Some("ISO8859-1" | "ISO-8859-1") => Ok(bytes.iter().map(|b| *b as char).collect()),
+            Some(encoding) => Err(DictionaryError::UnsupportedEncoding(encoding.to_string())),
+        };
+        Self::parse(&decode(aff)?, &decode(dic)?)
+    }
+
+    pub fn parse(aff: &str, dic: &str) -> Result<Self, DictionaryError> {
+        let mut dictionary = Self::default();
+        let flag_type = dictionary.read_aff(aff)?;
+
+        for (i, line) in dic.lines().enumerate() {
+            // The stems, after their count on the first line, with morphological fields after a
+            // tab
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            if i == 0 && entry.parse::<usize>().is_ok() {
+                continue;
+            }
+            let (stem, flags) = match entry.split_once('/') {
+                Some((stem, flags)) => (stem, flag_type.parse(flags)),
+                None => (entry, Vec::new()),
+            };
+            let stem = dictionary.strip_ignored(stem);
+            dictionary.stems.entry(stem).or_default().push(flags);
+        }
+
+        if dictionary.try_chars.is_empty() {
+            let mut counts: HashMap<char, usize> = HashMap::new();
+            for c in dictionary.stems.keys().flat_map(|stem| stem.chars()) {
+                *counts.entry(c).or_default() += 1;
+            }
+            let mut chars: Vec<(char, usize)> = counts.into_iter().collect();
+            chars.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            dictionary.try_chars = chars.into_iter().map(|(c, _)| c).collect();
+        }
+        Ok(dictionary)
+    }
+
+    fn read_aff(&mut self, aff: &str) -> Result<FlagType, DictionaryError> {
+        let mut flag_type = FlagType::default();
+        // Whether each affix flag allows cross products, from the header of its rules
+        let mut cross_products: HashMap<(bool, Flag), bool> = HashMap::new();
+        let mut replacements_left = None;
+        for (i, line) in aff.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let Some(directive) = fields.first().copied() else {
+                continue;
+            };
+            let invalid = || DictionaryError::InvalidDirective {
+                line: i + 1,
+                directive: directive.to_string(),
+            };
+            match directive {
+                "FLAG" => {
+                    flag_type = match fields.get(1).copied() {
+                        Some("long") => FlagType::Long,
+                        Some("num") => FlagType::Numeric,
+                        Some("UTF-8") => FlagType::Char,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "TRY" => self.try_chars = fields.get(1).ok_or_else(invalid)?.chars().collect(),
+                "IGNORE" => self.ignore = fields.get(1).ok_or_else(invalid)?.chars().collect(),
+                "FORBIDDENWORD" | "NEEDAFFIX" | "NOSUGGEST" => {
+                    let flag = fields
+                        .get(1)
+                        .and_then(|flag| flag_type.parse_one(flag))
+                        .ok_or_else(invalid)?;
+                    match directive {
+                        "FORBIDDENWORD" => self.forbidden = Some(flag),
+                        "NEEDAFFIX" => self.need_affix = Some(flag),
+                        _ => self.no_suggest = Some(flag),
+                    }
+                }
+                "REP" => match (fields.len(), replacements_left) {
+                    (2, None) => {
+                        replacements_left = Some(fields[1].parse::<usize>().map_err(|_| invalid())?)
+                    }
+                    (3.., Some(left)) if left > 0 => {
+                        self.replacements
+                            .push((fields[1].replace('_', " "), fields[2].replace('_', " ")));
+                        replacements_left = Some(left - 1);
+                    }
+                    _ => return Err(invalid()),
+                },
+                "PFX" | "SFX" => {
+                    let is_prefix = directive == "PFX";
+                    let flag = fields
+                        .get(1)
+                        .and_then(|flag| flag_type.parse_one(flag))
+                        .ok_or_else(invalid)?;
+                    let Some(&cross_product) = cross_products.get(&(is_prefix, flag)) else {
+                        // The header: `SFX flag Y|N count`
+                        if fields.len() < 4 {
+                            return Err(invalid());
+                        }
+                        cross_products.insert((is_prefix, flag), fields[2] == "Y");
+                        continue;
+                    };
+                    if fields.len() < 4 {
+                        return Err(invalid());
+                    }
+                    let affix = fields[3].split('/').next().unwrap_or_default();
+                    let affix = Affix {
+                        flag,
+                        cross_product,
+                        strip: none_if_zero(fields[2]),
+                        affix: self.strip_ignored(&none_if_zero(affix)),
+                        condition: Condition::parse(fields.get(4).copied().unwrap_or("."))
+                            .ok_or_else(invalid)?,
+                    };
+                    if is_prefix {
+                        self.prefixes.push(affix);
+                    } else {
+                        self.suffixes.push(affix);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(flag_type)
+    }
+
+    fn strip_ignored(&self, word: &str) -> String {
+        if self.ignore.is_empty() {
+            return word.to_string();
+        }
+        word.chars().filter(|c| !self.ignore.contains(c)).collect()
+    }
+
+    /// Whether `word` is spelled correctly: a stem of the dictionary or a stem with affixes that
+    /// its flags allow. A capitalized or upper case word is also correct in lower case, but not
+    /// the other way around.
+    pub fn check(&self, word: &str) -> bool {
+        let word = self.strip_ignored(word);
+        if word.is_empty() {
+            return true;
+        }
+        case_variants(&word)
+            .iter()
+            .any(|variant| self.check_exact(variant))
+    }
+
+    fn check_exact(&self, word: &str) -> bool {
+        if let Some(homonyms) = self.stems.get(word) {
+            if homonyms.iter().any(|flags| self.has(flags, self.forbidden)) {
+                return false;
+            }
+            if homonyms
+                .iter()
+                .any(|flags| !self.has(flags, self.need_affix))
+            {
+                return true;
+            }
+        }
+
+        for suffix in &self.suffixes {
+            let Some(stem) = suffix.suffix_stem(word) else {
+                continue;
+            };
+            if self.stem_has(&stem, &[suffix.flag]) {
+                return true;
+            }
+            if !suffix.cross_product {
+                continue;
+            }
+            for prefix in self.prefixes.iter().filter(|prefix| prefix.cross_product) {
+                if prefix
+                    .prefix_stem(&stem)
+                    .is_some_and(|root| self.stem_has(&root, &[prefix.flag, suffix.flag]))
+                {
+                    return true;
+                }
+            }
+        }
+        self.prefixes.iter().any(|prefix| {
+            prefix
+                .prefix_stem(word)
+                .is_some_and(|stem| self.stem_has(&stem, &[prefix.flag]))
+        })
+    }
+
+    /// Whether `stem` has a homonym with all of `flags` that isn't forbidden.
+    fn stem_has(&self, stem: &str, flags: &[Flag]) -> bool {
+        self.stems.get(stem).is_some_and(|homonyms| {
+            homonyms.iter().any(|stem_flags| {
+                !self.has(stem_flags, self.forbidden)
+                    && flags.iter().all(|flag| stem_flags.contains(flag))
+            })
+        })
+    }
+
+    fn has(&self, flags: &[Flag], flag: Option<Flag>) -> bool {
+        flag.is_some_and(|flag| flags.contains(&flag))
+    }
+
+    /// Whether `word` can be suggested, being correct and not marked to never be suggested.
+    fn suggestible(&self, word: &str) -> bool {
+        self.check(word)
+            && !self.stems.get(word).is_some_and(|homonyms| {
+                homonyms
+                    .iter()
+                    .all(|flags| self.has(flags, self.no_suggest))
+            })
+    }
+
+    /// Correct words close to `word`, from the common misspellings of the dictionary and the
+    /// words one edit away: a character removed, added or replaced, two characters swapped, or a
+    /// space added. Suggestions keep the case of a capitalized or upper case word.
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let word = self.strip_ignored(word);
+        let chars: Vec<char> = word.chars().collect();
+        let mut candidates: Vec<String> = Vec::new();
+
+        for (from, to) in &self.replacements {
+            for (i, _) in word.match_indices(from.as_str()) {
+                candidates.push(format!("{}{to}{}", &word[..i], &word[i + from.len()..]));
+            }
+        }
+        candidates.push(word.to_lowercase());
+        candidates.push(capitalize(&word));
+        let collect = |chars: Vec<char>| chars.into_iter().collect::<String>();
+        for i in 0..chars.len().saturating_sub(1) {
+            let mut swapped = chars.clone();
+            swapped.swap(i, i + 1);
+            candidates.push(collect(swapped));
+        }
+        for i in 0..chars.len() {
+            let mut removed = chars.clone();
+            removed.remove(i);
+            candidates.push(collect(removed));
+        }
+        for i in 0..chars.len() {
+            for c in &self.try_chars {
+                let mut replaced = chars.clone();
+                replaced[i] = *c;
+                candidates.push(collect(replaced));
+            }
+        }
+        for i in 0..=chars.len() {
+            for c in &self.try_chars {
+                let mut inserted = chars.clone();
+                inserted.insert(i, *c);
+                candidates.push(collect(inserted));
+            }
+        }
+        for i in 1..chars.len() {
+            let (first, second) = chars.split_at(i);
+            let (first, second) = (collect(first.to_vec()), collect(second.to_vec()));
+            if self.suggestible(&first) && self.suggestible(&second) {
+                candidates.push(format!("{first} {second}"));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut suggestions = Vec::new();
+        for candidate in candidates {
+            if candidate.is_empty() || candidate == word || !seen.insert(candidate.clone()) {
+                continue;
+            }
+            let suggestible = candidate.split(' ').all(|part| self.suggestible(part));
+            if suggestible {
+                suggestions.push(match_case(&word, &candidate));
+                if suggestions.len() == MAX_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+        suggestions.dedup();
+        suggestions
+    }
+}
+
+fn none_if_zero(affix: &str) -> String {
+    if affix == "0" {
+        String::new()
+    } else {
+        affix.to_string()
+    }
+}
+
+/// `word`, and the lower case forms that it stands for when capitalized or in upper case.
+fn case_variants(word: &str) -> Vec<String> {
+    let mut variants = vec![word.to_string()];
+    let mut chars = word.chars();
+    let first_upper = chars.next().is_some_and(char::is_uppercase);
+    if !first_upper {
+        return variants;
+    }
+    let lower = word.to_lowercase();
+    if chars.clone().all(|c| !c.is_lowercase()) {
+        // All in upper case, which can stand for a capitalized word too
+        variants.push(capitalize(&lower));
+    }
+    variants.push(lower);
+    variants
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `suggestion` in the case of `word`, if that is capitalized or upper case.
+fn match_case(word: &str, suggestion: &str) -> String {
+    let mut chars = word.chars();
+    if !chars.next().is_some_and(char::is_uppercase) {
+        return suggestion.to_string();
+    }
+    if word.chars().count() > 1 && chars.all(|c| !c.is_lowercase()) {
+        suggestion.to_uppercase()
+    } else {
+        capitalize(suggestion)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dictionary, DictionaryError};
+
+    const EN_AFF: &str = "SET UTF-8
+TRY esianrtolcdugmphbyfvkwzESIANRTOLCDUGMPHBYFVKWZ'
+FORBIDDENWORD !
+NOSUGGEST ?
+
+REP 2
+REP f ph
+REP ph f
+
+PFX U Y 1
+PFX U 0 un .
+
+SFX S Y 4
+SFX S y ies [^aeiou]y
+SFX S 0 s [aeiou]y
+SFX S 0 es [sxzh]
+SFX S 0 s [^sxzhy]
+
+SFX D Y 3
+SFX D 0 d e
+SFX D y ied [^aeiou]y
+SFX D 0 ed [^ey]
+";
+
+    const EN_DIC: &str = "9
+hello/S
+word/S
+try/SD
+do/U
+paris
+Paris
+phone/S
+kill/DU
+damn/?
+";
+
+    fn dictionary() -> Dictionary {
+        Dictionary::parse(EN_AFF, EN_DIC).unwrap()
+    }
+
+    #[test]
+    fn check_with_affixes() {
+        let dictionary = dictionary();
+        for word in [
+            "hello", "hellos", "Hello", "HELLOS", "words", "tries", "tried", "undo", "unkilled",
+            "killed", "Paris", "paris",
+        ] {
+            assert!(dictionary.check(word), "{word}");
+        }
+        for word in ["helo", "trys", "tryed", "unword", "undos", "hELLO", "ph"] {
+            assert!(!dictionary.check(word), "{word}");
+        }
+    }
+
+    #[test]
+    fn forbidden_and_needed_affixes() {
+        let dictionary = Dictionary::parse(
+            "FORBIDDENWORD !\nNEEDAFFIX _\nSFX S Y 1\nSFX S 0 s .\n",
+            "3\ncolour/S\ncolours/!\nfoo/_S\n",
+        )
+        .unwrap();
+        assert!(dictionary.check("colour"));
+        assert!(!dictionary.check("colours"));
+        assert!(!dictionary.check("foo"));
+        assert!(dictionary.check("foos"));
+    }
+
+    #[test]
+    fn long_and_numeric_flags() {
+        let dictionary =
+            Dictionary::parse("FLAG long\nSFX Aa Y 1\nSFX Aa 0 ing .\n", "1\nsing/AaBb\n").unwrap();
+        assert!(dictionary.check("singing"));
+        let dictionary =
+            Dictionary::parse("FLAG num\nSFX 101 Y 1\nSFX 101 0 er .\n", "1\nsing/7,101\n")
+                .unwrap();
+        assert!(dictionary.check("singer"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let dictionary = dictionary();
+        assert_eq!(dictionary.suggest("helo"), ["hello"]);
+        assert_eq!(dictionary.suggest("Wrod"), ["Word"]);
+        assert_eq!(dictionary.suggest("fones"), ["phones"]);
+        assert_eq!(dictionary.suggest("helloword"), ["hello word"]);
+        // Words marked with NOSUGGEST are correct but not suggested
+        assert!(dictionary.check("damn"));
+        assert!(dictionary.suggest("dman").is_empty());
+    }
+
+    #[test]
+    fn encodings() {
+        let dictionary = Dictionary::from_bytes(b"SET ISO8859-1\n", b"1\ncaf\xe9\n").unwrap();
+        assert!(dictionary.check("café"));
+        assert_eq!(
+            Dictionary::from_bytes(b"SET KOI8-R\n", b"").unwrap_err(),
+            DictionaryError::UnsupportedEncoding("KOI8-R".to_string())
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! Offline spell checking with Hunspell dictionaries, for English and Arabic text.
+//!
+//! The dictionaries are read from the [`DICTIONARIES_DIR`] of the data directory, a `.aff` and
+//! a `.dic` file for each language, such as `en_US.aff` and `en_US.dic`. Words in Arabic script
+//! are checked with the Arabic dictionaries and other words with the rest, and a word of a
+//! script that no dictionary covers is taken to be correct.
+//!
+//! A [`SpellCheck`] holds the misspellings of a text by line, and checks again only the lines
+//! that an edit invalidated.
+
+pub mod arabic;
+pub mod hunspell;
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use jihaz_primal::file_man::DataDirectory;
+use lapce_xi_rope::Rope;
+
+use crate::{
+    buffer::{rope_text::RopeText, InvalLines},
+    word::WordCursor,
+};
+
+use self::arabic::{is_arabic, is_haraka, split_proclitics, strip_harakat};
+pub use self::hunspell::{Dictionary, DictionaryError, MAX_SUGGESTIONS};
+
+/// The directory of the dictionaries, within the data directory.
+pub const DICTIONARIES_DIR: &str = "dictionaries";
+/// The file of the words added by the user, within [`DICTIONARIES_DIR`].
+pub const USER_DICTIONARY_FILE_NAME: &str = "user.dic";
+
+/// The words added by the user, one per line in a plain text file.
+#[derive(Clone, Debug, Default)]
+pub struct UserDictionary {
+    path: Option<PathBuf>,
+    words: BTreeSet<String>,
+}
+
+impl UserDictionary {
+    /// Load the words of the file at `path`, which is created when a word is first added.
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let words = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path: Some(path),
+            words,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &str> + '_ {
+        self.words.iter().map(String::as_str)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(strip_harakat(word).as_ref())
+    }
+
+    /// Add `word`, without its harakat, and append it to the file. Returns `false` if the word
+    /// was already there.
+    pub fn add(&mut self, word: &str) -> io::Result<bool> {
+        let word = strip_harakat(word.trim()).into_owned();
+        if word.is_empty() || self.words.contains(&word) {
+            return Ok(false);
+        }
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "{word}")?;
+        }
+        self.words.insert(word);
+        Ok(true)
+    }
+}
+
+/// The dictionaries of the languages in use, and the user dictionary.
+#[derive(Clone, Debug, Default)]
+pub struct SpellChecker {
+    /// The dictionaries by language, such as `en_US` or `ar`.
+    dictionaries: Vec<(String, Dictionary)>,
+    user: UserDictionary,
+}
+
+impl SpellChecker {
+    pub fn new(dictionaries: Vec<(String, Dictionary)>, user: UserDictionary) -> Self {
+        Self { dictionaries, user }
+    }
+
+    /// Load the dictionaries of `languages` and the user dictionary from the data directory.
+    pub fn load(data_dir: &DataDirectory, languages: &[&str]) -> Result<Self, DictionaryError> {
+        let dir = data_dir.child_path(DICTIONARIES_DIR);
+        let mut dictionaries = Vec::with_capacity(languages.len());
+        for language in languages {
+            let dictionary = Dictionary::load(&dir.join(language))?;
+            dictionaries.push((language.to_string(), dictionary));
+        }
+        let user = UserDictionary::load(dir.join(USER_DICTIONARY_FILE_NAME))
+            .map_err(|err| DictionaryError::Io(err.to_string()))?;
+        Ok(Self::new(dictionaries, user))
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = &str> + '_ {
+        self.dictionaries
+            .iter()
+            .map(|(language, _)| language.as_str())
+    }
+
+    pub fn user_dictionary(&self) -> &UserDictionary {
+        &self.user
+    }
+
+    /// Add `word` to the user dictionary, so that it's no longer reported.
+    pub fn add_word(&mut self, word: &str) -> io::Result<bool> {
+        self.user.add(word)
+    }
+
+    /// The dictionaries for the script of `word`.
+    fn dictionaries_for(&self, word: &str) -> impl Iterator<Item = &Dictionary> + '_ {
+        let arabic = word.chars().any(is_arabic);
+        self.dictionaries
+            .iter()
+            .filter(move |(language, _)| is_arabic_language(language) == arabic)
+            .map(|(_, dictionary)| dictionary)
+    }
+
+    /// Whether `word` is spelled correctly, ignoring its harakat. An Arabic word is also correct
+    /// when a dictionary has its stem without the proclitics, such as كتاب in وبالكتاب.
+    pub fn check(&self, word: &str) -> bool {
+        let word = strip_harakat(word);
+        if self.user.contains(&word) {
+            return true;
+        }
+        let mut dictionaries = self.dictionaries_for(&word).peekable();
+        if dictionaries.peek().is_none() {
+            return true;
+        }
+        let dictionaries: Vec<&Dictionary> = dictionaries.collect();
+        if dictionaries
+            .iter()
+            .any(|dictionary| dictionary.check(&word))
+        {
+            return true;
+        }
+        word.chars().any(is_arabic)
+            && split_proclitics(&word).iter().any(|(_, stem)| {
+                self.user.contains(stem) || dictionaries.iter().any(|d| d.check(stem))
+            })
+    }
+
+    /// The corrections of `word`, at most [`MAX_SUGGESTIONS`]. The proclitics of an Arabic word
+    /// are kept, and its stem corrected.
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let word = strip_harakat(word);
+        let mut suggestions: Vec<String> = Vec::new();
+        let mut add = |suggestion: String| {
+            if suggestions.len() < MAX_SUGGESTIONS && !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+        };
+        for dictionary in self.dictionaries_for(&word) {
+            for suggestion in dictionary.suggest(&word) {
+                add(suggestion);
+            }
+            if word.chars().any(is_arabic) {
+                for (proclitics, stem) in split_proclitics(&word) {
+                    for suggestion in dictionary.suggest(stem) {
+                        add(format!("{proclitics}{suggestion}"));
+                    }
+                }
+            }
+        }
+        suggestions
+    }
+}
+
+fn is_arabic_language(language: &str) -> bool {
+    language == "ar" || language.starts_with("ar_") || language.starts_with("ar-")
+}
+
+/// Whether `c` is part of a word to check: a letter, or a mark on one.
+fn is_word_char(c: char) -> bool {
+    c.is_alphabetic() || is_haraka(c) || matches!(c, '\u{0300}'..='\u{036F}')
+}
+
+/// The ranges of the words to check in `range` of the text.
+///
+/// The text is split at the word boundaries of [`WordCursor`], and then around the characters
+/// that aren't letters, such as Arabic punctuation. Runs with digits or underscores are skipped
+/// as numbers or identifiers, and an apostrophe between letters is kept within the word, as in
+/// "don't".
+pub fn words(text: &Rope, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut words: Vec<Range<usize>> = Vec::new();
+    let mut cursor = WordCursor::new(text, range.start);
+    let mut start = range.start;
+    while start < range.end {
+        let end = cursor
+            .next_boundary()
+            .unwrap_or(range.end)
+            .clamp(start, range.end);
+        if end == start {
+            break;
+        }
+        let segment = text.slice_to_cow(start..end);
+        if !segment.chars().any(|c| c.is_numeric() || c == '_') {
+            let mut word_start = None;
+            for (i, c) in segment.char_indices() {
+                match (is_word_char(c), word_start) {
+                    (true, None) => word_start = Some(start + i),
+                    (false, Some(word)) => {
+                        words.push(word..start + i);
+                        word_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(word) = word_start {
+                words.push(word..end);
+            }
+        }
+        start = end;
+    }
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(words.len());
+    for word in words {
+        if let Some(last) = merged.last_mut() {
+            let between = text.slice_to_cow(last.end..word.start);
+            if matches!(between.as_ref(), "'" | "’") {
+                last.end = word.end;
+                continue;
+            }
+        }
+        merged.push(word);
+    }
+    merged
+}
+
+/// A misspelled word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Misspelling {
+    pub range: Range<usize>,
+    pub word: String,
+}
+
+impl Misspelling {
+    /// The corrections of the word. They are only looked for when asked, such as when the menu at
+    /// the cursor is opened, as that is much slower than checking.
+    pub fn suggestions(&self, checker: &SpellChecker) -> Vec<String> {
+        checker.suggest(&self.word)
+    }
+}
+
+/// The misspellings of a text, by line.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     buffer::{rope_text::RopeText, Buffer},
+/// #     editor::EditType,
+/// #     selection::Selection,
+/// #     spell::{Dictionary, SpellCheck, SpellChecker, UserDictionary},
+/// # };
+/// let dictionary = Dictionary::parse("SFX S Y 1\nSFX S 0 s .\n", "2\nhello/S\nworld/S\n").unwrap();
+/// let checker = SpellChecker::new(vec![("en_US".into(), dictionary)], UserDictionary::default());
+///
+/// let mut buffer = Buffer::new("hello wrld\nhellos");
+/// let mut check = SpellCheck::new(&buffer, &checker);
+/// let misspellings = check.misspellings(&buffer, 0..buffer.num_lines());
+/// assert_eq!(misspellings[0].range, 6..10);
+/// assert_eq!(misspellings[0].suggestions(&checker), ["world"]);
+///
+/// let (_, _, inval_lines) = buffer.edit(&[(Selection::region(6, 10), "worlds")], EditType::Other);
+/// check.update(&buffer, &inval_lines, &checker);
+/// assert!(check.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpellCheck {
+    /// The misspellings of each line, with ranges relative to the start of the line, so that
+    /// they stay valid when lines before them change.
+    lines: Vec<Vec<Misspelling>>,
+}
+
+impl SpellCheck {
+    pub fn new(text: &impl RopeText, checker: &SpellChecker) -> Self {
+        Self {
+            lines: (0..text.num_lines())
+                .map(|line| check_line(text, line, checker))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(Vec::is_empty)
+    }
+
+    /// Check again the lines of `text` that an edit invalidated.
+    pub fn update(
+        &mut self,
+        text: &impl RopeText,
+        inval_lines: &InvalLines,
+        checker: &SpellChecker,
+    ) {
+        let start = inval_lines.start_line.min(self.lines.len());
+        let end = (start + inval_lines.inval_count).min(self.lines.len());
+        let lines: Vec<Vec<Misspelling>> = (start..start + inval_lines.new_count)
+            .map(|line| check_line(text, line, checker))
+            .collect();
+        self.lines.splice(start..end, lines);
+    }
+
+    /// Forget the misspellings of `word`, such as once it's added to the user dictionary.
+    pub fn remove_word(&mut self, word: &str) {
+        let word = strip_harakat(word);
+        for line in &mut self.lines {
+            line.retain(|misspelling| strip_harakat(&misspelling.word) != word);
+        }
+    }
+
+    /// The misspellings on `lines`, with offsets into `text`.
+    pub fn misspellings(&self, text: &impl RopeText, lines: Range<usize>) -> Vec<Misspelling> {
+        let end = lines.end.min(self.lines.len());
+        let start = lines.start.min(end);
+        let mut misspellings = Vec::new();
+        for (line, line_misspellings) in self.lines[start..end].iter().enumerate() {
+            let offset = text.offset_of_line(start + line);
+            misspellings.extend(line_misspellings.iter().map(|misspelling| Misspelling {
+                range: offset + misspelling.range.start..offset + misspelling.range.end,
+                ..misspelling.clone()
+            }));
+        }
+        misspellings
+    }
+
+    /// The misspelling at `offset`, including at its end, such as for the suggestions at the
+    /// cursor.
+    pub fn misspelling_at(&self, text: &impl RopeText, offset: usize) -> Option<Misspelling> {
+        let line = text.line_of_offset(offset);
+        self.misspellings(text, line..line + 1)
+            .into_iter()
+            .find(|misspelling| {
+                misspelling.range.contains(&offset) || misspelling.range.end == offset
+            })
+    }
+}
+
+fn check_line(text: &impl RopeText, line: usize, checker: &SpellChecker) -> Vec<Misspelling> {
+    let start = text.offset_of_line(line);
+    let end = text.offset_of_line(line + 1);
+    words(text.text(), start..end)
+        .into_iter()
+        .filter_map(|range| {
+            let word = text.slice_to_cow(range.clone());
+            (!checker.check(&word)).then(|| Misspelling {
+                range: range.start - start..range.end - start,
+                word: word.into_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use jihaz_primal::file_man::{DataDirectory, FilePath};
+    use lapce_xi_rope::Rope;
+
+    use super::{words, Dictionary, SpellCheck, SpellChecker, UserDictionary};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        editor::EditType,
+        selection::Selection,
+    };
+
+    const EN_AFF: &str = "TRY etaoinshrdlu\nSFX S Y 2\nSFX S 0 s [^s]\nSFX S 0 es s\n";
+    const EN_DIC: &str = "6\nthe/S\ncat/S\ndog/S\nsat\ndon't\nclass/S\n";
+    const AR_AFF: &str = "SET UTF-8\nTRY اليمونتبرعكهسدفقجحشصطخذضزظغثةءآأإؤئى\n";
+    const AR_DIC: &str = "4\nكتاب\nمدرسة\nقال\nمرحبا\n";
+
+    fn checker() -> SpellChecker {
+        SpellChecker::new(
+            vec![
+                ("en_US".into(), Dictionary::parse(EN_AFF, EN_DIC).unwrap()),
+                ("ar".into(), Dictionary::parse(AR_AFF, AR_DIC).unwrap()),
+            ],
+            UserDictionary::default(),
+        )
+    }
+
+    fn text_of(text: &str, ranges: Vec<std::ops::Range<usize>>) -> Vec<&str> {
+        ranges.into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn tokenize() {
+        let text = "The cat's x2 foo_bar (dog), don't.\nقالَ: مرحبا، وبالكتاب؟";
+        assert_eq!(
+            text_of(text, words(&Rope::from(text), 0..text.len())),
+            ["The", "cat's", "dog", "don't", "قالَ", "مرحبا", "وبالكتاب"]
+        );
+    }
+
+    #[test]
+    fn check_english_and_arabic() {
+        let checker = checker();
+        for word in [
+            "the",
+            "Cats",
+            "classes",
+            "don't",
+            "كتاب",
+            "كِتَابٌ",
+            "وبالكتاب",
+            "للمدرسة",
+        ] {
+            assert!(checker.check(word), "{word}");
+        }
+        for word in ["teh", "classs", "كتب", "ومدرسه"] {
+            assert!(!checker.check(word), "{word}");
+        }
+        // Words of a script that no dictionary covers aren't reported
+        assert!(SpellChecker::default().check("teh"));
+        let english_only = SpellChecker::new(
+            vec![("en_US".into(), Dictionary::parse(EN_AFF, EN_DIC).unwrap())],
+            UserDictionary::default(),
+        );
+        assert!(english_only.check("كتب"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let checker = checker();
+        assert_eq!(checker.suggest("teh"), ["the"]);
+        assert_eq!(checker.suggest("Dgo"), ["Dog"]);
+        assert_eq!(checker.suggest("مدرسه"), ["مدرسة"]);
+        // The proclitics are kept
+        assert_eq!(checker.suggest("ومدرسه"), ["ومدرسة"]);
+    }
+
+    #[test]
+    fn incremental_check() {
+        let checker = checker();
+        let mut buffer = Buffer::new("the cat\nteh dgo\nمرحبا يا\n");
+        let mut check = SpellCheck::new(&buffer, &checker);
+        let misspelled = |check: &SpellCheck, buffer: &Buffer| {
+            check
+                .misspellings(buffer, 0..buffer.num_lines())
+                .into_iter()
+                .map(|m| (m.word, m.range))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            misspelled(&check, &buffer),
+            [
+                ("teh".to_string(), 8..11),
+                ("dgo".to_string(), 12..15),
+                ("يا".to_string(), 27..31)
+            ]
+        );
+
+        let edits: &[(usize, usize, &str)] = &[
+            (0, 0, "a dgo\n"),
+            (14, 17, "the"),
+            (4, 10, ""),
+            (0, 0, "xyz "),
+            (10, 10, "\n\n"),
+        ];
+        for (start, end, text) in edits {
+            let (_, _, inval_lines) =
+                buffer.edit(&[(Selection::region(*start, *end), *text)], EditType::Other);
+            check.update(&buffer, &inval_lines, &checker);
+            assert_eq!(
+                check,
+                SpellCheck::new(&buffer, &checker),
+                "{:?}",
+                buffer.text()
+            );
+        }
+        assert_eq!(
+            check.misspelling_at(&buffer, 3).map(|m| m.word),
+            Some("xyz".to_string())
+        );
+        assert_eq!(check.misspelling_at(&buffer, 15), None);
+    }
+
+    #[test]
+    fn user_dictionary() {
+        let dir = std::env::temp_dir().join(format!("jihaz-spell-{}", std::process::id()));
+        let dictionaries = dir.join(super::DICTIONARIES_DIR);
+        std::fs::create_dir_all(&dictionaries).unwrap();
+        std::fs::write(dictionaries.join("en_US.aff"), EN_AFF).unwrap();
+        std::fs::write(dictionaries.join("en_US.dic"), EN_DIC).unwrap();
+        let data_dir = DataDirectory {
+            use_external_data_directory: true,
+            external_data_directory: FilePath(Some(dir.clone().into())),
+        };
+
+        let mut checker = SpellChecker::load(&data_dir, &["en_US"]).unwrap();
+        let buffer = Buffer::new("the jihaz editor");
+        let mut check = SpellCheck::new(&buffer, &checker);
+        assert_eq!(check.misspellings(&buffer, 0..1).len(), 2);
+
+        assert!(checker.add_word("jihaz").unwrap());
+        assert!(!checker.add_word("jihaz").unwrap());
+        check.remove_word("jihaz");
+        assert_eq!(check.misspellings(&buffer, 0..1)[0].word, "editor");
+
+        // The word is kept across launches
+        let reloaded = SpellChecker::load(&data_dir, &["en_US"]).unwrap();
+        let missing = SpellChecker::load(&data_dir, &["fr"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(reloaded.check("jihaz"));
+        assert!(!reloaded.check("editor"));
+        assert!(missing.is_err());
+    }
+}
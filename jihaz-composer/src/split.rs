@@ -0,0 +1,795 @@
+//! The layout of editor panes as a tree of splits, independent of any GUI.
+//!
+//! The leaves of a [`SplitTree`] are [`Pane`]s, each showing a document with a cursor and scroll
+//! position of its own, and its other nodes split their area between their children along
+//! a [`SplitDirection`], by ratio. [`SplitTree::layout`] gives the area of each pane for
+//! a frontend to render, and [`SplitTree::do_command`] runs the split commands of
+//! [`FocusCommand`].
+
+use lapce_xi_rope::RopeDelta;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{command::FocusCommand, cursor::Cursor};
+
+/// Identifies a pane within its [`SplitTree`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PaneId(u64);
+
+/// Identifies a document, as assigned by the frontend that opens it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DocumentId(pub u64);
+
+/// The position a view is scrolled to, as the buffer line at its top and the horizontal scroll
+/// offset.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScrollPosition {
+    pub line: usize,
+    pub x: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SplitDirection {
+    /// Children side by side, separated by vertical dividers, as vim's `:vsplit`.
+    Vertical,
+    /// Children one above the other, separated by horizontal dividers, as vim's `:split`.
+    Horizontal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SplitMoveDirection {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+/// An area of the layout, in the units of the bounds given to [`SplitTree::layout`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PaneRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl PaneRect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Pane {
+    pub id: PaneId,
+    pub document: DocumentId,
+    pub cursor: Cursor,
+    pub scroll: ScrollPosition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SplitNode {
+    Pane(Pane),
+    Split {
+        direction: SplitDirection,
+        children: Vec<SplitChild>,
+    },
+}
+
+/// A child of a split, with its share of the area of the split.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SplitChild {
+    /// The share of the area, relative to the sum of the ratios of the children.
+    pub ratio: f64,
+    pub node: SplitNode,
+}
+
+impl SplitNode {
+    fn empty() -> Self {
+        SplitNode::Split {
+            direction: SplitDirection::Vertical,
+            children: Vec::new(),
+        }
+    }
+
+    fn first_pane(&self) -> Option<&Pane> {
+        match self {
+            SplitNode::Pane(pane) => Some(pane),
+            SplitNode::Split { children, .. } => {
+                children.iter().find_map(|child| child.node.first_pane())
+            }
+        }
+    }
+
+    fn last_pane(&self) -> Option<&Pane> {
+        match self {
+            SplitNode::Pane(pane) => Some(pane),
+            SplitNode::Split { children, .. } => children
+                .iter()
+                .rev()
+                .find_map(|child| child.node.last_pane()),
+        }
+    }
+
+    fn panes<'a>(&'a self, panes: &mut Vec<&'a Pane>) {
+        match self {
+            SplitNode::Pane(pane) => panes.push(pane),
+            SplitNode::Split { children, .. } => {
+                for child in children {
+                    child.node.panes(panes);
+                }
+            }
+        }
+    }
+
+    fn panes_mut<'a>(&'a mut self, panes: &mut Vec<&'a mut Pane>) {
+        match self {
+            SplitNode::Pane(pane) => panes.push(pane),
+            SplitNode::Split { children, .. } => {
+                for child in children {
+                    child.node.panes_mut(panes);
+                }
+            }
+        }
+    }
+
+    /// The indices of the children on the way to the pane `id`.
+    fn path_to(&self, id: PaneId) -> Option<Vec<usize>> {
+        match self {
+            SplitNode::Pane(pane) => (pane.id == id).then(Vec::new),
+            SplitNode::Split { children, .. } => {
+                children.iter().enumerate().find_map(|(index, child)| {
+                    let mut path = child.node.path_to(id)?;
+                    path.insert(0, index);
+                    Some(path)
+                })
+            }
+        }
+    }
+
+    fn node_mut(&mut self, path: &[usize]) -> &mut SplitNode {
+        match (self, path.split_first()) {
+            (SplitNode::Split { children, .. }, Some((index, rest))) => {
+                children[*index].node.node_mut(rest)
+            }
+            (node, _) => node,
+        }
+    }
+
+    fn layout(&self, rect: PaneRect, layout: &mut Vec<(PaneId, PaneRect)>) {
+        let (direction, children) = match self {
+            SplitNode::Pane(pane) => {
+                layout.push((pane.id, rect));
+                return;
+            }
+            SplitNode::Split {
+                direction,
+                children,
+            } => (direction, children),
+        };
+        let total: f64 = children.iter().map(|child| child.ratio.max(0.0)).sum();
+        let mut start = 0.0;
+        for child in children {
+            let share = if total > 0.0 {
+                child.ratio.max(0.0) / total
+            } else {
+                1.0 / children.len() as f64
+            };
+            let child_rect = match direction {
+                SplitDirection::Vertical => PaneRect::new(
+                    rect.x + start * rect.width,
+                    rect.y,
+                    share * rect.width,
+                    rect.height,
+                ),
+                SplitDirection::Horizontal => PaneRect::new(
+                    rect.x,
+                    rect.y + start * rect.height,
+                    rect.width,
+                    share * rect.height,
+                ),
+            };
+            child.node.layout(child_rect, layout);
+            start += share;
+        }
+    }
+
+    /// Collapse the splits left with a single child into that child, drop those left without
+    /// any, and merge the splits into their parents when both go in the same direction.
+    fn normalize(&mut self) {
+        let SplitNode::Split {
+            direction,
+            children,
+        } = self
+        else {
+            return;
+        };
+        let mut merged = Vec::with_capacity(children.len());
+        for mut child in children.drain(..) {
+            child.node.normalize();
+            match child.node {
+                // Only a tree that was loaded can have a split without children
+                SplitNode::Split {
+                    children: ref grandchildren,
+                    ..
+                } if grandchildren.is_empty() => {}
+                SplitNode::Split {
+                    direction: child_direction,
+                    children: grandchildren,
+                } if child_direction == *direction => {
+                    let total: f64 = grandchildren.iter().map(|c| c.ratio).sum();
+                    for mut grandchild in grandchildren {
+                        if total > 0.0 {
+                            grandchild.ratio *= child.ratio / total;
+                        }
+                        merged.push(grandchild);
+                    }
+                }
+                _ => merged.push(child),
+            }
+        }
+        *children = merged;
+        if children.len() == 1 {
+            let child = children.pop().unwrap();
+            *self = child.node;
+        }
+    }
+}
+
+/// A tree of splits, along with the pane that has focus.
+///
+/// A tree has at least one pane: closing the last one does nothing.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     cursor::Cursor,
+/// #     split::{DocumentId, PaneRect, SplitDirection, SplitMoveDirection, SplitTree},
+/// # };
+/// let mut tree = SplitTree::new(DocumentId(1), Cursor::origin(true));
+/// let left = tree.focus();
+/// let right = tree.split(left, SplitDirection::Vertical).unwrap();
+/// let bottom = tree.split(right, SplitDirection::Horizontal).unwrap();
+///
+/// let layout = tree.layout(PaneRect::new(0.0, 0.0, 800.0, 600.0));
+/// assert_eq!(layout[0], (left, PaneRect::new(0.0, 0.0, 400.0, 600.0)));
+/// assert_eq!(layout[2], (bottom, PaneRect::new(400.0, 300.0, 400.0, 300.0)));
+///
+/// tree.move_focus(SplitMoveDirection::Left);
+/// assert_eq!(tree.focus(), left);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SplitTreeData"))]
+pub struct SplitTree {
+    pub root: SplitNode,
+    focus: PaneId,
+    next_id: u64,
+}
+
+/// A [`SplitTree`] as it is stored, before it is checked.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SplitTreeData {
+    root: SplitNode,
+    focus: PaneId,
+    next_id: u64,
+}
+
+/// A loaded tree is normalized, as the operations of the tree expect every split to have two
+/// children or more, and its focus and next id are reset if they are not those of a pane.
+#[cfg(feature = "serde")]
+impl TryFrom<SplitTreeData> for SplitTree {
+    type Error = String;
+
+    fn try_from(data: SplitTreeData) -> Result<Self, Self::Error> {
+        let mut root = data.root;
+        root.normalize();
+        let mut panes = Vec::new();
+        root.panes(&mut panes);
+        let mut ids: Vec<PaneId> = panes.iter().map(|pane| pane.id).collect();
+        let Some(&first) = ids.first() else {
+            return Err("a split tree has no panes".to_string());
+        };
+        let focus = if ids.contains(&data.focus) {
+            data.focus
+        } else {
+            first
+        };
+        ids.sort_unstable();
+        if ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("a split tree has two panes with the same id".to_string());
+        }
+        let last = ids.last().map_or(0, |id| id.0);
+        Ok(Self {
+            root,
+            focus,
+            next_id: data.next_id.max(last + 1),
+        })
+    }
+}
+
+impl SplitTree {
+    /// A tree of a single pane showing `document`, which has focus.
+    pub fn new(document: DocumentId, cursor: Cursor) -> Self {
+        Self {
+            root: SplitNode::Pane(Pane {
+                id: PaneId(0),
+                document,
+                cursor,
+                scroll: ScrollPosition::default(),
+            }),
+            focus: PaneId(0),
+            next_id: 1,
+        }
+    }
+
+    pub fn focus(&self) -> PaneId {
+        self.focus
+    }
+
+    /// Give focus to the pane `id`. Returns false if there is no such pane.
+    pub fn set_focus(&mut self, id: PaneId) -> bool {
+        let exists = self.pane(id).is_some();
+        if exists {
+            self.focus = id;
+        }
+        exists
+    }
+
+    /// The panes, from left to right and from top to bottom within each split.
+    pub fn panes(&self) -> Vec<&Pane> {
+        let mut panes = Vec::new();
+        self.root.panes(&mut panes);
+        panes
+    }
+
+    pub fn pane(&self, id: PaneId) -> Option<&Pane> {
+        self.panes().into_iter().find(|pane| pane.id == id)
+    }
+
+    pub fn pane_mut(&mut self, id: PaneId) -> Option<&mut Pane> {
+        let mut panes = Vec::new();
+        self.root.panes_mut(&mut panes);
+        panes.into_iter().find(|pane| pane.id == id)
+    }
+
+    pub fn focused(&self) -> &Pane {
+        self.pane(self.focus)
+            .expect("the focused pane is in the tree")
+    }
+
+    pub fn focused_mut(&mut self) -> &mut Pane {
+        let focus = self.focus;
+        self.pane_mut(focus)
+            .expect("the focused pane is in the tree")
+    }
+
+    /// Split the pane `id` in two along `direction`, the new pane showing the same document
+    /// after it, and give focus to the new pane.
+    ///
+    /// Returns the new pane, or `None` if there is no pane `id`.
+    pub fn split(&mut self, id: PaneId, direction: SplitDirection) -> Option<PaneId> {
+        let path = self.root.path_to(id)?;
+        let new_id = PaneId(self.next_id);
+        self.next_id += 1;
+        let node = self.root.node_mut(&path);
+        let SplitNode::Pane(pane) = node else {
+            return None;
+        };
+        let new_pane = SplitNode::Pane(Pane {
+            id: new_id,
+            ..pane.clone()
+        });
+
+        let old = std::mem::replace(node, SplitNode::empty());
+        *node = SplitNode::Split {
+            direction,
+            children: vec![
+                SplitChild {
+                    ratio: 1.0,
+                    node: old,
+                },
+                SplitChild {
+                    ratio: 1.0,
+                    node: new_pane,
+                },
+            ],
+        };
+        // A split within a split of the same direction becomes two of its children, which
+        // share the area of the pane that was split
+        self.root.normalize();
+        self.focus = new_id;
+        Some(new_id)
+    }
+
+    /// Close the pane `id`, giving its area to the sibling before it, or else to the one after
+    /// it. A split left with a single child is replaced by that child.
+    ///
+    /// Returns false if there is no pane `id` or it is the last pane.
+    pub fn close(&mut self, id: PaneId) -> bool {
+        let Some(path) = self.root.path_to(id) else {
+            return false;
+        };
+        let Some((&index, parent_path)) = path.split_last() else {
+            return false;
+        };
+        let SplitNode::Split { children, .. } = self.root.node_mut(parent_path) else {
+            return false;
+        };
+        let removed = children.remove(index);
+        let (neighbor, next_focus) = if index > 0 {
+            (index - 1, children[index - 1].node.last_pane())
+        } else {
+            (0, children[0].node.first_pane())
+        };
+        let next_focus = next_focus.map(|pane| pane.id);
+        children[neighbor].ratio += removed.ratio;
+
+        self.root.normalize();
+        if self.focus == id {
+            if let Some(next_focus) = next_focus {
+                self.focus = next_focus;
+            }
+        }
+        true
+    }
+
+    /// Swap the pane `id` with the sibling after it, or with the one before it if it is the
+    /// last. The siblings keep their areas, so the pane moves to the area of its sibling.
+    ///
+    /// Returns false if there is no pane `id` or it has no sibling.
+    pub fn exchange(&mut self, id: PaneId) -> bool {
+        let Some(path) = self.root.path_to(id) else {
+            return false;
+        };
+        let Some((&index, parent_path)) = path.split_last() else {
+            return false;
+        };
+        let SplitNode::Split { children, .. } = self.root.node_mut(parent_path) else {
+            return false;
+        };
+        let other = if index + 1 < children.len() {
+            index + 1
+        } else {
+            index - 1
+        };
+        children.swap(index, other);
+        let ratio = children[index].ratio;
+        children[index].ratio = children[other].ratio;
+        children[other].ratio = ratio;
+        true
+    }
+
+    /// The area of each pane within `bounds`, in the order of [`SplitTree::panes`].
+    pub fn layout(&self, bounds: PaneRect) -> Vec<(PaneId, PaneRect)> {
+        let mut layout = Vec::new();
+        self.root.layout(bounds, &mut layout);
+        layout
+    }
+
+    /// The pane next to the pane `id` in `direction`, by the layout of the tree.
+    ///
+    /// Of the panes on that side whose span across `direction` overlaps with that of `id`, the
+    /// closest is taken, and of those equally close the one that overlaps it most, and then the
+    /// topmost or leftmost.
+    pub fn neighbor(&self, id: PaneId, direction: SplitMoveDirection) -> Option<PaneId> {
+        const EPSILON: f64 = 1e-9;
+        let layout = self.layout(PaneRect::new(0.0, 0.0, 1.0, 1.0));
+        let (_, from) = layout.iter().find(|(pane, _)| *pane == id)?;
+
+        let overlap = |start: f64, end: f64, other_start: f64, other_end: f64| {
+            end.min(other_end) - start.max(other_start)
+        };
+        layout
+            .iter()
+            .filter(|(pane, _)| *pane != id)
+            .filter_map(|(pane, rect)| {
+                let (distance, overlap, position) = match direction {
+                    SplitMoveDirection::Left => (
+                        from.x - rect.right(),
+                        overlap(from.y, from.bottom(), rect.y, rect.bottom()),
+                        rect.y,
+                    ),
+                    SplitMoveDirection::Right => (
+                        rect.x - from.right(),
+                        overlap(from.y, from.bottom(), rect.y, rect.bottom()),
+                        rect.y,
+                    ),
+                    SplitMoveDirection::Up => (
+                        from.y - rect.bottom(),
+                        overlap(from.x, from.right(), rect.x, rect.right()),
+                        rect.x,
+                    ),
+                    SplitMoveDirection::Down => (
+                        rect.y - from.bottom(),
+                        overlap(from.x, from.right(), rect.x, rect.right()),
+                        rect.x,
+                    ),
+                };
+                (distance > -EPSILON && overlap > EPSILON)
+                    .then_some((*pane, distance, overlap, position))
+            })
+            .min_by(|a, b| {
+                a.1.total_cmp(&b.1)
+                    .then(b.2.total_cmp(&a.2))
+                    .then(a.3.total_cmp(&b.3))
+            })
+            .map(|(pane, ..)| pane)
+    }
+
+    /// Move the focus to the pane next to it in `direction`. Returns false if there is none.
+    pub fn move_focus(&mut self, direction: SplitMoveDirection) -> bool {
+        match self.neighbor(self.focus, direction) {
+            Some(pane) => {
+                self.focus = pane;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebase the cursors of the panes that show the same document as the pane `editor`
+    /// through an edit made in `editor`, whose own cursor is already moved by the edit.
+    pub fn apply_delta(&mut self, editor: PaneId, delta: &RopeDelta) {
+        let Some(document) = self.pane(editor).map(|pane| pane.document) else {
+            return;
+        };
+        let mut panes = Vec::new();
+        self.root.panes_mut(&mut panes);
+        for pane in panes {
+            if pane.document == document && pane.id != editor {
+                pane.cursor.apply_delta(delta);
+            }
+        }
+    }
+
+    /// Run a split command on the focused pane.
+    ///
+    /// Returns false for the commands of [`FocusCommand`] that aren't split commands.
+    pub fn do_command(&mut self, cmd: &FocusCommand) -> bool {
+        let focus = self.focus;
+        match cmd {
+            FocusCommand::SplitVertical => {
+                self.split(focus, SplitDirection::Vertical);
+            }
+            FocusCommand::SplitHorizontal => {
+                self.split(focus, SplitDirection::Horizontal);
+            }
+            FocusCommand::SplitExchange => {
+                self.exchange(focus);
+            }
+            FocusCommand::SplitClose => {
+                self.close(focus);
+            }
+            FocusCommand::SplitLeft => {
+                self.move_focus(SplitMoveDirection::Left);
+            }
+            FocusCommand::SplitRight => {
+                self.move_focus(SplitMoveDirection::Right);
+            }
+            FocusCommand::SplitUp => {
+                self.move_focus(SplitMoveDirection::Up);
+            }
+            FocusCommand::SplitDown => {
+                self.move_focus(SplitMoveDirection::Down);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapce_xi_rope::{Rope, RopeDelta};
+
+    use super::{DocumentId, PaneRect, SplitDirection, SplitMoveDirection, SplitNode, SplitTree};
+    use crate::{
+        command::FocusCommand,
+        cursor::{Cursor, CursorMode},
+    };
+
+    fn rects(tree: &SplitTree) -> Vec<(u64, f64, f64, f64, f64)> {
+        tree.layout(PaneRect::new(0.0, 0.0, 120.0, 60.0))
+            .into_iter()
+            .map(|(id, rect)| (id.0, rect.x, rect.y, rect.width, rect.height))
+            .collect()
+    }
+
+    #[test]
+    fn split_and_close() {
+        let mut tree = SplitTree::new(
+            DocumentId(7),
+            Cursor::new(CursorMode::Normal(3), None, None),
+        );
+        let first = tree.focus();
+        let second = tree.split(first, SplitDirection::Vertical).unwrap();
+        assert_eq!(tree.focus(), second);
+        assert_eq!(tree.focused().document, DocumentId(7));
+        assert_eq!(tree.focused().cursor.offset(), 3);
+
+        // Splitting again in the same direction shares the area of the pane that was split
+        let third = tree.split(first, SplitDirection::Vertical).unwrap();
+        assert_eq!(
+            rects(&tree),
+            [
+                (0, 0.0, 0.0, 30.0, 60.0),
+                (2, 30.0, 0.0, 30.0, 60.0),
+                (1, 60.0, 0.0, 60.0, 60.0)
+            ]
+        );
+
+        let fourth = tree.split(second, SplitDirection::Horizontal).unwrap();
+        assert_eq!(
+            rects(&tree)[2..],
+            [(1, 60.0, 0.0, 60.0, 30.0), (3, 60.0, 30.0, 60.0, 30.0)]
+        );
+
+        // The area goes to the sibling before, and the split of a single child collapses
+        assert!(tree.close(fourth));
+        assert_eq!(tree.focus(), second);
+        assert_eq!(rects(&tree)[2], (1, 60.0, 0.0, 60.0, 60.0));
+        assert!(matches!(
+            &tree.root,
+            SplitNode::Split { direction: SplitDirection::Vertical, children } if children.len() == 3
+        ));
+
+        assert!(tree.close(first));
+        assert_eq!(
+            rects(&tree),
+            [(2, 0.0, 0.0, 60.0, 60.0), (1, 60.0, 0.0, 60.0, 60.0)]
+        );
+        assert!(tree.close(second));
+        assert_eq!(tree.focus(), third);
+        assert!(matches!(tree.root, SplitNode::Pane(_)));
+        assert!(!tree.close(third));
+        assert_eq!(tree.panes().len(), 1);
+    }
+
+    #[test]
+    fn focus_by_geometry() {
+        // +---+---+
+        // |   | 2 |
+        // | 0 +---+
+        // |   | 3 |
+        // +---+---+
+        // |   1   |
+        // +-------+
+        let mut tree = SplitTree::new(DocumentId(0), Cursor::origin(true));
+        let left = tree.focus();
+        let bottom = tree.split(left, SplitDirection::Horizontal).unwrap();
+        let top_right = tree.split(left, SplitDirection::Vertical).unwrap();
+        let bottom_right = tree.split(top_right, SplitDirection::Horizontal).unwrap();
+
+        let neighbor = |tree: &SplitTree, id, direction| tree.neighbor(id, direction);
+        assert_eq!(
+            neighbor(&tree, left, SplitMoveDirection::Right),
+            Some(top_right)
+        );
+        assert_eq!(
+            neighbor(&tree, bottom_right, SplitMoveDirection::Left),
+            Some(left)
+        );
+        assert_eq!(
+            neighbor(&tree, bottom_right, SplitMoveDirection::Down),
+            Some(bottom)
+        );
+        assert_eq!(neighbor(&tree, bottom, SplitMoveDirection::Up), Some(left));
+        assert_eq!(neighbor(&tree, top_right, SplitMoveDirection::Up), None);
+
+        tree.set_focus(bottom);
+        assert!(tree.do_command(&FocusCommand::SplitUp));
+        assert!(tree.do_command(&FocusCommand::SplitRight));
+        assert!(tree.do_command(&FocusCommand::SplitDown));
+        assert_eq!(tree.focus(), bottom_right);
+        assert!(!tree.move_focus(SplitMoveDirection::Right));
+        assert!(!tree.do_command(&FocusCommand::SearchForward));
+    }
+
+    #[test]
+    fn exchange() {
+        let mut tree = SplitTree::new(DocumentId(0), Cursor::origin(true));
+        let first = tree.focus();
+        let second = tree.split(first, SplitDirection::Vertical).unwrap();
+        let SplitNode::Split { children, .. } = &mut tree.root else {
+            panic!("the root is a split");
+        };
+        children[0].ratio = 3.0;
+
+        tree.set_focus(first);
+        tree.do_command(&FocusCommand::SplitExchange);
+        assert_eq!(tree.focus(), first);
+        assert_eq!(
+            rects(&tree),
+            [(1, 0.0, 0.0, 90.0, 60.0), (0, 90.0, 0.0, 30.0, 60.0)]
+        );
+        // The last pane exchanges with the one before it
+        assert!(tree.exchange(first));
+        assert_eq!(tree.panes()[0].id, first);
+        assert!(tree.close(second));
+        assert!(!tree.exchange(first));
+    }
+
+    #[test]
+    fn edits_move_other_cursors() {
+        let mut tree = SplitTree::new(
+            DocumentId(0),
+            Cursor::new(CursorMode::Normal(4), None, None),
+        );
+        let first = tree.focus();
+        let second = tree.split(first, SplitDirection::Vertical).unwrap();
+        let other = tree.split(second, SplitDirection::Vertical).unwrap();
+        tree.focused_mut().document = DocumentId(1);
+
+        let delta =
+            RopeDelta::simple_edit(lapce_xi_rope::Interval::new(0, 0), Rope::from("ab"), 10);
+        tree.apply_delta(first, &delta);
+        assert_eq!(tree.pane(first).unwrap().cursor.offset(), 4);
+        assert_eq!(tree.pane(second).unwrap().cursor.offset(), 6);
+        assert_eq!(tree.pane(other).unwrap().cursor.offset(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize() {
+        let mut tree = SplitTree::new(DocumentId(3), Cursor::origin(false));
+        let first = tree.focus();
+        tree.split(first, SplitDirection::Horizontal);
+        tree.set_focus(first);
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: SplitTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, tree);
+        assert_eq!(restored.focus(), first);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn validate_on_load() {
+        let mut tree = SplitTree::new(DocumentId(3), Cursor::origin(false));
+        let first = tree.focus();
+        let second = tree.split(first, SplitDirection::Horizontal).unwrap();
+
+        // A split left with one child, a focus and a next id that are not those of a pane
+        let mut json = serde_json::to_value(&tree).unwrap();
+        json["root"]["Split"]["children"]
+            .as_array_mut()
+            .unwrap()
+            .remove(0);
+        json["focus"] = 7.into();
+        json["next_id"] = 0.into();
+        let mut restored: SplitTree = serde_json::from_value(json.clone()).unwrap();
+        assert!(matches!(restored.root, SplitNode::Pane(_)));
+        assert_eq!(restored.focus(), second);
+        assert_eq!(restored.focused().document, DocumentId(3));
+        assert!(!restored.exchange(second));
+        assert!(!restored.close(second));
+        let third = restored.split(second, SplitDirection::Vertical).unwrap();
+        assert_ne!(third, second);
+
+        json["root"]["Split"]["children"] = serde_json::json!([]);
+        assert!(serde_json::from_value::<SplitTree>(json).is_err());
+    }
+}
@@ -0,0 +1,504 @@
+//! The lines of a buffer that a view shows, and the scrolling of them along with the cursor.
+//!
+//! A [`Viewport`] counts in display lines, the lines as a view lays them out: buffer lines as
+//! they are ([`BufferLines`]), or with the closed folds hidden ([`FoldState`]), or soft wrapped
+//! into visual lines ([`VisualLines`]). Any of them can be given as the [`DisplayLines`] of
+//! a viewport.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    buffer::rope_text::RopeText,
+    command::ScrollCommand,
+    cursor::{Cursor, CursorAffinity, CursorMode},
+    fold::FoldState,
+    movement::{column_of_offset, last_cursor_offset, offset_of_column},
+    selection::SelRegion,
+    visual_line::{TextMeasure, VisualLines},
+};
+
+/// The lines of a buffer as a view lays them out.
+pub trait DisplayLines {
+    /// The number of display lines, which is at least one.
+    fn num_display_lines(&mut self, text: &impl RopeText) -> usize;
+
+    /// The display line that `offset` is on.
+    fn display_line_of_offset(&mut self, text: &impl RopeText, offset: usize) -> usize;
+
+    /// The start and end offsets of the text of a display line, excluding the line ending.
+    fn display_line_range(&mut self, text: &impl RopeText, line: usize) -> Range<usize>;
+}
+
+/// Buffer lines, each displayed as a single line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferLines;
+
+impl DisplayLines for BufferLines {
+    fn num_display_lines(&mut self, text: &impl RopeText) -> usize {
+        text.num_lines()
+    }
+
+    fn display_line_of_offset(&mut self, text: &impl RopeText, offset: usize) -> usize {
+        text.line_of_offset(offset)
+    }
+
+    fn display_line_range(&mut self, text: &impl RopeText, line: usize) -> Range<usize> {
+        text.offset_of_line(line)..text.line_end_offset(line, true)
+    }
+}
+
+/// The visible lines, a closed fold being displayed as its first line.
+impl DisplayLines for FoldState {
+    fn num_display_lines(&mut self, text: &impl RopeText) -> usize {
+        self.num_visible_lines(text)
+    }
+
+    fn display_line_of_offset(&mut self, text: &impl RopeText, offset: usize) -> usize {
+        self.visible_line(text, text.line_of_offset(offset))
+    }
+
+    fn display_line_range(&mut self, text: &impl RopeText, line: usize) -> Range<usize> {
+        BufferLines.display_line_range(text, self.buffer_line(text, line))
+    }
+}
+
+/// The visual lines. An offset at a wrap point is on the visual line that it starts.
+impl<M: TextMeasure> DisplayLines for VisualLines<M> {
+    fn num_display_lines(&mut self, text: &impl RopeText) -> usize {
+        self.num_visual_lines(text)
+    }
+
+    fn display_line_of_offset(&mut self, text: &impl RopeText, offset: usize) -> usize {
+        self.offset_to_visual_line_col(text, offset, CursorAffinity::Forward)
+            .0
+    }
+
+    fn display_line_range(&mut self, text: &impl RopeText, line: usize) -> Range<usize> {
+        self.visual_line_range(text, line)
+    }
+}
+
+/// The display lines that a view shows.
+///
+/// The cursor is kept `scroll_off` lines away from the top and bottom of the viewport, like
+/// vim's `scrolloff`, except where the start or end of the text is in view.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::{
+/// #     buffer::{rope_text::RopeText, Buffer},
+/// #     command::ScrollCommand,
+/// #     cursor::{Cursor, CursorMode},
+/// #     viewport::{BufferLines, Viewport},
+/// # };
+/// let text: String = (0..100).map(|i| format!("  line {i}\n")).collect();
+/// let buffer = Buffer::new(text.as_str());
+/// let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+/// let mut viewport = Viewport::new(10, 2);
+///
+/// // A page down shows the next page, keeping two lines of the last one, and moves the cursor
+/// // to the first non-blank character of the topmost line it can be on
+/// viewport.do_command(&ScrollCommand::PageDown, None, &buffer, &mut BufferLines, &mut cursor);
+/// assert_eq!(viewport.lines(), 8..18);
+/// assert_eq!(cursor.offset(), buffer.offset_of_line(10) + 2);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Viewport {
+    /// The display line at the top of the view.
+    pub first_line: usize,
+    /// The number of display lines that fit in the view.
+    pub height: usize,
+    /// The number of lines to keep between the cursor and the top and bottom of the view.
+    pub scroll_off: usize,
+}
+
+impl Viewport {
+    pub fn new(height: usize, scroll_off: usize) -> Self {
+        Self {
+            first_line: 0,
+            height,
+            scroll_off,
+        }
+    }
+
+    /// The display lines in view, some of which may be past the end of the text.
+    pub fn lines(&self) -> Range<usize> {
+        self.first_line..self.first_line + self.height
+    }
+
+    /// The scroll-off, limited so that the margins leave a line for the cursor.
+    fn margin(&self) -> usize {
+        self.scroll_off.min(self.height.saturating_sub(1) / 2)
+    }
+
+    /// The first and last display line that the cursor can be on without scrolling.
+    fn cursor_lines(&self, num_lines: usize) -> (usize, usize) {
+        let last_line = num_lines - 1;
+        let margin = self.margin();
+        let top = if self.first_line == 0 {
+            0
+        } else {
+            (self.first_line + margin).min(last_line)
+        };
+        let view_bottom = self.first_line + self.height.max(1) - 1;
+        let bottom = if view_bottom >= last_line {
+            last_line
+        } else {
+            view_bottom - margin
+        };
+        (top, bottom.max(top))
+    }
+
+    /// Scroll as little as needed for `line` to be in view, along with its scroll-off.
+    pub fn scroll_to_line(&mut self, line: usize, num_lines: usize) {
+        let last_line = num_lines.saturating_sub(1);
+        let line = line.min(last_line);
+        let margin = self.margin();
+        self.first_line = self.first_line.min(last_line);
+        if line < self.first_line + margin {
+            self.first_line = line.saturating_sub(margin);
+        } else {
+            let bottom = (line + margin).min(last_line);
+            if bottom >= self.first_line + self.height {
+                self.first_line = (bottom + 1).saturating_sub(self.height);
+            }
+        }
+    }
+
+    /// Scroll for the cursor to be in view, such as after it moved or the text was edited.
+    pub fn scroll_to_cursor(
+        &mut self,
+        text: &impl RopeText,
+        lines: &mut impl DisplayLines,
+        cursor: &Cursor,
+    ) {
+        let line = lines.display_line_of_offset(text, cursor.offset());
+        let num_lines = lines.num_display_lines(text);
+        self.scroll_to_line(line, num_lines);
+    }
+
+    /// Run a scroll command, repeated `count` times for the commands that scroll by lines or
+    /// pages, as vim does:
+    ///
+    /// - [`ScrollCommand::PageDown`] and [`ScrollCommand::PageUp`] scroll by a page less two
+    ///   lines, like `ctrl+f` and `ctrl+b`, and the cursor moves to the first non-blank
+    ///   character of its new line.
+    /// - [`ScrollCommand::ScrollDown`] and [`ScrollCommand::ScrollUp`] scroll by a line, like
+    ///   `ctrl+e` and `ctrl+y`, and the cursor keeps its column.
+    /// - [`ScrollCommand::CenterOfWindow`], [`ScrollCommand::TopOfWindow`] and
+    ///   [`ScrollCommand::BottomOfWindow`] scroll the cursor line to the center, top or bottom
+    ///   of the view, like `zz`, `zt` and `zb`.
+    ///
+    /// The cursor only moves if scrolling would leave it out of view, or within the scroll-off.
+    /// Of several cursors, only the primary one is moved.
+    pub fn do_command(
+        &mut self,
+        cmd: &ScrollCommand,
+        count: Option<usize>,
+        text: &impl RopeText,
+        lines: &mut impl DisplayLines,
+        cursor: &mut Cursor,
+    ) {
+        let num_lines = lines.num_display_lines(text);
+        let last_line = num_lines - 1;
+        let line = lines.display_line_of_offset(text, cursor.offset());
+        let count = count.unwrap_or(1);
+        let page = self.height.saturating_sub(2).max(1);
+        let margin = self.margin();
+
+        self.first_line = match cmd {
+            ScrollCommand::PageDown => self.first_line + page * count,
+            ScrollCommand::PageUp => self.first_line.saturating_sub(page * count),
+            ScrollCommand::ScrollDown => self.first_line + count,
+            ScrollCommand::ScrollUp => self.first_line.saturating_sub(count),
+            ScrollCommand::CenterOfWindow => line.saturating_sub(self.height.saturating_sub(1) / 2),
+            ScrollCommand::TopOfWindow => line.saturating_sub(margin),
+            ScrollCommand::BottomOfWindow => (line + margin + 1).saturating_sub(self.height),
+        }
+        .min(last_line);
+
+        let (top, bottom) = self.cursor_lines(num_lines);
+        let new_line = line.clamp(top, bottom);
+        if new_line == line {
+            return;
+        }
+        let caret = cursor.is_insert();
+        let offset = match cmd {
+            ScrollCommand::ScrollDown | ScrollCommand::ScrollUp => {
+                let range = lines.display_line_range(text, line);
+                let column = column_of_offset(text, range.start, cursor.offset());
+                let new_range = lines.display_line_range(text, new_line);
+                let last = last_cursor_offset(text, &new_range, caret);
+                offset_of_column(text, new_range.start, column, last)
+            }
+            _ => {
+                let range = lines.display_line_range(text, new_line);
+                let last = last_cursor_offset(text, &range, caret);
+                let line_start = text.offset_of_line(text.line_of_offset(range.start));
+                if range.start == line_start {
+                    // The first non-blank character, unless it is on a following visual line
+                    let first_non_blank =
+                        text.first_non_blank_character_on_line(text.line_of_offset(range.start));
+                    first_non_blank.min(last)
+                } else {
+                    range.start
+                }
+            }
+        };
+
+        cursor.mode = match &cursor.mode {
+            CursorMode::Normal(_) => CursorMode::Normal(offset),
+            CursorMode::Visual { start, mode, .. } => CursorMode::Visual {
+                start: *start,
+                end: offset,
+                mode: *mode,
+            },
+            CursorMode::Insert(selection) => {
+                let mut selection = selection.clone();
+                selection.replace_last_inserted_region(SelRegion::caret(offset));
+                CursorMode::Insert(selection)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BufferLines, Viewport};
+    use crate::{
+        buffer::{rope_text::RopeText, Buffer},
+        command::ScrollCommand,
+        cursor::{Cursor, CursorMode},
+        fold::{FoldRange, FoldState},
+        selection::{SelRegion, Selection},
+        visual_line::{MonospaceMeasure, VisualLines},
+    };
+
+    fn buffer(lines: usize) -> Buffer {
+        let text: String = (0..lines).map(|i| format!("  line {i}\n")).collect();
+        Buffer::new(text.as_str())
+    }
+
+    fn normal(offset: usize) -> Cursor {
+        Cursor::new(CursorMode::Normal(offset), None, None)
+    }
+
+    #[test]
+    fn pages() {
+        let buffer = buffer(100);
+        let mut cursor = normal(0);
+        let mut viewport = Viewport::new(10, 2);
+        let command = |viewport: &mut Viewport, cursor: &mut Cursor, cmd, count| {
+            viewport.do_command(&cmd, count, &buffer, &mut BufferLines, cursor);
+            buffer.line_of_offset(cursor.offset())
+        };
+
+        assert_eq!(
+            command(&mut viewport, &mut cursor, ScrollCommand::PageDown, Some(2)),
+            18
+        );
+        assert_eq!(viewport.first_line, 16);
+        // The cursor is still in view after a page up, a page back
+        assert_eq!(
+            command(&mut viewport, &mut cursor, ScrollCommand::PageUp, None),
+            15
+        );
+        assert_eq!(viewport.first_line, 8);
+        assert_eq!(cursor.offset(), buffer.offset_of_line(15) + 2);
+
+        // At the end, the last line can be scrolled to the top
+        assert_eq!(
+            command(
+                &mut viewport,
+                &mut cursor,
+                ScrollCommand::PageDown,
+                Some(50)
+            ),
+            100
+        );
+        assert_eq!(viewport.first_line, 100);
+        assert_eq!(
+            command(&mut viewport, &mut cursor, ScrollCommand::PageUp, Some(50)),
+            7
+        );
+        assert_eq!(viewport.first_line, 0);
+    }
+
+    #[test]
+    fn scroll_lines_keeps_the_column() {
+        let buffer = Buffer::new("abc\nسلام عليكم\nx\nabcdef\nabcdef\nabcdef");
+        let mut cursor = normal(buffer.offset_of_line(1) + 6);
+        let mut viewport = Viewport::new(3, 0);
+        viewport.first_line = 1;
+
+        viewport.do_command(
+            &ScrollCommand::ScrollDown,
+            None,
+            &buffer,
+            &mut BufferLines,
+            &mut cursor,
+        );
+        assert_eq!(viewport.first_line, 2);
+        // The third character of the line, or its last one
+        assert_eq!(cursor.offset(), buffer.offset_of_line(2));
+        viewport.do_command(
+            &ScrollCommand::ScrollDown,
+            None,
+            &buffer,
+            &mut BufferLines,
+            &mut cursor,
+        );
+        assert_eq!(cursor.offset(), buffer.offset_of_line(3));
+
+        let mut cursor = normal(buffer.offset_of_line(5) + 3);
+        viewport.do_command(
+            &ScrollCommand::ScrollUp,
+            Some(2),
+            &buffer,
+            &mut BufferLines,
+            &mut cursor,
+        );
+        assert_eq!(viewport.first_line, 1);
+        assert_eq!(cursor.offset(), buffer.offset_of_line(3) + 3);
+
+        // Scrolling that leaves the cursor in view doesn't move it
+        viewport.do_command(
+            &ScrollCommand::ScrollDown,
+            None,
+            &buffer,
+            &mut BufferLines,
+            &mut cursor,
+        );
+        assert_eq!(cursor.offset(), buffer.offset_of_line(3) + 3);
+    }
+
+    #[test]
+    fn scroll_lines_counts_graphemes_and_keeps_other_cursors() {
+        let buffer = Buffer::new("كَتَبَ\nabcdef\nabcdef");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(27));
+        // After two letters with their harakat, the primary cursor
+        selection.add_region(SelRegion::caret(8));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let mut viewport = Viewport::new(2, 0);
+
+        viewport.do_command(
+            &ScrollCommand::ScrollDown,
+            None,
+            &buffer,
+            &mut BufferLines,
+            &mut cursor,
+        );
+        assert_eq!(viewport.first_line, 1);
+        assert_eq!(cursor.offset(), buffer.offset_of_line(1) + 2);
+        assert_eq!(
+            cursor.regions_iter().collect::<Vec<_>>(),
+            [(15, 15), (27, 27)]
+        );
+    }
+
+    #[test]
+    fn cursor_line_positions() {
+        let buffer = buffer(100);
+        let mut cursor = normal(buffer.offset_of_line(50));
+        let mut viewport = Viewport::new(10, 2);
+        let mut command = |viewport: &mut Viewport, cmd| {
+            viewport.do_command(&cmd, None, &buffer, &mut BufferLines, &mut cursor);
+            viewport.first_line
+        };
+        assert_eq!(command(&mut viewport, ScrollCommand::CenterOfWindow), 46);
+        assert_eq!(command(&mut viewport, ScrollCommand::TopOfWindow), 48);
+        assert_eq!(command(&mut viewport, ScrollCommand::BottomOfWindow), 43);
+        assert_eq!(cursor.offset(), buffer.offset_of_line(50));
+    }
+
+    #[test]
+    fn follow_the_cursor() {
+        let mut buffer = buffer(100);
+        let mut viewport = Viewport::new(10, 3);
+        viewport.scroll_to_cursor(
+            &buffer,
+            &mut BufferLines,
+            &normal(buffer.offset_of_line(20)),
+        );
+        assert_eq!(viewport.first_line, 14);
+        viewport.scroll_to_cursor(
+            &buffer,
+            &mut BufferLines,
+            &normal(buffer.offset_of_line(10)),
+        );
+        assert_eq!(viewport.first_line, 7);
+        viewport.scroll_to_cursor(
+            &buffer,
+            &mut BufferLines,
+            &normal(buffer.offset_of_line(98)),
+        );
+        assert_eq!(viewport.first_line, 91);
+
+        // Deleting the lines in view scrolls back to the end of the text
+        let end = buffer.len();
+        buffer.edit(
+            &[(Selection::region(buffer.offset_of_line(5), end), "")],
+            crate::editor::EditType::Delete,
+        );
+        viewport.scroll_to_cursor(&buffer, &mut BufferLines, &normal(buffer.offset_of_line(5)));
+        assert_eq!(viewport.first_line, 2);
+    }
+
+    #[test]
+    fn folded_lines() {
+        let buffer = buffer(100);
+        let mut folds = FoldState::new();
+        folds.fold(&buffer, FoldRange::new(2, 60));
+        let mut cursor = normal(0);
+        let mut viewport = Viewport::new(10, 0);
+
+        viewport.do_command(
+            &ScrollCommand::PageDown,
+            None,
+            &buffer,
+            &mut folds,
+            &mut cursor,
+        );
+        // Display line 8 is buffer line 66
+        assert_eq!(viewport.first_line, 8);
+        assert_eq!(cursor.offset(), buffer.offset_of_line(66) + 2);
+    }
+
+    #[test]
+    fn wrapped_lines() {
+        let buffer = Buffer::new("aaaa bbbb cccc dddd\nee");
+        let mut lines = VisualLines::new(5.0, MonospaceMeasure::default());
+        let mut cursor = normal(0);
+        let mut viewport = Viewport::new(2, 0);
+
+        viewport.do_command(
+            &ScrollCommand::ScrollDown,
+            Some(2),
+            &buffer,
+            &mut lines,
+            &mut cursor,
+        );
+        assert_eq!(viewport.first_line, 2);
+        // The start of the third visual line, rather than the first non-blank of the buffer line
+        assert_eq!(cursor.offset(), 10);
+
+        let mut cursor = Cursor::new(CursorMode::Insert(Selection::caret(20)), None, None);
+        viewport.scroll_to_cursor(&buffer, &mut lines, &cursor);
+        assert_eq!(viewport.first_line, 3);
+        viewport.do_command(
+            &ScrollCommand::PageUp,
+            None,
+            &buffer,
+            &mut lines,
+            &mut cursor,
+        );
+        assert_eq!(viewport.first_line, 2);
+        assert_eq!(cursor.offset(), 15);
+    }
+}
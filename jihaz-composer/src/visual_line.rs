@@ -0,0 +1,727 @@
+//! Soft wrapping of buffer lines into visual lines, independently of the renderer.
+//!
+//! [`VisualLines`] splits each buffer line into the visual lines it occupies for a given wrap
+//! width, using a [`TextMeasure`] to know how wide text is, and maps buffer offsets to and from
+//! `(visual line, column)` positions. As the [`DisplayLines`](crate::viewport::DisplayLines) of
+//! a view, they are the lines that [`move_offset_in`](crate::movement::move_offset_in) moves up
+//! and down through, and [`VisualLines::lens`] gives the heights of the buffer lines they make.
+
+use std::sync::Arc;
+
+use parley::{FontContext, LayoutContext, StyleProperty};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    buffer::{rope_text::RopeText, InvalLines},
+    cursor::CursorAffinity,
+    lens::{Lens, LensBuilder},
+};
+
+/// Measures the width of text, in the same unit as the wrap width of [`VisualLines`].
+///
+/// [`MonospaceMeasure`] counts columns and [`ParleyMeasure`] lays the text out for proportional
+/// fonts. Any `FnMut(&str) -> f64` closure can be used as a measure too.
+pub trait TextMeasure {
+    /// The advance width of `text`, which never contains a line ending.
+    fn measure(&mut self, text: &str) -> f64;
+}
+
+impl<F: FnMut(&str) -> f64> TextMeasure for F {
+    fn measure(&mut self, text: &str) -> f64 {
+        self(text)
+    }
+}
+
+/// Measures text by counting columns, where every character takes its unicode display width
+/// (so CJK characters take two columns and combining marks none) times `char_width`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MonospaceMeasure {
+    pub char_width: f64,
+    /// The number of columns a tab takes.
+    pub tab_width: usize,
+}
+
+impl Default for MonospaceMeasure {
+    fn default() -> Self {
+        Self {
+            char_width: 1.0,
+            tab_width: 4,
+        }
+    }
+}
+
+impl TextMeasure for MonospaceMeasure {
+    fn measure(&mut self, text: &str) -> f64 {
+        // A grapheme cluster never takes more than two columns, even when it is made of several
+        // wide characters like a ZWJ emoji sequence
+        let columns: usize = text
+            .graphemes(true)
+            .map(|grapheme| {
+                if grapheme == "\t" {
+                    self.tab_width
+                } else {
+                    let width: usize = grapheme.chars().map(|c| c.width().unwrap_or(0)).sum();
+                    width.min(2)
+                }
+            })
+            .sum();
+        columns as f64 * self.char_width
+    }
+}
+
+/// Measures text by laying it out with parley, for proportional fonts.
+pub struct ParleyMeasure {
+    font_cx: FontContext,
+    layout_cx: LayoutContext<()>,
+    styles: Vec<StyleProperty<'static, ()>>,
+}
+
+impl ParleyMeasure {
+    /// A measure that lays text out with the fonts of `font_cx` in `styles`.
+    pub fn new(font_cx: FontContext, styles: Vec<StyleProperty<'static, ()>>) -> Self {
+        Self {
+            font_cx,
+            layout_cx: LayoutContext::new(),
+            styles,
+        }
+    }
+}
+
+impl TextMeasure for ParleyMeasure {
+    fn measure(&mut self, text: &str) -> f64 {
+        let mut builder = self.layout_cx.ranged_builder(&mut self.font_cx, text, 1.0);
+        for style in &self.styles {
+            builder.push_default(style.clone());
+        }
+        let mut layout = builder.build(text);
+        layout.break_all_lines(None);
+        layout.width() as f64
+    }
+}
+
+/// How the visual lines that continue a wrapped buffer line are indented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContinuationIndent {
+    /// Continuation lines start at the left edge.
+    None,
+    /// Continuation lines are indented by a fixed width.
+    Fixed(f64),
+    /// Continuation lines are aligned with the indentation of their buffer line, plus `extra`.
+    SameAsLine { extra: f64 },
+}
+
+/// The visual lines that a single buffer line is wrapped into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineWraps {
+    /// Start offset, relative to the start of the buffer line, of every visual line.
+    /// The first one is always `0`.
+    starts: Vec<usize>,
+    /// Length of the buffer line, excluding its line ending.
+    len: usize,
+    /// The width at which continuation lines start.
+    indent: f64,
+}
+
+impl LineWraps {
+    /// The number of visual lines of the buffer line.
+    pub fn count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The range, relative to the start of the buffer line, of the visual line `index`.
+    /// The last visual line excludes the line ending.
+    pub fn range(&self, index: usize) -> std::ops::Range<usize> {
+        let start = self.starts[index];
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.len);
+        start..end
+    }
+
+    /// The width at which the visual line `index` starts.
+    pub fn indent(&self, index: usize) -> f64 {
+        if index == 0 {
+            0.0
+        } else {
+            self.indent
+        }
+    }
+
+    /// The visual line containing the line relative offset `offset`.
+    /// An offset at a wrap point belongs to the end of the previous visual line with
+    /// [`CursorAffinity::Backward`], and to the start of the next one otherwise.
+    pub fn index_of(&self, offset: usize, affinity: CursorAffinity) -> usize {
+        let index = self.starts.partition_point(|start| *start <= offset) - 1;
+        if index > 0 && affinity == CursorAffinity::Backward && self.starts[index] == offset {
+            index - 1
+        } else {
+            index
+        }
+    }
+}
+
+/// A soft wrapped view of the lines of a buffer.
+///
+/// The wraps of every buffer line are computed lazily and cached, and the cache has to be kept
+/// up to date with [`VisualLines::update`] after every edit of the buffer.
+///
+/// **Example:**
+///
+/// ```rust
+/// # use jihaz_composer::buffer::Buffer;
+/// # use jihaz_composer::cursor::CursorAffinity;
+/// # use jihaz_composer::visual_line::{MonospaceMeasure, VisualLines};
+/// let buffer = Buffer::new("hello wrapped world\nok");
+/// let mut lines = VisualLines::new(10.0, MonospaceMeasure::default());
+/// assert_eq!(lines.num_visual_lines(&buffer), 4);
+/// assert_eq!(lines.offset_to_visual_line_col(&buffer, 8, CursorAffinity::Forward), (1, 2));
+/// assert_eq!(lines.offset_of_visual_line_col(&buffer, 3, 1), 21);
+/// ```
+pub struct VisualLines<M: TextMeasure = MonospaceMeasure> {
+    width: f64,
+    measure: M,
+    continuation_indent: ContinuationIndent,
+    /// The wraps of every buffer line, if computed.
+    lines: Vec<Option<Arc<LineWraps>>>,
+    /// `prefix[i]` is the number of visual lines before buffer line `i`, valid for the buffer
+    /// lines `0..prefix.len()`.
+    prefix: Vec<usize>,
+}
+
+impl<M: TextMeasure> VisualLines<M> {
+    /// Create a model that wraps lines wider than `width`.
+    /// A `width` that is not positive and finite disables wrapping.
+    pub fn new(width: f64, measure: M) -> Self {
+        Self {
+            width,
+            measure,
+            continuation_indent: ContinuationIndent::None,
+            lines: Vec::new(),
+            prefix: Vec::new(),
+        }
+    }
+
+    pub fn with_continuation_indent(mut self, indent: ContinuationIndent) -> Self {
+        self.continuation_indent = indent;
+        self.clear();
+        self
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Change the wrap width, which invalidates every cached line.
+    pub fn set_width(&mut self, width: f64) {
+        if self.width != width {
+            self.width = width;
+            self.clear();
+        }
+    }
+
+    pub fn set_continuation_indent(&mut self, indent: ContinuationIndent) {
+        if self.continuation_indent != indent {
+            self.continuation_indent = indent;
+            self.clear();
+        }
+    }
+
+    pub fn measure_mut(&mut self) -> &mut M {
+        &mut self.measure
+    }
+
+    /// Forget every cached line, e.g. after changing the font used by the measure.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.prefix.clear();
+    }
+
+    /// Invalidate the cached lines affected by an edit of the buffer.
+    pub fn update(&mut self, inval_lines: &InvalLines) {
+        let start = inval_lines.start_line.min(self.lines.len());
+        let end = (inval_lines.start_line + inval_lines.inval_count).min(self.lines.len());
+        self.lines
+            .splice(start..end, (0..inval_lines.new_count).map(|_| None));
+        self.prefix.truncate(start);
+    }
+
+    /// The wraps of the buffer line `line`.
+    pub fn line_wraps(&mut self, text: &impl RopeText, line: usize) -> Arc<LineWraps> {
+        self.sync_len(text);
+        if let Some(wraps) = &self.lines[line] {
+            return wraps.clone();
+        }
+        let wraps = Arc::new(self.wrap_line(text, line));
+        self.lines[line] = Some(wraps.clone());
+        wraps
+    }
+
+    /// The heights of the buffer lines, each as tall as its visual lines of `line_height`.
+    ///
+    /// **Example:**
+    ///
+    /// ```rust
+    /// # use jihaz_composer::buffer::Buffer;
+    /// # use jihaz_composer::visual_line::{MonospaceMeasure, VisualLines};
+    /// let buffer = Buffer::new("hello wrapped world\nok");
+    /// let mut lines = VisualLines::new(10.0, MonospaceMeasure::default());
+    /// let lens = lines.lens(&buffer, 20);
+    /// assert_eq!(lens.height_of_line(1), 60);
+    /// assert_eq!(lens.line_of_height(50), 0);
+    /// ```
+    pub fn lens(&mut self, text: &impl RopeText, line_height: usize) -> Lens {
+        let mut builder = LensBuilder::new();
+        // Consecutive lines of the same height make a single section
+        let mut section: Option<(usize, usize)> = None;
+        for line in 0..=text.last_line() {
+            let height = self.line_wraps(text, line).count() * line_height;
+            match &mut section {
+                Some((len, section_height)) if *section_height == height => *len += 1,
+                _ => {
+                    if let Some((len, height)) = section.replace((1, height)) {
+                        builder.add_section(len, height);
+                    }
+                }
+            }
+        }
+        if let Some((len, height)) = section {
+            builder.add_section(len, height);
+        }
+        builder.build()
+    }
+
+    /// The total number of visual lines of the buffer, which lays out every buffer line that
+    /// isn't yet.
+    pub fn num_visual_lines(&mut self, text: &impl RopeText) -> usize {
+        let last_line = text.last_line();
+        self.first_visual_line(text, last_line) + self.line_wraps(text, last_line).count()
+    }
+
+    /// The index of the first visual line of the buffer line `line`.
+    pub fn first_visual_line(&mut self, text: &impl RopeText, line: usize) -> usize {
+        self.sync_len(text);
+        while self.prefix.len() <= line {
+            let previous = self.prefix.len();
+            let count = match previous.checked_sub(1) {
+                Some(before) => self.prefix[before] + self.line_wraps(text, before).count(),
+                None => 0,
+            };
+            self.prefix.push(count);
+        }
+        self.prefix[line]
+    }
+
+    /// The buffer line that the visual line `visual_line` belongs to, and the index of the
+    /// visual line within that buffer line.
+    /// Visual lines past the end of the buffer are clamped to the last one.
+    ///
+    /// Only the buffer lines up to the one of `visual_line` are laid out.
+    pub fn buffer_line_of_visual_line(
+        &mut self,
+        text: &impl RopeText,
+        visual_line: usize,
+    ) -> (usize, usize) {
+        self.sync_len(text);
+        let last_line = text.last_line();
+        // Start from the last buffer line known to start at or before the visual line
+        let mut line = self
+            .prefix
+            .partition_point(|first| *first <= visual_line)
+            .saturating_sub(1)
+            .min(last_line);
+        loop {
+            let first = self.first_visual_line(text, line);
+            let count = self.line_wraps(text, line).count();
+            if visual_line < first + count || line == last_line {
+                return (line, (visual_line - first).min(count - 1));
+            }
+            line += 1;
+        }
+    }
+
+    /// The visual line and the column, as a byte offset from the start of that visual line, of
+    /// `offset`.
+    ///
+    /// `affinity` decides whether an offset at a wrap point is at the end of the first visual line
+    /// or at the start of the second.
+    pub fn offset_to_visual_line_col(
+        &mut self,
+        text: &impl RopeText,
+        offset: usize,
+        affinity: CursorAffinity,
+    ) -> (usize, usize) {
+        let (line, col) = text.offset_to_line_col(offset);
+        let wraps = self.line_wraps(text, line);
+        let index = wraps.index_of(col, affinity);
+        (
+            self.first_visual_line(text, line) + index,
+            col - wraps.range(index).start,
+        )
+    }
+
+    /// The offset of the column `col` of the visual line `visual_line`.
+    /// The column is clamped to the length of the visual line.
+    pub fn offset_of_visual_line_col(
+        &mut self,
+        text: &impl RopeText,
+        visual_line: usize,
+        col: usize,
+    ) -> usize {
+        let (line, index) = self.buffer_line_of_visual_line(text, visual_line);
+        let range = self.line_wraps(text, line).range(index);
+        let line_start = text.offset_of_line(line);
+        let mut offset = line_start + (range.start + col).min(range.end);
+        while !text.text().is_codepoint_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    /// The start and end offsets of the visual line `visual_line`, excluding the line ending.
+    pub fn visual_line_range(
+        &mut self,
+        text: &impl RopeText,
+        visual_line: usize,
+    ) -> std::ops::Range<usize> {
+        let (line, index) = self.buffer_line_of_visual_line(text, visual_line);
+        let range = self.line_wraps(text, line).range(index);
+        let line_start = text.offset_of_line(line);
+        line_start + range.start..line_start + range.end
+    }
+
+    /// The horizontal position of `offset` within its visual line, including the continuation
+    /// indent.
+    pub fn offset_x(
+        &mut self,
+        text: &impl RopeText,
+        offset: usize,
+        affinity: CursorAffinity,
+    ) -> f64 {
+        let (visual_line, col) = self.offset_to_visual_line_col(text, offset, affinity);
+        let (_, index) = self.buffer_line_of_visual_line(text, visual_line);
+        let range = self.visual_line_range(text, visual_line);
+        let before = text.slice_to_cow(range.start..range.start + col);
+        let line = text.line_of_offset(range.start);
+        self.line_wraps(text, line).indent(index) + self.measure.measure(&before)
+    }
+
+    /// The offset in the visual line `visual_line` that is the closest to the horizontal position
+    /// `x`, which is what moving the cursor up and down between visual lines should use.
+    pub fn offset_of_x(&mut self, text: &impl RopeText, visual_line: usize, x: f64) -> usize {
+        let (line, index) = self.buffer_line_of_visual_line(text, visual_line);
+        let indent = self.line_wraps(text, line).indent(index);
+        let range = self.visual_line_range(text, visual_line);
+        let content = text.slice_to_cow(range.clone());
+
+        let mut current = indent;
+        for (i, grapheme) in content.grapheme_indices(true) {
+            let width = self.measure.measure(grapheme);
+            if current + width / 2.0 > x {
+                return range.start + i;
+            }
+            current += width;
+        }
+        range.end
+    }
+
+    fn sync_len(&mut self, text: &impl RopeText) {
+        let num_lines = text.num_lines();
+        if self.lines.len() != num_lines {
+            self.lines.resize(num_lines, None);
+            self.prefix.truncate(num_lines);
+        }
+    }
+
+    fn wrap_line(&mut self, text: &impl RopeText, line: usize) -> LineWraps {
+        let start = text.offset_of_line(line);
+        let end = text.line_end_offset(line, true);
+        let content = text.slice_to_cow(start..end);
+        wrap(
+            &content,
+            self.width,
+            self.continuation_indent,
+            &mut self.measure,
+        )
+    }
+}
+
+/// Wrap `content` at word boundaries so that no visual line is wider than `width`, breaking
+/// words that can't fit on a line of their own.
+fn wrap(
+    content: &str,
+    width: f64,
+    continuation_indent: ContinuationIndent,
+    measure: &mut impl TextMeasure,
+) -> LineWraps {
+    let mut wraps = LineWraps {
+        starts: vec![0],
+        len: content.len(),
+        indent: 0.0,
+    };
+    if !(width.is_finite() && width > 0.0) || content.is_empty() {
+        return wraps;
+    }
+
+    let indent = match continuation_indent {
+        ContinuationIndent::None => 0.0,
+        ContinuationIndent::Fixed(indent) => indent,
+        ContinuationIndent::SameAsLine { extra } => {
+            let indent_len = content.len() - content.trim_start().len();
+            measure.measure(&content[..indent_len]) + extra
+        }
+    };
+    // Never let the indent leave less than half of the width for text
+    let indent = indent.min(width / 2.0).max(0.0);
+    wraps.indent = indent;
+
+    let mut x = 0.0;
+    let mut line_start = 0;
+    for (word_start, word) in words(content) {
+        let trimmed = word.trim_end();
+        let word_width = measure.measure(trimmed);
+        if x + word_width <= width || (word_start == line_start && trimmed.is_empty()) {
+            x += measure.measure(word);
+            continue;
+        }
+
+        if word_start > line_start {
+            wraps.starts.push(word_start);
+            line_start = word_start;
+            x = indent;
+            if x + word_width <= width {
+                x += measure.measure(word);
+                continue;
+            }
+        }
+
+        // The word is too wide for a line of its own, so break it between grapheme clusters
+        for (i, grapheme) in word.grapheme_indices(true) {
+            let offset = word_start + i;
+            let char_width = measure.measure(grapheme);
+            if x + char_width > width
+                && offset > line_start
+                && !grapheme.starts_with(char::is_whitespace)
+            {
+                wraps.starts.push(offset);
+                line_start = offset;
+                x = indent;
+            }
+            x += char_width;
+        }
+    }
+    wraps
+}
+
+/// Split `content` into words that each include the whitespace after them, along with their
+/// start offsets. Leading whitespace is a word of its own.
+fn words(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start >= content.len() {
+            return None;
+        }
+        let rest = &content[start..];
+        let word_len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let space_len = rest[word_len..]
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len() - word_len);
+        let word = (start, &rest[..word_len + space_len]);
+        start += word_len + space_len;
+        Some(word)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use parley::{FontContext, StyleProperty};
+
+    use super::{ContinuationIndent, MonospaceMeasure, ParleyMeasure, TextMeasure, VisualLines};
+    use crate::{
+        bidi::BidiMovement,
+        buffer::{rope_text::RopeText, Buffer},
+        cursor::CursorAffinity,
+        editor::EditType,
+        mode::Mode,
+        movement::{move_offset_in, Movement},
+        selection::Selection,
+    };
+
+    fn visual_lines<M: TextMeasure>(buffer: &Buffer, lines: &mut VisualLines<M>) -> Vec<String> {
+        (0..lines.num_visual_lines(buffer))
+            .map(|visual_line| {
+                let range = lines.visual_line_range(buffer, visual_line);
+                buffer.slice_to_cow(range).to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries() {
+        let buffer = Buffer::new("the quick brown fox jumps\nover");
+        let mut lines = VisualLines::new(10.0, MonospaceMeasure::default());
+        assert_eq!(
+            visual_lines(&buffer, &mut lines),
+            ["the quick ", "brown fox ", "jumps", "over"]
+        );
+    }
+
+    #[test]
+    fn vertical_movement_within_a_line() {
+        let buffer = Buffer::new("the quick brown fox jumps\nover");
+        let mut lines = VisualLines::new(10.0, MonospaceMeasure::default());
+        let mut offset = 4;
+        let mut horiz = None;
+        let mut offsets = Vec::new();
+        for movement in [Movement::Down, Movement::Down, Movement::Down, Movement::Up] {
+            (offset, horiz) = move_offset_in(
+                &buffer,
+                &mut lines,
+                offset,
+                horiz.as_ref(),
+                &movement,
+                1,
+                Mode::Insert,
+                BidiMovement::Logical,
+                &mut CursorAffinity::Forward,
+            );
+            offsets.push(offset);
+        }
+        // The column is kept over the shorter last line
+        assert_eq!(offsets, [14, 24, 30, 24]);
+    }
+
+    #[test]
+    fn breaks_long_words() {
+        let buffer = Buffer::new("a abcdefghij");
+        let mut lines = VisualLines::new(4.0, MonospaceMeasure::default());
+        assert_eq!(
+            visual_lines(&buffer, &mut lines),
+            ["a ", "abcd", "efgh", "ij"]
+        );
+    }
+
+    #[test]
+    fn wide_characters() {
+        let buffer = Buffer::new("日本語のテキスト");
+        let mut lines = VisualLines::new(6.0, MonospaceMeasure::default());
+        assert_eq!(
+            visual_lines(&buffer, &mut lines),
+            ["日本語", "のテキ", "スト"]
+        );
+    }
+
+    #[test]
+    fn continuation_indent() {
+        let buffer = Buffer::new("    aaa bbb ccc");
+        let mut lines = VisualLines::new(10.0, MonospaceMeasure::default())
+            .with_continuation_indent(ContinuationIndent::SameAsLine { extra: 2.0 });
+        assert_eq!(
+            visual_lines(&buffer, &mut lines),
+            ["    aaa ", "bbb ", "ccc"]
+        );
+        let wraps = lines.line_wraps(&buffer, 0);
+        assert_eq!(wraps.indent(0), 0.0);
+        assert_eq!(wraps.indent(1), 5.0);
+        assert_eq!(lines.offset_x(&buffer, 9, CursorAffinity::Forward), 6.0);
+    }
+
+    #[test]
+    fn offset_visual_line_col_round_trip() {
+        let buffer = Buffer::new("aaaa bbbb\ncc\n");
+        let mut lines = VisualLines::new(5.0, MonospaceMeasure::default());
+        assert_eq!(lines.num_visual_lines(&buffer), 4);
+        for offset in 0..buffer.len() {
+            let (visual_line, col) =
+                lines.offset_to_visual_line_col(&buffer, offset, CursorAffinity::Forward);
+            assert_eq!(
+                lines.offset_of_visual_line_col(&buffer, visual_line, col),
+                offset
+            );
+        }
+        // The wrap point belongs to either visual line depending on the affinity
+        assert_eq!(
+            lines.offset_to_visual_line_col(&buffer, 5, CursorAffinity::Forward),
+            (1, 0)
+        );
+        assert_eq!(
+            lines.offset_to_visual_line_col(&buffer, 5, CursorAffinity::Backward),
+            (0, 5)
+        );
+        assert_eq!(lines.offset_of_visual_line_col(&buffer, 1, 100), 9);
+        assert_eq!(lines.buffer_line_of_visual_line(&buffer, 3), (2, 0));
+    }
+
+    #[test]
+    fn offset_of_x() {
+        let buffer = Buffer::new("aaaa bbbb");
+        let mut lines = VisualLines::new(5.0, MonospaceMeasure::default());
+        assert_eq!(lines.offset_of_x(&buffer, 1, 2.2), 7);
+        assert_eq!(lines.offset_of_x(&buffer, 1, 100.0), 9);
+    }
+
+    #[test]
+    fn update_from_inval_lines() {
+        let mut buffer = Buffer::new("short\nshort\nshort");
+        let mut lines = VisualLines::new(5.0, MonospaceMeasure::default());
+        assert_eq!(lines.num_visual_lines(&buffer), 3);
+
+        let (_, _, inval_lines) = buffer.edit(
+            [(Selection::caret(11), " and now long\nnew")],
+            EditType::InsertChars,
+        );
+        lines.update(&inval_lines);
+        assert_eq!(
+            visual_lines(&buffer, &mut lines),
+            ["short", "short ", "and ", "now ", "long", "new", "short"]
+        );
+    }
+
+    #[test]
+    fn break_long_words_between_grapheme_clusters() {
+        // Three Arabic letters with harakat, then two flags, each cluster one or two columns wide
+        let buffer = Buffer::new("بَتِثُ🇸🇦🇺🇸");
+        let mut lines = VisualLines::new(2.0, MonospaceMeasure::default());
+        assert_eq!(visual_lines(&buffer, &mut lines), ["بَتِ", "ثُ", "🇸🇦", "🇺🇸"]);
+        // Clicking in the middle of a flag never lands inside it
+        assert_eq!(lines.offset_of_x(&buffer, 2, 0.9), 12);
+        assert_eq!(lines.offset_of_x(&buffer, 2, 1.1), 20);
+    }
+
+    #[test]
+    fn custom_measure() {
+        let buffer = Buffer::new("ab ab ab");
+        // Every character is two units wide
+        let mut lines = VisualLines::new(6.0, |text: &str| text.chars().count() as f64 * 2.0);
+        assert_eq!(visual_lines(&buffer, &mut lines), ["ab ", "ab ", "ab"]);
+    }
+
+    #[test]
+    fn lays_out_up_to_the_visual_line() {
+        let text: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        let buffer = Buffer::new(text.as_str());
+        let mut lines = VisualLines::new(4.0, MonospaceMeasure::default());
+        // Every buffer line but the last, empty one wraps into two visual lines
+        assert_eq!(lines.buffer_line_of_visual_line(&buffer, 5), (2, 1));
+        assert!(lines.lines[..3].iter().all(Option::is_some));
+        assert!(lines.lines[3..].iter().all(Option::is_none));
+
+        assert_eq!(lines.buffer_line_of_visual_line(&buffer, 1000), (100, 0));
+        assert_eq!(lines.buffer_line_of_visual_line(&buffer, 7), (3, 1));
+        assert_eq!(lines.num_visual_lines(&buffer), 201);
+    }
+
+    #[test]
+    fn parley_measure() {
+        let mut measure =
+            ParleyMeasure::new(FontContext::new(), vec![StyleProperty::FontSize(16.0)]);
+        assert_eq!(measure.measure(""), 0.0);
+        assert!(measure.measure("ab") >= measure.measure("a"));
+
+        let buffer = Buffer::new("hello wrapped world");
+        let width = measure.measure("hello ");
+        let mut lines = VisualLines::new(width, measure);
+        if width > 0.0 {
+            // Without any font, nothing has a width and nothing wraps
+            assert_eq!(visual_lines(&buffer, &mut lines)[0], "hello ");
+        }
+    }
+}